@@ -0,0 +1,125 @@
+#![warn(missing_docs)]
+//! This crate provides the `AnchorWithMetaData` derive macro of [`bdk_redb`], enabled with its
+//! `derive` feature. It should be used through the re-export in `bdk_redb::anchor_trait`.
+//!
+//! [`bdk_redb`]: <https://docs.rs/bdk_redb>
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Fields, LitInt, parse_macro_input};
+
+/// Derives `bdk_redb::anchor_trait::AnchorWithMetaData` for a struct with named fields, one of
+/// which is `block_id: BlockId`.
+///
+/// The metadata is the ciborium encoding of the other fields, in declaration order, so they must
+/// implement serde's `Serialize` and `Deserialize`. The `TAG` of the anchor type is given with
+/// the `#[anchor(tag = N)]` attribute. The struct must implement `bdk_chain::Anchor` itself.
+///
+/// ```ignore
+/// #[derive(AnchorWithMetaData)]
+/// #[anchor(tag = 3)]
+/// struct MyAnchor {
+///     block_id: BlockId,
+///     confirmations: u32,
+/// }
+/// ```
+#[proc_macro_derive(AnchorWithMetaData, attributes(anchor))]
+pub fn derive_anchor_with_metadata(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+// This function generates the implementation of AnchorWithMetaData for `input`.
+fn expand(input: DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let name = &input.ident;
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(
+            name,
+            "AnchorWithMetaData can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(Error::new_spanned(
+            name,
+            "AnchorWithMetaData can only be derived for structs with named fields",
+        ));
+    };
+    if !fields.named.iter().any(|field| {
+        field
+            .ident
+            .as_ref()
+            .is_some_and(|ident| ident == "block_id")
+    }) {
+        return Err(Error::new_spanned(
+            name,
+            "AnchorWithMetaData requires a `block_id: BlockId` field",
+        ));
+    }
+    let tag = parse_tag(&input)?;
+
+    let (metadata_fields, metadata_types): (Vec<_>, Vec<_>) = fields
+        .named
+        .iter()
+        .filter(|field| {
+            field
+                .ident
+                .as_ref()
+                .is_some_and(|ident| ident != "block_id")
+        })
+        .map(|field| (field.ident.clone(), &field.ty))
+        .unzip();
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::bdk_redb::anchor_trait::AnchorWithMetaData
+            for #name #ty_generics #where_clause
+        {
+            type MetaDataType = ::std::vec::Vec<u8>;
+            const TAG: u8 = #tag;
+
+            fn metadata(&self) -> ::std::vec::Vec<u8> {
+                ::bdk_redb::anchor_trait::__derive::encode_metadata(
+                    &(#(&self.#metadata_fields,)*)
+                )
+            }
+
+            fn from_id(
+                id: ::bdk_redb::anchor_trait::__derive::BlockId,
+                metadata: ::std::vec::Vec<u8>,
+            ) -> Self {
+                let (#(#metadata_fields,)*): (#(#metadata_types,)*) =
+                    ::bdk_redb::anchor_trait::__derive::decode_metadata(&metadata);
+                Self {
+                    block_id: id,
+                    #(#metadata_fields,)*
+                }
+            }
+        }
+    })
+}
+
+// This function parses the tag of the `#[anchor(tag = N)]` attribute.
+fn parse_tag(input: &DeriveInput) -> Result<u8, Error> {
+    let mut tag = None;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("anchor"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("tag") {
+                tag = Some(meta.value()?.parse::<LitInt>()?.base10_parse::<u8>()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `tag = N`"))
+            }
+        })?;
+    }
+    tag.ok_or_else(|| {
+        Error::new(
+            Span::call_site(),
+            "AnchorWithMetaData requires a `#[anchor(tag = N)]` attribute",
+        )
+    })
+}
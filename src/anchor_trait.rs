@@ -1,8 +1,15 @@
 #![warn(missing_docs)]
 //! This module contains [`AnchorWithMetaData`] trait and implementations of the trait for some
-//! types.
+//! types, along with the [`SerdeAnchor`] adapter which implements it for any serde [`Anchor`] with
+//! the `serde` feature.
+//!
+//! [`Anchor`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/trait.Anchor.html>
+#[cfg(feature = "serde")]
+use crate::wrapper::CborValue;
 use bdk_chain::{Anchor, BlockId, ConfirmationBlockTime};
 use redb::Value;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 
 /// A trait that provides metadata corresponding to an [`Anchor`].
 ///
@@ -13,6 +20,11 @@ pub trait AnchorWithMetaData: Anchor {
     /// Type corresponding to the Anchor's metadata.
     type MetaDataType: Value + 'static;
 
+    /// Discriminant stored with each anchor so that anchors of different types can be persisted
+    /// for the same transaction graph. It must be different for every anchor type used with a
+    /// wallet.
+    const TAG: u8;
+
     /// This function returns the metadata corresponding to the anchor.
     fn metadata(&self) -> <Self::MetaDataType as redb::Value>::SelfType<'_>;
 
@@ -22,6 +34,7 @@ pub trait AnchorWithMetaData: Anchor {
 
 impl AnchorWithMetaData for ConfirmationBlockTime {
     type MetaDataType = u64;
+    const TAG: u8 = 1;
 
     fn metadata(&self) -> <Self::MetaDataType as redb::Value>::SelfType<'_> {
         self.confirmation_time
@@ -37,6 +50,7 @@ impl AnchorWithMetaData for ConfirmationBlockTime {
 
 impl AnchorWithMetaData for BlockId {
     type MetaDataType = Option<()>;
+    const TAG: u8 = 0;
 
     fn metadata(&self) -> <Self::MetaDataType as redb::Value>::SelfType<'_> {
         None
@@ -47,3 +61,131 @@ impl AnchorWithMetaData for BlockId {
         id
     }
 }
+
+/// Adapter persisting any [`Anchor`] which implements serde's `Serialize` and `Deserialize`, so
+/// that custom anchor types do not need an [`AnchorWithMetaData`] implementation.
+///
+/// The metadata of a `SerdeAnchor<A>` is the whole wrapped anchor, stored as its ciborium
+/// encoding with [`CborValue`]. [`from_id`] decodes the anchor back from the metadata, the block
+/// id of the anchors table only being used for ordering and lookups. This is less compact than
+/// the dedicated encodings of [`ConfirmationBlockTime`] and [`BlockId`], which should be
+/// preferred for these types.
+///
+/// `TAG` is the [`AnchorWithMetaData::TAG`] of the adapter. It defaults to 2 and must be set to
+/// distinct values if several `SerdeAnchor` types are persisted for the same wallet. Enabled with
+/// the `serde` feature.
+///
+/// [`Anchor`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/trait.Anchor.html>
+/// [`from_id`]: AnchorWithMetaData::from_id
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SerdeAnchor<A, const TAG: u8 = 2>(pub A);
+
+#[cfg(feature = "serde")]
+impl<A: Anchor, const TAG: u8> Anchor for SerdeAnchor<A, TAG> {
+    fn anchor_block(&self) -> BlockId {
+        self.0.anchor_block()
+    }
+
+    fn confirmation_height_upper_bound(&self) -> u32 {
+        self.0.confirmation_height_upper_bound()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<A: Anchor + Serialize + DeserializeOwned + 'static, const TAG: u8> AnchorWithMetaData
+    for SerdeAnchor<A, TAG>
+{
+    type MetaDataType = CborValue<A>;
+    const TAG: u8 = TAG;
+
+    fn metadata(&self) -> <Self::MetaDataType as redb::Value>::SelfType<'_> {
+        self.0.clone()
+    }
+
+    // The anchor is decoded from the metadata, which was written by `metadata` along with the
+    // same block id.
+    fn from_id(id: BlockId, anchor: <Self::MetaDataType as redb::Value>::SelfType<'_>) -> Self {
+        debug_assert_eq!(anchor.anchor_block(), id);
+        SerdeAnchor(anchor)
+    }
+}
+
+/// Derives [`AnchorWithMetaData`] for a struct with named fields, one of which is
+/// `block_id: BlockId`, along with additional fields implementing serde's `Serialize` and
+/// `Deserialize`. Enabled with the `derive` feature.
+///
+/// The metadata is the ciborium encoding of the additional fields in declaration order, and
+/// [`from_id`] rebuilds the struct from the block id of the anchors table and the decoded
+/// fields. The [`AnchorWithMetaData::TAG`] is given with the `#[anchor(tag = N)]` attribute.
+/// The struct must still implement [`Anchor`].
+///
+/// ```
+/// use bdk_chain::{Anchor, BlockId};
+/// use bdk_redb::anchor_trait::AnchorWithMetaData;
+///
+/// #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, AnchorWithMetaData)]
+/// #[anchor(tag = 3)]
+/// struct CustomAnchor {
+///     block_id: BlockId,
+///     confirmations: u32,
+/// }
+///
+/// impl Anchor for CustomAnchor {
+///     fn anchor_block(&self) -> BlockId {
+///         self.block_id
+///     }
+/// }
+/// ```
+///
+/// A struct without a `block_id` field is rejected:
+///
+/// ```compile_fail
+/// use bdk_chain::BlockId;
+/// use bdk_redb::anchor_trait::AnchorWithMetaData;
+///
+/// #[derive(AnchorWithMetaData)]
+/// #[anchor(tag = 3)]
+/// struct CustomAnchor {
+///     id: BlockId,
+///     confirmations: u32,
+/// }
+/// ```
+///
+/// So is a struct without the `anchor` attribute:
+///
+/// ```compile_fail
+/// use bdk_chain::BlockId;
+/// use bdk_redb::anchor_trait::AnchorWithMetaData;
+///
+/// #[derive(AnchorWithMetaData)]
+/// struct CustomAnchor {
+///     block_id: BlockId,
+///     confirmations: u32,
+/// }
+/// ```
+///
+/// [`Anchor`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/trait.Anchor.html>
+/// [`from_id`]: AnchorWithMetaData::from_id
+#[cfg(feature = "derive")]
+pub use bdk_redb_derive::AnchorWithMetaData;
+
+// Items used by the code generated by the derive macro.
+#[cfg(feature = "derive")]
+#[doc(hidden)]
+pub mod __derive {
+    use serde::{Serialize, de::DeserializeOwned};
+
+    pub use bdk_chain::BlockId;
+
+    pub fn encode_metadata<T: Serialize>(fields: &T) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(fields, &mut bytes).expect("anchor fields must be serializable");
+        bytes
+    }
+
+    pub fn decode_metadata<T: DeserializeOwned>(metadata: &[u8]) -> T {
+        ciborium::from_reader(metadata).expect("metadata must be ciborium encoded anchor fields")
+    }
+}
@@ -0,0 +1,98 @@
+#![warn(missing_docs)]
+//! This module contains [`MemoryBackend`], an in-memory redb [`StorageBackend`] whose bytes can be
+//! exported and imported, for targets without a file system such as `wasm32-unknown-unknown`.
+//! A store is created on it with [`Store::from_backend`].
+//!
+//! [`StorageBackend`]: <https://docs.rs/redb/2.5.0/redb/trait.StorageBackend.html>
+//! [`Store::from_backend`]: crate::Store::from_backend
+use redb::StorageBackend;
+use std::io;
+use std::sync::{Arc, RwLock};
+
+/// An in-memory [`StorageBackend`] holding the bytes of a redb database.
+///
+/// Clones share the same bytes, so the application can keep a clone of the backend given to
+/// [`Store::from_backend`] and call [`to_bytes`] after a persist to save the database elsewhere
+/// (e.g. in IndexedDB), then restore it on the next start with [`from_bytes`].
+///
+/// [`StorageBackend`]: <https://docs.rs/redb/2.5.0/redb/trait.StorageBackend.html>
+/// [`Store::from_backend`]: crate::Store::from_backend
+/// [`to_bytes`]: Self::to_bytes
+/// [`from_bytes`]: Self::from_bytes
+#[derive(Debug, Default, Clone)]
+pub struct MemoryBackend {
+    bytes: Arc<RwLock<Vec<u8>>>,
+}
+
+impl MemoryBackend {
+    /// This function creates an empty backend, on which a new database is created.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// This function creates a backend holding the bytes of a database previously returned by
+    /// [`to_bytes`].
+    ///
+    /// [`to_bytes`]: Self::to_bytes
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        MemoryBackend {
+            bytes: Arc::new(RwLock::new(bytes)),
+        }
+    }
+
+    /// This function returns a copy of the bytes of the database. They include every committed
+    /// write transaction, whatever its durability.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.bytes
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+}
+
+// This function returns the error of an access beyond the end of the backend.
+fn out_of_range() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "out of range access to memory backend",
+    )
+}
+
+// This function returns the error of a lock poisoned by a panicking thread.
+fn poisoned<T>(_: T) -> io::Error {
+    io::Error::other("memory backend lock poisoned")
+}
+
+impl StorageBackend for MemoryBackend {
+    fn len(&self) -> Result<u64, io::Error> {
+        Ok(self.bytes.read().map_err(poisoned)?.len() as u64)
+    }
+
+    fn read(&self, offset: u64, len: usize) -> Result<Vec<u8>, io::Error> {
+        let bytes = self.bytes.read().map_err(poisoned)?;
+        let start = usize::try_from(offset).map_err(|_| out_of_range())?;
+        let end = start.checked_add(len).ok_or_else(out_of_range)?;
+        Ok(bytes.get(start..end).ok_or_else(out_of_range)?.to_vec())
+    }
+
+    fn set_len(&self, len: u64) -> Result<(), io::Error> {
+        let len = usize::try_from(len).map_err(|_| out_of_range())?;
+        self.bytes.write().map_err(poisoned)?.resize(len, 0);
+        Ok(())
+    }
+
+    fn sync_data(&self, _eventual: bool) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> Result<(), io::Error> {
+        let mut bytes = self.bytes.write().map_err(poisoned)?;
+        let start = usize::try_from(offset).map_err(|_| out_of_range())?;
+        let end = start.checked_add(data.len()).ok_or_else(out_of_range)?;
+        bytes
+            .get_mut(start..end)
+            .ok_or_else(out_of_range)?
+            .copy_from_slice(data);
+        Ok(())
+    }
+}
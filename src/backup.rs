@@ -0,0 +1,119 @@
+//! This module contains [`Store::backup_to`] which copies a wallet to another database file.
+use crate::error::StoreError;
+use crate::{
+    CHANGELOG_SEQUENCE, FORMAT_VERSION, NETWORK, NETWORK_BYTE, Store,
+    open_multimap_table_if_exists, open_table_if_exists,
+};
+use redb::{
+    Database, Key, MultimapTableDefinition, ReadTransaction, ReadableMultimapTable, ReadableTable,
+    TableDefinition, Value, WriteTransaction,
+};
+use std::path::Path;
+
+impl Store {
+    /// This function copies the wallet to the redb database file at `path`, creating it if it does
+    /// not exist. Every table of the wallet is copied along with its rows of the tables shared by
+    /// all wallets (network, format version and changelog sequence), so the backup can be opened
+    /// with [`attach`] under the same wallet name. Other wallets of the database are not copied.
+    ///
+    /// The tables are read from a single read transaction, so the backup is a consistent snapshot
+    /// of the wallet as it was when this function was called: persists made concurrently through
+    /// this or another [`Store`] are not blocked and are not part of the backup. The backup is
+    /// written in a single write transaction, committed once every table was copied.
+    ///
+    /// If `path` is an existing database file, rows of this wallet already in it are overwritten
+    /// but rows which are not in the snapshot are kept, so a new file should be used for every
+    /// backup.
+    ///
+    /// [`attach`]: Self::attach
+    pub fn backup_to<P: AsRef<Path>>(&self, path: P) -> Result<(), StoreError> {
+        let read_tx = self.db.begin_read()?;
+        self.backup_snapshot(&read_tx, path.as_ref())
+    }
+
+    // This function copies the wallet, as seen by `read_tx`, to the database file at `path`.
+    pub(crate) fn backup_snapshot(
+        &self,
+        read_tx: &ReadTransaction,
+        path: &Path,
+    ) -> Result<(), StoreError> {
+        let backup = Database::create(path)?;
+        let write_tx = backup.begin_write()?;
+
+        copy_table(read_tx, &write_tx, self.keychains_table_defn())?;
+        copy_table(read_tx, &write_tx, self.blocks_table_defn())?;
+        copy_table(read_tx, &write_tx, self.txs_table_defn())?;
+        copy_table(read_tx, &write_tx, self.txouts_table_defn())?;
+        copy_multimap_table(read_tx, &write_tx, self.anchors_table_defn())?;
+        copy_table(read_tx, &write_tx, self.last_seen_defn())?;
+        copy_table(read_tx, &write_tx, self.last_evicted_table_defn())?;
+        copy_table(read_tx, &write_tx, self.first_seen_table_defn())?;
+        copy_table(read_tx, &write_tx, self.last_revealed_table_defn())?;
+        copy_table(read_tx, &write_tx, self.spk_table_defn())?;
+        copy_table(read_tx, &write_tx, self.changelog_table_defn())?;
+        copy_table(read_tx, &write_tx, self.meta_table_defn())?;
+
+        copy_wallet_row(read_tx, &write_tx, NETWORK, &self.wallet_name)?;
+        copy_wallet_row(read_tx, &write_tx, NETWORK_BYTE, &self.wallet_name)?;
+        copy_wallet_row(read_tx, &write_tx, FORMAT_VERSION, &self.wallet_name)?;
+        copy_wallet_row(read_tx, &write_tx, CHANGELOG_SEQUENCE, &self.wallet_name)?;
+
+        write_tx.commit()?;
+        Ok(())
+    }
+}
+
+// This function copies every entry of a table to the same table of `write_tx`. Nothing is created
+// if the table does not exist.
+fn copy_table<K: Key + 'static, V: Value + 'static>(
+    read_tx: &ReadTransaction,
+    write_tx: &WriteTransaction,
+    table_defn: TableDefinition<K, V>,
+) -> Result<(), StoreError> {
+    let Some(source) = open_table_if_exists(read_tx, table_defn)? else {
+        return Ok(());
+    };
+    let mut target = write_tx.open_table(table_defn)?;
+    for entry in source.iter()? {
+        let (key, value) = entry?;
+        target.insert(key.value(), value.value())?;
+    }
+    Ok(())
+}
+
+// Same as copy_table for a multimap table.
+fn copy_multimap_table<K: Key + 'static, V: Key + 'static>(
+    read_tx: &ReadTransaction,
+    write_tx: &WriteTransaction,
+    table_defn: MultimapTableDefinition<K, V>,
+) -> Result<(), StoreError> {
+    let Some(source) = open_multimap_table_if_exists(read_tx, table_defn)? else {
+        return Ok(());
+    };
+    let mut target = write_tx.open_multimap_table(table_defn)?;
+    for entry in source.iter()? {
+        let (key, values) = entry?;
+        for value in values {
+            target.insert(key.value(), value?.value())?;
+        }
+    }
+    Ok(())
+}
+
+// This function copies the row of the wallet in a table shared by all wallets. The table is created
+// if it exists in the source, even if the wallet has no row in it.
+fn copy_wallet_row<V: Value + 'static>(
+    read_tx: &ReadTransaction,
+    write_tx: &WriteTransaction,
+    table_defn: TableDefinition<&str, V>,
+    wallet_name: &str,
+) -> Result<(), StoreError> {
+    let Some(source) = open_table_if_exists(read_tx, table_defn)? else {
+        return Ok(());
+    };
+    let mut target = write_tx.open_table(table_defn)?;
+    if let Some(value) = source.get(wallet_name)? {
+        target.insert(wallet_name, value.value())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,239 @@
+//! `bdk-redb-cli` inspects the wallets persisted in a redb database file written by [`bdk_redb`],
+//! for instance one sent along with a support request. It is built with the `cli` feature.
+//!
+//! ```text
+//! bdk-redb-cli wallets <file>
+//! bdk-redb-cli stats <file> <wallet>
+//! bdk-redb-cli dump <file> <wallet> --table <table>
+//! bdk-redb-cli verify <file> <wallet>
+//! ```
+//!
+//! The database file is never created nor written to: redb has no read-only mode, but only read
+//! transactions are committed (`stats` opens a write transaction to read the database wide
+//! statistics and aborts it). Anchors are read as [`ConfirmationBlockTime`]s.
+//!
+//! [`bdk_redb`]: <https://docs.rs/bdk_redb>
+//! [`ConfirmationBlockTime`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/struct.ConfirmationBlockTime.html>
+use bdk_chain::ConfirmationBlockTime;
+use bdk_redb::Store;
+use bdk_redb::error::StoreError;
+use bdk_redb::redb::Database;
+use std::io::{self, Write};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+const USAGE: &str = "usage:
+    bdk-redb-cli wallets <file>
+    bdk-redb-cli stats <file> <wallet>
+    bdk-redb-cli dump <file> <wallet> --table <table>
+    bdk-redb-cli verify <file> <wallet>
+
+tables: keychains, blocks, txs, txouts, anchors, last_seen, first_seen, last_evicted,
+        last_revealed, spks";
+
+// Tables accepted by `dump` along with the corresponding array of the JSON export.
+const DUMP_TABLES: [(&str, &str); 10] = [
+    ("keychains", "descriptors"),
+    ("blocks", "blocks"),
+    ("txs", "transactions"),
+    ("txouts", "txouts"),
+    ("anchors", "anchors"),
+    ("last_seen", "last_seen"),
+    ("first_seen", "first_seen"),
+    ("last_evicted", "last_evicted"),
+    ("last_revealed", "last_revealed"),
+    ("spks", "spks"),
+];
+
+// A parsed command line.
+enum Command {
+    Wallets {
+        file: String,
+    },
+    Stats {
+        file: String,
+        wallet: String,
+    },
+    Dump {
+        file: String,
+        wallet: String,
+        table: &'static str,
+    },
+    Verify {
+        file: String,
+        wallet: String,
+    },
+}
+
+// An error of the command line or of the store.
+enum CliError {
+    Usage(String),
+    Store(StoreError),
+}
+
+impl From<StoreError> for CliError {
+    fn from(err: StoreError) -> Self {
+        CliError::Store(err)
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(err: io::Error) -> Self {
+        CliError::Store(err.into())
+    }
+}
+
+impl From<bdk_redb::redb::DatabaseError> for CliError {
+    fn from(err: bdk_redb::redb::DatabaseError) -> Self {
+        CliError::Store(err.into())
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = parse_args(&args).and_then(|command| run(command, &mut io::stdout().lock()));
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(CliError::Usage(message)) => {
+            eprintln!("error: {message}\n\n{USAGE}");
+            ExitCode::from(2)
+        }
+        Err(CliError::Store(err)) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// This function parses the arguments following the name of the binary.
+fn parse_args(args: &[String]) -> Result<Command, CliError> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["wallets", file] => Ok(Command::Wallets {
+            file: file.to_string(),
+        }),
+        ["stats", file, wallet] => Ok(Command::Stats {
+            file: file.to_string(),
+            wallet: wallet.to_string(),
+        }),
+        ["dump", file, wallet, "--table", table] | ["dump", "--table", table, file, wallet] => {
+            let Some((_, array)) = DUMP_TABLES.iter().find(|(name, _)| name == table) else {
+                return Err(CliError::Usage(format!("unknown table `{table}`")));
+            };
+            Ok(Command::Dump {
+                file: file.to_string(),
+                wallet: wallet.to_string(),
+                table: array,
+            })
+        }
+        ["verify", file, wallet] => Ok(Command::Verify {
+            file: file.to_string(),
+            wallet: wallet.to_string(),
+        }),
+        [] => Err(CliError::Usage("missing command".to_string())),
+        [command, ..] => Err(CliError::Usage(format!(
+            "invalid arguments for `{command}`"
+        ))),
+    }
+}
+
+// This function runs `command`, writing its output to `out`. It returns `false` if the command
+// found a problem, i.e. `verify` found inconsistencies.
+fn run(command: Command, out: &mut impl Write) -> Result<bool, CliError> {
+    match command {
+        Command::Wallets { file } => {
+            let db = Database::open(file)?;
+            for (wallet_name, network) in bdk_redb::list_wallets(&db)? {
+                match network {
+                    Some(network) => writeln!(out, "{wallet_name}\t{network}")?,
+                    None => writeln!(out, "{wallet_name}\t-")?,
+                }
+            }
+        }
+        Command::Stats { file, wallet } => {
+            let stats = open_wallet(&file, wallet)?.stats()?;
+            writeln!(out, "table\tentries\tstored_bytes")?;
+            for (name, table) in [
+                ("keychains", stats.keychains),
+                ("blocks", stats.blocks),
+                ("txs", stats.txs),
+                ("txouts", stats.txouts),
+                ("anchors", stats.anchors),
+                ("last_seen", stats.last_seen),
+                ("first_seen", stats.first_seen),
+                ("last_evicted", stats.last_evicted),
+                ("last_revealed", stats.last_revealed),
+                ("spks", stats.spks),
+            ] {
+                writeln!(out, "{name}\t{}\t{}", table.entries, table.stored_bytes)?;
+            }
+        }
+        Command::Dump {
+            file,
+            wallet,
+            table,
+        } => {
+            let mut export = Vec::new();
+            open_wallet(&file, wallet)?.export_json::<ConfirmationBlockTime>(&mut export)?;
+            let export: serde_json::Value =
+                serde_json::from_slice(&export).map_err(StoreError::from)?;
+            for row in export[table].as_array().into_iter().flatten() {
+                writeln!(out, "{row}")?;
+            }
+        }
+        Command::Verify { file, wallet } => {
+            let report =
+                open_wallet(&file, wallet)?.verify_references::<ConfirmationBlockTime>()?;
+            for (block_id, txid) in &report.anchors_missing_tx {
+                writeln!(
+                    out,
+                    "anchor at {}:{} of missing tx {txid}",
+                    block_id.height, block_id.hash
+                )?;
+            }
+            for (block_id, hash) in &report.anchors_conflicting_block {
+                writeln!(
+                    out,
+                    "anchor at {}:{} conflicting with block {hash}",
+                    block_id.height, block_id.hash
+                )?;
+            }
+            for (name, txids) in [
+                ("last_seen", &report.last_seen_missing_tx),
+                ("first_seen", &report.first_seen_missing_tx),
+                ("last_evicted", &report.last_evicted_missing_tx),
+            ] {
+                for txid in txids {
+                    writeln!(out, "{name} of missing tx {txid}")?;
+                }
+            }
+            for (descriptor_id, range) in &report.spk_cache_gaps {
+                writeln!(
+                    out,
+                    "spk cache of {descriptor_id} missing indices {}..{}",
+                    range.start, range.end
+                )?;
+            }
+            if !report.is_consistent() {
+                return Ok(false);
+            }
+            writeln!(out, "ok")?;
+        }
+    }
+    Ok(true)
+}
+
+// This function opens the existing database file at `file` and the wallet named `wallet` in it,
+// without creating any table. Wallets created with a prefix are named `<prefix>:<wallet_name>`,
+// as listed by the wallets command.
+fn open_wallet(file: &str, wallet: String) -> Result<Store, CliError> {
+    let db = Arc::new(Database::open(file)?);
+    let store = match wallet.split_once(':') {
+        Some((prefix, wallet_name)) => {
+            Store::attach_with_prefix(db, prefix.to_string(), wallet_name.to_string())?
+        }
+        None => Store::attach(db, wallet)?,
+    };
+    Ok(store)
+}
@@ -0,0 +1,173 @@
+#![warn(missing_docs)]
+//! This module contains [`Store::export_changeset_cbor`] and [`Store::import_changeset_cbor`]
+//! which write and read compact binary backups of a wallet. They are available with the `serde`
+//! feature.
+//!
+//! # Format
+//!
+//! A backup starts with the [`CBOR_BACKUP_MAGIC`] bytes and the [`CBOR_BACKUP_VERSION`] byte,
+//! followed by the ciborium encoding of the changeset of the wallet: a [`ChangeSet`] with the
+//! `wallet` feature, a [`CombinedChangeSet`] without it. Both are encoded as CBOR maps with the
+//! same `local_chain`, `tx_graph` and `indexer` keys, so a backup written with one can be imported
+//! with the other. The descriptors and network are only in backups written with the `wallet`
+//! feature.
+//!
+//! [`Store::export_changeset_cbor`]: crate::Store::export_changeset_cbor
+//! [`Store::import_changeset_cbor`]: crate::Store::import_changeset_cbor
+//! [`ChangeSet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.ChangeSet.html>
+//! [`CombinedChangeSet`]: crate::combined::CombinedChangeSet
+use crate::error::StoreError;
+#[cfg(feature = "wallet")]
+use crate::set_wallet_descriptors;
+use crate::summary::PersistSummary;
+use crate::{Store, combined::CombinedChangeSet};
+use bdk_chain::{ConfirmationBlockTime, Merge};
+#[cfg(feature = "wallet")]
+use bdk_wallet::ChangeSet;
+use redb::{Durability, ReadTransaction, WriteTransaction};
+#[cfg(feature = "wallet")]
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+/// Magic bytes starting every backup written by [`Store::export_changeset_cbor`].
+///
+/// [`Store::export_changeset_cbor`]: crate::Store::export_changeset_cbor
+pub const CBOR_BACKUP_MAGIC: [u8; 4] = *b"BDKR";
+
+/// Version of the backups written by [`Store::export_changeset_cbor`]. It is incremented whenever
+/// the encoding of the changeset changes.
+///
+/// [`Store::export_changeset_cbor`]: crate::Store::export_changeset_cbor
+pub const CBOR_BACKUP_VERSION: u8 = 1;
+
+// The changeset written to backups.
+#[cfg(feature = "wallet")]
+type BackupChangeSet = ChangeSet;
+#[cfg(not(feature = "wallet"))]
+type BackupChangeSet = CombinedChangeSet;
+
+impl Store {
+    /// This function writes a backup of the wallet to `writer` in the compact binary format
+    /// documented in the [`cbor_backup`] module.
+    ///
+    /// The wallet is read in a single read transaction, so the backup is consistent even if the
+    /// store is written to concurrently. The spk cache is always included, whatever
+    /// [`with_spk_cache_loading`]. Unlike [`export_json`], the changeset is built in memory before
+    /// being written. The wallet is written decrypted if an encryption key is set, so the backup
+    /// should be encrypted by the caller if needed.
+    ///
+    /// [`cbor_backup`]: crate::cbor_backup
+    /// [`with_spk_cache_loading`]: Self::with_spk_cache_loading
+    /// [`export_json`]: Self::export_json
+    pub fn export_changeset_cbor(&self, mut writer: impl Write) -> Result<(), StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let changeset = self.read_backup_changeset(&read_tx)?;
+        writer.write_all(&CBOR_BACKUP_MAGIC)?;
+        writer.write_all(&[CBOR_BACKUP_VERSION])?;
+        ciborium::into_writer(&changeset, &mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// This function imports a backup written by [`export_changeset_cbor`], read from `reader`,
+    /// and returns a [`PersistSummary`] of the rows written.
+    ///
+    /// The backup is merged into the persisted wallet following BDK's [`Merge`] semantics (blocks,
+    /// txs and anchors are united, last revealed indices take the maximum), so importing into a
+    /// store which already has data keeps it. The tables are created if needed (see
+    /// [`create_tables`]) and the merged changeset is then persisted in a single write
+    /// transaction, so nothing is written if the import fails. The summary counts every row of
+    /// the merged changeset as inserted.
+    ///
+    /// [`StoreError::InvalidBackupMagic`] is returned if `reader` does not start with the magic
+    /// bytes, [`StoreError::UnsupportedBackupVersion`] if the version byte is not
+    /// [`CBOR_BACKUP_VERSION`] and [`StoreError::Deser`] if the changeset does not decode, for
+    /// instance because the backup was truncated. With the `wallet` feature,
+    /// [`StoreError::NetworkMismatch`] is returned if the wallet already has a network different
+    /// from the one of the backup, and [`StoreError::DescriptorImmutable`] if its descriptors are
+    /// different from the persisted ones.
+    ///
+    /// [`export_changeset_cbor`]: Self::export_changeset_cbor
+    /// [`create_tables`]: Self::create_tables
+    /// [`CBOR_BACKUP_VERSION`]: crate::cbor_backup::CBOR_BACKUP_VERSION
+    /// [`Merge`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/trait.Merge.html>
+    pub fn import_changeset_cbor(
+        &self,
+        mut reader: impl Read,
+    ) -> Result<PersistSummary, StoreError> {
+        let mut magic = [0; CBOR_BACKUP_MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if magic != CBOR_BACKUP_MAGIC {
+            return Err(StoreError::InvalidBackupMagic);
+        }
+        let mut version = [0];
+        reader.read_exact(&mut version)?;
+        if version[0] != CBOR_BACKUP_VERSION {
+            return Err(StoreError::UnsupportedBackupVersion(version[0]));
+        }
+        let imported: BackupChangeSet = ciborium::from_reader(reader)?;
+
+        self.create_tables::<ConfirmationBlockTime>()?;
+        let write_tx = self.begin_write(Durability::Immediate)?;
+        let read_tx = self.db.begin_read()?;
+        let mut changeset = self.read_backup_changeset(&read_tx)?;
+        #[cfg(feature = "wallet")]
+        if let (Some(ours), Some(theirs)) = (changeset.network, imported.network) {
+            if ours != theirs {
+                return Err(StoreError::NetworkMismatch { ours, theirs });
+            }
+        }
+        changeset.merge(imported);
+        let summary = self.persist_backup_changeset(&write_tx, &read_tx, &changeset)?;
+        self.commit(write_tx)?;
+        Ok(summary)
+    }
+
+    // This function reads the changeset written to backups in `read_tx`, with the spk cache.
+    fn read_backup_changeset(
+        &self,
+        read_tx: &ReadTransaction,
+    ) -> Result<BackupChangeSet, StoreError> {
+        let mut combined = CombinedChangeSet::default();
+        self.read_combined_in(read_tx, &mut combined, true)?;
+        #[cfg(feature = "wallet")]
+        {
+            let mut changeset = ChangeSet {
+                local_chain: combined.local_chain,
+                tx_graph: combined.tx_graph,
+                indexer: combined.indexer,
+                ..Default::default()
+            };
+            self.read_network_in(read_tx, &mut changeset.network)?;
+            let mut desc_changeset = BTreeMap::new();
+            self.read_keychains_in(read_tx, &mut desc_changeset)?;
+            set_wallet_descriptors(&mut changeset, desc_changeset);
+            Ok(changeset)
+        }
+        #[cfg(not(feature = "wallet"))]
+        Ok(combined)
+    }
+
+    // This function persists a changeset read from a backup in the given write transaction.
+    fn persist_backup_changeset(
+        &self,
+        write_tx: &WriteTransaction,
+        read_tx: &ReadTransaction,
+        changeset: &BackupChangeSet,
+    ) -> Result<PersistSummary, StoreError> {
+        #[allow(unused_mut)]
+        let mut summary = self.persist_sections_in(
+            write_tx,
+            read_tx,
+            &changeset.local_chain,
+            &changeset.tx_graph,
+            &changeset.indexer,
+        )?;
+        #[cfg(feature = "wallet")]
+        {
+            summary.network = self.persist_network_in(write_tx, &changeset.network)?;
+            summary.keychains = self.persist_wallet_keychains_in(write_tx, changeset)?;
+        }
+        Ok(summary)
+    }
+}
@@ -0,0 +1,131 @@
+//! This module contains the changelog of a wallet, an append-only log of the changesets persisted
+//! through [`Store::persist_wallet`] and [`Store::persist_tx_graph`].
+use crate::anchor_trait::AnchorWithMetaData;
+use crate::error::StoreError;
+use crate::{CHANGELOG_VALUE, Store, open_table_if_exists};
+use bdk_chain::{ConfirmationBlockTime, Merge, tx_graph};
+use bdk_wallet::ChangeSet;
+use redb::{Durability, Value};
+
+impl Store {
+    /// This function enables or disables the changelog of the wallet. It is disabled by default.
+    ///
+    /// When enabled, every successful call to [`persist_wallet`] (or [`persist_wallet_batched`])
+    /// and [`persist_tx_graph`] with a non-empty changeset appends a row to the
+    /// `<wallet_name>_changelog` table: a sequence number, the unix timestamp of the persist and
+    /// the changeset. Rows can be read with [`read_changelog`] to find out which changesets
+    /// were persisted and when.
+    ///
+    /// The row is written in the write transaction persisting the changeset, so a log entry is
+    /// committed if and only if the changeset is. Only [`persist_tx_graph`] calls with anchors of
+    /// type [`ConfirmationBlockTime`] (the anchors of a [`Wallet`]) are logged, as a [`ChangeSet`]
+    /// with only the `tx_graph` field set.
+    ///
+    /// The changelog grows with every persist and is never pruned automatically, see
+    /// [`truncate_changelog`].
+    ///
+    /// [`persist_wallet`]: Self::persist_wallet
+    /// [`persist_wallet_batched`]: Self::persist_wallet_batched
+    /// [`persist_tx_graph`]: Self::persist_tx_graph
+    /// [`read_changelog`]: Self::read_changelog
+    /// [`truncate_changelog`]: Self::truncate_changelog
+    /// [`TxGraph`]: <http://docs.rs/bdk_chain/0.23.0/bdk_chain/tx_graph/struct.TxGraph.html>
+    /// [`ConfirmationBlockTime`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/struct.ConfirmationBlockTime.html>
+    /// [`Wallet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.Wallet.html>
+    /// [`ChangeSet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.ChangeSet.html>
+    pub fn enable_changelog(&mut self, enabled: bool) {
+        self.changelog = enabled;
+    }
+
+    /// This function returns the changelog rows whose sequence number is at least `since_seq`, as
+    /// (sequence, unix timestamp, [`ChangeSet`]) triples ordered by sequence. Sequence numbers
+    /// start at 0 and are never reused, even after [`truncate_changelog`], so passing the last
+    /// sequence read plus one returns the changesets persisted since.
+    ///
+    /// Merging the changesets in order gives the changes made to the wallet while the changelog
+    /// was enabled.
+    ///
+    /// [`ChangeSet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.ChangeSet.html>
+    /// [`truncate_changelog`]: Self::truncate_changelog
+    pub fn read_changelog(&self, since_seq: u64) -> Result<Vec<(u64, u64, ChangeSet)>, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let Some(table) = open_table_if_exists(&read_tx, self.changelog_table_defn())? else {
+            return Ok(Vec::new());
+        };
+        let mut entries = Vec::new();
+        for entry in table.range(since_seq..)? {
+            let (sequence, row) = entry?;
+            let (timestamp, bytes) = row.value();
+            let bytes = self.open_value(CHANGELOG_VALUE, &sequence.value().to_be_bytes(), bytes)?;
+            let changeset: ChangeSet = ciborium::from_reader(bytes.as_slice())?;
+            entries.push((sequence.value(), timestamp, changeset));
+        }
+        Ok(entries)
+    }
+
+    /// This function removes the changelog rows whose sequence number is below `before_seq` and
+    /// returns the number of rows removed. Later rows keep their sequence numbers.
+    pub fn truncate_changelog(&self, before_seq: u64) -> Result<u64, StoreError> {
+        let write_tx = self.begin_write(Durability::Immediate)?;
+        let mut removed = 0;
+        {
+            let mut table = write_tx.open_table(self.changelog_table_defn())?;
+            table.retain_in(..before_seq, |_, _| {
+                removed += 1;
+                false
+            })?;
+        }
+        self.commit(write_tx)?;
+        Ok(removed)
+    }
+
+    // This function returns the encoding of `changeset` to be appended to the changelog, or
+    // `None` if the changelog is disabled or the changeset is empty.
+    pub(crate) fn changelog_entry(
+        &self,
+        changeset: &ChangeSet,
+    ) -> Result<Option<Vec<u8>>, StoreError> {
+        if !self.changelog || changeset.is_empty() {
+            return Ok(None);
+        }
+        let mut bytes = Vec::new();
+        ciborium::into_writer(changeset, &mut bytes)?;
+        Ok(Some(bytes))
+    }
+
+    // Same as changelog_entry for a tx_graph changeset, which is logged as a wallet ChangeSet if
+    // its anchors are ConfirmationBlockTimes. The anchors are converted through the bytes of their
+    // metadata, as when they are stored in the anchors table.
+    pub(crate) fn tx_graph_changelog_entry<A: AnchorWithMetaData>(
+        &self,
+        changeset: &tx_graph::ChangeSet<A>,
+    ) -> Result<Option<Vec<u8>>, StoreError> {
+        if !self.changelog || A::TAG != ConfirmationBlockTime::TAG {
+            return Ok(None);
+        }
+        let anchors = changeset
+            .anchors
+            .iter()
+            .map(|(anchor, txid)| {
+                let metadata = anchor.metadata();
+                let confirmation_time =
+                    u64::from_bytes(A::MetaDataType::as_bytes(&metadata).as_ref());
+                (
+                    ConfirmationBlockTime::from_id(anchor.anchor_block(), confirmation_time),
+                    *txid,
+                )
+            })
+            .collect();
+        self.changelog_entry(&ChangeSet {
+            tx_graph: tx_graph::ChangeSet {
+                txs: changeset.txs.clone(),
+                txouts: changeset.txouts.clone(),
+                anchors,
+                last_seen: changeset.last_seen.clone(),
+                last_evicted: changeset.last_evicted.clone(),
+                first_seen: changeset.first_seen.clone(),
+            },
+            ..Default::default()
+        })
+    }
+}
@@ -0,0 +1,85 @@
+#![warn(missing_docs)]
+//! This module contains [`CombinedChangeSet`] which holds the [`bdk_chain`] changesets of a
+//! wallet, see [`Store::read_combined`].
+//!
+//! [`bdk_chain`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/index.html>
+use crate::Store;
+use crate::error::StoreError;
+use bdk_chain::{ConfirmationBlockTime, Merge, keychain_txout, local_chain, tx_graph};
+use redb::ReadTransaction;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The [`bdk_chain`] changesets persisted for a wallet, without the descriptors and network which
+/// only a [`bdk_wallet::ChangeSet`] carries. It is available without the `wallet` feature.
+///
+/// [`bdk_chain`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/index.html>
+/// [`bdk_wallet::ChangeSet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.ChangeSet.html>
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CombinedChangeSet {
+    /// Changeset of the [`LocalChain`].
+    ///
+    /// [`LocalChain`]: <http://docs.rs/bdk_chain/0.23.0/bdk_chain/local_chain/struct.LocalChain.html>
+    pub local_chain: local_chain::ChangeSet,
+    /// Changeset of the [`TxGraph`].
+    ///
+    /// [`TxGraph`]: <http://docs.rs/bdk_chain/0.23.0/bdk_chain/tx_graph/struct.TxGraph.html>
+    pub tx_graph: tx_graph::ChangeSet<ConfirmationBlockTime>,
+    /// Changeset of the [`KeychainTxOutIndex`].
+    ///
+    /// [`KeychainTxOutIndex`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/indexer/keychain_txout/struct.KeychainTxOutIndex.html>
+    pub indexer: keychain_txout::ChangeSet,
+}
+
+impl Merge for CombinedChangeSet {
+    fn merge(&mut self, other: Self) {
+        self.local_chain.merge(other.local_chain);
+        self.tx_graph.merge(other.tx_graph);
+        self.indexer.merge(other.indexer);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.local_chain.is_empty() && self.tx_graph.is_empty() && self.indexer.is_empty()
+    }
+}
+
+impl Store {
+    /// This function loads the local chain, tx graph and indexer changesets of the wallet in a
+    /// single read transaction, so that they are consistent with each other even if the store is
+    /// written to concurrently. The spk cache is skipped if disabled with
+    /// [`with_spk_cache_loading`].
+    ///
+    /// [`with_spk_cache_loading`]: Self::with_spk_cache_loading
+    pub fn read_combined(&self) -> Result<CombinedChangeSet, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let mut changeset = CombinedChangeSet::default();
+        self.read_combined_in(&read_tx, &mut changeset, self.load_spk_cache)?;
+        Ok(changeset)
+    }
+
+    // Same as read_combined, in the given read transaction. The spk cache is read if
+    // `load_spk_cache` is set.
+    pub(crate) fn read_combined_in(
+        &self,
+        read_tx: &ReadTransaction,
+        changeset: &mut CombinedChangeSet,
+        load_spk_cache: bool,
+    ) -> Result<(), StoreError> {
+        self.read_blocks(read_tx, &mut changeset.local_chain.blocks)?;
+
+        let tx_graph = &mut changeset.tx_graph;
+        self.read_txs(read_tx, &mut tx_graph.txs)?;
+        self.read_txouts(read_tx, &mut tx_graph.txouts)?;
+        self.read_anchors(read_tx, &mut tx_graph.anchors)?;
+        self.read_last_seen(read_tx, &mut tx_graph.last_seen)?;
+        self.read_last_evicted(read_tx, &mut tx_graph.last_evicted)?;
+        self.read_first_seen(read_tx, &mut tx_graph.first_seen)?;
+
+        self.read_last_revealed(read_tx, &mut changeset.indexer.last_revealed)?;
+        if load_spk_cache {
+            self.read_spks(read_tx, &mut changeset.indexer.spk_cache)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+//! This module contains the helpers used to compress the transactions and, optionally, the spk
+//! cache scripts of a wallet with zstd when the `compression` feature is enabled, see
+//! [`Store::with_compression_level`].
+//!
+//! A compressed value is [`COMPRESSED_TAG`] followed by a zstd frame, and is only stored if it is
+//! smaller than the raw value, so a table can hold both. Compressed values are recognized whatever
+//! the feature, so that they are reported by [`StoreError::CompressionDisabled`] rather than
+//! decoded as garbage when it is disabled.
+//!
+//! [`Store::with_compression_level`]: crate::Store::with_compression_level
+use crate::error::StoreError;
+
+/// Tag prefixed to the compressed values. It is never the first byte of a ciborium encoding (0xfe
+/// is a reserved CBOR initial byte), and the zstd magic number following it tells compressed
+/// values apart from consensus encoded transactions and scripts starting with it.
+pub(crate) const COMPRESSED_TAG: u8 = 0xfe;
+
+/// Magic number starting every zstd frame, in little-endian.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+// This function compresses `bytes` with the given zstd level, returning them unchanged if the
+// compressed value is not smaller.
+#[cfg(feature = "compression")]
+pub(crate) fn compress(level: i32, bytes: Vec<u8>) -> Result<Vec<u8>, StoreError> {
+    let frame = zstd::bulk::compress(&bytes, level)?;
+    if 1 + frame.len() >= bytes.len() {
+        return Ok(bytes);
+    }
+    let mut compressed = Vec::with_capacity(1 + frame.len());
+    compressed.push(COMPRESSED_TAG);
+    compressed.extend_from_slice(&frame);
+    Ok(compressed)
+}
+
+// This function reverses compress, returning raw values unchanged.
+pub(crate) fn decompress(bytes: Vec<u8>) -> Result<Vec<u8>, StoreError> {
+    let Some(frame) = bytes.strip_prefix(&[COMPRESSED_TAG]) else {
+        return Ok(bytes);
+    };
+    if !frame.starts_with(&ZSTD_MAGIC) {
+        return Ok(bytes);
+    }
+    #[cfg(feature = "compression")]
+    {
+        Ok(zstd::stream::decode_all(frame)?)
+    }
+    #[cfg(not(feature = "compression"))]
+    Err(StoreError::CompressionDisabled)
+}
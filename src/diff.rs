@@ -0,0 +1,258 @@
+//! This module contains [`Store::would_change`] which compares a wallet changeset with the
+//! persisted wallet.
+use crate::anchor_trait::AnchorWithMetaData;
+use crate::error::StoreError;
+use crate::{
+    NETWORK, NETWORK_BYTE, SPKS_VALUE, Store, TXOUTS_VALUE, anchor_from_bytes, block_id_from_bytes,
+    network_to_byte, open_multimap_table_if_exists, open_table_if_exists, parse_stored_descriptor,
+};
+use bdk_chain::bitcoin::{Txid, hashes::Hash};
+use bdk_chain::miniscript::descriptor::{Descriptor, DescriptorPublicKey};
+use bdk_chain::{ConfirmationBlockTime, keychain_txout, local_chain, tx_graph};
+use bdk_wallet::ChangeSet;
+use redb::{ReadTransaction, TableDefinition};
+use std::collections::BTreeMap;
+
+impl Store {
+    /// This function returns whether persisting `changeset` with `persist_wallet` would modify
+    /// the persisted wallet, i.e. `false` if every value of the changeset is already persisted,
+    /// so that callers can skip a no-op commit and its fsync.
+    ///
+    /// Everything is read in a single read transaction, and only the rows of the changeset are
+    /// looked up. Tables which do not exist are treated as empty. Values are compared decoded, so
+    /// a transaction persisted with another codec or encrypted is unchanged if it is the same
+    /// transaction. Blocks to remove (`None` hashes) are changes only if they are persisted, and
+    /// the spk cache is ignored if [`with_spk_cache_persisting`] is disabled.
+    ///
+    /// Rows written by every persist whatever the changeset (the changelog entry if enabled, the
+    /// time of the last persist) are not considered. A changeset which would be rejected, e.g.
+    /// with a descriptor different from the persisted one, is reported as a change.
+    ///
+    /// [`with_spk_cache_persisting`]: Self::with_spk_cache_persisting
+    pub fn would_change(&self, changeset: &ChangeSet) -> Result<bool, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let mut keychains = BTreeMap::new();
+        keychains.extend(changeset.descriptor.clone().map(|desc| (0, desc)));
+        keychains.extend(changeset.change_descriptor.clone().map(|desc| (1, desc)));
+        Ok(self.network_would_change(&read_tx, changeset.network)?
+            || self.keychains_would_change(&read_tx, &keychains)?
+            || self.local_chain_would_change(&read_tx, &changeset.local_chain)?
+            || self.tx_graph_would_change(&read_tx, &changeset.tx_graph)?
+            || self.indexer_would_change(&read_tx, &changeset.indexer)?)
+    }
+
+    // This function returns whether persisting `network` would modify the table the network is
+    // written to, the NETWORK_BYTE one if the compact format is enabled.
+    fn network_would_change(
+        &self,
+        read_tx: &ReadTransaction,
+        network: Option<bdk_chain::bitcoin::Network>,
+    ) -> Result<bool, StoreError> {
+        let Some(network) = network else {
+            return Ok(false);
+        };
+        if self.compact_network {
+            let Some(table) = open_table_if_exists(read_tx, NETWORK_BYTE)? else {
+                return Ok(true);
+            };
+            let stored = table.get(&*self.wallet_name)?.map(|byte| byte.value());
+            return Ok(stored != Some(network_to_byte(network)));
+        }
+        let Some(table) = open_table_if_exists(read_tx, NETWORK)? else {
+            return Ok(true);
+        };
+        let stored = table
+            .get(&*self.wallet_name)?
+            .map(|network| network.value());
+        Ok(stored != Some(network.to_string()))
+    }
+
+    // This function returns whether persisting the descriptors mapped by label would modify the
+    // keychains table.
+    fn keychains_would_change(
+        &self,
+        read_tx: &ReadTransaction,
+        keychains: &BTreeMap<u64, Descriptor<DescriptorPublicKey>>,
+    ) -> Result<bool, StoreError> {
+        if keychains.is_empty() {
+            return Ok(false);
+        }
+        let Some(table) = open_table_if_exists(read_tx, self.keychains_table_defn())? else {
+            return Ok(true);
+        };
+        for (label, desc) in keychains {
+            let Some(stored) = table.get(label)? else {
+                return Ok(true);
+            };
+            let stored = self.decode_descriptor(*label, stored.value())?;
+            if parse_stored_descriptor(*label, &stored)? != *desc {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // This function returns whether persisting the local chain changeset would modify the blocks
+    // table.
+    fn local_chain_would_change(
+        &self,
+        read_tx: &ReadTransaction,
+        changeset: &local_chain::ChangeSet,
+    ) -> Result<bool, StoreError> {
+        if changeset.blocks.is_empty() {
+            return Ok(false);
+        }
+        let Some(table) = open_table_if_exists(read_tx, self.blocks_table_defn())? else {
+            return Ok(changeset.blocks.values().any(Option::is_some));
+        };
+        for (height, hash) in &changeset.blocks {
+            let stored = table.get(height)?.map(|hash| hash.value());
+            if stored != hash.map(|hash| hash.to_byte_array()) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // This function returns whether persisting the tx graph changeset would modify the tables of
+    // the tx graph.
+    fn tx_graph_would_change(
+        &self,
+        read_tx: &ReadTransaction,
+        changeset: &tx_graph::ChangeSet<ConfirmationBlockTime>,
+    ) -> Result<bool, StoreError> {
+        if !changeset.txs.is_empty() {
+            let Some(table) = open_table_if_exists(read_tx, self.txs_table_defn())? else {
+                return Ok(true);
+            };
+            let codec = self.tx_codec_in(read_tx)?;
+            for tx in &changeset.txs {
+                let txid = tx.compute_txid();
+                let Some(stored) = table.get(txid.to_byte_array())? else {
+                    return Ok(true);
+                };
+                if self.decode_tx(codec, txid, stored.value())? != **tx {
+                    return Ok(true);
+                }
+            }
+        }
+
+        if !changeset.txouts.is_empty() {
+            let Some(table) = open_table_if_exists(read_tx, self.txouts_table_defn())? else {
+                return Ok(true);
+            };
+            for (outpoint, txout) in &changeset.txouts {
+                let key = (outpoint.txid.to_byte_array(), outpoint.vout);
+                let Some(stored) = table.get(key)? else {
+                    return Ok(true);
+                };
+                let (value, script) = stored.value();
+                if value != txout.value.to_sat()
+                    || self.decode_script(TXOUTS_VALUE, key, script)? != txout.script_pubkey
+                {
+                    return Ok(true);
+                }
+            }
+        }
+
+        if !changeset.anchors.is_empty() {
+            let Some(table) = open_multimap_table_if_exists(read_tx, self.anchors_table_defn())?
+            else {
+                return Ok(true);
+            };
+            for (anchor, txid) in &changeset.anchors {
+                let mut found = false;
+                for value in table.get(txid.to_byte_array())? {
+                    let value = value?;
+                    let (block_id_bytes, tag, metadata) = value.value();
+                    if tag == ConfirmationBlockTime::TAG
+                        && anchor_from_bytes::<ConfirmationBlockTime>(
+                            block_id_from_bytes(&block_id_bytes)?,
+                            metadata,
+                        )? == *anchor
+                    {
+                        found = true;
+                        break;
+                    }
+                }
+                if !found {
+                    return Ok(true);
+                }
+            }
+        }
+
+        Ok(
+            timestamps_would_change(read_tx, self.last_seen_defn(), &changeset.last_seen)?
+                || timestamps_would_change(
+                    read_tx,
+                    self.first_seen_table_defn(),
+                    &changeset.first_seen,
+                )?
+                || timestamps_would_change(
+                    read_tx,
+                    self.last_evicted_table_defn(),
+                    &changeset.last_evicted,
+                )?,
+        )
+    }
+
+    // This function returns whether persisting the indexer changeset would modify the
+    // last_revealed and spk tables.
+    fn indexer_would_change(
+        &self,
+        read_tx: &ReadTransaction,
+        changeset: &keychain_txout::ChangeSet,
+    ) -> Result<bool, StoreError> {
+        if !changeset.last_revealed.is_empty() {
+            let Some(table) = open_table_if_exists(read_tx, self.last_revealed_table_defn())?
+            else {
+                return Ok(true);
+            };
+            for (desc, index) in &changeset.last_revealed {
+                let stored = table.get(desc.to_byte_array())?.map(|index| index.value());
+                if stored != Some(*index) {
+                    return Ok(true);
+                }
+            }
+        }
+
+        if self.persist_spk_cache && changeset.spk_cache.values().any(|spks| !spks.is_empty()) {
+            let Some(table) = open_table_if_exists(read_tx, self.spk_table_defn())? else {
+                return Ok(true);
+            };
+            for (desc, spks) in &changeset.spk_cache {
+                for (&index, spk) in spks {
+                    let key = (desc.to_byte_array(), index);
+                    let Some(stored) = table.get(key)? else {
+                        return Ok(true);
+                    };
+                    if self.decode_script(SPKS_VALUE, key, stored.value())? != *spk {
+                        return Ok(true);
+                    }
+                }
+            }
+        }
+        Ok(false)
+    }
+}
+
+// This function returns whether persisting the timestamps mapped by txid would modify the given
+// timestamp table.
+fn timestamps_would_change(
+    read_tx: &ReadTransaction,
+    table_defn: TableDefinition<[u8; 32], u64>,
+    timestamps: &BTreeMap<Txid, u64>,
+) -> Result<bool, StoreError> {
+    if timestamps.is_empty() {
+        return Ok(false);
+    }
+    let Some(table) = open_table_if_exists(read_tx, table_defn)? else {
+        return Ok(true);
+    };
+    for (txid, time) in timestamps {
+        if table.get(txid.to_byte_array())?.map(|time| time.value()) != Some(*time) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
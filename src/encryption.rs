@@ -0,0 +1,147 @@
+#![warn(missing_docs)]
+//! This module contains [`EncryptionKey`] and the helpers used to encrypt wallet contents at rest
+//! when the `encryption` feature is enabled.
+//!
+//! Values are sealed using AES-256-GCM. A random nonce is generated for every write and
+//! stored in front of the ciphertext. Descriptors are hex encoded so that the keychain table keeps
+//! the same value type irrespective of whether encryption is used, while the byte values of the
+//! txs, txouts, spk and changelog tables are stored as is.
+use crate::error::StoreError;
+use bdk_chain::bitcoin::hex::{DisplayHex, FromHex};
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fmt;
+
+/// Length in bytes of an [`EncryptionKey`].
+pub const KEY_LEN: usize = 32;
+
+/// A symmetric key used to encrypt descriptors, transactions, scripts and changelog entries before
+/// persisting them, see [`Store::with_encryption_key`].
+///
+/// [`Store::with_encryption_key`]: crate::Store::with_encryption_key
+///
+/// The [`Debug`] implementation does not print the key bytes.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; KEY_LEN]);
+
+impl EncryptionKey {
+    /// This function creates an [`EncryptionKey`] from raw key bytes.
+    pub fn new(bytes: [u8; KEY_LEN]) -> Self {
+        EncryptionKey(bytes)
+    }
+
+    fn less_safe_key(&self) -> LessSafeKey {
+        LessSafeKey::new(
+            UnboundKey::new(&AES_256_GCM, &self.0).expect("key has the correct length"),
+        )
+    }
+}
+
+impl From<[u8; KEY_LEN]> for EncryptionKey {
+    fn from(bytes: [u8; KEY_LEN]) -> Self {
+        EncryptionKey::new(bytes)
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+// This function encrypts the plaintext and returns hex(nonce || ciphertext || tag). The label is
+// used as associated data so that a ciphertext cannot be moved to another keychain.
+pub(crate) fn encrypt(
+    key: &EncryptionKey,
+    label: u64,
+    plaintext: &str,
+) -> Result<String, StoreError> {
+    let blob = seal_with_aad(key, &label.to_be_bytes(), plaintext.as_bytes().to_vec())?;
+    Ok(blob.to_lower_hex_string())
+}
+
+// This function reverses `encrypt`. Any failure (malformed blob, wrong key or tampered data) is
+// reported as `StoreError::Decryption`.
+pub(crate) fn decrypt(
+    key: &EncryptionKey,
+    label: u64,
+    ciphertext: &str,
+) -> Result<String, StoreError> {
+    let blob = Vec::<u8>::from_hex(ciphertext).map_err(|_| StoreError::Decryption)?;
+    let plaintext = open_with_aad(key, &label.to_be_bytes(), &blob)?;
+    String::from_utf8(plaintext).map_err(|_| StoreError::Decryption)
+}
+
+// This function encrypts a value stored under `row_key` in the table identified by `table` and
+// returns nonce || ciphertext || tag. The table and row key are used as associated data so that a
+// ciphertext cannot be moved to another row or table.
+pub(crate) fn seal(
+    key: &EncryptionKey,
+    table: u8,
+    row_key: &[u8],
+    plaintext: Vec<u8>,
+) -> Result<Vec<u8>, StoreError> {
+    seal_with_aad(key, &value_aad(table, row_key), plaintext)
+}
+
+// This function reverses `seal`. Any failure (malformed blob, wrong key or tampered data) is
+// reported as `StoreError::Decryption`.
+pub(crate) fn open(
+    key: &EncryptionKey,
+    table: u8,
+    row_key: &[u8],
+    blob: &[u8],
+) -> Result<Vec<u8>, StoreError> {
+    open_with_aad(key, &value_aad(table, row_key), blob)
+}
+
+// This function returns the associated data of a value sealed with `seal`.
+fn value_aad(table: u8, row_key: &[u8]) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(1 + row_key.len());
+    aad.push(table);
+    aad.extend_from_slice(row_key);
+    aad
+}
+
+// This function encrypts the plaintext with a random nonce and returns
+// nonce || ciphertext || tag.
+fn seal_with_aad(
+    key: &EncryptionKey,
+    aad: &[u8],
+    mut in_out: Vec<u8>,
+) -> Result<Vec<u8>, StoreError> {
+    let mut nonce_bytes = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce_bytes)
+        .map_err(|_| StoreError::Encryption)?;
+
+    key.less_safe_key()
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce_bytes),
+            Aad::from(aad),
+            &mut in_out,
+        )
+        .map_err(|_| StoreError::Encryption)?;
+
+    let mut blob = Vec::with_capacity(NONCE_LEN + in_out.len());
+    blob.extend_from_slice(&nonce_bytes);
+    blob.extend_from_slice(&in_out);
+    Ok(blob)
+}
+
+// This function reverses `seal_with_aad`.
+fn open_with_aad(key: &EncryptionKey, aad: &[u8], blob: &[u8]) -> Result<Vec<u8>, StoreError> {
+    if blob.len() < NONCE_LEN {
+        return Err(StoreError::Decryption);
+    }
+    let (nonce_bytes, sealed) = blob.split_at(NONCE_LEN);
+    let nonce =
+        Nonce::try_assume_unique_for_key(nonce_bytes).map_err(|_| StoreError::Decryption)?;
+
+    let mut in_out = sealed.to_vec();
+    let plaintext = key
+        .less_safe_key()
+        .open_in_place(nonce, Aad::from(aad), &mut in_out)
+        .map_err(|_| StoreError::Decryption)?;
+    Ok(plaintext.to_vec())
+}
@@ -1,5 +1,6 @@
 #![warn(missing_docs)]
 //! This module contains the crate's error type.
+use bdk_chain::DescriptorId;
 use bdk_chain::bitcoin;
 use std::io::Error as IoError;
 
@@ -18,18 +19,56 @@ pub enum StoreError {
     /// Error while inserting, removing, retrieving or iterating over db table entries
     #[error("Storage error: {0}")]
     RedbStorage(#[from] redb::StorageError),
+    /// Error while creating or restoring db savepoints
+    #[error("Savepoint error: {0}")]
+    RedbSavepoint(#[from] redb::SavepointError),
+    /// Error while compacting the redb database
+    #[error("Compaction error: {0}")]
+    RedbCompaction(#[from] redb::CompactionError),
     /// Error while creating redb database
     // for convenience of downstream users
     #[error("Database error: {0}")]
-    RedbDatabase(#[from] redb::DatabaseError),
+    RedbDatabase(redb::DatabaseError),
+    /// Error thrown when opening a database file written in an older on-disk format of redb (the
+    /// given file format version), which this version of redb cannot read.
+    ///
+    /// The file must be upgraded manually: open it with the major version of redb which wrote it
+    /// and copy its tables to a new database file created with this one, or call redb's
+    /// `Database::upgrade` if it supports that format version.
+    #[error(
+        "Database file format version {0} requires a manual upgrade: it was written by an older \
+         major version of redb"
+    )]
+    RedbFormatUpgradeRequired(u8),
     /// Error while serializing transaction using [`ciborium`]
+    #[cfg(feature = "serde")]
     #[error("ciborium serialization error: {0}")]
     Ser(#[from] ciborium::ser::Error<IoError>),
     /// Error while deserializing transaction using [`ciborium`]
     ///
     /// [`ciborium`]: <https://docs.rs/ciborium/0.2.2/ciborium/index.html>
+    #[cfg(feature = "serde")]
     #[error("ciborium deserialization error: {0}")]
     Deser(#[from] ciborium::de::Error<IoError>),
+    /// Error thrown when reading or persisting a [`ciborium`] encoded transaction (see
+    /// [`TxCodec::Cbor`]) while the `serde` feature is disabled.
+    ///
+    /// [`ciborium`]: <https://docs.rs/ciborium/0.2.2/ciborium/index.html>
+    /// [`TxCodec::Cbor`]: crate::tx_codec::TxCodec::Cbor
+    #[error("Transaction is ciborium encoded but the serde feature is disabled")]
+    CborDisabled,
+    /// Error thrown when a persisted transaction is neither [`ciborium`] nor consensus encoded.
+    ///
+    /// [`ciborium`]: <https://docs.rs/ciborium/0.2.2/ciborium/index.html>
+    #[error("Transaction encoding not recognized")]
+    UnknownTxEncoding,
+    /// Error thrown when the checksum of a persisted transaction does not match its bytes, i.e. the
+    /// row of the txs table was corrupted.
+    #[error("Checksum mismatch for tx {txid}")]
+    ChecksumMismatch {
+        /// The txid keying the corrupted row.
+        txid: bitcoin::Txid,
+    },
     /// Error while deserializing [`BlockHash`] from slice
     ///
     /// [`BlockHash`]: <https://docs.rs/bitcoin/latest/bitcoin/struct.BlockHash.html>
@@ -39,4 +78,201 @@ pub enum StoreError {
     /// anchors, last_seen, last_evicted or first_seen.
     #[error("Tx corresponding to txid is missing")]
     TxMissing(bitcoin::Txid),
+    /// Error thrown when an operation needs exclusive access to the [`Database`] but other handles
+    /// to it are still alive.
+    ///
+    /// [`Database`]: <https://docs.rs/redb/2.5.0/redb/struct.Database.html>
+    #[error("Database is in use, {0} handles to it are alive")]
+    DatabaseInUse(usize),
+    /// Error thrown when loading a wallet whose tables were never created, i.e. from a database
+    /// in which the wallet was not initialized.
+    #[error("Wallet {0} is not initialized")]
+    WalletNotInitialized(String),
+    /// Error thrown when creating a store for a wallet name or a prefix containing `:`, which
+    /// separates the prefix from the wallet name (see [`Store::new_with_prefix`]).
+    ///
+    /// [`Store::new_with_prefix`]: crate::Store::new_with_prefix
+    #[error("Invalid wallet name or prefix {0}: it must not contain ':'")]
+    InvalidWalletName(String),
+    /// Error while reading or writing a CBOR backup.
+    #[error("I/O error: {0}")]
+    Io(#[from] IoError),
+    /// Error thrown when importing a CBOR backup which does not start with the magic bytes, i.e.
+    /// which was not written by [`Store::export_changeset_cbor`].
+    ///
+    /// [`Store::export_changeset_cbor`]: crate::Store::export_changeset_cbor
+    #[error("Not a bdk_redb backup")]
+    InvalidBackupMagic,
+    /// Error thrown when importing a CBOR backup whose version is not supported, for instance one
+    /// written by a newer version of this crate.
+    #[error("Unsupported backup version: {0}")]
+    UnsupportedBackupVersion(u8),
+    /// Error thrown when persisting a network different from the one of the wallet, or when
+    /// merging or importing the data of a wallet on a network into one on another network.
+    #[error("Network mismatch: store is on {ours}, not {theirs}")]
+    NetworkMismatch {
+        /// The network of the store being persisted or merged into.
+        ours: bitcoin::Network,
+        /// The network being persisted, or the one of the data being merged or imported.
+        theirs: bitcoin::Network,
+    },
+    /// Error thrown when merging the data of a wallet into a store holding a different descriptor
+    /// under the same label, see [`Store::merge_from`].
+    ///
+    /// [`Store::merge_from`]: crate::Store::merge_from
+    #[error("Descriptor mismatch for keychain {label}: the stores hold different wallets")]
+    DescriptorMismatch {
+        /// The label whose descriptors differ (0 for the external one, 1 for the change one).
+        label: u64,
+    },
+    /// Error thrown when the network of a wallet is stored as a byte which does not correspond to
+    /// any known [`Network`], for instance one written by a newer version of this crate.
+    ///
+    /// [`Network`]: <https://docs.rs/bitcoin/latest/bitcoin/enum.Network.html>
+    #[error("Unknown network byte: {0}")]
+    UnknownNetworkByte(u8),
+    /// Error thrown when the network of a wallet is stored as a string which does not correspond to
+    /// any known [`Network`].
+    ///
+    /// [`Network`]: <https://docs.rs/bitcoin/latest/bitcoin/enum.Network.html>
+    #[error("Unknown network: {0}")]
+    UnknownNetwork(String),
+    /// Error thrown when setting the last revealed index of a descriptor below the persisted one.
+    #[error("Last revealed index of {descriptor_id} is {current}, cannot lower it to {index}")]
+    NonMonotonicRevealedIndex {
+        /// The descriptor whose last revealed index was being set.
+        descriptor_id: DescriptorId,
+        /// The persisted last revealed index.
+        current: u32,
+        /// The rejected index.
+        index: u32,
+    },
+    /// Error thrown when persisting a descriptor under a label which already has a different one,
+    /// or when [`Store::replace_descriptor`] is called without confirmation or for a label without
+    /// a descriptor.
+    ///
+    /// [`Store::replace_descriptor`]: crate::Store::replace_descriptor
+    #[error("Descriptor of keychain {0} cannot be modified")]
+    DescriptorImmutable(u64),
+    /// Error thrown when persisting a wallet changeset with a change descriptor but without an
+    /// external descriptor, for a wallet which has none persisted either.
+    #[error("Change descriptor persisted without an external descriptor")]
+    MissingExternalDescriptor,
+    /// Error thrown when writing or deleting a metadata entry whose key starts with
+    /// [`RESERVED_META_PREFIX`].
+    ///
+    /// [`RESERVED_META_PREFIX`]: crate::meta::RESERVED_META_PREFIX
+    #[error("Metadata key {0} is reserved")]
+    ReservedMetaKey(String),
+    /// Error thrown when reading a metadata entry reserved for this crate whose value is not in
+    /// the expected format.
+    #[error("Invalid value for metadata key {0}")]
+    InvalidMeta(String),
+    /// Error thrown when setting the birthday of a wallet which already has a different one.
+    #[error("Birthday of the wallet cannot be modified")]
+    BirthdayImmutable,
+    /// Error thrown when the checksum of a persisted descriptor does not match its string, i.e. the
+    /// row of the keychains table was corrupted.
+    #[error("Checksum mismatch for the descriptor of keychain {label}")]
+    DescriptorChecksumMismatch {
+        /// The label keying the corrupted row.
+        label: u64,
+    },
+    /// Error thrown when a persisted descriptor cannot be parsed, i.e. the row of the keychains
+    /// table was written by another program or with another encryption key.
+    #[error("Invalid descriptor for keychain {label}")]
+    InvalidDescriptor {
+        /// The label keying the invalid row.
+        label: u64,
+    },
+    /// Error thrown in strict txouts mode when persisting a txout different from the one stored for
+    /// the same outpoint, see [`Store::with_strict_txouts`].
+    ///
+    /// [`Store::with_strict_txouts`]: crate::Store::with_strict_txouts
+    #[error("Conflicting txout for outpoint {outpoint}")]
+    ConflictingTxOut {
+        /// The outpoint of the conflicting txouts.
+        outpoint: bitcoin::OutPoint,
+    },
+    /// Error thrown when reading a value compressed with zstd (see
+    /// [`Store::with_compression_level`]) while the `compression` feature is disabled.
+    ///
+    /// [`Store::with_compression_level`]: crate::Store::with_compression_level
+    #[error("Value is compressed but the compression feature is disabled")]
+    CompressionDisabled,
+    /// Error thrown when persisting transactions with a store whose codec is not the one recorded
+    /// for the wallet, see [`Store::with_tx_codec`].
+    ///
+    /// [`Store::with_tx_codec`]: crate::Store::with_tx_codec
+    #[error("Tx codec mismatch: wallet uses {stored:?}, store uses {requested:?}")]
+    TxCodecMismatch {
+        /// The codec of the transactions of the wallet.
+        stored: crate::tx_codec::TxCodec,
+        /// The codec of the store.
+        requested: crate::tx_codec::TxCodec,
+    },
+    /// Error thrown when a function generic over the anchor type is called with a type other
+    /// than the one recorded for the wallet, see [`Store::with_anchor_type_check`].
+    ///
+    /// [`Store::with_anchor_type_check`]: crate::Store::with_anchor_type_check
+    #[error("Anchor type mismatch: wallet uses {stored}, {requested} was requested")]
+    AnchorTypeMismatch {
+        /// The type name of the anchors of the wallet.
+        stored: String,
+        /// The type name of the anchors requested.
+        requested: String,
+    },
+    /// Error thrown when a block id or the metadata of an anchor read from the anchors table does
+    /// not have the length of its encoding, instead of panicking while decoding it.
+    #[error("Invalid anchor encoding: expected {expected} bytes, found {found}")]
+    AnchorEncoding {
+        /// The length of the encoding.
+        expected: usize,
+        /// The length of the bytes being decoded.
+        found: usize,
+    },
+    /// Error thrown when persisting a range of blocks whose end height does not fit in a `u32`.
+    #[error("Block range of {len} blocks starting at height {start_height} overflows")]
+    BlockRangeOverflow {
+        /// The height of the first block of the range.
+        start_height: u32,
+        /// The number of blocks of the range.
+        len: usize,
+    },
+    /// Error while encrypting a descriptor before persisting it.
+    #[cfg(feature = "encryption")]
+    #[error("Encryption error")]
+    Encryption,
+    /// Error while decrypting a persisted descriptor. This happens when the key is wrong or the
+    /// stored bytes were tampered with.
+    #[cfg(feature = "encryption")]
+    #[error("Decryption error: wrong key or corrupted data")]
+    Decryption,
+    /// Error while serializing to or deserializing from JSON using [`serde_json`]
+    ///
+    /// [`serde_json`]: <https://docs.rs/serde_json/1/serde_json/index.html>
+    #[cfg(feature = "json")]
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    /// Error thrown when importing a JSON export whose format version is not supported, for
+    /// instance one written by a newer version of this crate.
+    #[cfg(feature = "json")]
+    #[error("Unsupported export format version: {0}")]
+    UnsupportedExportVersion(u64),
+    /// Error thrown when importing a JSON export holding an invalid value, for instance a raw
+    /// transaction which does not decode.
+    #[cfg(feature = "json")]
+    #[error("Invalid export: {0}")]
+    InvalidExport(String),
+}
+
+impl From<redb::DatabaseError> for StoreError {
+    fn from(err: redb::DatabaseError) -> Self {
+        match err {
+            redb::DatabaseError::UpgradeRequired(version) => {
+                StoreError::RedbFormatUpgradeRequired(version)
+            }
+            err => StoreError::RedbDatabase(err),
+        }
+    }
 }
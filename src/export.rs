@@ -0,0 +1,469 @@
+#![warn(missing_docs)]
+//! This module contains [`Store::export_json`] which writes the persisted state of a wallet as
+//! human-readable JSON, for instance to be attached to a support request, and
+//! [`Store::import_json`] which persists such an export, returning [`ImportStats`].
+//!
+//! # Format
+//!
+//! The export is a single JSON object with the following fields, in this order:
+//!
+//! - `format_version`: the version of this format, [`EXPORT_FORMAT_VERSION`].
+//! - `wallet_name`: the name of the wallet.
+//! - `network`: the network in lowercase (`"bitcoin"`, `"testnet"`, `"testnet4"`, `"signet"` or
+//!   `"regtest"`), or `null` if none was persisted.
+//! - `descriptors`: `{"keychain", "descriptor"}` objects, the label of the keychain and its
+//!   descriptor with checksum.
+//! - `blocks`: `{"height", "hash"}` objects, the blocks of the local chain.
+//! - `transactions`: `{"txid", "raw"}` objects, where `raw` is the consensus encoding of the
+//!   transaction.
+//! - `txouts`: `{"txid", "vout", "value", "script_pubkey"}` objects, the floating txouts.
+//! - `anchors`: `{"txid", "anchor"}` objects, where `anchor` is the anchor of the exported type
+//!   serialized with serde.
+//! - `last_seen`, `first_seen` and `last_evicted`: `{"txid", "timestamp"}` objects.
+//! - `last_revealed`: `{"descriptor_id", "index"}` objects.
+//! - `spks`: `{"descriptor_id", "index", "script_pubkey"}` objects, the spk cache.
+//!
+//! Hashes, txids, descriptor ids, scripts and raw transactions are hex encoded and amounts are in
+//! satoshis. Arrays are ordered by the keys of the corresponding tables.
+//!
+//! [`Store::export_json`]: crate::Store::export_json
+//! [`Store::import_json`]: crate::Store::import_json
+use crate::anchor_trait::AnchorWithMetaData;
+use crate::error::StoreError;
+use crate::summary::PersistSummary;
+use crate::{SPKS_VALUE, Store, TXOUTS_VALUE, anchor_from_bytes, block_id_from_bytes};
+use bdk_chain::bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bdk_chain::bitcoin::{
+    Amount, BlockHash, Network, OutPoint, ScriptBuf, Transaction, TxOut, Txid, hashes::Hash,
+};
+use bdk_chain::miniscript::descriptor::{Descriptor, DescriptorPublicKey};
+use bdk_chain::{DescriptorId, keychain_txout, local_chain, tx_graph};
+use redb::{Durability, ReadableMultimapTable, ReadableTable};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Version of the JSON format written by [`Store::export_json`]. It is incremented whenever a
+/// field is removed or changes meaning.
+///
+/// [`Store::export_json`]: crate::Store::export_json
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct DescriptorRow {
+    keychain: u64,
+    descriptor: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BlockRow {
+    height: u32,
+    hash: BlockHash,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TransactionRow {
+    txid: Txid,
+    raw: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TxOutRow {
+    txid: Txid,
+    vout: u32,
+    value: u64,
+    script_pubkey: ScriptBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AnchorRow<A> {
+    txid: Txid,
+    anchor: A,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TimestampRow {
+    txid: Txid,
+    timestamp: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LastRevealedRow {
+    descriptor_id: DescriptorId,
+    index: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SpkRow {
+    descriptor_id: DescriptorId,
+    index: u32,
+    script_pubkey: ScriptBuf,
+}
+
+// The whole export, as read back by `Store::import_json`.
+#[derive(Deserialize)]
+struct WalletExport<A> {
+    wallet_name: String,
+    network: Option<Network>,
+    descriptors: Vec<DescriptorRow>,
+    blocks: Vec<BlockRow>,
+    transactions: Vec<TransactionRow>,
+    txouts: Vec<TxOutRow>,
+    anchors: Vec<AnchorRow<A>>,
+    last_seen: Vec<TimestampRow>,
+    first_seen: Vec<TimestampRow>,
+    last_evicted: Vec<TimestampRow>,
+    last_revealed: Vec<LastRevealedRow>,
+    spks: Vec<SpkRow>,
+}
+
+/// Statistics of an import made with [`Store::import_json`].
+///
+/// [`Store::import_json`]: crate::Store::import_json
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ImportStats {
+    /// Name of the exported wallet, which can differ from the name of the wallet it was imported
+    /// into.
+    pub wallet_name: String,
+    /// Rows written to each table. Rows which were already persisted are counted as inserted.
+    pub summary: PersistSummary,
+}
+
+impl Store {
+    /// This function writes the persisted state of the wallet to `writer` as JSON, in the format
+    /// documented in the [`export`] module. Anchors of type `A` are exported, anchors of other
+    /// types are skipped.
+    ///
+    /// The tables are read from a single read transaction and each row is written as soon as it
+    /// is read, so the export is a consistent snapshot and the wallet is never loaded in memory as
+    /// a whole. `writer` is not buffered by this function, wrapping it in a [`BufWriter`] is
+    /// recommended. The tables of the wallet must have been created (see [`create_tables`]).
+    ///
+    /// The wallet is exported decrypted if an encryption key is set. Descriptors only contain
+    /// public keys, but reveal the whole history of the wallet: the export should only be shared
+    /// with trusted parties.
+    ///
+    /// [`export`]: crate::export
+    /// [`BufWriter`]: std::io::BufWriter
+    /// [`create_tables`]: Self::create_tables
+    pub fn export_json<A: AnchorWithMetaData + Serialize>(
+        &self,
+        writer: impl Write,
+    ) -> Result<(), StoreError> {
+        self.check_anchor_type::<A>()?;
+        let read_tx = self.db.begin_read()?;
+        let mut json = JsonWriter::new(writer)?;
+        json.field("format_version", &EXPORT_FORMAT_VERSION)?;
+        json.field("wallet_name", &self.wallet_name)?;
+        let mut network: Option<Network> = None;
+        self.read_network_in(&read_tx, &mut network)?;
+        json.field("network", &network)?;
+
+        json.begin_array("descriptors")?;
+        for entry in read_tx.open_table(self.keychains_table_defn())?.iter()? {
+            let (label, desc_str) = entry?;
+            json.row(&DescriptorRow {
+                keychain: label.value(),
+                descriptor: self.decode_descriptor(label.value(), desc_str.value())?,
+            })?;
+        }
+        json.end_array()?;
+
+        json.begin_array("blocks")?;
+        for entry in read_tx.open_table(self.blocks_table_defn())?.iter()? {
+            let (height, hash) = entry?;
+            json.row(&BlockRow {
+                height: height.value(),
+                hash: BlockHash::from_byte_array(hash.value()),
+            })?;
+        }
+        json.end_array()?;
+
+        json.begin_array("transactions")?;
+        let codec = self.tx_codec_in(&read_tx)?;
+        for entry in read_tx.open_table(self.txs_table_defn())?.iter()? {
+            let (txid, tx_vec) = entry?;
+            let txid = Txid::from_byte_array(txid.value());
+            json.row(&TransactionRow {
+                txid,
+                raw: serialize_hex(&self.decode_tx(codec, txid, tx_vec.value())?),
+            })?;
+        }
+        json.end_array()?;
+
+        json.begin_array("txouts")?;
+        for entry in read_tx.open_table(self.txouts_table_defn())?.iter()? {
+            let (outpoint, txout) = entry?;
+            let (txid, vout) = outpoint.value();
+            let (value, script_pubkey) = txout.value();
+            json.row(&TxOutRow {
+                txid: Txid::from_byte_array(txid),
+                vout,
+                value,
+                script_pubkey: self.decode_script(TXOUTS_VALUE, (txid, vout), script_pubkey)?,
+            })?;
+        }
+        json.end_array()?;
+
+        json.begin_array("anchors")?;
+        for entry in read_tx
+            .open_multimap_table(self.anchors_table_defn())?
+            .iter()?
+        {
+            let (txid, values) = entry?;
+            for value in values {
+                let value = value?;
+                let (block_id_bytes, tag, metadata) = value.value();
+                // skip anchors of other types
+                if tag != A::TAG {
+                    continue;
+                }
+                let block_id = block_id_from_bytes(&block_id_bytes)?;
+                json.row(&AnchorRow {
+                    txid: Txid::from_byte_array(txid.value()),
+                    anchor: anchor_from_bytes::<A>(block_id, metadata)?,
+                })?;
+            }
+        }
+        json.end_array()?;
+
+        for (name, table_defn) in [
+            ("last_seen", self.last_seen_defn()),
+            ("first_seen", self.first_seen_table_defn()),
+            ("last_evicted", self.last_evicted_table_defn()),
+        ] {
+            json.begin_array(name)?;
+            for entry in read_tx.open_table(table_defn)?.iter()? {
+                let (txid, timestamp) = entry?;
+                json.row(&TimestampRow {
+                    txid: Txid::from_byte_array(txid.value()),
+                    timestamp: timestamp.value(),
+                })?;
+            }
+            json.end_array()?;
+        }
+
+        json.begin_array("last_revealed")?;
+        for entry in read_tx
+            .open_table(self.last_revealed_table_defn())?
+            .iter()?
+        {
+            let (descriptor_id, index) = entry?;
+            json.row(&LastRevealedRow {
+                descriptor_id: DescriptorId::from_byte_array(descriptor_id.value()),
+                index: index.value(),
+            })?;
+        }
+        json.end_array()?;
+
+        json.begin_array("spks")?;
+        for entry in read_tx.open_table(self.spk_table_defn())?.iter()? {
+            let (key, spk) = entry?;
+            let (descriptor_id, index) = key.value();
+            json.row(&SpkRow {
+                descriptor_id: DescriptorId::from_byte_array(descriptor_id),
+                index,
+                script_pubkey: self.decode_script(SPKS_VALUE, key.value(), spk.value())?,
+            })?;
+        }
+        json.end_array()?;
+
+        json.finish()
+    }
+
+    /// This function persists a wallet exported with [`export_json`], read from `reader`, and
+    /// returns [`ImportStats`]. Anchors are read as anchors of type `A`, which must be the type
+    /// they were exported with.
+    ///
+    /// The export is imported into this store's wallet whatever the name of the exported one. The
+    /// tables are created if needed (see [`create_tables`]) and every row is then written in a
+    /// single write transaction, so nothing is persisted if the import fails. Rows already
+    /// persisted are overwritten with the same values, so importing an export twice is a no-op.
+    /// The spk cache is not written if [`with_spk_cache_persisting`] disabled it.
+    ///
+    /// [`StoreError::UnsupportedExportVersion`] is returned if the format version of the export is
+    /// not [`EXPORT_FORMAT_VERSION`], [`StoreError::InvalidExport`] if it holds an invalid value
+    /// (for instance a raw transaction not matching its txid) and [`StoreError::NetworkMismatch`]
+    /// if the wallet already has a network different from the exported one.
+    ///
+    /// The export is parsed as a whole before anything is written, so it is held in memory.
+    ///
+    /// [`export_json`]: Self::export_json
+    /// [`create_tables`]: Self::create_tables
+    /// [`with_spk_cache_persisting`]: Self::with_spk_cache_persisting
+    pub fn import_json<A: AnchorWithMetaData + DeserializeOwned>(
+        &self,
+        reader: impl Read,
+    ) -> Result<ImportStats, StoreError> {
+        self.check_anchor_type::<A>()?;
+        let value: serde_json::Value = serde_json::from_reader(reader)?;
+        // the version is checked first as the other fields may differ in other versions
+        let format_version = value
+            .get("format_version")
+            .and_then(serde_json::Value::as_u64)
+            .ok_or_else(|| StoreError::InvalidExport("missing format_version".to_string()))?;
+        if format_version != u64::from(EXPORT_FORMAT_VERSION) {
+            return Err(StoreError::UnsupportedExportVersion(format_version));
+        }
+        let export: WalletExport<A> = serde_json::from_value(value)?;
+
+        let mut descriptors = BTreeMap::new();
+        for row in export.descriptors {
+            let descriptor = Descriptor::<DescriptorPublicKey>::from_str(&row.descriptor)
+                .map_err(|err| StoreError::InvalidExport(err.to_string()))?;
+            descriptors.insert(row.keychain, descriptor);
+        }
+        let blocks = export
+            .blocks
+            .into_iter()
+            .map(|row| (row.height, Some(row.hash)))
+            .collect();
+        let mut tx_graph_changeset = tx_graph::ChangeSet::<A>::default();
+        for row in export.transactions {
+            let tx: Transaction = deserialize_hex(&row.raw)
+                .map_err(|err| StoreError::InvalidExport(err.to_string()))?;
+            if tx.compute_txid() != row.txid {
+                return Err(StoreError::InvalidExport(format!(
+                    "raw transaction does not match txid {}",
+                    row.txid
+                )));
+            }
+            tx_graph_changeset.txs.insert(Arc::new(tx));
+        }
+        tx_graph_changeset.txouts = export
+            .txouts
+            .into_iter()
+            .map(|row| {
+                (
+                    OutPoint::new(row.txid, row.vout),
+                    TxOut {
+                        value: Amount::from_sat(row.value),
+                        script_pubkey: row.script_pubkey,
+                    },
+                )
+            })
+            .collect();
+        tx_graph_changeset.anchors = export
+            .anchors
+            .into_iter()
+            .map(|row| (row.anchor, row.txid))
+            .collect();
+        let timestamps = |rows: Vec<TimestampRow>| {
+            rows.into_iter()
+                .map(|row| (row.txid, row.timestamp))
+                .collect()
+        };
+        tx_graph_changeset.last_seen = timestamps(export.last_seen);
+        tx_graph_changeset.first_seen = timestamps(export.first_seen);
+        tx_graph_changeset.last_evicted = timestamps(export.last_evicted);
+        let mut indexer_changeset = keychain_txout::ChangeSet {
+            last_revealed: export
+                .last_revealed
+                .into_iter()
+                .map(|row| (row.descriptor_id, row.index))
+                .collect(),
+            ..Default::default()
+        };
+        for row in export.spks {
+            indexer_changeset
+                .spk_cache
+                .entry(row.descriptor_id)
+                .or_default()
+                .insert(row.index, row.script_pubkey);
+        }
+
+        self.create_tables::<A>()?;
+        let write_tx = self.begin_write(Durability::Immediate)?;
+        let read_tx = self.db.begin_read()?;
+        let mut network = None;
+        self.read_network_in(&read_tx, &mut network)?;
+        if let (Some(ours), Some(theirs)) = (network, export.network) {
+            if ours != theirs {
+                return Err(StoreError::NetworkMismatch { ours, theirs });
+            }
+        }
+
+        let mut summary = self.persist_sections_in(
+            &write_tx,
+            &read_tx,
+            &local_chain::ChangeSet { blocks },
+            &tx_graph_changeset,
+            &indexer_changeset,
+        )?;
+        summary.network = self.persist_network_in(&write_tx, &export.network)?;
+        summary.keychains = self.persist_keychains_in(&write_tx, &descriptors)?;
+        self.commit(write_tx)?;
+        Ok(ImportStats {
+            wallet_name: export.wallet_name,
+            summary,
+        })
+    }
+}
+
+// Writes a JSON object field by field, with one array row per line, without holding the object in
+// memory.
+struct JsonWriter<W: Write> {
+    writer: W,
+    // Number of fields written so far.
+    fields: usize,
+    // Number of rows written to the array being written.
+    rows: usize,
+}
+
+impl<W: Write> JsonWriter<W> {
+    fn new(writer: W) -> Result<Self, StoreError> {
+        let mut json = JsonWriter {
+            writer,
+            fields: 0,
+            rows: 0,
+        };
+        json.write("{")?;
+        Ok(json)
+    }
+
+    fn write(&mut self, s: &str) -> Result<(), StoreError> {
+        self.writer
+            .write_all(s.as_bytes())
+            .map_err(serde_json::Error::io)?;
+        Ok(())
+    }
+
+    fn key(&mut self, name: &str) -> Result<(), StoreError> {
+        self.write(if self.fields == 0 { "\n  " } else { ",\n  " })?;
+        self.fields += 1;
+        serde_json::to_writer(&mut self.writer, name)?;
+        self.write(": ")
+    }
+
+    fn field<T: Serialize>(&mut self, name: &str, value: &T) -> Result<(), StoreError> {
+        self.key(name)?;
+        serde_json::to_writer(&mut self.writer, value)?;
+        Ok(())
+    }
+
+    fn begin_array(&mut self, name: &str) -> Result<(), StoreError> {
+        self.key(name)?;
+        self.rows = 0;
+        self.write("[")
+    }
+
+    fn row<T: Serialize>(&mut self, row: &T) -> Result<(), StoreError> {
+        self.write(if self.rows == 0 { "\n    " } else { ",\n    " })?;
+        self.rows += 1;
+        serde_json::to_writer(&mut self.writer, row)?;
+        Ok(())
+    }
+
+    fn end_array(&mut self) -> Result<(), StoreError> {
+        self.write(if self.rows == 0 { "]" } else { "\n  ]" })
+    }
+
+    fn finish(mut self) -> Result<(), StoreError> {
+        self.write("\n}\n")?;
+        self.writer.flush().map_err(serde_json::Error::io)?;
+        Ok(())
+    }
+}
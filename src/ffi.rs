@@ -0,0 +1,199 @@
+#![warn(missing_docs)]
+//! This module contains [`FfiStore`], a minimal object model over [`Store`] meant to be exported
+//! to other languages, for instance to back the persistence hooks of bdk-ffi from Kotlin or Swift.
+//!
+//! Only types which binding generators such as uniffi can export are used: the store is an object
+//! handed out as an [`Arc`], changesets cross the boundary as the [`ciborium`] encoding of a
+//! [`ChangeSet`], statistics are a plain record ([`FfiStats`]) and errors are the flat
+//! [`FfiError`] enum, whose variants group the variants of [`StoreError`] and only carry their
+//! message.
+//!
+//! With the `uniffi` feature, these types derive the [`uniffi`] traits and the functions of
+//! [`FfiStore`] are exported, so that Kotlin and Swift bindings can be generated from the library
+//! with `uniffi-bindgen`.
+//!
+//! [`uniffi`]: <https://docs.rs/uniffi/0.29.5/uniffi/index.html>
+//! [`ciborium`]: <https://docs.rs/ciborium/0.2.2/ciborium/index.html>
+//! [`ChangeSet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.ChangeSet.html>
+use crate::error::StoreError;
+use crate::{Store, list_wallets};
+use bdk_wallet::ChangeSet;
+use std::sync::Arc;
+
+/// Flat error returned by [`FfiStore`]. Each variant holds the message of the [`StoreError`] it
+/// was converted from.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error), uniffi(flat_error))]
+pub enum FfiError {
+    /// Error of the underlying redb database, e.g. while opening the file or committing.
+    #[error("{0}")]
+    Database(String),
+    /// Error of the file system.
+    #[error("{0}")]
+    Io(String),
+    /// Error while encoding or decoding a value, including the changesets given to or returned by
+    /// [`FfiStore`].
+    #[error("{0}")]
+    Encoding(String),
+    /// Error thrown when persisted data does not match its checksum or cannot be decrypted.
+    #[error("{0}")]
+    Corrupted(String),
+    /// Error thrown when reading a wallet which was not initialized.
+    #[error("{0}")]
+    NotInitialized(String),
+    /// Error thrown when a changeset or an operation is rejected because it conflicts with the
+    /// persisted wallet or the settings of the store.
+    #[error("{0}")]
+    Rejected(String),
+}
+
+impl From<StoreError> for FfiError {
+    fn from(err: StoreError) -> Self {
+        let msg = err.to_string();
+        // the match is exhaustive so that new variants of StoreError have to be mapped
+        match err {
+            StoreError::RedbTx(_)
+            | StoreError::RedbTable(_)
+            | StoreError::RedbCommit(_)
+            | StoreError::RedbStorage(_)
+            | StoreError::RedbSavepoint(_)
+            | StoreError::RedbCompaction(_)
+            | StoreError::RedbDatabase(_)
+            | StoreError::RedbFormatUpgradeRequired(_)
+            | StoreError::DatabaseInUse(_) => FfiError::Database(msg),
+            StoreError::Io(_) => FfiError::Io(msg),
+            StoreError::Ser(_)
+            | StoreError::Deser(_)
+            | StoreError::UnknownTxEncoding
+            | StoreError::BlockHashFromSlice(_)
+            | StoreError::InvalidBackupMagic
+            | StoreError::UnsupportedBackupVersion(_)
+            | StoreError::UnknownNetworkByte(_)
+            | StoreError::UnknownNetwork(_)
+            | StoreError::InvalidMeta(_)
+            | StoreError::AnchorEncoding { .. }
+            | StoreError::CompressionDisabled
+            | StoreError::CborDisabled => FfiError::Encoding(msg),
+            #[cfg(feature = "json")]
+            StoreError::Json(_)
+            | StoreError::UnsupportedExportVersion(_)
+            | StoreError::InvalidExport(_) => FfiError::Encoding(msg),
+            StoreError::ChecksumMismatch { .. }
+            | StoreError::DescriptorChecksumMismatch { .. }
+            | StoreError::InvalidDescriptor { .. } => FfiError::Corrupted(msg),
+            #[cfg(feature = "encryption")]
+            StoreError::Decryption => FfiError::Corrupted(msg),
+            StoreError::WalletNotInitialized(_) => FfiError::NotInitialized(msg),
+            StoreError::InvalidWalletName(_) => FfiError::Rejected(msg),
+            StoreError::TxMissing(_)
+            | StoreError::NetworkMismatch { .. }
+            | StoreError::DescriptorMismatch { .. }
+            | StoreError::NonMonotonicRevealedIndex { .. }
+            | StoreError::DescriptorImmutable(_)
+            | StoreError::MissingExternalDescriptor
+            | StoreError::ReservedMetaKey(_)
+            | StoreError::BirthdayImmutable
+            | StoreError::ConflictingTxOut { .. }
+            | StoreError::TxCodecMismatch { .. }
+            | StoreError::AnchorTypeMismatch { .. }
+            | StoreError::BlockRangeOverflow { .. } => FfiError::Rejected(msg),
+            #[cfg(feature = "encryption")]
+            StoreError::Encryption => FfiError::Rejected(msg),
+        }
+    }
+}
+
+/// Storage statistics of a wallet returned by [`FfiStore::stats`], a subset of [`StoreStats`].
+///
+/// [`StoreStats`]: crate::stats::StoreStats
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct FfiStats {
+    /// Number of persisted transactions.
+    pub transactions: u64,
+    /// Number of persisted floating txouts.
+    pub txouts: u64,
+    /// Number of persisted anchors.
+    pub anchors: u64,
+    /// Number of persisted blocks.
+    pub blocks: u64,
+    /// Number of bytes allocated in the database file, for all wallets.
+    pub allocated_bytes: u64,
+    /// Number of bytes allocated in the database file but not used.
+    pub fragmented_bytes: u64,
+}
+
+/// A [`Store`] for a single wallet of a database file, exposing byte oriented functions for
+/// foreign language bindings.
+#[derive(Debug)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Object))]
+pub struct FfiStore {
+    store: Store,
+}
+
+#[cfg_attr(feature = "uniffi", uniffi::export)]
+impl FfiStore {
+    /// This function opens the database file at `path`, creating it if it does not exist, and
+    /// returns the store of the wallet named `wallet_name` in it with its tables created, see
+    /// [`Store::from_path_str`].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(feature = "uniffi", uniffi::constructor)]
+    pub fn open(path: String, wallet_name: String) -> Result<Arc<Self>, FfiError> {
+        let store = Store::from_path_str(&path, &wallet_name)?;
+        Ok(Arc::new(FfiStore { store }))
+    }
+
+    /// This function persists a [`ChangeSet`] given as its [`ciborium`] encoding, see
+    /// [`Store::persist_wallet`]. [`FfiError::Encoding`] is returned if `changeset` does not
+    /// decode.
+    ///
+    /// [`ciborium`]: <https://docs.rs/ciborium/0.2.2/ciborium/index.html>
+    /// [`ChangeSet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.ChangeSet.html>
+    pub fn persist(&self, changeset: Vec<u8>) -> Result<(), FfiError> {
+        let changeset: ChangeSet =
+            ciborium::from_reader(changeset.as_slice()).map_err(StoreError::from)?;
+        self.store.persist_wallet(&changeset)?;
+        Ok(())
+    }
+
+    /// This function reads the persisted [`ChangeSet`] of the wallet and returns its [`ciborium`]
+    /// encoding. Like the [`WalletPersister::initialize`] implementation of [`Store`], it records
+    /// the birthday of the wallet if nothing was persisted yet, so it is the function to call when
+    /// a wallet is created or loaded.
+    ///
+    /// [`ciborium`]: <https://docs.rs/ciborium/0.2.2/ciborium/index.html>
+    /// [`ChangeSet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.ChangeSet.html>
+    /// [`WalletPersister::initialize`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/trait.WalletPersister.html#tymethod.initialize>
+    pub fn read_changeset(&self) -> Result<Vec<u8>, FfiError> {
+        let changeset = self.store.initialize_wallet()?;
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&changeset, &mut bytes).map_err(StoreError::from)?;
+        Ok(bytes)
+    }
+
+    /// This function returns the names of the wallets persisted in the database file of the
+    /// store, see [`list_wallets`].
+    ///
+    /// [`list_wallets`]: crate::list_wallets
+    pub fn list_wallets(&self) -> Result<Vec<String>, FfiError> {
+        Ok(list_wallets(&self.store.db)?.into_keys().collect())
+    }
+
+    /// This function deletes the wallet of the store, see [`Store::delete_wallet`].
+    pub fn delete_wallet(&self) -> Result<bool, FfiError> {
+        Ok(self.store.delete_wallet()?)
+    }
+
+    /// This function returns the storage statistics of the wallet, see [`Store::stats`].
+    pub fn stats(&self) -> Result<FfiStats, FfiError> {
+        let stats = self.store.stats()?;
+        Ok(FfiStats {
+            transactions: stats.txs.entries,
+            txouts: stats.txouts.entries,
+            anchors: stats.anchors.entries,
+            blocks: stats.blocks.entries,
+            allocated_bytes: stats.allocated_bytes,
+            fragmented_bytes: stats.fragmented_bytes,
+        })
+    }
+}
@@ -75,23 +75,81 @@
 //! documentation of each function links to the structures it is trying to eventually persist, the
 //! function actually uses the corresponding ChangeSets.
 #![cfg_attr(coverage_nightly, feature(coverage_attribute))]
+
+// The code generated by the derive macro refers to `::bdk_redb`, which must also resolve in the
+// tests of this crate.
+#[cfg(feature = "derive")]
+extern crate self as bdk_redb;
 pub use redb;
 
 pub mod anchor_trait;
+pub mod backend;
+#[cfg(not(target_arch = "wasm32"))]
+mod backup;
+#[cfg(feature = "serde")]
+pub mod cbor_backup;
+#[cfg(feature = "wallet")]
+mod changelog;
+pub mod combined;
+mod compression;
+#[cfg(feature = "wallet")]
+mod diff;
+#[cfg(feature = "encryption")]
+pub mod encryption;
 pub mod error;
+#[cfg(feature = "json")]
+pub mod export;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
+pub mod meta;
+pub mod rollback;
+#[cfg(feature = "wallet")]
+pub mod shared;
+pub mod spk_iter;
+pub mod stats;
+pub mod summary;
+#[cfg(feature = "serde")]
+pub mod sync_state;
+#[cfg(feature = "metrics")]
+pub mod telemetry;
+pub mod tx_codec;
+pub mod tx_node;
+mod utxos;
+pub mod verify;
+#[cfg(feature = "serde")]
+pub mod wrapper;
 
 use anchor_trait::AnchorWithMetaData;
+#[cfg(any(feature = "tracing", feature = "wallet"))]
+use bdk_chain::Merge;
 use bdk_chain::bitcoin::{self, Network, OutPoint, Transaction, Txid};
 use bdk_chain::bitcoin::{Amount, BlockHash, ScriptBuf, TxOut, hashes::Hash};
-use bdk_chain::miniscript::descriptor::{Descriptor, DescriptorPublicKey};
-use bdk_chain::{BlockId, DescriptorId, keychain_txout, local_chain, tx_graph};
+use bdk_chain::miniscript::descriptor::{Descriptor, DescriptorPublicKey, checksum::desc_checksum};
+use bdk_chain::{BlockId, DescriptorExt, DescriptorId, keychain_txout, local_chain, tx_graph};
 #[cfg(feature = "wallet")]
 use bdk_wallet::{ChangeSet, WalletPersister};
+#[cfg(feature = "encryption")]
+use encryption::EncryptionKey;
 use error::StoreError;
-use redb::{Database, ReadTransaction, ReadableTable, TableDefinition, WriteTransaction};
+#[cfg(feature = "tracing")]
+use redb::ReadableTableMetadata;
+use redb::{
+    Database, Durability, Key, MultimapTable, MultimapTableDefinition, ReadOnlyMultimapTable,
+    ReadOnlyTable, ReadTransaction, ReadableMultimapTable, ReadableTable, StorageBackend,
+    TableDefinition, TableError, TableHandle, Value, WriteTransaction,
+};
+use std::cell::Cell;
 use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
 use std::str::FromStr;
 use std::sync::Arc;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::time::{SystemTime, UNIX_EPOCH};
+use summary::{PersistSummary, ReplacedBlock, TableChanges};
+use tx_codec::TxCodec;
 
 #[cfg(feature = "wallet")]
 use bdk_chain::ConfirmationBlockTime;
@@ -100,6 +158,55 @@ use bdk_chain::ConfirmationBlockTime;
 /// a database file.
 const NETWORK: TableDefinition<&str, String> = TableDefinition::new("network");
 
+/// The following table stores (wallet_name, network byte) pairs for the wallets whose network is
+/// stored in the compact format (see [`Store::with_compact_network`]). A wallet's network is in
+/// either this table or [`NETWORK`]. This is common to all wallets in a database file.
+const NETWORK_BYTE: TableDefinition<&str, u8> = TableDefinition::new("network_byte");
+
+/// The following table stores (wallet_name, format version) pairs, i.e. the version of the on-disk
+/// format of each wallet's tables. This is common to all wallets in a database file.
+const FORMAT_VERSION: TableDefinition<&str, u32> = TableDefinition::new("format_version");
+
+/// The following table stores (wallet_name, next changelog sequence number) pairs, so that
+/// sequence numbers are never reused after the changelog was truncated. This is common to all
+/// wallets in a database file.
+const CHANGELOG_SEQUENCE: TableDefinition<&str, u64> = TableDefinition::new("changelog_sequence");
+
+/// Tag prefixed to the transactions written in the txs table, followed by a [`TX_CHECKSUM_LEN`]
+/// bytes checksum and the ciborium encoded transaction. It is never the first byte of a ciborium
+/// encoding (0xff is the CBOR break code), so rows written without a checksum can still be read.
+const CHECKSUMMED_TX_TAG: u8 = 0xff;
+
+/// Length of the checksum of a transaction in the txs table, the first bytes of the sha256 of its
+/// ciborium encoding.
+#[cfg(feature = "serde")]
+const TX_CHECKSUM_LEN: usize = 4;
+
+/// Identifiers of the tables whose values are encrypted when an encryption key is set (see
+/// [`Store::with_encryption_key`]). They are part of the associated data of the encrypted values,
+/// so that a value cannot be moved to another table. They must never change as they are persisted.
+const TXS_VALUE: u8 = 0;
+const TXOUTS_VALUE: u8 = 1;
+const SPKS_VALUE: u8 = 2;
+const CHANGELOG_VALUE: u8 = 3;
+
+/// Version of the on-disk format written by this crate. Wallets without an entry in
+/// [`FORMAT_VERSION`] are at version 0, which stored the heights of anchor blocks in little-endian
+/// instead of big-endian. Versions 0 and 1 stored the anchors in a table keyed by
+/// (txid, block id, tag) instead of a multimap keyed by txid. Versions before 3 could store
+/// descriptors without their checksum.
+const CURRENT_FORMAT_VERSION: u32 = 3;
+
+/// Length of the big-endian height starting an encoded block id.
+const HEIGHT_LEN: usize = 4;
+
+/// Length of a block id encoded in the anchors table: the height followed by the block hash.
+const BLOCK_ID_LEN: usize = HEIGHT_LEN + 32;
+
+/// A value of the anchors table: the encoded block id, the tag of the anchor type and the bytes of
+/// the metadata.
+type AnchorValue = ([u8; BLOCK_ID_LEN], u8, &'static [u8]);
+
 /// Persists the [`bdk_chain`] and [`bdk_wallet`] structures in a [`redb`] database.
 ///
 /// [`bdk_chain`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/index.html>
@@ -108,25 +215,165 @@ const NETWORK: TableDefinition<&str, String> = TableDefinition::new("network");
 /// This is the primary struct of this crate. It holds the database corresponding to a wallet.
 /// It also holds the table names of redb tables which are specific to each wallet in a database
 /// file.
-#[derive(Debug)]
+///
+/// Writes to a [`Database`] are serialized by redb: a persist function called while another write
+/// transaction is open (from another [`Store`] or thread) waits for it to be committed or aborted
+/// instead of failing, so no retry is needed. A database file can only be opened by one process at
+/// a time, which redb reports when opening it with `DatabaseError::DatabaseAlreadyOpen`.
+///
+/// A [`Store`] is [`Send`] and [`Sync`], and cloning it is cheap: the database, the names and the
+/// callbacks are shared between clones through [`Arc`]s, so a wallet's store can be cloned into
+/// each task using it instead of being wrapped in a mutex. Clones read concurrently, each read
+/// seeing the last committed state, while their writes still serialize on redb's writer lock as
+/// described above. Settings changed on a clone (e.g. with [`set_on_persist`]) do not affect the
+/// others.
+///
+/// The [`Debug`] implementation prints the wallet name, the table names and the settings of the
+/// store, but not the [`Database`] nor the encryption key.
+///
+/// [`Database`]: <https://docs.rs/redb/2.5.0/redb/struct.Database.html>
+/// [`Arc`]: std::sync::Arc
+/// [`set_on_persist`]: Self::set_on_persist
+#[derive(Clone)]
 pub struct Store {
     // We use a reference so as to avoid taking ownership of the Database, allowing other
     // applications to write to it. Arc is for thread safety.
     db: Arc<Database>,
-    wallet_name: String,
+    // The name keying the wallet's tables, prefixed with the namespace if any.
+    wallet_name: Arc<str>,
+    // The namespace given to `Store::new_with_prefix`, if any.
+    prefix: Option<Arc<str>>,
 
     // These could be removed if we can find a way to combine a String and an &str to create a
     // String without using unsafe Rust.
-    keychain_table_name: String,
-    last_revealed_table_name: String,
-    blocks_table_name: String,
-    txouts_table_name: String,
-    last_seen_table_name: String,
-    txs_table_name: String,
-    anchors_table_name: String,
-    last_evicted_table_name: String,
-    first_seen_table_name: String,
-    spk_table_name: String,
+    keychain_table_name: Arc<str>,
+    last_revealed_table_name: Arc<str>,
+    blocks_table_name: Arc<str>,
+    txouts_table_name: Arc<str>,
+    last_seen_table_name: Arc<str>,
+    txs_table_name: Arc<str>,
+    anchors_table_name: Arc<str>,
+    last_evicted_table_name: Arc<str>,
+    first_seen_table_name: Arc<str>,
+    spk_table_name: Arc<str>,
+    changelog_table_name: Arc<str>,
+    meta_table_name: Arc<str>,
+
+    // Whether the spk cache is loaded when reading the indexer.
+    load_spk_cache: bool,
+
+    // Whether the spk cache is written when persisting the indexer.
+    persist_spk_cache: bool,
+
+    // Whether the network is stored as a single byte instead of a string.
+    compact_network: bool,
+
+    // Whether the trailing zero bytes of fixed width anchor metadata are dropped.
+    compact_anchor_metadata: bool,
+
+    // Whether persisting a txout different from the one stored for its outpoint is an error.
+    strict_txouts: bool,
+
+    // Whether anchor-generic functions check the anchor type recorded for the wallet.
+    check_anchor_type: bool,
+
+    // The encoding of the transactions persisted in the txs table.
+    tx_codec: TxCodec,
+
+    // Whether persisted wallet changesets are appended to the changelog table.
+    #[cfg(feature = "wallet")]
+    changelog: bool,
+
+    // Key used to encrypt descriptors at rest, if any.
+    #[cfg(feature = "encryption")]
+    encryption_key: Option<EncryptionKey>,
+
+    // The zstd level transactions are compressed with, if compression is enabled.
+    #[cfg(feature = "compression")]
+    compression_level: Option<i32>,
+
+    // Whether the spk cache scripts are also compressed.
+    #[cfg(feature = "compression")]
+    compress_spks: bool,
+
+    // Callback invoked after a successful persist, if any.
+    on_persist: Option<OnPersist>,
+
+    // Source of the unix timestamps written by the store, the system clock if None.
+    clock: Option<Clock>,
+}
+
+// Stores are shared between threads by cloning them.
+const _: fn() = || {
+    fn assert_clone_send_sync<T: Clone + Send + Sync>() {}
+    assert_clone_send_sync::<Store>();
+};
+
+// The callback registered with `Store::set_on_persist`.
+#[derive(Clone)]
+struct OnPersist(Arc<dyn Fn(&PersistSummary) + Send + Sync>);
+
+impl fmt::Debug for OnPersist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("OnPersist(..)")
+    }
+}
+
+// The clock registered with `Store::with_clock`.
+#[derive(Clone)]
+struct Clock(Arc<dyn Fn() -> u64 + Send + Sync>);
+
+impl fmt::Debug for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Clock(..)")
+    }
+}
+
+// The database is omitted as its internals are of no use when logging a store, and so is the
+// encryption key, of which only the presence is printed.
+impl fmt::Debug for Store {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Store");
+        debug
+            .field("wallet_name", &self.wallet_name)
+            .field("prefix", &self.prefix)
+            .field("keychain_table_name", &self.keychain_table_name)
+            .field("last_revealed_table_name", &self.last_revealed_table_name)
+            .field("blocks_table_name", &self.blocks_table_name)
+            .field("txouts_table_name", &self.txouts_table_name)
+            .field("last_seen_table_name", &self.last_seen_table_name)
+            .field("txs_table_name", &self.txs_table_name)
+            .field("anchors_table_name", &self.anchors_table_name)
+            .field("last_evicted_table_name", &self.last_evicted_table_name)
+            .field("first_seen_table_name", &self.first_seen_table_name)
+            .field("spk_table_name", &self.spk_table_name)
+            .field("changelog_table_name", &self.changelog_table_name)
+            .field("meta_table_name", &self.meta_table_name)
+            .field("load_spk_cache", &self.load_spk_cache)
+            .field("persist_spk_cache", &self.persist_spk_cache)
+            .field("compact_network", &self.compact_network)
+            .field("compact_anchor_metadata", &self.compact_anchor_metadata)
+            .field("strict_txouts", &self.strict_txouts)
+            .field("check_anchor_type", &self.check_anchor_type)
+            .field("tx_codec", &self.tx_codec);
+        #[cfg(feature = "wallet")]
+        debug.field("changelog", &self.changelog);
+        #[cfg(feature = "encryption")]
+        debug.field("encrypted", &self.encryption_key.is_some());
+        #[cfg(feature = "compression")]
+        debug
+            .field("compression_level", &self.compression_level)
+            .field("compress_spks", &self.compress_spks);
+        debug
+            .field("on_persist", &self.on_persist)
+            .field("clock", &self.clock)
+            .finish_non_exhaustive()
+    }
+}
+
+thread_local! {
+    // Whether an on_persist callback is running on this thread.
+    static IN_ON_PERSIST: Cell<bool> = const { Cell::new(false) };
 }
 
 impl Store {
@@ -151,14 +398,22 @@ impl Store {
         TableDefinition::new(&self.txouts_table_name)
     }
 
-    // This table stores ((Txid, BlockId), Metadata) pairs on a high level where Metadata refers to
-    // extra information stored inside the anchor. For example confirmation time would be metadata
-    // in case of ConfirmationBlockTime and None in case of BlockId.
-    // The key was chosen like this because a transaction can be anchored in multiple Blocks
-    // (in different chains ) and a Block can anchor multiple transactions.
-    fn anchors_table_defn<A: AnchorWithMetaData>(
+    // This multimap table stores (Txid, (BlockId, tag, Metadata)) pairs on a high level where
+    // Metadata refers to extra information stored inside the anchor. For example confirmation time
+    // would be metadata in case of ConfirmationBlockTime and None in case of BlockId.
+    // A multimap was chosen because a transaction can be anchored in multiple Blocks (in different
+    // chains), and the values of a txid are ordered by BlockId. The tag identifies the anchor type
+    // (see AnchorWithMetaData::TAG) so that different types of anchors can coexist, and the
+    // Metadata is stored as the bytes of the corresponding redb Value.
+    fn anchors_table_defn(&self) -> MultimapTableDefinition<[u8; 32], AnchorValue> {
+        MultimapTableDefinition::new(&self.anchors_table_name)
+    }
+
+    // The anchors table of format versions 0 and 1, keyed by (Txid, BlockId, tag). It is only
+    // read when migrating.
+    fn legacy_anchors_table_defn(
         &self,
-    ) -> TableDefinition<([u8; 32], [u8; 36]), A::MetaDataType> {
+    ) -> TableDefinition<([u8; 32], [u8; BLOCK_ID_LEN], u8), Vec<u8>> {
         TableDefinition::new(&self.anchors_table_name)
     }
 
@@ -187,10 +442,27 @@ impl Store {
         TableDefinition::new(&self.spk_table_name)
     }
 
+    // This table stores (sequence, (timestamp, ChangeSet)) pairs on a high level, where ChangeSet
+    // is the ciborium encoding of a persisted wallet changeset.
+    fn changelog_table_defn(&self) -> TableDefinition<u64, (u64, Vec<u8>)> {
+        TableDefinition::new(&self.changelog_table_name)
+    }
+
     /// This function creates a brand new [`Store`].
     ///
+    /// [`StoreError::InvalidWalletName`] is returned if `wallet_name` contains `:`, which is
+    /// reserved for the wallets of [`new_with_prefix`].
+    ///
     /// [`Store`]: crate::Store
+    /// [`new_with_prefix`]: Self::new_with_prefix
     pub fn new(db: Arc<Database>, wallet_name: String) -> Result<Self, StoreError> {
+        check_name(&wallet_name)?;
+        Self::new_keyed(db, wallet_name)
+    }
+
+    // This function creates a store for the wallet keyed by `wallet_name`, which is the name
+    // given to `new` or `<prefix>:<wallet_name>` for `new_with_prefix`.
+    fn new_keyed(db: Arc<Database>, wallet_name: String) -> Result<Self, StoreError> {
         // Create table names to be stored in the Store.
         let mut keychain_table_name = wallet_name.clone();
         keychain_table_name.push_str("_keychain");
@@ -212,1437 +484,9600 @@ impl Store {
         last_revealed_table_name.push_str("_last_revealed");
         let mut spk_table_name = wallet_name.clone();
         spk_table_name.push_str("_spk");
+        let mut changelog_table_name = wallet_name.clone();
+        changelog_table_name.push_str("_changelog");
+        let mut meta_table_name = wallet_name.clone();
+        meta_table_name.push_str("_meta");
         Ok(Store {
             db,
-            wallet_name,
-            keychain_table_name,
-            blocks_table_name,
-            txs_table_name,
-            txouts_table_name,
-            anchors_table_name,
-            last_seen_table_name,
-            last_evicted_table_name,
-            first_seen_table_name,
-            last_revealed_table_name,
-            spk_table_name,
+            wallet_name: wallet_name.into(),
+            prefix: None,
+            keychain_table_name: keychain_table_name.into(),
+            blocks_table_name: blocks_table_name.into(),
+            txs_table_name: txs_table_name.into(),
+            txouts_table_name: txouts_table_name.into(),
+            anchors_table_name: anchors_table_name.into(),
+            last_seen_table_name: last_seen_table_name.into(),
+            last_evicted_table_name: last_evicted_table_name.into(),
+            first_seen_table_name: first_seen_table_name.into(),
+            last_revealed_table_name: last_revealed_table_name.into(),
+            spk_table_name: spk_table_name.into(),
+            changelog_table_name: changelog_table_name.into(),
+            meta_table_name: meta_table_name.into(),
+            load_spk_cache: true,
+            persist_spk_cache: true,
+            compact_network: false,
+            compact_anchor_metadata: false,
+            strict_txouts: false,
+            check_anchor_type: false,
+            tx_codec: TxCodec::default(),
+            #[cfg(feature = "wallet")]
+            changelog: false,
+            #[cfg(feature = "encryption")]
+            encryption_key: None,
+            #[cfg(feature = "compression")]
+            compression_level: None,
+            #[cfg(feature = "compression")]
+            compress_spks: false,
+            on_persist: None,
+            clock: None,
         })
     }
 
-    /// This function creates or opens (if already created) all redb tables corresponding to a
-    /// [`Wallet`].
+    /// This function creates a brand new [`Store`] for the wallet named `wallet_name` in the
+    /// namespace `prefix`, so that applications sharing a database file can use the same wallet
+    /// names without their wallets colliding.
     ///
-    /// [`Wallet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.Wallet.html>
-    pub fn create_tables<A: AnchorWithMetaData>(&self) -> Result<(), StoreError> {
-        let write_tx = self.db.begin_write()?;
+    /// The wallet is keyed by `<prefix>:<wallet_name>`: its tables are named
+    /// `<prefix>:<wallet_name>_<table>` and its rows of the tables common to all wallets (such as
+    /// the `network` table) use `<prefix>:<wallet_name>` as key. This is also the name reported in
+    /// errors, spans and metrics. A store created with [`new`] uses `wallet_name` alone.
+    ///
+    /// [`StoreError::InvalidWalletName`] is returned if `prefix` or `wallet_name` contains `:`, so
+    /// that the key of a wallet never collides with the one of another prefix or of a wallet
+    /// created with [`new`] (e.g. `new_with_prefix("a", "b:c")` and `new("a:b:c")`).
+    ///
+    /// [`new`]: Self::new
+    pub fn new_with_prefix(
+        db: Arc<Database>,
+        prefix: String,
+        wallet_name: String,
+    ) -> Result<Self, StoreError> {
+        check_name(&prefix)?;
+        check_name(&wallet_name)?;
+        let mut store = Self::new_keyed(db, format!("{prefix}:{wallet_name}"))?;
+        store.prefix = Some(prefix.into());
+        Ok(store)
+    }
 
-        let _ = write_tx.open_table(NETWORK)?;
-        let _ = write_tx.open_table(self.keychains_table_defn())?;
-        write_tx.commit()?;
+    /// This function creates a [`Store`] for the wallet named `wallet_name` in the same
+    /// [`Database`] and namespace (see [`new_with_prefix`]) as this one. It is equivalent to
+    /// calling [`new`] or [`new_with_prefix`] with a clone of the `Arc` returned by [`database`].
+    ///
+    /// The new store has the default settings: the settings of this store (encryption key, spk
+    /// cache loading and persisting, compact network and anchor metadata, strict txouts, anchor
+    /// type check, tx codec, compression, changelog, on_persist callback, clock) are not copied.
+    ///
+    /// [`new`]: Self::new
+    /// [`new_with_prefix`]: Self::new_with_prefix
+    /// [`database`]: Self::database
+    /// [`Database`]: <https://docs.rs/redb/2.5.0/redb/struct.Database.html>
+    pub fn with_wallet_name(&self, wallet_name: String) -> Result<Store, StoreError> {
+        match &self.prefix {
+            Some(prefix) => Self::new_with_prefix(self.db.clone(), prefix.to_string(), wallet_name),
+            None => Self::new(self.db.clone(), wallet_name),
+        }
+    }
 
-        self.create_local_chain_tables()?;
-        self.create_tx_graph_tables::<A>()?;
-        self.create_indexer_tables()?;
+    /// This function creates a [`Store`] for a wallet whose tables were already created in `db`,
+    /// for instance by a previous run of the application.
+    ///
+    /// Unlike calling [`create_tables`] after [`new`], no write transaction is opened: a single
+    /// read transaction checks that all the tables of the wallet exist and are in the current
+    /// on-disk format. This avoids a write per wallet at startup when many wallets share a database
+    /// file. [`StoreError::WalletNotInitialized`] is returned if the check fails, in which case
+    /// [`create_tables`] has to be called (it also migrates tables written by older versions of
+    /// this crate).
+    ///
+    /// [`create_tables`]: Self::create_tables
+    /// [`new`]: Self::new
+    pub fn attach(db: Arc<Database>, wallet_name: String) -> Result<Self, StoreError> {
+        Self::new(db, wallet_name)?.check_initialized()
+    }
 
-        Ok(())
+    /// This function is [`attach`] for a wallet created with [`new_with_prefix`].
+    ///
+    /// [`attach`]: Self::attach
+    /// [`new_with_prefix`]: Self::new_with_prefix
+    pub fn attach_with_prefix(
+        db: Arc<Database>,
+        prefix: String,
+        wallet_name: String,
+    ) -> Result<Self, StoreError> {
+        Self::new_with_prefix(db, prefix, wallet_name)?.check_initialized()
     }
 
-    /// This function creates or opens (if already created) the redb tables corresponding to
-    /// local_chain.
-    pub fn create_local_chain_tables(&self) -> Result<(), StoreError> {
-        let write_tx = self.db.begin_write()?;
-        let _ = write_tx.open_table(self.blocks_table_defn())?;
-        write_tx.commit()?;
-        Ok(())
+    // This function returns the store if its wallet is initialized, see attach.
+    fn check_initialized(self) -> Result<Self, StoreError> {
+        if !self.is_initialized()? {
+            return Err(StoreError::WalletNotInitialized(
+                self.wallet_name.to_string(),
+            ));
+        }
+        Ok(self)
     }
 
-    /// This function creates or opens (if already created) the redb tables corresponding to
-    /// [`TxGraph`].
+    /// This function opens the database file at `path`, creating it if it does not exist, and
+    /// returns a [`Store`] for the wallet named `wallet_name` in it with its tables created (see
+    /// [`create_tables`]). It is meant for configuration driven setups, where the database is
+    /// only used by this store.
     ///
-    /// [`TxGraph`]: <http://docs.rs/bdk_chain/0.23.0/bdk_chain/tx_graph/struct.TxGraph.html>
-    pub fn create_tx_graph_tables<A: AnchorWithMetaData>(&self) -> Result<(), StoreError> {
-        let write_tx = self.db.begin_write()?;
-        let _ = write_tx.open_table(self.txs_table_defn())?;
-        let _ = write_tx.open_table(self.txouts_table_defn())?;
-        let _ = write_tx.open_table(self.anchors_table_defn::<A>())?;
-        let _ = write_tx.open_table(self.last_seen_defn())?;
-        let _ = write_tx.open_table(self.last_evicted_table_defn())?;
-        let _ = write_tx.open_table(self.first_seen_table_defn())?;
-
-        write_tx.commit()?;
-        Ok(())
+    /// Filesystem errors, for instance when the parent directory does not exist, are returned as
+    /// [`StoreError::Io`]. Anchors are assumed to be [`ConfirmationBlockTime`]s, the anchors of a
+    /// [`Wallet`].
+    ///
+    /// [`create_tables`]: Self::create_tables
+    /// [`ConfirmationBlockTime`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/struct.ConfirmationBlockTime.html>
+    /// [`Wallet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.Wallet.html>
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_path_str(path: &str, wallet_name: &str) -> Result<Self, StoreError> {
+        let db = Database::create(path).map_err(|err| match err {
+            redb::DatabaseError::Storage(redb::StorageError::Io(err)) => StoreError::Io(err),
+            err => err.into(),
+        })?;
+        let store = Self::new(Arc::new(db), wallet_name.to_string())?;
+        store.create_tables::<bdk_chain::ConfirmationBlockTime>()?;
+        Ok(store)
     }
 
-    /// This function creates or opens (if already created) the redb tables corresponding to
-    /// [`indexer`].
+    /// This function creates a database on `backend` and returns a [`Store`] for the wallet named
+    /// `wallet_name` in it with its tables created (see [`create_tables`]). It is meant for
+    /// targets without a file system such as `wasm32-unknown-unknown`, where the database lives in
+    /// memory, for instance in a [`MemoryBackend`] whose bytes the application saves elsewhere.
     ///
-    /// [`indexer`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/indexer/index.html>
-    pub fn create_indexer_tables(&self) -> Result<(), StoreError> {
-        let write_tx = self.db.begin_write()?;
-        let _ = write_tx.open_table(self.spk_table_defn())?;
+    /// A backend holding an existing database is opened rather than overwritten. Anchors are
+    /// assumed to be [`ConfirmationBlockTime`]s, the anchors of a [`Wallet`]. On
+    /// `wasm32-unknown-unknown` the system clock is not available, so a clock should be set with
+    /// [`with_clock`] for the timestamps written by the store to be meaningful.
+    ///
+    /// [`create_tables`]: Self::create_tables
+    /// [`MemoryBackend`]: crate::backend::MemoryBackend
+    /// [`with_clock`]: Self::with_clock
+    /// [`ConfirmationBlockTime`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/struct.ConfirmationBlockTime.html>
+    /// [`Wallet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.Wallet.html>
+    pub fn from_backend(
+        backend: impl StorageBackend,
+        wallet_name: &str,
+    ) -> Result<Self, StoreError> {
+        let db = Database::builder().create_with_backend(backend)?;
+        let store = Self::new(Arc::new(db), wallet_name.to_string())?;
+        store.create_tables::<bdk_chain::ConfirmationBlockTime>()?;
+        Ok(store)
+    }
 
-        let _ = write_tx.open_table(self.last_revealed_table_defn())?;
-        write_tx.commit()?;
+    /// This function checks that the `NETWORK` table and all the tables of the wallet created by
+    /// [`create_tables`] exist and are readable, so that a missing or corrupted table is reported
+    /// at startup rather than by the first read or persist.
+    ///
+    /// Each table is opened with its expected key and value types in a single read transaction,
+    /// and its first entry is read. The first error encountered is returned, e.g.
+    /// [`StoreError::RedbTable`] with [`TableError::TableDoesNotExist`] for a missing table.
+    /// Nothing is created nor migrated.
+    ///
+    /// [`create_tables`]: Self::create_tables
+    /// [`TableError::TableDoesNotExist`]: <https://docs.rs/redb/2.5.0/redb/enum.TableError.html#variant.TableDoesNotExist>
+    pub fn health_check(&self) -> Result<(), StoreError> {
+        let read_tx = self.db.begin_read()?;
+        check_table(&read_tx, NETWORK)?;
+        check_table(&read_tx, self.keychains_table_defn())?;
+        check_table(&read_tx, self.blocks_table_defn())?;
+        check_table(&read_tx, self.txs_table_defn())?;
+        check_table(&read_tx, self.txouts_table_defn())?;
+        read_tx
+            .open_multimap_table(self.anchors_table_defn())?
+            .iter()?
+            .next()
+            .transpose()?;
+        check_table(&read_tx, self.last_seen_defn())?;
+        check_table(&read_tx, self.last_evicted_table_defn())?;
+        check_table(&read_tx, self.first_seen_table_defn())?;
+        check_table(&read_tx, self.last_revealed_table_defn())?;
+        check_table(&read_tx, self.spk_table_defn())?;
         Ok(())
     }
 
-    /// This function creates or opens (if already created) the keychains redb table corresponding
-    /// to the wallet.
-    pub fn create_keychains_table(&self) -> Result<(), StoreError> {
-        let write_tx = self.db.begin_write()?;
-        let _ = write_tx.open_table(self.keychains_table_defn())?;
-        write_tx.commit()?;
-        Ok(())
+    // This function checks that all the tables of the wallet exist and that no migration is
+    // needed, using a single read transaction.
+    fn is_initialized(&self) -> Result<bool, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let up_to_date = match open_table_if_exists(&read_tx, FORMAT_VERSION)? {
+            Some(table) => table
+                .get(&*self.wallet_name)?
+                .is_some_and(|version| version.value() >= CURRENT_FORMAT_VERSION),
+            None => false,
+        };
+        Ok(up_to_date
+            && !self.network_needs_migration(&read_tx)?
+            && open_table_if_exists(&read_tx, NETWORK)?.is_some()
+            && open_table_if_exists(&read_tx, self.keychains_table_defn())?.is_some()
+            && open_table_if_exists(&read_tx, self.blocks_table_defn())?.is_some()
+            && open_table_if_exists(&read_tx, self.txs_table_defn())?.is_some()
+            && open_table_if_exists(&read_tx, self.txouts_table_defn())?.is_some()
+            && open_multimap_table_if_exists(&read_tx, self.anchors_table_defn())?.is_some()
+            && open_table_if_exists(&read_tx, self.last_seen_defn())?.is_some()
+            && open_table_if_exists(&read_tx, self.last_evicted_table_defn())?.is_some()
+            && open_table_if_exists(&read_tx, self.first_seen_table_defn())?.is_some()
+            && open_table_if_exists(&read_tx, self.last_revealed_table_defn())?.is_some()
+            && open_table_if_exists(&read_tx, self.spk_table_defn())?.is_some())
     }
 
-    /// This function creates or opens (if already created) the [`Network`] redb table. This table
-    /// is common to all wallets persisted in the database file.
-    ///
-    /// [`Network`]: <https://docs.rs/bitcoin/latest/bitcoin/enum.Network.html>
-    pub fn create_network_table(&self) -> Result<(), StoreError> {
-        let write_tx = self.db.begin_write()?;
-        let _ = write_tx.open_table(NETWORK)?;
-        write_tx.commit()?;
-        Ok(())
+    // This function checks whether the network of the wallet is stored as a string while the
+    // compact format is enabled.
+    fn network_needs_migration(&self, read_tx: &ReadTransaction) -> Result<bool, StoreError> {
+        if !self.compact_network {
+            return Ok(false);
+        }
+        Ok(match open_table_if_exists(read_tx, NETWORK)? {
+            Some(table) => table.get(&*self.wallet_name)?.is_some(),
+            None => false,
+        })
     }
 
-    #[cfg(feature = "wallet")]
-    /// This function persists the [`Wallet`] into our db. It persists each field by calling
-    /// corresponding persistence functions.
+    /// This function enables or disables loading the spk cache when reading the indexer (in
+    /// [`read_indexer`], [`read_wallet`] and so when loading a [`Wallet`], and [`read_combined`]).
+    /// It is enabled by default.
     ///
+    /// When disabled, the `spk_cache` of the loaded [`keychain_txout::ChangeSet`] is left empty
+    /// while `last_revealed` is still read, and the wallet derives the scripts it needs itself.
+    /// This trades CPU for memory and startup time when the cache is large. The persisted cache is
+    /// not affected.
+    ///
+    /// [`read_indexer`]: Self::read_indexer
+    /// [`read_wallet`]: Self::read_wallet
+    /// [`read_combined`]: Self::read_combined
     /// [`Wallet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.Wallet.html>
-    pub fn persist_wallet(&self, changeset: &ChangeSet) -> Result<(), StoreError> {
-        self.persist_network(&changeset.network)?;
-        let mut desc_changeset: BTreeMap<u64, Descriptor<DescriptorPublicKey>> = BTreeMap::new();
-        if let Some(desc) = &changeset.descriptor {
-            desc_changeset.insert(0, desc.clone());
-            if let Some(change_desc) = &changeset.change_descriptor {
-                desc_changeset.insert(1, change_desc.clone());
-            }
-        }
-        self.persist_keychains(&desc_changeset)?;
-        self.persist_local_chain(&changeset.local_chain)?;
-        self.persist_indexer(&changeset.indexer)?;
-        self.persist_tx_graph::<ConfirmationBlockTime>(&changeset.tx_graph)?;
-        Ok(())
+    /// [`keychain_txout::ChangeSet`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/indexer/keychain_txout/struct.ChangeSet.html>
+    pub fn with_spk_cache_loading(mut self, enabled: bool) -> Self {
+        self.load_spk_cache = enabled;
+        self
     }
 
-    /// This function persists the [`TxGraph`] into our db. It persists each field
-    /// by calling corresponding persistence functions.
+    /// This function enables or disables writing the spk cache when persisting the indexer (in
+    /// [`persist_indexer`] and [`persist_wallet`]). It is enabled by default.
     ///
-    /// [`TxGraph`]: <http://docs.rs/bdk_chain/0.23.0/bdk_chain/tx_graph/struct.TxGraph.html>
-    pub fn persist_tx_graph<A: AnchorWithMetaData>(
-        &self,
-        changeset: &tx_graph::ChangeSet<A>,
-    ) -> Result<(), StoreError> {
-        let write_tx = self.db.begin_write()?;
-        let read_tx = self.db.begin_read()?;
-        self.persist_txs(&write_tx, &changeset.txs)?;
-        self.persist_txouts(&write_tx, &changeset.txouts)?;
-        self.persist_anchors::<A>(&write_tx, &read_tx, &changeset.anchors, &changeset.txs)?;
-        self.persist_last_seen(&write_tx, &read_tx, &changeset.last_seen, &changeset.txs)?;
-        self.persist_last_evicted(&write_tx, &read_tx, &changeset.last_evicted, &changeset.txs)?;
-        self.persist_first_seen(&write_tx, &read_tx, &changeset.first_seen, &changeset.txs)?;
-        write_tx.commit()?;
-        Ok(())
+    /// When disabled, the `spk_cache` of persisted [`keychain_txout::ChangeSet`]s is ignored while
+    /// `last_revealed` is still written. The database stays smaller, but a loaded wallet has to
+    /// derive its scripts again, which makes a cold start slower. Entries persisted before are
+    /// kept (see [`clear_spk_cache`]); if writing is enabled again, the cache is filled back by the
+    /// following changesets.
+    ///
+    /// [`clear_spk_cache`]: Self::clear_spk_cache
+    /// [`persist_indexer`]: Self::persist_indexer
+    /// [`persist_wallet`]: Self::persist_wallet
+    /// [`keychain_txout::ChangeSet`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/indexer/keychain_txout/struct.ChangeSet.html>
+    pub fn with_spk_cache_persisting(mut self, enabled: bool) -> Self {
+        self.persist_spk_cache = enabled;
+        self
     }
 
-    /// This function persists the [`indexer`] structures into our db. It persists each
-    /// field by calling corresponding persistence functions.
+    /// This function enables or disables storing the [`Network`] of the wallet as a single byte
+    /// instead of its name. It is disabled by default.
     ///
-    /// [`indexer`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/indexer/index.html>
-    pub fn persist_indexer(&self, changeset: &keychain_txout::ChangeSet) -> Result<(), StoreError> {
-        let write_tx = self.db.begin_write()?;
-        self.persist_last_revealed(&write_tx, &changeset.last_revealed)?;
-        self.persist_spks(&write_tx, &changeset.spk_cache)?;
-        write_tx.commit()?;
-        Ok(())
+    /// The byte is written to the `network_byte` table instead of the `network` one by
+    /// [`persist_network`], and [`create_tables`] (or [`create_network_table`]) migrates a network
+    /// stored as a string to it. [`read_network`] reads both formats whatever this setting, so it
+    /// can be turned on for existing wallets.
+    ///
+    /// [`Network`]: <https://docs.rs/bitcoin/latest/bitcoin/enum.Network.html>
+    /// [`persist_network`]: Self::persist_network
+    /// [`create_tables`]: Self::create_tables
+    /// [`create_network_table`]: Self::create_network_table
+    /// [`read_network`]: Self::read_network
+    pub fn with_compact_network(mut self, enabled: bool) -> Self {
+        self.compact_network = enabled;
+        self
     }
 
-    /// This function persists the descriptors into our db.
-    pub fn persist_keychains(
-        &self,
-        // maps label to descriptor
-        changeset: &BTreeMap<u64, Descriptor<DescriptorPublicKey>>,
-    ) -> Result<(), StoreError> {
-        let write_tx = self.db.begin_write()?;
-        {
-            let mut table = write_tx.open_table(self.keychains_table_defn())?;
+    /// This function enables or disables storing anchor metadata without its trailing zero bytes.
+    /// It is disabled by default.
+    ///
+    /// The metadata of an anchor (the confirmation time of a [`ConfirmationBlockTime`]) is stored
+    /// with its fixed width redb encoding, e.g. 8 little-endian bytes for a `u64`, although a
+    /// confirmation time fits in 4 of them until 2106. When enabled, [`persist_tx_graph`] drops
+    /// the trailing zero bytes of metadata whose type has a fixed width, and they are restored
+    /// when reading, so anchors round-trip exactly. Metadata of types without a fixed width is
+    /// stored as is.
+    ///
+    /// Both encodings are read whatever this setting, so it can be turned on for existing wallets:
+    /// new anchors are then stored compactly, and [`compact_anchors`] rewrites the ones already
+    /// persisted.
+    ///
+    /// [`ConfirmationBlockTime`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/struct.ConfirmationBlockTime.html>
+    /// [`persist_tx_graph`]: Self::persist_tx_graph
+    /// [`compact_anchors`]: Self::compact_anchors
+    pub fn with_compact_anchor_metadata(mut self, enabled: bool) -> Self {
+        self.compact_anchor_metadata = enabled;
+        self
+    }
 
-            // assuming descriptors corresponding to a label(keychain) are never modified.
-            for (label, desc) in changeset {
-                table.insert(label, desc.to_string())?;
-            }
-        }
-        write_tx.commit()?;
-        Ok(())
+    /// This function enables or disables the strict txouts mode. It is disabled by default.
+    ///
+    /// Floating txouts are keyed by outpoint, and persisting a txout for an outpoint which already
+    /// has one overwrites it. A different txout for the same outpoint should never be produced, so
+    /// it reveals a bug of the chain source or of the application: in strict mode
+    /// [`StoreError::ConflictingTxOut`] is returned instead, and nothing is written. Persisting the
+    /// same txout again is allowed in both modes.
+    pub fn with_strict_txouts(mut self, enabled: bool) -> Self {
+        self.strict_txouts = enabled;
+        self
     }
 
-    /// This function persists the [`Network`] into our db.
-    /// <div class="warning">Warning: Do Not use with MAINNET</div>
+    /// This function enables or disables the anchor type check. It is disabled by default.
     ///
-    /// [`Network`]: <https://docs.rs/bitcoin/latest/bitcoin/enum.Network.html>
-    pub fn persist_network(&self, network: &Option<bitcoin::Network>) -> Result<(), StoreError> {
-        let write_tx = self.db.begin_write()?;
-        {
-            let mut table = write_tx.open_table(NETWORK)?;
-            // assuming network will be persisted once and only once
-            if let Some(network) = network {
-                table.insert(&*self.wallet_name, network.to_string())?;
-            }
-        }
-        write_tx.commit()?;
-        Ok(())
+    /// The name of the anchor type `A` given to [`create_tx_graph_tables`] (or [`create_tables`])
+    /// or [`persist_tx_graph`] the first time is recorded for the wallet. Anchors of different
+    /// types can be persisted for the same wallet (see [`AnchorWithMetaData::TAG`]), but
+    /// functions generic over the anchor type only see the anchors of the requested type, so
+    /// reading with the wrong type silently returns no anchors. When enabled, these functions
+    /// return [`StoreError::AnchorTypeMismatch`] if the requested type is not the recorded one.
+    ///
+    /// [`create_tx_graph_tables`]: Self::create_tx_graph_tables
+    /// [`create_tables`]: Self::create_tables
+    /// [`persist_tx_graph`]: Self::persist_tx_graph
+    pub fn with_anchor_type_check(mut self, enabled: bool) -> Self {
+        self.check_anchor_type = enabled;
+        self
     }
 
-    /// This function persists the [`LocalChain`] structure into our db. It persists each
-    /// field by calling corresponding persistence functions.
+    /// This function sets the encoding of the transactions persisted in the txs table. It is
+    /// [`TxCodec::Cbor`] by default, or [`TxCodec::Consensus`] without the `serde` feature.
     ///
-    /// [`LocalChain`]: <http://docs.rs/bdk_chain/0.23.0/bdk_chain/local_chain/struct.LocalChain.html>
-    pub fn persist_local_chain(
-        &self,
-        changeset: &local_chain::ChangeSet,
-    ) -> Result<(), StoreError> {
-        let write_tx = self.db.begin_write()?;
-        self.persist_blocks(&write_tx, &changeset.blocks)?;
-        write_tx.commit()?;
-        Ok(())
+    /// [`TxCodec::Consensus`] stores the consensus encoding of transactions, which is smaller and
+    /// can be handed as is to a broadcaster or compared with the dumps of other tools. The codec
+    /// is recorded in the metadata of the wallet the first time transactions are persisted, and
+    /// reads use the recorded one whatever this setting. Persisting transactions with a store
+    /// whose codec is not the recorded one returns [`StoreError::TxCodecMismatch`]:
+    /// [`migrate_tx_codec`] rewrites the transactions of an existing wallet with another codec.
+    ///
+    /// [`migrate_tx_codec`]: Self::migrate_tx_codec
+    pub fn with_tx_codec(mut self, codec: TxCodec) -> Self {
+        self.tx_codec = codec;
+        self
     }
 
-    // This function persists blocks corresponding to a local_chain.
-    fn persist_blocks(
+    // This function runs `persist` and invokes the on_persist callback once it succeeded.
+    fn with_notify(
         &self,
-        write_tx: &WriteTransaction,
-        blocks: &BTreeMap<u32, Option<BlockHash>>,
-    ) -> Result<(), StoreError> {
-        let mut table = write_tx.open_table(self.blocks_table_defn())?;
-        for (ht, hash) in blocks {
-            match hash {
-                &Some(hash) => table.insert(*ht, hash.to_byte_array())?,
-                // remove the block if hash is None
-                // assuming it is guaranteed that (ht, None) => there is an entry of form (ht,_) in
-                // the Table.
-                None => table.remove(*ht)?,
-            };
+        persist: impl FnOnce() -> Result<PersistSummary, StoreError>,
+    ) -> Result<PersistSummary, StoreError> {
+        let summary = persist()?;
+        if let Some(on_persist) = &self.on_persist {
+            // resets the flag even if the callback panics
+            struct Reset;
+            impl Drop for Reset {
+                fn drop(&mut self) {
+                    IN_ON_PERSIST.with(|in_on_persist| in_on_persist.set(false));
+                }
+            }
+            if !IN_ON_PERSIST.with(|in_on_persist| in_on_persist.replace(true)) {
+                let _reset = Reset;
+                (on_persist.0)(&summary);
+            }
         }
-        Ok(())
+        Ok(summary)
     }
 
-    // This function persists txs corresponding to a tx_graph.
-    fn persist_txs(
-        &self,
-        write_tx: &WriteTransaction,
-        txs: &BTreeSet<Arc<Transaction>>,
-    ) -> Result<(), StoreError> {
-        let mut table = write_tx.open_table(self.txs_table_defn())?;
-        for tx in txs {
-            let mut vec: Vec<u8> = Vec::new();
-            ciborium::into_writer(tx, &mut vec)?;
-            table.insert(tx.compute_txid().to_byte_array(), vec)?;
-        }
-        Ok(())
+    /// This function registers a callback invoked with the [`PersistSummary`] of every successful
+    /// call to a persist function returning one ([`persist_tx_graph`], [`persist_indexer`],
+    /// [`persist_local_chain`], [`persist_block_range`] and, with the `wallet` feature,
+    /// `persist_wallet` and `persist_wallet_batched`). A single callback is kept: registering one
+    /// replaces the previous.
+    ///
+    /// The callback runs after the data was committed and is never invoked when the persist
+    /// fails. No lock on the database is held while it runs, so it may persist through the same
+    /// [`Store`], but persists made from within a callback (on the same thread) do not invoke the
+    /// callback again.
+    ///
+    /// [`persist_tx_graph`]: Self::persist_tx_graph
+    /// [`persist_indexer`]: Self::persist_indexer
+    /// [`persist_local_chain`]: Self::persist_local_chain
+    /// [`persist_block_range`]: Self::persist_block_range
+    pub fn set_on_persist(&mut self, callback: Box<dyn Fn(&PersistSummary) + Send + Sync>) {
+        self.on_persist = Some(OnPersist(callback.into()));
     }
 
-    // This function persists txouts corresponding to a tx_graph.
-    fn persist_txouts(
-        &self,
-        write_tx: &WriteTransaction,
-        txouts: &BTreeMap<OutPoint, TxOut>,
-    ) -> Result<(), StoreError> {
-        let mut table = write_tx.open_table(self.txouts_table_defn())?;
-        for (outpoint, txout) in txouts {
-            table.insert(
-                (outpoint.txid.to_byte_array(), outpoint.vout),
-                (
-                    txout.value.to_sat(),
-                    txout.script_pubkey.clone().into_bytes(),
-                ),
-            )?;
-        }
-        Ok(())
+    /// This function sets the clock returning the unix timestamps (in seconds) written by the
+    /// store: the time of the last persist (see [`last_persist_at`]), the timestamps of the
+    /// changelog and the birthday set when a wallet is created. The system clock is used by
+    /// default, this allows tests to be deterministic. On `wasm32-unknown-unknown`, which has no
+    /// system clock, the default clock always returns 0.
+    ///
+    /// [`last_persist_at`]: Self::last_persist_at
+    pub fn with_clock(mut self, clock: Box<dyn Fn() -> u64 + Send + Sync>) -> Self {
+        self.clock = Some(Clock(clock.into()));
+        self
     }
 
-    // This function persists anchors corresponding to a tx_graph.
-    fn persist_anchors<A: AnchorWithMetaData>(
-        &self,
-        write_tx: &WriteTransaction,
-        read_tx: &ReadTransaction,
-        anchors: &BTreeSet<(A, Txid)>,
-        txs: &BTreeSet<Arc<Transaction>>,
-    ) -> Result<(), StoreError> {
-        let mut table = write_tx.open_table(self.anchors_table_defn::<A>())?;
-        let txs_table = read_tx.open_table(self.txs_table_defn())?;
-        for (anchor, txid) in anchors {
-            // if the corresponding txn exists in Txs table (trying to imitate the
-            // referential behavior in case of sqlite)
-            let found = txs.iter().any(|tx| tx.compute_txid() == *txid);
-            if txs_table.get(txid.to_byte_array())?.is_some() || found {
-                let mut bytes: [u8; 36] = [0; 36];
-                let anchor_block = anchor.anchor_block();
-                bytes[0..4].copy_from_slice(&anchor_block.height.to_le_bytes());
-                bytes[4..].copy_from_slice(&anchor_block.hash.to_byte_array());
-                table.insert((txid.to_byte_array(), bytes), &anchor.metadata())?;
-            } else {
-                return Err(StoreError::TxMissing(*txid));
-            }
+    // This function returns the current unix timestamp according to the clock of the store.
+    pub(crate) fn now(&self) -> u64 {
+        match &self.clock {
+            Some(clock) => (clock.0)(),
+            // SystemTime::now panics on wasm32-unknown-unknown
+            #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+            None => 0,
+            #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+            None => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs()),
         }
+    }
+
+    // This function begins a write transaction that will be committed with the given durability.
+    fn begin_write(&self, durability: Durability) -> Result<WriteTransaction, StoreError> {
+        let mut write_tx = self.db.begin_write()?;
+        write_tx.set_durability(durability);
+        Ok(write_tx)
+    }
+
+    // This function commits the write transaction, recording how long it took when the `metrics`
+    // feature is enabled.
+    fn commit(&self, write_tx: WriteTransaction) -> Result<(), StoreError> {
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+        write_tx.commit()?;
+        #[cfg(feature = "metrics")]
+        telemetry::record_commit(&self.wallet_name, start.elapsed());
         Ok(())
     }
 
-    // This function persists last_seen flags corresponding to a tx_graph.
-    fn persist_last_seen(
-        &self,
-        write_tx: &WriteTransaction,
-        read_tx: &ReadTransaction,
-        last_seen: &BTreeMap<Txid, u64>,
-        txs: &BTreeSet<Arc<Transaction>>,
-    ) -> Result<(), StoreError> {
-        let mut table = write_tx.open_table(self.last_seen_defn())?;
-        let txs_table = read_tx.open_table(self.txs_table_defn())?;
-        for (txid, last_seen_time) in last_seen {
-            // if the corresponding txn exists in Txs table (trying to duplicate the
-            // referential behavior in case of sqlite)
-            let found = txs.iter().any(|tx| tx.compute_txid() == *txid);
-            if txs_table.get(txid.to_byte_array())?.is_some() || found {
-                table.insert(txid.to_byte_array(), *last_seen_time)?;
-            } else {
-                return Err(StoreError::TxMissing(*txid));
-            }
-        }
+    #[cfg(feature = "compression")]
+    /// This function enables compressing the transactions of the txs table with zstd at the given
+    /// level, or disables it with `None`. It is disabled by default.
+    ///
+    /// Transactions are compressed before being encrypted (see [`with_encryption_key`]), and are
+    /// stored compressed only if that makes them smaller. Compressed values start with a tag byte,
+    /// so both kinds are read whatever this setting and it can be toggled for existing wallets.
+    /// Compressed transactions are no longer raw consensus encodings with
+    /// [`TxCodec::Consensus`]. Without the `compression` feature, reading a compressed value
+    /// returns [`StoreError::CompressionDisabled`].
+    ///
+    /// [`with_encryption_key`]: Self::with_encryption_key
+    pub fn with_compression_level(mut self, level: Option<i32>) -> Self {
+        self.compression_level = level;
+        self
+    }
+
+    #[cfg(feature = "compression")]
+    /// This function enables or disables compressing the scripts of the spk cache too, when a
+    /// compression level is set (see [`with_compression_level`]). It is disabled by default, as
+    /// scripts are short and seldom shrink.
+    ///
+    /// [`with_compression_level`]: Self::with_compression_level
+    pub fn with_spk_compression(mut self, enabled: bool) -> Self {
+        self.compress_spks = enabled;
+        self
+    }
+
+    #[cfg(feature = "encryption")]
+    /// This function sets the [`EncryptionKey`] used to encrypt the contents of the wallet at
+    /// rest: the descriptors of the keychains table, the transactions of the txs table, the
+    /// scripts of the txouts and spk tables and the changesets of the changelog. Values persisted
+    /// with a key can only be read back with the same key, other keys failing with
+    /// [`StoreError::Decryption`].
+    ///
+    /// Table keys (txids, heights, outpoints, descriptor ids and indices) are stored in plaintext
+    /// so that lookups keep working, as are block hashes, anchors, timestamps, amounts and last
+    /// revealed indices. The key is never persisted. It must be set from the creation of the
+    /// wallet: values persisted without a key are not readable with one.
+    ///
+    /// Values are encrypted with AES-256-GCM, see [`encryption`]. The key can be given as its 32
+    /// bytes.
+    ///
+    /// [`encryption`]: crate::encryption
+    pub fn with_encryption_key(mut self, key: impl Into<EncryptionKey>) -> Self {
+        self.encryption_key = Some(key.into());
+        self
+    }
+
+    /// This function returns the [`Database`] backing this [`Store`], so that applications can keep
+    /// their own tables in the same file.
+    ///
+    /// The wallet tables are named `<wallet_name>_<table>` (for instance `wallet1_txs`), or
+    /// `<prefix>:<wallet_name>_<table>` for stores created with [`new_with_prefix`], and the
+    /// `network` table is shared by all wallets. Custom tables must not use these names.
+    ///
+    /// [`new_with_prefix`]: Self::new_with_prefix
+    /// [`Database`]: <https://docs.rs/redb/2.5.0/redb/struct.Database.html>
+    pub fn database(&self) -> Arc<Database> {
+        self.db.clone()
+    }
+
+    /// This function compacts the database file, reclaiming the space freed by deleted entries. It
+    /// returns `true` if any compaction was performed.
+    ///
+    /// Compaction needs exclusive access to the [`Database`], so this [`Store`] must hold the only
+    /// strong reference to it: if any other clone of the `Arc` (for instance another [`Store`] on
+    /// the same file) is alive, [`StoreError::DatabaseInUse`] is returned instead of blocking.
+    /// Open read transactions also make compaction fail.
+    ///
+    /// [`Database`]: <https://docs.rs/redb/2.5.0/redb/struct.Database.html>
+    pub fn compact(&mut self) -> Result<bool, StoreError> {
+        compact_db(&mut self.db)
+    }
+
+    /// This function checks the integrity of the database file, repairing it if needed. It returns
+    /// `true` if the database was consistent and `false` if repairs were made.
+    ///
+    /// Like [`compact`], this needs exclusive access to the [`Database`]: if any other clone of the
+    /// `Arc` is alive, [`StoreError::DatabaseInUse`] is returned. Errors found while checking are
+    /// surfaced as [`StoreError::RedbDatabase`].
+    ///
+    /// [`compact`]: Self::compact
+    /// [`Database`]: <https://docs.rs/redb/2.5.0/redb/struct.Database.html>
+    pub fn check_integrity(&mut self) -> Result<bool, StoreError> {
+        Ok(exclusive_db(&mut self.db)?.check_integrity()?)
+    }
+
+    /// This function makes every commit made so far durable, including the ones made with a
+    /// weaker durability by [`persist_wallet_batched`]. An empty write transaction is committed
+    /// with `Durability::Immediate`, so the disk is waited for once, and any error the file system
+    /// reports while doing so is returned.
+    ///
+    /// [`persist_wallet_batched`]: Self::persist_wallet_batched
+    pub fn sync(&self) -> Result<(), StoreError> {
+        self.commit(self.begin_write(Durability::Immediate)?)
+    }
+
+    /// This function closes the [`Store`], making every commit made so far durable.
+    ///
+    /// The commits made with a weaker durability (for instance by [`persist_wallet_batched`]) are
+    /// first flushed with [`sync`], which returns any error the file system reports while doing
+    /// so. This store's handle to the [`Database`] is then dropped.
+    ///
+    /// The [`Database`] is shared through an `Arc`, so it is only closed once its last strong
+    /// reference is dropped: if this store held the last one, the file is closed before this
+    /// function returns. Otherwise it stays open until the other handles (clones returned by
+    /// [`database`] or other [`Store`]s on the same file) are dropped, and only the flush is
+    /// guaranteed. redb does not report errors happening while the file is closed; these only
+    /// cause a repair when the file is next opened and never lose committed data.
+    ///
+    /// [`persist_wallet_batched`]: Self::persist_wallet_batched
+    /// [`sync`]: Self::sync
+    /// [`database`]: Self::database
+    /// [`Database`]: <https://docs.rs/redb/2.5.0/redb/struct.Database.html>
+    pub fn close(self) -> Result<(), StoreError> {
+        self.sync()?;
+        drop(self.db);
         Ok(())
     }
 
-    // This function persists last_evicted flags corresponding to a tx_graph .
-    fn persist_last_evicted(
-        &self,
-        write_tx: &WriteTransaction,
-        read_tx: &ReadTransaction,
-        last_evicted: &BTreeMap<Txid, u64>,
-        txs: &BTreeSet<Arc<Transaction>>,
-    ) -> Result<(), StoreError> {
-        let mut table = write_tx.open_table(self.last_evicted_table_defn())?;
-        let txs_table = read_tx.open_table(self.txs_table_defn())?;
-        for (txid, last_evicted_time) in last_evicted {
-            // if the corresponding txn exists in Txs table (trying to duplicate the
-            // referential behavior in case of sqlite)
-            let found = txs.iter().any(|tx| tx.compute_txid() == *txid);
-            if txs_table.get(txid.to_byte_array())?.is_some() || found {
-                table.insert(txid.to_byte_array(), last_evicted_time)?;
-            } else {
-                return Err(StoreError::TxMissing(*txid));
+    /// This function deletes the wallet from the database: every table of the wallet (including
+    /// its changelog and metadata) and its rows of the tables shared by all wallets (network,
+    /// format version and changelog sequence) are removed in a single write transaction. It
+    /// returns `true` if anything was deleted, i.e. `false` if the wallet was not persisted.
+    ///
+    /// Other wallets of the database are left untouched. The freed space is only reclaimed by
+    /// [`compact`]. The store can still be used afterwards, the tables being created again by
+    /// [`create_tables`] or the next persist.
+    ///
+    /// [`compact`]: Self::compact
+    /// [`create_tables`]: Self::create_tables
+    pub fn delete_wallet(&self) -> Result<bool, StoreError> {
+        let write_tx = self.begin_write(Durability::Immediate)?;
+        let mut deleted = false;
+        deleted |= write_tx.delete_table(self.keychains_table_defn())?;
+        deleted |= write_tx.delete_table(self.blocks_table_defn())?;
+        deleted |= write_tx.delete_table(self.txs_table_defn())?;
+        deleted |= write_tx.delete_table(self.txouts_table_defn())?;
+        // wallets which were not migrated yet have a legacy anchors table, which is not a multimap
+        deleted |= match write_tx.delete_multimap_table(self.anchors_table_defn()) {
+            Err(TableError::TableIsNotMultimap(_)) => {
+                write_tx.delete_table(self.legacy_anchors_table_defn())?
             }
-        }
-        Ok(())
+            result => result?,
+        };
+        deleted |= write_tx.delete_table(self.last_seen_defn())?;
+        deleted |= write_tx.delete_table(self.last_evicted_table_defn())?;
+        deleted |= write_tx.delete_table(self.first_seen_table_defn())?;
+        deleted |= write_tx.delete_table(self.last_revealed_table_defn())?;
+        deleted |= write_tx.delete_table(self.spk_table_defn())?;
+        deleted |= write_tx.delete_table(self.changelog_table_defn())?;
+        deleted |= write_tx.delete_table(self.meta_table_defn())?;
+
+        deleted |= remove_wallet_row(&write_tx, NETWORK, &self.wallet_name)?;
+        deleted |= remove_wallet_row(&write_tx, NETWORK_BYTE, &self.wallet_name)?;
+        deleted |= remove_wallet_row(&write_tx, FORMAT_VERSION, &self.wallet_name)?;
+        deleted |= remove_wallet_row(&write_tx, CHANGELOG_SEQUENCE, &self.wallet_name)?;
+        self.commit(write_tx)?;
+        Ok(deleted)
     }
 
-    // This function persists first_seen flags corresponding to a tx_graph .
-    fn persist_first_seen(
-        &self,
-        write_tx: &WriteTransaction,
-        read_tx: &ReadTransaction,
-        first_seen: &BTreeMap<Txid, u64>,
-        txs: &BTreeSet<Arc<Transaction>>,
-    ) -> Result<(), StoreError> {
-        let mut table = write_tx.open_table(self.first_seen_table_defn())?;
-        let txs_table = read_tx.open_table(self.txs_table_defn())?;
-        for (txid, first_seen_time) in first_seen {
-            // if the corresponding txn exists in Txs table (trying to duplicate the
-            // referential behavior in case of sqlite)
-            let found = txs.iter().any(|tx| tx.compute_txid() == *txid);
-            if txs_table.get(txid.to_byte_array())?.is_some() || found {
-                table.insert(txid.to_byte_array(), first_seen_time)?;
-            } else {
-                return Err(StoreError::TxMissing(*txid));
+    /// This function removes the transactions whose every anchor is below `height` and whose every
+    /// output is spent by another persisted transaction, along with their txouts, anchors and
+    /// last_seen, first_seen and last_evicted entries. It returns the number of transactions
+    /// removed.
+    ///
+    /// Anchors of all types are taken into account, so a transaction with any anchor at or above
+    /// `height` is kept, as is any transaction without anchors. A transaction with an output not
+    /// spent by a persisted transaction is kept whoever owns the output, so that the balance of the
+    /// wallet never changes; sent transactions whose recipients' outputs are not persisted as
+    /// spent are therefore kept too. All rows are removed in a single write transaction.
+    ///
+    /// <div class="warning">The pruned transactions are lost: a wallet loaded afterwards will not
+    /// know about them, e.g. in its transaction history.</div>
+    pub fn prune_confirmed_below(&self, height: u32) -> Result<usize, StoreError> {
+        let write_tx = self.begin_write(Durability::Immediate)?;
+        // begun after write_tx so that it sees the latest committed state
+        let read_tx = self.db.begin_read()?;
+        let mut txs = BTreeSet::new();
+        if open_table_if_exists(&read_tx, self.txs_table_defn())?.is_some() {
+            self.read_txs(&read_tx, &mut txs)?;
+        }
+        drop(read_tx);
+        let spent: BTreeSet<OutPoint> = txs
+            .iter()
+            .flat_map(|tx| tx.input.iter().map(|txin| txin.previous_output))
+            .collect();
+        // the transactions with an output which is not spent by a persisted transaction
+        let unspent: BTreeSet<[u8; 32]> = txs
+            .iter()
+            .filter_map(|tx| {
+                let txid = tx.compute_txid();
+                (0..tx.output.len() as u32)
+                    .any(|vout| !spent.contains(&OutPoint::new(txid, vout)))
+                    .then_some(txid.to_byte_array())
+            })
+            .collect();
+
+        let mut pruned = 0;
+        {
+            let mut anchors_table = write_tx.open_multimap_table(self.anchors_table_defn())?;
+
+            let mut below = BTreeSet::new();
+            let mut keep = BTreeSet::new();
+            for entry in anchors_table.iter()? {
+                let (txid, values) = entry?;
+                let txid = txid.value();
+                for value in values {
+                    let (block_id_bytes, _, _) = value?.value();
+                    if block_id_from_bytes(&block_id_bytes)?.height < height
+                        && !unspent.contains(&txid)
+                    {
+                        below.insert(txid);
+                    } else {
+                        keep.insert(txid);
+                    }
+                }
+            }
+
+            let mut txs_table = write_tx.open_table(self.txs_table_defn())?;
+            let mut txouts_table = write_tx.open_table(self.txouts_table_defn())?;
+            let mut last_seen_table = write_tx.open_table(self.last_seen_defn())?;
+            let mut first_seen_table = write_tx.open_table(self.first_seen_table_defn())?;
+            let mut last_evicted_table = write_tx.open_table(self.last_evicted_table_defn())?;
+            for txid in below.difference(&keep) {
+                if txs_table.remove(txid)?.is_some() {
+                    pruned += 1;
+                }
+                txouts_table.retain_in((*txid, 0)..=(*txid, u32::MAX), |_, _| false)?;
+                anchors_table.remove_all(txid)?;
+                last_seen_table.remove(txid)?;
+                first_seen_table.remove(txid)?;
+                last_evicted_table.remove(txid)?;
             }
         }
-        Ok(())
+        self.commit(write_tx)?;
+        Ok(pruned)
     }
 
-    // This function persists last_revealed corresponding to keychain_txout .
-    fn persist_last_revealed(
+    /// This function removes the blocks below `height` from the local chain, except the genesis
+    /// block, the blocks whose height is a multiple of `keep_every` (if given and not 0) which are
+    /// kept as checkpoints, and the blocks anchoring a transaction. It runs in a single write
+    /// transaction and returns the number of blocks removed.
+    ///
+    /// Blocks anchoring transactions (with anchors of any type) are kept because BDK only considers
+    /// a transaction confirmed if the block of its anchor is in the local chain.
+    pub fn prune_blocks_below(
         &self,
-        write_tx: &WriteTransaction,
-        last_revealed: &BTreeMap<DescriptorId, u32>,
-    ) -> Result<(), StoreError> {
-        let mut table = write_tx.open_table(self.last_revealed_table_defn())?;
-        for (&desc, &idx) in last_revealed {
-            table.insert(desc.to_byte_array(), idx)?;
+        height: u32,
+        keep_every: Option<u32>,
+    ) -> Result<u64, StoreError> {
+        let write_tx = self.begin_write(Durability::Immediate)?;
+        let mut anchored = BTreeSet::new();
+        if let Some(anchors_table) = match write_tx.open_multimap_table(self.anchors_table_defn()) {
+            Ok(table) => Some(table),
+            Err(TableError::TableDoesNotExist(_)) => None,
+            Err(err) => return Err(err.into()),
+        } {
+            for entry in anchors_table.iter()? {
+                for value in entry?.1 {
+                    let (block_id_bytes, _, _) = value?.value();
+                    anchored.insert(block_id_from_bytes(&block_id_bytes)?.height);
+                }
+            }
         }
-        Ok(())
-    }
 
-    // This function persists spk_cache corresponding to keychain_txout .
-    fn persist_spks(
-        &self,
-        write_tx: &WriteTransaction,
-        spk_cache: &BTreeMap<DescriptorId, BTreeMap<u32, ScriptBuf>>,
-    ) -> Result<(), StoreError> {
-        let mut table = write_tx.open_table(self.spk_table_defn())?;
-        for (&desc, map) in spk_cache {
-            map.iter().try_for_each(|entry| {
-                table
-                    .insert((desc.to_byte_array(), *entry.0), entry.1.to_bytes())
-                    .map(|_| ())
+        let keep_every = keep_every.filter(|&n| n != 0);
+        let mut removed = 0;
+        {
+            let mut table = write_tx.open_table(self.blocks_table_defn())?;
+            table.retain_in(1..height, |ht, _| {
+                let keep = anchored.contains(&ht) || keep_every.is_some_and(|n| ht % n == 0);
+                if !keep {
+                    removed += 1;
+                }
+                keep
             })?;
         }
-        Ok(())
+        #[cfg(feature = "metrics")]
+        telemetry::record_persist(
+            &self.wallet_name,
+            "blocks",
+            TableChanges {
+                inserted: 0,
+                removed,
+                skipped: 0,
+            },
+        );
+        self.commit(write_tx)?;
+        Ok(removed)
     }
 
-    #[cfg(feature = "wallet")]
-    /// This function loads the [`Wallet`]  by calling corresponding load functions for each of its
-    /// fields.s
+    /// This function removes the transaction with the given txid along with every row referring
+    /// to it: its anchors (of all types), its last_seen, first_seen and last_evicted entries and
+    /// the txouts whose outpoint belongs to it. All rows are removed in a single write transaction.
+    /// It returns `true` if any row was removed.
     ///
-    /// [`Wallet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.Wallet.html>
-    pub fn read_wallet(&self, changeset: &mut ChangeSet) -> Result<(), StoreError> {
-        self.read_network(&mut changeset.network)?;
-        let mut desc_changeset: BTreeMap<u64, Descriptor<DescriptorPublicKey>> = BTreeMap::new();
-        self.read_keychains(&mut desc_changeset)?;
-        if let Some(desc) = desc_changeset.get(&0) {
-            changeset.descriptor = Some(desc.clone());
-            if let Some(change_desc) = desc_changeset.get(&1) {
-                changeset.change_descriptor = Some(change_desc.clone());
+    /// Transactions spending the outputs of the evicted one are kept.
+    pub fn evict_tx(&self, txid: Txid) -> Result<bool, StoreError> {
+        let write_tx = self.begin_write(Durability::Immediate)?;
+        let txid = txid.to_byte_array();
+        let mut removed = false;
+        {
+            removed |= write_tx
+                .open_table(self.txs_table_defn())?
+                .remove(txid)?
+                .is_some();
+            let mut txouts_table = write_tx.open_table(self.txouts_table_defn())?;
+            txouts_table.retain_in((txid, 0)..=(txid, u32::MAX), |_, _| {
+                removed = true;
+                false
+            })?;
+            removed |= !write_tx
+                .open_multimap_table(self.anchors_table_defn())?
+                .remove_all(txid)?
+                .is_empty();
+            for table_defn in [
+                self.last_seen_defn(),
+                self.first_seen_table_defn(),
+                self.last_evicted_table_defn(),
+            ] {
+                removed |= write_tx.open_table(table_defn)?.remove(txid)?.is_some();
             }
         }
-        self.read_local_chain(&mut changeset.local_chain)?;
-        self.read_tx_graph::<ConfirmationBlockTime>(&mut changeset.tx_graph)?;
-        self.read_indexer(&mut changeset.indexer)?;
-
-        Ok(())
+        self.commit(write_tx)?;
+        Ok(removed)
     }
 
-    /// This function loads the [`TxGraph`] from db. It loads each field
-    /// by calling corresponding load functions.
+    /// This function removes the cached script pubkeys of the descriptor with the given id, or of
+    /// all descriptors if `descriptor_id` is `None`, in a single write transaction. It returns the
+    /// number of rows removed.
     ///
-    /// [`TxGraph`]: <http://docs.rs/bdk_chain/0.23.0/bdk_chain/tx_graph/struct.TxGraph.html>
-    pub fn read_tx_graph<A: AnchorWithMetaData>(
-        &self,
-        changeset: &mut tx_graph::ChangeSet<A>,
-    ) -> Result<(), StoreError> {
-        let read_tx = self.db.begin_read()?;
-        self.read_txs(&read_tx, &mut changeset.txs)?;
-        self.read_txouts(&read_tx, &mut changeset.txouts)?;
-        self.read_anchors::<A>(&read_tx, &mut changeset.anchors)?;
-        self.read_last_seen(&read_tx, &mut changeset.last_seen)?;
-        self.read_last_evicted(&read_tx, &mut changeset.last_evicted)?;
-        self.read_first_seen(&read_tx, &mut changeset.first_seen)?;
-        Ok(())
+    /// The last revealed indices are kept, so a wallet loaded afterwards derives the scripts it
+    /// needs again. Use [`compact`] to give the freed space back to the file system.
+    ///
+    /// [`compact`]: Self::compact
+    pub fn clear_spk_cache(&self, descriptor_id: Option<DescriptorId>) -> Result<u64, StoreError> {
+        let write_tx = self.begin_write(Durability::Immediate)?;
+        let mut removed = 0;
+        {
+            let mut table = write_tx.open_table(self.spk_table_defn())?;
+            let remove = |_, _| {
+                removed += 1;
+                false
+            };
+            match descriptor_id {
+                Some(descriptor_id) => {
+                    let id = descriptor_id.to_byte_array();
+                    table.retain_in((id, 0)..=(id, u32::MAX), remove)?;
+                }
+                None => table.retain(remove)?,
+            }
+        }
+        #[cfg(feature = "metrics")]
+        telemetry::record_persist(
+            &self.wallet_name,
+            "spks",
+            TableChanges {
+                inserted: 0,
+                removed,
+                skipped: 0,
+            },
+        );
+        self.commit(write_tx)?;
+        Ok(removed)
     }
 
-    /// This function loads the [`indexer`] structures from our db. It loads each
-    /// field by calling corresponding load functions.
+    /// This function removes the cached script pubkeys of the descriptor with the given id whose
+    /// index is greater than its last revealed index plus `keep_past_revealed`, in a single write
+    /// transaction. It returns the number of rows removed.
     ///
-    /// [`indexer`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/indexer/index.html>
-    pub fn read_indexer(
+    /// This bounds the size of a cache filled with a large lookahead (for instance during a full
+    /// scan) while keeping the scripts a wallet is about to use. Nothing is removed if no last
+    /// revealed index is persisted for the descriptor.
+    pub fn prune_spk_cache(
         &self,
-        changeset: &mut keychain_txout::ChangeSet,
-    ) -> Result<(), StoreError> {
-        let read_tx = self.db.begin_read()?;
-        self.read_last_revealed(&read_tx, &mut changeset.last_revealed)?;
-        self.read_spks(&read_tx, &mut changeset.spk_cache)?;
-        Ok(())
+        descriptor_id: DescriptorId,
+        keep_past_revealed: u32,
+    ) -> Result<u64, StoreError> {
+        let write_tx = self.begin_write(Durability::Immediate)?;
+        let id = descriptor_id.to_byte_array();
+        let last_revealed = write_tx
+            .open_table(self.last_revealed_table_defn())?
+            .get(id)?
+            .map(|index| index.value());
+        let mut removed = 0;
+        if let Some(first_pruned) =
+            last_revealed.and_then(|index| index.checked_add(keep_past_revealed)?.checked_add(1))
+        {
+            let mut table = write_tx.open_table(self.spk_table_defn())?;
+            table.retain_in((id, first_pruned)..=(id, u32::MAX), |_, _| {
+                removed += 1;
+                false
+            })?;
+        }
+        #[cfg(feature = "metrics")]
+        telemetry::record_persist(
+            &self.wallet_name,
+            "spks",
+            TableChanges {
+                inserted: 0,
+                removed,
+                skipped: 0,
+            },
+        );
+        self.commit(write_tx)?;
+        Ok(removed)
     }
 
-    /// This function loads descriptors from db.
-    pub fn read_keychains(
-        &self,
-        desc_changeset: &mut BTreeMap<u64, Descriptor<DescriptorPublicKey>>,
-    ) -> Result<(), StoreError> {
-        let read_tx = self.db.begin_read()?;
-        let table = read_tx.open_table(self.keychains_table_defn())?;
-
-        for entry in table.iter()? {
-            let (label, keychain) = entry?;
-            desc_changeset.insert(
-                label.value(),
-                Descriptor::<DescriptorPublicKey>::from_str(keychain.value().as_str())
-                    .expect("should be valid descriptors"),
-            );
+    /// This function removes the cached script pubkeys whose index is greater than the last
+    /// revealed index of their descriptor, for every descriptor, in a single write transaction. It
+    /// returns the number of rows removed.
+    ///
+    /// This is [`prune_spk_cache`] with no window applied to all descriptors at once. The scripts
+    /// of descriptors without a persisted last revealed index are kept.
+    ///
+    /// [`prune_spk_cache`]: Self::prune_spk_cache
+    pub fn vacuum_spk_cache(&self) -> Result<usize, StoreError> {
+        let write_tx = self.begin_write(Durability::Immediate)?;
+        let mut removed = 0;
+        {
+            let last_revealed_table = write_tx.open_table(self.last_revealed_table_defn())?;
+            let mut table = write_tx.open_table(self.spk_table_defn())?;
+            for entry in last_revealed_table.iter()? {
+                let (id, index) = entry?;
+                let id = id.value();
+                if let Some(first_pruned) = index.value().checked_add(1) {
+                    table.retain_in((id, first_pruned)..=(id, u32::MAX), |_, _| {
+                        removed += 1;
+                        false
+                    })?;
+                }
+            }
         }
-
-        Ok(())
+        #[cfg(feature = "metrics")]
+        telemetry::record_persist(
+            &self.wallet_name,
+            "spks",
+            TableChanges {
+                inserted: 0,
+                removed: removed as u64,
+                skipped: 0,
+            },
+        );
+        self.commit(write_tx)?;
+        Ok(removed)
     }
 
-    /// This function loads the [`Network`] from our db.
-    /// <div class="warning">Warning: Do Not use with MAINNET</div>
+    /// This function rewrites the persisted anchors of type `A` in the compact metadata encoding
+    /// (see [`with_compact_anchor_metadata`]), in a single write transaction. It returns the number
+    /// of anchors rewritten, i.e. those whose metadata had trailing zero bytes.
     ///
-    /// [`Network`]: <https://docs.rs/bitcoin/latest/bitcoin/enum.Network.html>
-    pub fn read_network(&self, network: &mut Option<bitcoin::Network>) -> Result<(), StoreError> {
-        let read_tx = self.db.begin_read()?;
-        let table = read_tx.open_table(NETWORK)?;
-        *network = table
-            .get(&*self.wallet_name)?
-            .map(|network| Network::from_str(&network.value()).expect("should be valid network"));
-        Ok(())
+    /// It is the migration path for wallets persisted before the compact encoding was enabled, and
+    /// can be run whatever that setting. Nothing is rewritten if the metadata of `A` does not have
+    /// a fixed width.
+    ///
+    /// [`with_compact_anchor_metadata`]: Self::with_compact_anchor_metadata
+    pub fn compact_anchors<A: AnchorWithMetaData>(&self) -> Result<usize, StoreError> {
+        self.check_anchor_type::<A>()?;
+        let write_tx = self.begin_write(Durability::Immediate)?;
+        let mut rewritten = 0;
+        if A::MetaDataType::fixed_width().is_some() {
+            let mut table = write_tx.open_multimap_table(self.anchors_table_defn())?;
+            let mut anchors = Vec::new();
+            for entry in table.iter()? {
+                let (txid, values) = entry?;
+                for value in values {
+                    let value = value?;
+                    let (block_id_bytes, tag, metadata) = value.value();
+                    if tag == A::TAG && metadata.last() == Some(&0) {
+                        anchors.push((txid.value(), block_id_bytes, metadata.to_vec()));
+                    }
+                }
+            }
+            for (txid, block_id_bytes, metadata) in anchors {
+                let anchor: A =
+                    anchor_from_bytes(block_id_from_bytes(&block_id_bytes)?, &metadata)?;
+                table.remove(txid, (block_id_bytes, A::TAG, metadata.as_slice()))?;
+                table.insert(
+                    txid,
+                    (
+                        block_id_bytes,
+                        A::TAG,
+                        anchor_metadata_to_bytes(&anchor, true).as_slice(),
+                    ),
+                )?;
+                rewritten += 1;
+            }
+        }
+        self.commit(write_tx)?;
+        Ok(rewritten)
     }
 
-    /// This function loads the [`LocalChain`] structure from our db. It loads each
-    /// field by calling corresponding load functions.
+    /// This function creates or opens (if already created) all redb tables corresponding to a
+    /// [`Wallet`].
     ///
-    /// [`LocalChain`]: <http://docs.rs/bdk_chain/0.23.0/bdk_chain/local_chain/struct.LocalChain.html>
-    pub fn read_local_chain(
-        &self,
-        changeset: &mut local_chain::ChangeSet,
-    ) -> Result<(), StoreError> {
-        let read_tx = self.db.begin_read()?;
-        self.read_blocks(&read_tx, &mut changeset.blocks)?;
-        Ok(())
-    }
+    /// If all the tables already exist in the current on-disk format (see [`attach`]), nothing is
+    /// written.
+    ///
+    /// [`Wallet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.Wallet.html>
+    /// [`attach`]: Self::attach
+    pub fn create_tables<A: AnchorWithMetaData>(&self) -> Result<(), StoreError> {
+        if self.is_initialized()? {
+            return Ok(());
+        }
+        let write_tx = self.db.begin_write()?;
 
-    // This function loads blocks corresponding to local_chain .
-    fn read_blocks(
-        &self,
-        read_tx: &ReadTransaction,
-        blocks: &mut BTreeMap<u32, Option<BlockHash>>,
-    ) -> Result<(), StoreError> {
-        let table = read_tx.open_table(self.blocks_table_defn())?;
+        let _ = write_tx.open_table(NETWORK)?;
+        self.migrate_network(&write_tx)?;
+        let _ = write_tx.open_table(self.keychains_table_defn())?;
+        self.commit(write_tx)?;
 
-        for entry in table.iter()? {
-            let (height, hash) = entry?;
-            blocks.insert(
-                height.value(),
-                Some(BlockHash::from_byte_array(hash.value())),
-            );
-        }
+        self.create_local_chain_tables()?;
+        self.create_tx_graph_tables::<A>()?;
+        self.create_indexer_tables()?;
 
         Ok(())
     }
 
-    // This function loads txs corresponding to tx_graph.
-    fn read_txs(
-        &self,
-        read_tx: &ReadTransaction,
-        txs: &mut BTreeSet<Arc<Transaction>>,
-    ) -> Result<(), StoreError> {
-        let table = read_tx.open_table(self.txs_table_defn())?;
-
-        for entry in table.iter()? {
-            let tx_vec = entry?.1.value();
-            let tx = ciborium::from_reader(tx_vec.as_slice())?;
-            txs.insert(Arc::new(tx));
-        }
+    /// This function creates or opens (if already created) the redb tables corresponding to
+    /// local_chain.
+    pub fn create_local_chain_tables(&self) -> Result<(), StoreError> {
+        let write_tx = self.db.begin_write()?;
+        let _ = write_tx.open_table(self.blocks_table_defn())?;
+        self.commit(write_tx)?;
         Ok(())
     }
 
-    // This function loads txouts corresponding to tx_graph.
-    fn read_txouts(
-        &self,
-        read_tx: &ReadTransaction,
-        txouts: &mut BTreeMap<OutPoint, TxOut>,
-    ) -> Result<(), StoreError> {
-        let table = read_tx.open_table(self.txouts_table_defn())?;
+    /// This function creates or opens (if already created) the redb tables corresponding to
+    /// [`TxGraph`].
+    ///
+    /// Tables written by an older version of this crate are migrated to the current on-disk
+    /// format, so this (or [`create_tables`]) should be called before reading from them.
+    ///
+    /// [`TxGraph`]: <http://docs.rs/bdk_chain/0.23.0/bdk_chain/tx_graph/struct.TxGraph.html>
+    /// [`create_tables`]: Self::create_tables
+    pub fn create_tx_graph_tables<A: AnchorWithMetaData>(&self) -> Result<(), StoreError> {
+        self.check_anchor_type::<A>()?;
+        let write_tx = self.db.begin_write()?;
+        // the anchors table of older versions must be migrated before the multimap is opened
+        self.migrate(&write_tx)?;
+        let _ = write_tx.open_table(self.txs_table_defn())?;
+        let _ = write_tx.open_table(self.txouts_table_defn())?;
+        let _ = write_tx.open_multimap_table(self.anchors_table_defn())?;
+        let _ = write_tx.open_table(self.last_seen_defn())?;
+        let _ = write_tx.open_table(self.last_evicted_table_defn())?;
+        let _ = write_tx.open_table(self.first_seen_table_defn())?;
+        self.record_anchor_type_in::<A>(&write_tx)?;
 
-        for entry in table.iter()? {
-            let (outpoint, txout) = entry?;
-            txouts.insert(
-                OutPoint {
-                    txid: Txid::from_byte_array(outpoint.value().0),
-                    vout: outpoint.value().1,
-                },
-                TxOut {
-                    value: Amount::from_sat(txout.value().0),
-                    script_pubkey: ScriptBuf::from_bytes(txout.value().1),
-                },
-            );
+        self.commit(write_tx)?;
+        Ok(())
+    }
+
+    // This function upgrades the wallet's tables to CURRENT_FORMAT_VERSION.
+    fn migrate(&self, write_tx: &WriteTransaction) -> Result<(), StoreError> {
+        let mut version_table = write_tx.open_table(FORMAT_VERSION)?;
+        let version = version_table
+            .get(&*self.wallet_name)?
+            .map_or(0, |version| version.value());
+        if version >= CURRENT_FORMAT_VERSION {
+            return Ok(());
+        }
+
+        let has_legacy_anchors = write_tx
+            .list_tables()?
+            .any(|table| table.name() == &*self.anchors_table_name);
+        if version < 2 && has_legacy_anchors {
+            let mut anchors = Vec::new();
+            for entry in write_tx
+                .open_table(self.legacy_anchors_table_defn())?
+                .iter()?
+            {
+                let (key, metadata) = entry?;
+                let (txid, mut block_id_bytes, tag) = key.value();
+                if version < 1 {
+                    // anchor heights were stored in little-endian, so anchors were not ordered by
+                    // height
+                    block_id_bytes[..HEIGHT_LEN].reverse();
+                }
+                anchors.push((txid, block_id_bytes, tag, metadata.value()));
+            }
+            write_tx.delete_table(self.legacy_anchors_table_defn())?;
+            let mut table = write_tx.open_multimap_table(self.anchors_table_defn())?;
+            for (txid, block_id_bytes, tag, metadata) in anchors {
+                table.insert(txid, (block_id_bytes, tag, metadata.as_slice()))?;
+            }
+        }
+        if version < 3 {
+            self.migrate_descriptor_checksums(write_tx)?;
         }
 
+        version_table.insert(&*self.wallet_name, CURRENT_FORMAT_VERSION)?;
         Ok(())
     }
 
-    // This function loads anchors corresponding to tx_graph.
-    fn read_anchors<A: AnchorWithMetaData>(
-        &self,
-        read_tx: &ReadTransaction,
-        anchors: &mut BTreeSet<(A, Txid)>,
-    ) -> Result<(), StoreError> {
-        let table = read_tx.open_table(self.anchors_table_defn::<A>())?;
-
+    // This function rewrites the descriptors stored without their checksum with it. Rows which
+    // cannot be decoded or parsed are left as is, reading them returns the error.
+    fn migrate_descriptor_checksums(&self, write_tx: &WriteTransaction) -> Result<(), StoreError> {
+        let has_keychains = write_tx
+            .list_tables()?
+            .any(|table| table.name() == &*self.keychain_table_name);
+        if !has_keychains {
+            return Ok(());
+        }
+        let mut table = write_tx.open_table(self.keychains_table_defn())?;
+        let mut unchecksummed = Vec::new();
         for entry in table.iter()? {
-            let (anchor, metadata) = entry?;
-            let (txid_bytes, block_id_bytes) = anchor.value();
-            let block_id = BlockId {
-                height: u32::from_le_bytes(
-                    block_id_bytes[0..4].try_into().expect("slice has length 4"),
-                ),
-                hash: BlockHash::from_slice(&block_id_bytes[4..])?,
+            let (label, stored) = entry?;
+            let label = label.value();
+            let Ok(stored) = self.decode_descriptor(label, stored.value()) else {
+                continue;
             };
-            anchors.insert((
-                A::from_id(block_id, metadata.value()),
-                Txid::from_byte_array(txid_bytes),
-            ));
+            if stored.contains('#') {
+                continue;
+            }
+            if let Ok(desc) = parse_stored_descriptor(label, &stored) {
+                unchecksummed.push((label, desc));
+            }
+        }
+        for (label, desc) in unchecksummed {
+            table.insert(label, self.encode_descriptor(label, &desc)?)?;
         }
+        Ok(())
+    }
+
+    /// This function creates or opens (if already created) the redb tables corresponding to
+    /// [`indexer`].
+    ///
+    /// [`indexer`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/indexer/index.html>
+    pub fn create_indexer_tables(&self) -> Result<(), StoreError> {
+        let write_tx = self.db.begin_write()?;
+        let _ = write_tx.open_table(self.spk_table_defn())?;
 
+        let _ = write_tx.open_table(self.last_revealed_table_defn())?;
+        self.commit(write_tx)?;
         Ok(())
     }
 
-    // This function loads last_seen flags corresponding to tx_graph.
-    fn read_last_seen(
-        &self,
-        read_tx: &ReadTransaction,
-        last_seen: &mut BTreeMap<Txid, u64>,
-    ) -> Result<(), StoreError> {
-        let table = read_tx.open_table(self.last_seen_defn())?;
+    /// This function creates or opens (if already created) the keychains redb table corresponding
+    /// to the wallet.
+    pub fn create_keychains_table(&self) -> Result<(), StoreError> {
+        let write_tx = self.db.begin_write()?;
+        let _ = write_tx.open_table(self.keychains_table_defn())?;
+        self.commit(write_tx)?;
+        Ok(())
+    }
 
-        for entry in table.iter()? {
-            let (txid, last_seen_num) = entry?;
-            last_seen.insert(Txid::from_byte_array(txid.value()), last_seen_num.value());
+    /// This function creates or opens (if already created) the [`Network`] redb table. This table
+    /// is common to all wallets persisted in the database file.
+    ///
+    /// [`Network`]: <https://docs.rs/bitcoin/latest/bitcoin/enum.Network.html>
+    ///
+    /// If the compact network format is enabled, a network stored as a string is migrated to it
+    /// (see [`with_compact_network`]).
+    ///
+    /// [`with_compact_network`]: Self::with_compact_network
+    pub fn create_network_table(&self) -> Result<(), StoreError> {
+        let write_tx = self.db.begin_write()?;
+        let _ = write_tx.open_table(NETWORK)?;
+        self.migrate_network(&write_tx)?;
+        self.commit(write_tx)?;
+        Ok(())
+    }
+
+    // This function moves the network of the wallet from the NETWORK table to the NETWORK_BYTE one
+    // if the compact format is enabled.
+    fn migrate_network(&self, write_tx: &WriteTransaction) -> Result<(), StoreError> {
+        if !self.compact_network {
+            return Ok(());
+        }
+        let mut table = write_tx.open_table(NETWORK)?;
+        if let Some(network) = table.remove(&*self.wallet_name)? {
+            let network = network_from_str(&network.value())?;
+            write_tx
+                .open_table(NETWORK_BYTE)?
+                .insert(&*self.wallet_name, network_to_byte(network))?;
         }
         Ok(())
     }
 
-    // This function loads last_evicted flags corresponding to tx_graph .
-    fn read_last_evicted(
+    #[cfg(feature = "wallet")]
+    /// This function persists the [`Wallet`] into our db. It persists each field by calling
+    /// corresponding persistence functions and returns a [`PersistSummary`] of the rows written.
+    ///
+    /// All the fields are written in a single write transaction. If any of them fails, the
+    /// transaction is dropped without being committed, so the error is returned with nothing
+    /// persisted. The other persist functions behave the same way.
+    ///
+    /// [`Wallet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.Wallet.html>
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(wallet = %self.wallet_name))
+    )]
+    pub fn persist_wallet(&self, changeset: &ChangeSet) -> Result<PersistSummary, StoreError> {
+        self.with_notify(|| {
+            let write_tx = self.begin_write(Durability::Immediate)?;
+            let summary = self.persist_wallet_in_tx(&write_tx, changeset)?;
+            self.commit(write_tx)?;
+            Ok(summary)
+        })
+    }
+
+    #[cfg(feature = "wallet")]
+    /// This function persists the [`Wallet`] like [`persist_wallet`] but in the caller's write
+    /// transaction, which it does not commit, so that the wallet can be persisted atomically with
+    /// the caller's own tables. It returns a [`PersistSummary`] of the rows written.
+    ///
+    /// Nothing is persisted until `write_tx` is committed, and aborting it discards the wallet
+    /// data along with the caller's writes. The [`set_on_persist`] callback is left to the caller.
+    /// References from anchors and timestamps to transactions are checked against the committed
+    /// state of the wallet and `changeset`, so transactions persisted earlier in `write_tx` are not
+    /// seen.
+    ///
+    /// [`Wallet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.Wallet.html>
+    /// [`persist_wallet`]: Self::persist_wallet
+    /// [`set_on_persist`]: Self::set_on_persist
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(wallet = %self.wallet_name))
+    )]
+    pub fn persist_wallet_in_tx(
         &self,
-        read_tx: &ReadTransaction,
-        last_evicted: &mut BTreeMap<Txid, u64>,
-    ) -> Result<(), StoreError> {
-        let table = read_tx.open_table(self.last_evicted_table_defn())?;
+        write_tx: &WriteTransaction,
+        changeset: &ChangeSet,
+    ) -> Result<PersistSummary, StoreError> {
+        let changelog_entry = self.changelog_entry(changeset)?;
+        let mut summary = PersistSummary {
+            network: self.persist_network_in(write_tx, &changeset.network)?,
+            ..Default::default()
+        };
+        summary.keychains = self.persist_wallet_keychains_in(write_tx, changeset)?;
+        summary.merge(self.persist_local_chain_in(write_tx, &changeset.local_chain)?);
+        summary.merge(self.persist_indexer_in(write_tx, &changeset.indexer)?);
+        summary.merge(self.persist_tx_graph_in::<ConfirmationBlockTime>(
+            write_tx,
+            &changeset.tx_graph,
+            changelog_entry.as_deref(),
+        )?);
+        Ok(summary)
+    }
 
-        for entry in table.iter()? {
-            let (txid, last_evicted_num) = entry?;
-            last_evicted.insert(
-                Txid::from_byte_array(txid.value()),
-                last_evicted_num.value(),
-            );
+    #[cfg(feature = "wallet")]
+    /// This function adds the wallet data persisted in `other` to this [`Store`], for instance to
+    /// combine two partial backups of the same wallet.
+    ///
+    /// Both wallets are read and `other`'s [`ChangeSet`] is merged into this store's one following
+    /// BDK's [`Merge`] semantics (blocks, txs and anchors are united, last revealed indices take
+    /// the maximum), then the result is persisted with [`persist_wallet`]. If both stores have a
+    /// network and they differ, [`StoreError::NetworkMismatch`] is returned and nothing is written.
+    /// The stores must hold the same wallet: if both have a descriptor (or a change descriptor) and
+    /// they differ, [`StoreError::DescriptorMismatch`] is returned and nothing is written. A
+    /// descriptor only persisted in `other` is added to this store.
+    ///
+    /// [`ChangeSet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.ChangeSet.html>
+    /// [`Merge`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/trait.Merge.html>
+    /// [`persist_wallet`]: Self::persist_wallet
+    pub fn merge_from(&self, other: &Store) -> Result<(), StoreError> {
+        let mut changeset = ChangeSet::default();
+        self.read_wallet(&mut changeset)?;
+        let mut other_changeset = ChangeSet::default();
+        other.read_wallet(&mut other_changeset)?;
+        if let (Some(ours), Some(theirs)) = (changeset.network, other_changeset.network) {
+            if ours != theirs {
+                return Err(StoreError::NetworkMismatch { ours, theirs });
+            }
+        }
+        // `ChangeSet::merge` assumes the descriptors never change, so they are compared here.
+        for (label, ours, theirs) in [
+            (0, &changeset.descriptor, &other_changeset.descriptor),
+            (
+                1,
+                &changeset.change_descriptor,
+                &other_changeset.change_descriptor,
+            ),
+        ] {
+            if let (Some(ours), Some(theirs)) = (ours, theirs) {
+                if ours != theirs {
+                    return Err(StoreError::DescriptorMismatch { label });
+                }
+            }
         }
+        changeset.merge(other_changeset);
+        self.persist_wallet(&changeset)?;
         Ok(())
     }
 
-    // This function loads first_seen flags corresponding to tx_graph.
-    fn read_first_seen(
+    #[cfg(feature = "wallet")]
+    /// This function persists the [`Wallet`] like [`persist_wallet`], in a single write
+    /// transaction, but commits it with `Durability::None` (see [`Durability`]): the data is
+    /// visible to readers of the database right away, but the disk is not waited for. It returns a
+    /// [`PersistSummary`] of the rows written.
+    ///
+    /// Persisting many changesets in a row (e.g. during a full scan) with this function and then
+    /// calling [`sync`] once waits for the disk a single time, instead of once per changeset. The
+    /// next commit made with `Durability::Immediate`, such as [`persist_wallet`] or [`close`],
+    /// also makes them durable. `Durability::Eventual` is not used since redb still syncs the file
+    /// on such commits on most platforms.
+    ///
+    /// <div class="warning">Crash consistency: each changeset is still committed atomically, so the
+    /// wallet is never loaded with a partially applied ChangeSet, but a crash before the next
+    /// durable commit loses all the changesets persisted with this function since the previous
+    /// one, and the wallet then has to sync the corresponding data again. The pages freed by these
+    /// commits are only reused after a durable commit, so [`sync`] should be called regularly
+    /// during long batches.</div>
+    ///
+    /// [`Wallet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.Wallet.html>
+    /// [`persist_wallet`]: Self::persist_wallet
+    /// [`sync`]: Self::sync
+    /// [`close`]: Self::close
+    /// [`Durability`]: <https://docs.rs/redb/2.5.0/redb/enum.Durability.html>
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(wallet = %self.wallet_name))
+    )]
+    pub fn persist_wallet_batched(
         &self,
-        read_tx: &ReadTransaction,
-        first_seen: &mut BTreeMap<Txid, u64>,
-    ) -> Result<(), StoreError> {
-        let table = read_tx.open_table(self.first_seen_table_defn())?;
+        changeset: &ChangeSet,
+    ) -> Result<PersistSummary, StoreError> {
+        self.with_notify(|| {
+            let write_tx = self.begin_write(Durability::None)?;
+            let summary = self.persist_wallet_in_tx(&write_tx, changeset)?;
+            self.commit(write_tx)?;
+            Ok(summary)
+        })
+    }
 
-        for entry in table.iter()? {
-            let (txid, first_seen_num) = entry?;
-            first_seen.insert(Txid::from_byte_array(txid.value()), first_seen_num.value());
-        }
-        Ok(())
+    /// This function persists the [`TxGraph`] into our db. It persists each field
+    /// by calling corresponding persistence functions and returns a [`PersistSummary`] of the
+    /// rows written.
+    ///
+    /// [`TxGraph`]: <http://docs.rs/bdk_chain/0.23.0/bdk_chain/tx_graph/struct.TxGraph.html>
+    pub fn persist_tx_graph<A: AnchorWithMetaData>(
+        &self,
+        changeset: &tx_graph::ChangeSet<A>,
+    ) -> Result<PersistSummary, StoreError> {
+        #[cfg(feature = "wallet")]
+        let changelog_entry = self.tx_graph_changelog_entry(changeset)?;
+        #[cfg(not(feature = "wallet"))]
+        let changelog_entry: Option<Vec<u8>> = None;
+        self.with_notify(|| {
+            self.persist_tx_graph_with_durability::<A>(
+                changeset,
+                Durability::Immediate,
+                changelog_entry.as_deref(),
+            )
+        })
     }
 
-    // This function loads last_revealed corresponding to keychain_txout .
-    fn read_last_revealed(
+    // Same as persist_tx_graph but commits with the given durability.
+    // `changelog_entry` is appended to the changelog in the same write transaction, if any.
+    fn persist_tx_graph_with_durability<A: AnchorWithMetaData>(
         &self,
-        read_tx: &ReadTransaction,
-        last_revealed: &mut BTreeMap<DescriptorId, u32>,
-    ) -> Result<(), StoreError> {
-        let table = read_tx.open_table(self.last_revealed_table_defn())?;
+        changeset: &tx_graph::ChangeSet<A>,
+        durability: Durability,
+        changelog_entry: Option<&[u8]>,
+    ) -> Result<PersistSummary, StoreError> {
+        let write_tx = self.begin_write(durability)?;
+        let summary = self.persist_tx_graph_in(&write_tx, changeset, changelog_entry)?;
+        self.commit(write_tx)?;
+        Ok(summary)
+    }
 
-        for entry in table.iter()? {
-            let (desc, last_revealed_idx) = entry?;
-            last_revealed.insert(
-                DescriptorId::from_byte_array(desc.value()),
-                last_revealed_idx.value(),
-            );
+    // This function persists the tx graph changeset in the given write transaction, along with
+    // `changelog_entry` and the time of the persist.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "persist_tx_graph",
+            level = "debug",
+            skip_all,
+            fields(wallet = %self.wallet_name)
+        )
+    )]
+    fn persist_tx_graph_in<A: AnchorWithMetaData>(
+        &self,
+        write_tx: &WriteTransaction,
+        changeset: &tx_graph::ChangeSet<A>,
+        changelog_entry: Option<&[u8]>,
+    ) -> Result<PersistSummary, StoreError> {
+        #[cfg(feature = "tracing")]
+        if changeset.is_empty() {
+            tracing::debug!("empty tx_graph changeset");
         }
-        Ok(())
+        self.check_anchor_type::<A>()?;
+        // anchors must not be written in the current format next to ones in an older format
+        self.migrate(write_tx)?;
+        self.record_anchor_type_in::<A>(write_tx)?;
+        // begun after write_tx so that it sees the latest committed state
+        let read_tx = self.db.begin_read()?;
+        let summary = PersistSummary {
+            txs: self.persist_txs(write_tx, &changeset.txs)?,
+            txouts: self.persist_txouts(write_tx, &changeset.txouts)?,
+            anchors: self.persist_anchors::<A>(
+                write_tx,
+                &read_tx,
+                &changeset.anchors,
+                &changeset.txs,
+            )?,
+            last_seen: self.persist_last_seen(
+                write_tx,
+                &read_tx,
+                &changeset.last_seen,
+                &changeset.txs,
+            )?,
+            last_evicted: self.persist_last_evicted(
+                write_tx,
+                &read_tx,
+                &changeset.last_evicted,
+                &changeset.txs,
+            )?,
+            first_seen: self.persist_first_seen(
+                write_tx,
+                &read_tx,
+                &changeset.first_seen,
+                &changeset.txs,
+            )?,
+            ..Default::default()
+        };
+        if let Some(entry) = changelog_entry {
+            self.append_changelog(write_tx, entry)?;
+        }
+        self.record_last_persist(write_tx)?;
+        Ok(summary)
     }
 
-    // This function loads spk_cache corresponding to keychain_txout .
-    fn read_spks(
+    // This function persists the local chain, tx graph and indexer changesets in the given write
+    // transaction, so that they are committed together. `read_tx` must be begun after `write_tx`
+    // so that it sees the latest committed state, as in persist_tx_graph.
+    #[cfg(feature = "serde")]
+    fn persist_sections_in<A: AnchorWithMetaData>(
         &self,
+        write_tx: &WriteTransaction,
         read_tx: &ReadTransaction,
-        spk_cache: &mut BTreeMap<DescriptorId, BTreeMap<u32, ScriptBuf>>,
-    ) -> Result<(), StoreError> {
-        let table = read_tx.open_table(self.spk_table_defn())?;
-
-        for entry in table.iter()? {
-            let (desc, spk) = entry?;
-            spk_cache
-                .entry(DescriptorId::from_byte_array(desc.value().0))
-                .or_default()
-                .insert(desc.value().1, ScriptBuf::from_bytes(spk.value()));
+        local_chain: &local_chain::ChangeSet,
+        tx_graph: &tx_graph::ChangeSet<A>,
+        indexer: &keychain_txout::ChangeSet,
+    ) -> Result<PersistSummary, StoreError> {
+        self.migrate(write_tx)?;
+        let (blocks, replaced_blocks) = self.persist_blocks(write_tx, &local_chain.blocks)?;
+        let txs = &tx_graph.txs;
+        let mut summary = PersistSummary {
+            blocks,
+            replaced_blocks,
+            txs: self.persist_txs(write_tx, txs)?,
+            txouts: self.persist_txouts(write_tx, &tx_graph.txouts)?,
+            anchors: self.persist_anchors(write_tx, read_tx, &tx_graph.anchors, txs)?,
+            last_seen: self.persist_last_seen(write_tx, read_tx, &tx_graph.last_seen, txs)?,
+            last_evicted: self.persist_last_evicted(
+                write_tx,
+                read_tx,
+                &tx_graph.last_evicted,
+                txs,
+            )?,
+            first_seen: self.persist_first_seen(write_tx, read_tx, &tx_graph.first_seen, txs)?,
+            last_revealed: self.persist_last_revealed(write_tx, &indexer.last_revealed)?,
+            ..Default::default()
+        };
+        if self.persist_spk_cache {
+            summary.spks = self.persist_spks(write_tx, &indexer.spk_cache)?;
         }
-        Ok(())
+        Ok(summary)
     }
-}
 
-#[cfg(feature = "wallet")]
-impl WalletPersister for Store {
-    type Error = StoreError;
-    fn initialize(persister: &mut Self) -> Result<ChangeSet, Self::Error> {
-        persister.create_tables::<ConfirmationBlockTime>()?;
-        let mut changeset = ChangeSet::default();
-        persister.read_wallet(&mut changeset)?;
-        Ok(changeset)
+    /// This function persists the [`indexer`] structures into our db. It persists each
+    /// field by calling corresponding persistence functions and returns a [`PersistSummary`] of
+    /// the rows written.
+    ///
+    /// [`indexer`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/indexer/index.html>
+    pub fn persist_indexer(
+        &self,
+        changeset: &keychain_txout::ChangeSet,
+    ) -> Result<PersistSummary, StoreError> {
+        self.with_notify(|| self.persist_indexer_with_durability(changeset, Durability::Immediate))
     }
 
-    fn persist(persister: &mut Self, changeset: &ChangeSet) -> Result<(), Self::Error> {
-        persister.persist_wallet(changeset)?;
-        Ok(())
+    // Same as persist_indexer but commits with the given durability.
+    fn persist_indexer_with_durability(
+        &self,
+        changeset: &keychain_txout::ChangeSet,
+        durability: Durability,
+    ) -> Result<PersistSummary, StoreError> {
+        let write_tx = self.begin_write(durability)?;
+        let summary = self.persist_indexer_in(&write_tx, changeset)?;
+        self.commit(write_tx)?;
+        Ok(summary)
     }
-}
-
-#[cfg(test)]
-#[cfg_attr(coverage_nightly, coverage(off))]
-mod test {
-    use super::*;
-    use bdk_chain::BlockId;
-    use bdk_chain::ConfirmationBlockTime;
-    use bdk_chain::{
-        DescriptorExt, Merge,
-        bitcoin::{
-            self, Amount, BlockHash, OutPoint, ScriptBuf, Transaction, TxIn, TxOut, absolute,
-            hashes::Hash, transaction, transaction::Txid,
-        },
-        keychain_txout, local_chain,
-        miniscript::descriptor::Descriptor,
-    };
-
-    use std::sync::Arc;
-    use std::{collections::BTreeMap, path::Path};
-    use tempfile::NamedTempFile;
-
-    use bdk_testenv::{block_id, hash, utils};
 
-    const DESCRIPTORS: [&str; 4] = [
-        "tr([5940b9b9/86'/0'/0']tpubDDVNqmq75GNPWQ9UNKfP43UwjaHU4GYfoPavojQbfpyfZp2KetWgjGBRRAy4tYCrAA6SB11mhQAkqxjh1VtQHyKwT4oYxpwLaGHvoKmtxZf/1/*)#ypcpw2dr",
-        "tr([5940b9b9/86'/0'/0']tpubDDVNqmq75GNPWQ9UNKfP43UwjaHU4GYfoPavojQbfpyfZp2KetWgjGBRRAy4tYCrAA6SB11mhQAkqxjh1VtQHyKwT4oYxpwLaGHvoKmtxZf/0/*)#44aqnlam",
-        "wpkh([41f2aed0/84h/1h/0h]tpubDDFSdQWw75hk1ewbwnNpPp5DvXFRKt68ioPoyJDY752cNHKkFxPWqkqCyCf4hxrEfpuxh46QisehL3m8Bi6MsAv394QVLopwbtfvryFQNUH/1/*)#emtwewtk",
-        "wpkh([41f2aed0/84h/1h/0h]tpubDDFSdQWw75hk1ewbwnNpPp5DvXFRKt68ioPoyJDY752cNHKkFxPWqkqCyCf4hxrEfpuxh46QisehL3m8Bi6MsAv394QVLopwbtfvryFQNUH/0/*)#g0w0ymmw",
-    ];
+    // This function persists the indexer changeset in the given write transaction.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "persist_indexer",
+            level = "debug",
+            skip_all,
+            fields(wallet = %self.wallet_name)
+        )
+    )]
+    fn persist_indexer_in(
+        &self,
+        write_tx: &WriteTransaction,
+        changeset: &keychain_txout::ChangeSet,
+    ) -> Result<PersistSummary, StoreError> {
+        #[cfg(feature = "tracing")]
+        if changeset.is_empty() {
+            tracing::debug!("empty indexer changeset");
+        }
+        let mut summary = PersistSummary {
+            last_revealed: self.persist_last_revealed(write_tx, &changeset.last_revealed)?,
+            ..Default::default()
+        };
+        if self.persist_spk_cache {
+            summary.spks = self.persist_spks(write_tx, &changeset.spk_cache)?;
+        }
+        Ok(summary)
+    }
 
-    fn create_db(path: impl AsRef<Path>) -> Database {
-        Database::create(path).unwrap()
+    /// This function persists the descriptors into our db and returns a [`PersistSummary`] of the
+    /// rows written.
+    ///
+    /// Persisting the descriptor already persisted under a label is a no-op, but
+    /// [`StoreError::DescriptorImmutable`] is returned, and nothing is written, if a label already
+    /// has a different descriptor (see [`replace_descriptor`]).
+    ///
+    /// Descriptors are stored with their checksum. Descriptors persisted without one by older
+    /// versions of this crate are rewritten with it once, when the tables of the wallet are
+    /// migrated (see [`create_tables`]).
+    ///
+    /// [`create_tables`]: Self::create_tables
+    ///
+    /// [`replace_descriptor`]: Self::replace_descriptor
+    pub fn persist_keychains(
+        &self,
+        // maps label to descriptor
+        changeset: &BTreeMap<u64, Descriptor<DescriptorPublicKey>>,
+    ) -> Result<PersistSummary, StoreError> {
+        self.with_notify(|| {
+            Ok(PersistSummary {
+                keychains: self
+                    .persist_keychains_with_durability(changeset, Durability::Immediate)?,
+                ..Default::default()
+            })
+        })
     }
 
-    fn create_test_store(db: Arc<Database>, wallet_name: &str) -> Store {
-        Store::new(db, wallet_name.to_string()).unwrap()
+    // Same as persist_keychains but commits with the given durability.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "persist_keychains",
+            level = "debug",
+            skip_all,
+            fields(wallet = %self.wallet_name)
+        )
+    )]
+    fn persist_keychains_with_durability(
+        &self,
+        // maps label to descriptor
+        changeset: &BTreeMap<u64, Descriptor<DescriptorPublicKey>>,
+        durability: Durability,
+    ) -> Result<TableChanges, StoreError> {
+        #[cfg(feature = "tracing")]
+        if changeset.is_empty() {
+            tracing::debug!("no descriptors to persist");
+        }
+        let write_tx = self.begin_write(durability)?;
+        let changes = self.persist_keychains_in(&write_tx, changeset)?;
+        self.commit(write_tx)?;
+        Ok(changes)
     }
 
-    #[test]
-    fn test_network_persistence() {
-        let tmpfile = NamedTempFile::new().unwrap();
-        let db = create_db(tmpfile.path());
+    #[cfg(feature = "wallet")]
+    // This function persists the descriptor (label 0) and change descriptor (label 1) of a wallet
+    // changeset in the given write transaction. Either can be missing, but a change descriptor is
+    // only accepted if the wallet has an external one, in the changeset or already persisted, as
+    // a wallet cannot be loaded from a change descriptor alone.
+    fn persist_wallet_keychains_in(
+        &self,
+        write_tx: &WriteTransaction,
+        changeset: &ChangeSet,
+    ) -> Result<TableChanges, StoreError> {
+        let mut desc_changeset: BTreeMap<u64, Descriptor<DescriptorPublicKey>> = BTreeMap::new();
+        if let Some(desc) = &changeset.descriptor {
+            desc_changeset.insert(0, desc.clone());
+        }
+        if let Some(change_desc) = &changeset.change_descriptor {
+            if changeset.descriptor.is_none()
+                && write_tx
+                    .open_table(self.keychains_table_defn())?
+                    .get(0)?
+                    .is_none()
+            {
+                return Err(StoreError::MissingExternalDescriptor);
+            }
+            desc_changeset.insert(1, change_desc.clone());
+        }
+        self.persist_keychains_in(write_tx, &desc_changeset)
+    }
+
+    // This function persists the descriptors in the given write transaction.
+    fn persist_keychains_in(
+        &self,
+        write_tx: &WriteTransaction,
+        changeset: &BTreeMap<u64, Descriptor<DescriptorPublicKey>>,
+    ) -> Result<TableChanges, StoreError> {
+        let mut table = write_tx.open_table(self.keychains_table_defn())?;
+
+        // descriptors corresponding to a label(keychain) are never modified, see
+        // replace_descriptor.
+        for (label, desc) in changeset {
+            if let Some(stored) = table.get(label)? {
+                let stored = self.decode_descriptor(*label, stored.value())?;
+                if parse_stored_descriptor(*label, &stored)? != *desc {
+                    return Err(StoreError::DescriptorImmutable(*label));
+                }
+            }
+        }
+        for (label, desc) in changeset {
+            table.insert(label, self.encode_descriptor(*label, desc)?)?;
+        }
+        let changes = TableChanges::from_inserted(changeset.len());
+        #[cfg(feature = "metrics")]
+        telemetry::record_persist(&self.wallet_name, "keychains", changes);
+        Ok(changes)
+    }
+
+    /// This function replaces the descriptor persisted under `label` with `new`.
+    ///
+    /// Descriptors are immutable: [`persist_keychains`] (and so `persist_wallet`) refuses to
+    /// overwrite a persisted descriptor with a different one, since changing the descriptor of a
+    /// wallet orphans all the addresses it revealed. This function is the deliberate way to do so,
+    /// and [`StoreError::DescriptorImmutable`] is returned unless `confirm` is `true` and a
+    /// descriptor is already persisted under `label`.
+    ///
+    /// Only the keychains table is written. The last revealed index and spk cache of the old
+    /// descriptor are kept under its [`DescriptorId`], and nothing is persisted for the new one.
+    ///
+    /// [`persist_keychains`]: Self::persist_keychains
+    /// [`DescriptorId`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/struct.DescriptorId.html>
+    pub fn replace_descriptor(
+        &self,
+        label: u64,
+        new: Descriptor<DescriptorPublicKey>,
+        confirm: bool,
+    ) -> Result<(), StoreError> {
+        if !confirm {
+            return Err(StoreError::DescriptorImmutable(label));
+        }
+        let write_tx = self.begin_write(Durability::Immediate)?;
+        {
+            let mut table = write_tx.open_table(self.keychains_table_defn())?;
+            if table.get(label)?.is_none() {
+                return Err(StoreError::DescriptorImmutable(label));
+            }
+            table.insert(label, self.encode_descriptor(label, &new)?)?;
+        }
+        #[cfg(feature = "metrics")]
+        telemetry::record_persist(
+            &self.wallet_name,
+            "keychains",
+            TableChanges::from_inserted(1),
+        );
+        self.commit(write_tx)?;
+        Ok(())
+    }
+
+    /// This function persists the [`Network`] into our db and returns a [`PersistSummary`] of the
+    /// rows written.
+    ///
+    /// The network of a wallet cannot be changed: [`StoreError::NetworkMismatch`] is returned, and
+    /// nothing is written, if a different network is already persisted. `reset_network` (with the
+    /// `dangerous` feature) is the explicit way to overwrite it.
+    /// <div class="warning">Warning: Do Not use with MAINNET</div>
+    ///
+    /// [`Network`]: <https://docs.rs/bitcoin/latest/bitcoin/enum.Network.html>
+    pub fn persist_network(
+        &self,
+        network: &Option<bitcoin::Network>,
+    ) -> Result<PersistSummary, StoreError> {
+        self.with_notify(|| {
+            Ok(PersistSummary {
+                network: self.persist_network_with_durability(network, Durability::Immediate)?,
+                ..Default::default()
+            })
+        })
+    }
+
+    // Same as persist_network but commits with the given durability.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "persist_network",
+            level = "debug",
+            skip_all,
+            fields(wallet = %self.wallet_name)
+        )
+    )]
+    fn persist_network_with_durability(
+        &self,
+        network: &Option<bitcoin::Network>,
+        durability: Durability,
+    ) -> Result<TableChanges, StoreError> {
+        #[cfg(feature = "tracing")]
+        if network.is_none() {
+            tracing::debug!("no network to persist");
+        }
+        let write_tx = self.begin_write(durability)?;
+        let changes = self.persist_network_in(&write_tx, network)?;
+        self.commit(write_tx)?;
+        Ok(changes)
+    }
+
+    // This function persists the network in the given write transaction, returning
+    // StoreError::NetworkMismatch if the wallet already has a different one.
+    fn persist_network_in(
+        &self,
+        write_tx: &WriteTransaction,
+        network: &Option<bitcoin::Network>,
+    ) -> Result<TableChanges, StoreError> {
+        if let Some(theirs) = *network {
+            let byte = write_tx
+                .open_table(NETWORK_BYTE)?
+                .get(&*self.wallet_name)?
+                .map(|byte| byte.value());
+            let stored = match byte {
+                Some(byte) => Some(network_from_byte(byte)?),
+                None => write_tx
+                    .open_table(NETWORK)?
+                    .get(&*self.wallet_name)?
+                    .map(|network| network_from_str(&network.value()))
+                    .transpose()?,
+            };
+            if let Some(ours) = stored.filter(|ours| *ours != theirs) {
+                return Err(StoreError::NetworkMismatch { ours, theirs });
+            }
+        }
+        self.write_network_in(write_tx, network)
+    }
+
+    // This function writes the network in the given write transaction, whatever the persisted
+    // one.
+    fn write_network_in(
+        &self,
+        write_tx: &WriteTransaction,
+        network: &Option<bitcoin::Network>,
+    ) -> Result<TableChanges, StoreError> {
+        let mut table = write_tx.open_table(NETWORK)?;
+        let mut byte_table = write_tx.open_table(NETWORK_BYTE)?;
+        if let Some(network) = network {
+            // the network is only kept in one of the tables
+            if self.compact_network {
+                byte_table.insert(&*self.wallet_name, network_to_byte(*network))?;
+                table.remove(&*self.wallet_name)?;
+            } else {
+                table.insert(&*self.wallet_name, network.to_string())?;
+                byte_table.remove(&*self.wallet_name)?;
+            }
+        }
+        let changes = TableChanges::from_inserted(network.iter().count());
+        #[cfg(feature = "metrics")]
+        telemetry::record_persist(&self.wallet_name, "network", changes);
+        Ok(changes)
+    }
+
+    /// This function overwrites the [`Network`] of the wallet with `network`, whatever the
+    /// persisted one. It is meant for development, to reuse a database file on another network,
+    /// and is only available with the `dangerous` feature.
+    ///
+    /// <div class="warning">Warning: this is destructive. The persisted transactions, blocks and
+    /// descriptors of the wallet are kept but belong to the previous network, so they should be
+    /// cleared or the wallet recreated. The previous network cannot be recovered.</div>
+    ///
+    /// [`Network`]: <https://docs.rs/bitcoin/latest/bitcoin/enum.Network.html>
+    #[cfg(feature = "dangerous")]
+    pub fn reset_network(&self, network: Network) -> Result<(), StoreError> {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(wallet = %self.wallet_name, %network, "resetting network");
+        let write_tx = self.begin_write(Durability::Immediate)?;
+        self.write_network_in(&write_tx, &Some(network))?;
+        self.commit(write_tx)?;
+        Ok(())
+    }
+
+    /// This function persists the [`LocalChain`] structure into our db. It persists each
+    /// field by calling corresponding persistence functions and returns a [`PersistSummary`] of
+    /// the rows written.
+    ///
+    /// [`LocalChain`]: <http://docs.rs/bdk_chain/0.23.0/bdk_chain/local_chain/struct.LocalChain.html>
+    pub fn persist_local_chain(
+        &self,
+        changeset: &local_chain::ChangeSet,
+    ) -> Result<PersistSummary, StoreError> {
+        self.with_notify(|| {
+            self.persist_local_chain_with_durability(changeset, Durability::Immediate)
+        })
+    }
+
+    // Same as persist_local_chain but commits with the given durability.
+    fn persist_local_chain_with_durability(
+        &self,
+        changeset: &local_chain::ChangeSet,
+        durability: Durability,
+    ) -> Result<PersistSummary, StoreError> {
+        let write_tx = self.begin_write(durability)?;
+        let summary = self.persist_local_chain_in(&write_tx, changeset)?;
+        self.commit(write_tx)?;
+        Ok(summary)
+    }
+
+    // This function persists the local chain changeset in the given write transaction.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "persist_local_chain",
+            level = "debug",
+            skip_all,
+            fields(wallet = %self.wallet_name)
+        )
+    )]
+    fn persist_local_chain_in(
+        &self,
+        write_tx: &WriteTransaction,
+        changeset: &local_chain::ChangeSet,
+    ) -> Result<PersistSummary, StoreError> {
+        #[cfg(feature = "tracing")]
+        if changeset.is_empty() {
+            tracing::debug!("empty local_chain changeset");
+        }
+        let (blocks, replaced_blocks) = self.persist_blocks(write_tx, &changeset.blocks)?;
+        Ok(PersistSummary {
+            blocks,
+            replaced_blocks,
+            ..Default::default()
+        })
+    }
+
+    /// This function persists a contiguous run of blocks, `hashes[i]` being the hash of the block
+    /// at height `start_height + i`, by calling [`persist_local_chain`] with a changeset holding
+    /// those blocks. They are written in a single write transaction, the [`set_on_persist`]
+    /// callback is invoked, and the returned [`PersistSummary`] reports the blocks whose persisted
+    /// hash was replaced.
+    ///
+    /// Blocks already persisted at those heights are overwritten.
+    /// [`StoreError::BlockRangeOverflow`] is returned, and nothing is written, if
+    /// `start_height + hashes.len()` does not fit in a `u32`.
+    ///
+    /// [`persist_local_chain`]: Self::persist_local_chain
+    /// [`set_on_persist`]: Self::set_on_persist
+    pub fn persist_block_range(
+        &self,
+        start_height: u32,
+        hashes: &[BlockHash],
+    ) -> Result<PersistSummary, StoreError> {
+        let overflow = || StoreError::BlockRangeOverflow {
+            start_height,
+            len: hashes.len(),
+        };
+        let len = u32::try_from(hashes.len()).map_err(|_| overflow())?;
+        start_height.checked_add(len).ok_or_else(overflow)?;
+
+        self.persist_local_chain(&local_chain::ChangeSet {
+            blocks: (start_height..)
+                .zip(hashes)
+                .map(|(height, &hash)| (height, Some(hash)))
+                .collect(),
+        })
+    }
+
+    // This function persists blocks corresponding to a local_chain. It also returns the blocks
+    // whose persisted hash was overwritten by a different one.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(wallet = %self.wallet_name, table = "blocks", rows = blocks.len())
+        )
+    )]
+    fn persist_blocks(
+        &self,
+        write_tx: &WriteTransaction,
+        blocks: &BTreeMap<u32, Option<BlockHash>>,
+    ) -> Result<(TableChanges, Vec<ReplacedBlock>), StoreError> {
+        let mut table = write_tx.open_table(self.blocks_table_defn())?;
+        let mut changes = TableChanges::default();
+        let mut replaced = Vec::new();
+        for (ht, hash) in blocks {
+            match hash {
+                &Some(hash) => {
+                    let old_hash = table
+                        .insert(*ht, hash.to_byte_array())?
+                        .map(|old_hash| BlockHash::from_byte_array(old_hash.value()));
+                    changes.inserted += 1;
+                    if let Some(old_hash) = old_hash.filter(|&old_hash| old_hash != hash) {
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!(height = ht, %old_hash, new_hash = %hash, "block replaced");
+                        replaced.push(ReplacedBlock {
+                            height: *ht,
+                            old_hash,
+                            new_hash: hash,
+                        });
+                    }
+                }
+                // remove the block if hash is None
+                // assuming it is guaranteed that (ht, None) => there is an entry of form (ht,_) in
+                // the Table.
+                None => {
+                    if table.remove(*ht)?.is_some() {
+                        changes.removed += 1;
+                    }
+                }
+            };
+        }
+        #[cfg(feature = "metrics")]
+        telemetry::record_persist(&self.wallet_name, "blocks", changes);
+        Ok((changes, replaced))
+    }
+
+    // This function persists txs corresponding to a tx_graph.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(wallet = %self.wallet_name, table = "txs", rows = txs.len())
+        )
+    )]
+    fn persist_txs(
+        &self,
+        write_tx: &WriteTransaction,
+        txs: &BTreeSet<Arc<Transaction>>,
+    ) -> Result<TableChanges, StoreError> {
+        if !txs.is_empty() {
+            self.check_tx_codec_in(write_tx)?;
+        }
+        let mut table = write_tx.open_table(self.txs_table_defn())?;
+        for tx in txs {
+            let txid = tx.compute_txid().to_byte_array();
+            table.insert(txid, self.encode_tx(self.tx_codec, tx)?)?;
+        }
+        let changes = TableChanges::from_inserted(txs.len());
+        #[cfg(feature = "metrics")]
+        telemetry::record_persist(&self.wallet_name, "txs", changes);
+        Ok(changes)
+    }
+
+    // This function persists txouts corresponding to a tx_graph.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(wallet = %self.wallet_name, table = "txouts", rows = txouts.len())
+        )
+    )]
+    fn persist_txouts(
+        &self,
+        write_tx: &WriteTransaction,
+        txouts: &BTreeMap<OutPoint, TxOut>,
+    ) -> Result<TableChanges, StoreError> {
+        let mut table = write_tx.open_table(self.txouts_table_defn())?;
+        for (outpoint, txout) in txouts {
+            let key = (outpoint.txid.to_byte_array(), outpoint.vout);
+            if self.strict_txouts {
+                if let Some(stored) = table.get(key)? {
+                    let (value, script) = stored.value();
+                    if value != txout.value.to_sat()
+                        || self.decode_script(TXOUTS_VALUE, key, script)? != txout.script_pubkey
+                    {
+                        return Err(StoreError::ConflictingTxOut {
+                            outpoint: *outpoint,
+                        });
+                    }
+                }
+            }
+            table.insert(
+                key,
+                (
+                    txout.value.to_sat(),
+                    self.seal_value(TXOUTS_VALUE, &row_key(key), txout.script_pubkey.to_bytes())?,
+                ),
+            )?;
+        }
+        let changes = TableChanges::from_inserted(txouts.len());
+        #[cfg(feature = "metrics")]
+        telemetry::record_persist(&self.wallet_name, "txouts", changes);
+        Ok(changes)
+    }
+
+    // This function persists anchors corresponding to a tx_graph.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(wallet = %self.wallet_name, table = "anchors", rows = anchors.len())
+        )
+    )]
+    fn persist_anchors<A: AnchorWithMetaData>(
+        &self,
+        write_tx: &WriteTransaction,
+        read_tx: &ReadTransaction,
+        anchors: &BTreeSet<(A, Txid)>,
+        txs: &BTreeSet<Arc<Transaction>>,
+    ) -> Result<TableChanges, StoreError> {
+        let mut table = write_tx.open_multimap_table(self.anchors_table_defn())?;
+        let txs_table = read_tx.open_table(self.txs_table_defn())?;
+        for (anchor, txid) in anchors {
+            // if the corresponding txn exists in Txs table (trying to imitate the
+            // referential behavior in case of sqlite)
+            let found = txs.iter().any(|tx| tx.compute_txid() == *txid);
+            if txs_table.get(txid.to_byte_array())?.is_some() || found {
+                insert_anchor(
+                    &mut table,
+                    txid.to_byte_array(),
+                    block_id_to_bytes(&anchor.anchor_block()),
+                    A::TAG,
+                    &anchor_metadata_to_bytes(anchor, self.compact_anchor_metadata),
+                )?;
+            } else {
+                return Err(StoreError::TxMissing(*txid));
+            }
+        }
+        let changes = TableChanges::from_inserted(anchors.len());
+        #[cfg(feature = "metrics")]
+        telemetry::record_persist(&self.wallet_name, "anchors", changes);
+        Ok(changes)
+    }
+
+    // This function persists last_seen flags corresponding to a tx_graph.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(wallet = %self.wallet_name, table = "last_seen", rows = last_seen.len())
+        )
+    )]
+    fn persist_last_seen(
+        &self,
+        write_tx: &WriteTransaction,
+        read_tx: &ReadTransaction,
+        last_seen: &BTreeMap<Txid, u64>,
+        txs: &BTreeSet<Arc<Transaction>>,
+    ) -> Result<TableChanges, StoreError> {
+        let mut table = write_tx.open_table(self.last_seen_defn())?;
+        let txs_table = read_tx.open_table(self.txs_table_defn())?;
+        for (txid, last_seen_time) in last_seen {
+            // if the corresponding txn exists in Txs table (trying to duplicate the
+            // referential behavior in case of sqlite)
+            let found = txs.iter().any(|tx| tx.compute_txid() == *txid);
+            if txs_table.get(txid.to_byte_array())?.is_some() || found {
+                table.insert(txid.to_byte_array(), *last_seen_time)?;
+            } else {
+                return Err(StoreError::TxMissing(*txid));
+            }
+        }
+        let changes = TableChanges::from_inserted(last_seen.len());
+        #[cfg(feature = "metrics")]
+        telemetry::record_persist(&self.wallet_name, "last_seen", changes);
+        Ok(changes)
+    }
+
+    // This function persists last_evicted flags corresponding to a tx_graph .
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(wallet = %self.wallet_name, table = "last_evicted", rows = last_evicted.len())
+        )
+    )]
+    fn persist_last_evicted(
+        &self,
+        write_tx: &WriteTransaction,
+        read_tx: &ReadTransaction,
+        last_evicted: &BTreeMap<Txid, u64>,
+        txs: &BTreeSet<Arc<Transaction>>,
+    ) -> Result<TableChanges, StoreError> {
+        let mut table = write_tx.open_table(self.last_evicted_table_defn())?;
+        let txs_table = read_tx.open_table(self.txs_table_defn())?;
+        for (txid, last_evicted_time) in last_evicted {
+            // if the corresponding txn exists in Txs table (trying to duplicate the
+            // referential behavior in case of sqlite)
+            let found = txs.iter().any(|tx| tx.compute_txid() == *txid);
+            if txs_table.get(txid.to_byte_array())?.is_some() || found {
+                table.insert(txid.to_byte_array(), last_evicted_time)?;
+            } else {
+                return Err(StoreError::TxMissing(*txid));
+            }
+        }
+        let changes = TableChanges::from_inserted(last_evicted.len());
+        #[cfg(feature = "metrics")]
+        telemetry::record_persist(&self.wallet_name, "last_evicted", changes);
+        Ok(changes)
+    }
+
+    // This function persists first_seen flags corresponding to a tx_graph .
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(wallet = %self.wallet_name, table = "first_seen", rows = first_seen.len())
+        )
+    )]
+    fn persist_first_seen(
+        &self,
+        write_tx: &WriteTransaction,
+        read_tx: &ReadTransaction,
+        first_seen: &BTreeMap<Txid, u64>,
+        txs: &BTreeSet<Arc<Transaction>>,
+    ) -> Result<TableChanges, StoreError> {
+        let mut table = write_tx.open_table(self.first_seen_table_defn())?;
+        let txs_table = read_tx.open_table(self.txs_table_defn())?;
+        for (txid, first_seen_time) in first_seen {
+            // if the corresponding txn exists in Txs table (trying to duplicate the
+            // referential behavior in case of sqlite)
+            let found = txs.iter().any(|tx| tx.compute_txid() == *txid);
+            if txs_table.get(txid.to_byte_array())?.is_some() || found {
+                table.insert(txid.to_byte_array(), first_seen_time)?;
+            } else {
+                return Err(StoreError::TxMissing(*txid));
+            }
+        }
+        let changes = TableChanges::from_inserted(first_seen.len());
+        #[cfg(feature = "metrics")]
+        telemetry::record_persist(&self.wallet_name, "first_seen", changes);
+        Ok(changes)
+    }
+
+    // This function appends an encoded changeset to the changelog table under the next sequence
+    // number, along with the current unix timestamp.
+    fn append_changelog(
+        &self,
+        write_tx: &WriteTransaction,
+        entry: &[u8],
+    ) -> Result<(), StoreError> {
+        let mut sequence_table = write_tx.open_table(CHANGELOG_SEQUENCE)?;
+        let sequence = sequence_table
+            .get(&*self.wallet_name)?
+            .map_or(0, |sequence| sequence.value());
+        sequence_table.insert(&*self.wallet_name, sequence + 1)?;
+        let timestamp = self.now();
+        let mut table = write_tx.open_table(self.changelog_table_defn())?;
+        let entry = self.seal_value(CHANGELOG_VALUE, &sequence.to_be_bytes(), entry.to_vec())?;
+        table.insert(sequence, (timestamp, entry))?;
+        Ok(())
+    }
+
+    // This function persists last_revealed corresponding to keychain_txout .
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(wallet = %self.wallet_name, table = "last_revealed", rows = last_revealed.len())
+        )
+    )]
+    fn persist_last_revealed(
+        &self,
+        write_tx: &WriteTransaction,
+        last_revealed: &BTreeMap<DescriptorId, u32>,
+    ) -> Result<TableChanges, StoreError> {
+        let mut table = write_tx.open_table(self.last_revealed_table_defn())?;
+        for (&desc, &idx) in last_revealed {
+            table.insert(desc.to_byte_array(), idx)?;
+        }
+        let changes = TableChanges::from_inserted(last_revealed.len());
+        #[cfg(feature = "metrics")]
+        telemetry::record_persist(&self.wallet_name, "last_revealed", changes);
+        Ok(changes)
+    }
+
+    /// This function sets the last revealed index of the descriptor with the given id to `index`,
+    /// without building a [`keychain_txout::ChangeSet`].
+    ///
+    /// Last revealed indices only grow, so setting an index lower than the persisted one returns
+    /// [`StoreError::NonMonotonicRevealedIndex`] and leaves it unchanged. Setting the same index
+    /// is a no-op.
+    ///
+    /// [`keychain_txout::ChangeSet`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/indexer/keychain_txout/struct.ChangeSet.html>
+    pub fn set_last_revealed(&self, id: DescriptorId, index: u32) -> Result<(), StoreError> {
+        let write_tx = self.begin_write(Durability::Immediate)?;
+        {
+            let mut table = write_tx.open_table(self.last_revealed_table_defn())?;
+            let current = table.get(id.to_byte_array())?.map(|idx| idx.value());
+            match current {
+                Some(current) if current > index => {
+                    return Err(StoreError::NonMonotonicRevealedIndex {
+                        descriptor_id: id,
+                        current,
+                        index,
+                    });
+                }
+                Some(current) if current == index => return Ok(()),
+                _ => {
+                    table.insert(id.to_byte_array(), index)?;
+                }
+            }
+        }
+        self.commit(write_tx)?;
+        Ok(())
+    }
+
+    // This function persists spk_cache corresponding to keychain_txout .
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(
+                wallet = %self.wallet_name,
+                table = "spks",
+                rows = spk_cache.values().map(BTreeMap::len).sum::<usize>()
+            )
+        )
+    )]
+    fn persist_spks(
+        &self,
+        write_tx: &WriteTransaction,
+        spk_cache: &BTreeMap<DescriptorId, BTreeMap<u32, ScriptBuf>>,
+    ) -> Result<TableChanges, StoreError> {
+        let mut table = write_tx.open_table(self.spk_table_defn())?;
+        let mut changes = TableChanges::default();
+        for (&desc, map) in spk_cache {
+            for (&index, spk) in map {
+                let key = (desc.to_byte_array(), index);
+                // scripts are derived, so a stored script is usually the same as the incoming one.
+                // Values which cannot be decoded are overwritten.
+                let unchanged = match table.get(key)? {
+                    Some(stored) => self
+                        .decode_script(SPKS_VALUE, key, stored.value())
+                        .is_ok_and(|stored| stored == *spk),
+                    None => false,
+                };
+                if unchanged {
+                    changes.skipped += 1;
+                    continue;
+                }
+                table.insert(
+                    key,
+                    self.seal_value(
+                        SPKS_VALUE,
+                        &row_key(key),
+                        self.compress_value(SPKS_VALUE, spk.to_bytes())?,
+                    )?,
+                )?;
+                changes.inserted += 1;
+            }
+        }
+        #[cfg(feature = "metrics")]
+        telemetry::record_persist(&self.wallet_name, "spks", changes);
+        Ok(changes)
+    }
+
+    #[cfg(feature = "wallet")]
+    /// This function loads the [`Wallet`]  by calling corresponding load functions for each of its
+    /// fields.
+    ///
+    /// If any of the tables of the wallet does not exist, [`StoreError::WalletNotInitialized`] is
+    /// returned. This is the case for a fresh database, until [`create_tables`] is called.
+    ///
+    /// The descriptors labelled 0 and 1 are read into `descriptor` and `change_descriptor`
+    /// independently, so a watch-only wallet without change keychain only has a `descriptor`.
+    /// `persist_wallet` never writes a change descriptor without an external one, but a change
+    /// descriptor persisted alone with [`persist_keychains`] is returned without `descriptor`, in
+    /// which case loading the [`Wallet`] fails with a missing descriptor error.
+    ///
+    /// [`create_tables`]: Self::create_tables
+    /// [`persist_keychains`]: Self::persist_keychains
+    /// [`Wallet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.Wallet.html>
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(wallet = %self.wallet_name))
+    )]
+    pub fn read_wallet(&self, changeset: &mut ChangeSet) -> Result<(), StoreError> {
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+        self.read_wallet_tables(changeset)
+            .map_err(|err| match err {
+                StoreError::RedbTable(TableError::TableDoesNotExist(_)) => {
+                    StoreError::WalletNotInitialized(self.wallet_name.to_string())
+                }
+                err => err,
+            })?;
+        #[cfg(feature = "metrics")]
+        telemetry::record_read_wallet(&self.wallet_name, start.elapsed());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "wallet")]
+    /// This function loads the whole persisted [`Wallet`] into a new [`ChangeSet`], see
+    /// [`read_wallet`]. It is meant for diagnostics and tests, to inspect or compare the
+    /// persisted state.
+    ///
+    /// [`read_wallet`]: Self::read_wallet
+    /// [`Wallet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.Wallet.html>
+    /// [`ChangeSet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.ChangeSet.html>
+    pub fn snapshot_changeset(&self) -> Result<ChangeSet, StoreError> {
+        let mut changeset = ChangeSet::default();
+        self.read_wallet(&mut changeset)?;
+        Ok(changeset)
+    }
+
+    #[cfg(feature = "wallet")]
+    // This function loads each field of the wallet changeset from its tables.
+    fn read_wallet_tables(&self, changeset: &mut ChangeSet) -> Result<(), StoreError> {
+        self.read_network(&mut changeset.network)?;
+        set_wallet_descriptors(changeset, self.read_all_keychains()?);
+        self.read_local_chain(&mut changeset.local_chain)?;
+        self.read_tx_graph::<ConfirmationBlockTime>(&mut changeset.tx_graph)?;
+        self.read_indexer(&mut changeset.indexer)?;
+        Ok(())
+    }
+
+    /// This function loads the [`TxGraph`] from db. It loads each field
+    /// by calling corresponding load functions.
+    ///
+    /// [`TxGraph`]: <http://docs.rs/bdk_chain/0.23.0/bdk_chain/tx_graph/struct.TxGraph.html>
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(wallet = %self.wallet_name))
+    )]
+    pub fn read_tx_graph<A: AnchorWithMetaData>(
+        &self,
+        changeset: &mut tx_graph::ChangeSet<A>,
+    ) -> Result<(), StoreError> {
+        self.check_anchor_type::<A>()?;
+        let read_tx = self.db.begin_read()?;
+        self.read_txs(&read_tx, &mut changeset.txs)?;
+        self.read_txouts(&read_tx, &mut changeset.txouts)?;
+        self.read_anchors::<A>(&read_tx, &mut changeset.anchors)?;
+        self.read_last_seen(&read_tx, &mut changeset.last_seen)?;
+        self.read_last_evicted(&read_tx, &mut changeset.last_evicted)?;
+        self.read_first_seen(&read_tx, &mut changeset.first_seen)?;
+        Ok(())
+    }
+
+    /// This function loads the txids of all persisted transactions. Unlike [`read_tx_graph`] it
+    /// only goes through the keys of the txs table, without deserializing the transactions.
+    ///
+    /// [`read_tx_graph`]: Self::read_tx_graph
+    pub fn txids(&self) -> Result<BTreeSet<Txid>, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let table = read_tx.open_table(self.txs_table_defn())?;
+
+        let mut txids = BTreeSet::new();
+        for entry in table.iter()? {
+            txids.insert(Txid::from_byte_array(entry?.0.value()));
+        }
+        Ok(txids)
+    }
+
+    /// This function returns `true` if the transaction with the given txid is persisted. Only the
+    /// key is looked up, the transaction is not deserialized (see [`get_tx`]). `false` is returned
+    /// if the txs table does not exist.
+    ///
+    /// [`get_tx`]: Self::get_tx
+    pub fn contains_tx(&self, txid: Txid) -> Result<bool, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let Some(table) = open_table_if_exists(&read_tx, self.txs_table_defn())? else {
+            return Ok(false);
+        };
+        Ok(table.get(txid.to_byte_array())?.is_some())
+    }
+
+    /// This function loads the transaction with the given txid, or returns `None` if it is not
+    /// persisted. Only that transaction is read and deserialized.
+    pub fn get_tx(&self, txid: Txid) -> Result<Option<Arc<Transaction>>, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let Some(table) = open_table_if_exists(&read_tx, self.txs_table_defn())? else {
+            return Ok(None);
+        };
+        let codec = self.tx_codec_in(&read_tx)?;
+        table
+            .get(txid.to_byte_array())?
+            .map(|tx_vec| Ok(Arc::new(self.decode_tx(codec, txid, tx_vec.value())?)))
+            .transpose()
+    }
+
+    /// This function loads the txout at the given outpoint, or returns `None` if it is not
+    /// persisted. The txouts table is looked up first, and if the outpoint is not in it the output
+    /// is taken from the transaction in the txs table, if that is persisted.
+    pub fn get_txout(&self, outpoint: OutPoint) -> Result<Option<TxOut>, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        if let Some(table) = open_table_if_exists(&read_tx, self.txouts_table_defn())? {
+            let key = (outpoint.txid.to_byte_array(), outpoint.vout);
+            if let Some(txout) = table.get(key)? {
+                let (value, script_pubkey) = txout.value();
+                return Ok(Some(TxOut {
+                    value: Amount::from_sat(value),
+                    script_pubkey: self.decode_script(TXOUTS_VALUE, key, script_pubkey)?,
+                }));
+            }
+        }
+
+        let Some(txs_table) = open_table_if_exists(&read_tx, self.txs_table_defn())? else {
+            return Ok(None);
+        };
+        match txs_table.get(outpoint.txid.to_byte_array())? {
+            Some(tx_vec) => Ok(self
+                .decode_tx(self.tx_codec_in(&read_tx)?, outpoint.txid, tx_vec.value())?
+                .output
+                .get(outpoint.vout as usize)
+                .cloned()),
+            None => Ok(None),
+        }
+    }
+
+    /// This function loads the txouts persisted in the txouts table whose outpoint has the given
+    /// txid, mapped by vout. The map is empty if none is persisted. Only the rows of that txid are
+    /// read.
+    ///
+    /// The outputs of a full transaction persisted in the txs table are not included unless they
+    /// were also persisted as txouts, see [`get_tx`].
+    ///
+    /// [`get_tx`]: Self::get_tx
+    pub fn txouts_for_tx(&self, txid: Txid) -> Result<BTreeMap<u32, TxOut>, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let mut txouts = BTreeMap::new();
+        let Some(table) = open_table_if_exists(&read_tx, self.txouts_table_defn())? else {
+            return Ok(txouts);
+        };
+        let txid_bytes = txid.to_byte_array();
+        for entry in table.range((txid_bytes, 0)..=(txid_bytes, u32::MAX))? {
+            let (key, txout) = entry?;
+            let key = key.value();
+            let (value, script_pubkey) = txout.value();
+            txouts.insert(
+                key.1,
+                TxOut {
+                    value: Amount::from_sat(value),
+                    script_pubkey: self.decode_script(TXOUTS_VALUE, key, script_pubkey)?,
+                },
+            );
+        }
+        Ok(txouts)
+    }
+
+    /// This function returns the block with the greatest height in the persisted local chain, or
+    /// `None` if no block is persisted. Only that block is read from the blocks table.
+    pub fn chain_tip(&self) -> Result<Option<BlockId>, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let Some(table) = open_table_if_exists(&read_tx, self.blocks_table_defn())? else {
+            return Ok(None);
+        };
+        table
+            .range::<u32>(..)?
+            .next_back()
+            .map(|entry| {
+                let (height, hash) = entry?;
+                Ok(BlockId {
+                    height: height.value(),
+                    hash: BlockHash::from_byte_array(hash.value()),
+                })
+            })
+            .transpose()
+    }
+
+    /// This function returns the hash of the block at `height` in the persisted local chain, or
+    /// `None` if no block is persisted at that height.
+    pub fn get_block_hash(&self, height: u32) -> Result<Option<BlockHash>, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let Some(table) = open_table_if_exists(&read_tx, self.blocks_table_defn())? else {
+            return Ok(None);
+        };
+        Ok(table
+            .get(height)?
+            .map(|hash| BlockHash::from_byte_array(hash.value())))
+    }
+
+    /// This function returns the last revealed index of the descriptor with the given id, or
+    /// `None` if none is persisted for it. Only that entry of the last_revealed table is read.
+    pub fn get_last_revealed(
+        &self,
+        descriptor_id: DescriptorId,
+    ) -> Result<Option<u32>, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let Some(table) = open_table_if_exists(&read_tx, self.last_revealed_table_defn())? else {
+            return Ok(None);
+        };
+        Ok(table
+            .get(descriptor_id.to_byte_array())?
+            .map(|index| index.value()))
+    }
+
+    /// This function returns the ids of the descriptors which have a last revealed index
+    /// persisted, in ascending order. An empty list is returned if the table does not exist.
+    pub fn descriptor_ids(&self) -> Result<Vec<DescriptorId>, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let Some(table) = open_table_if_exists(&read_tx, self.last_revealed_table_defn())? else {
+            return Ok(Vec::new());
+        };
+        let mut descriptor_ids = Vec::new();
+        for entry in table.iter()? {
+            descriptor_ids.push(DescriptorId::from_byte_array(entry?.0.value()));
+        }
+        Ok(descriptor_ids)
+    }
+
+    /// This function returns, for each descriptor with a last revealed index, the highest index
+    /// of its spk cache minus its last revealed index. A positive gap is the number of scripts
+    /// cached beyond the last revealed one, a negative gap means the cache is behind. A
+    /// descriptor without cached scripts counts as having a highest index of -1.
+    ///
+    /// The highest index of each descriptor is found with a reverse range query on the spk table,
+    /// so the cache is not scanned. Everything is read in a single read transaction.
+    pub fn spk_cache_gap(&self) -> Result<BTreeMap<DescriptorId, i64>, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let Some(last_revealed_table) =
+            open_table_if_exists(&read_tx, self.last_revealed_table_defn())?
+        else {
+            return Ok(BTreeMap::new());
+        };
+        let spk_table = open_table_if_exists(&read_tx, self.spk_table_defn())?;
+        let mut gaps = BTreeMap::new();
+        for entry in last_revealed_table.iter()? {
+            let (id, last_revealed) = entry?;
+            let id = id.value();
+            let max_cached = match &spk_table {
+                Some(table) => table
+                    .range((id, 0)..=(id, u32::MAX))?
+                    .next_back()
+                    .transpose()?
+                    .map_or(-1, |(key, _)| i64::from(key.value().1)),
+                None => -1,
+            };
+            gaps.insert(
+                DescriptorId::from_byte_array(id),
+                max_cached - i64::from(last_revealed.value()),
+            );
+        }
+        Ok(gaps)
+    }
+
+    /// This function loads the anchors of type `A` of the transaction with the given txid, ordered
+    /// by height. The block and metadata (e.g. the confirmation time of a
+    /// [`ConfirmationBlockTime`]) of each anchor are available from `A`.
+    ///
+    /// The anchors table is a multimap keyed by txid, so only the anchors of that transaction are
+    /// read. An empty list is returned if the transaction is not anchored or the table does not
+    /// exist.
+    ///
+    /// [`ConfirmationBlockTime`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/struct.ConfirmationBlockTime.html>
+    pub fn anchors_for_tx<A: AnchorWithMetaData>(&self, txid: Txid) -> Result<Vec<A>, StoreError> {
+        self.check_anchor_type::<A>()?;
+        let read_tx = self.db.begin_read()?;
+        let Some(table) = open_multimap_table_if_exists(&read_tx, self.anchors_table_defn())?
+        else {
+            return Ok(Vec::new());
+        };
+
+        // block ids are encoded with a big-endian height, so the anchors are in height order
+        let mut anchors = Vec::new();
+        for value in table.get(txid.to_byte_array())? {
+            let value = value?;
+            let (block_id_bytes, tag, metadata) = value.value();
+            if tag != A::TAG {
+                continue;
+            }
+            anchors.push(anchor_from_bytes(
+                block_id_from_bytes(&block_id_bytes)?,
+                metadata,
+            )?);
+        }
+        Ok(anchors)
+    }
+
+    /// This function loads the anchors of type `A` whose block height is in `range`, whatever the
+    /// transaction they anchor, ordered by height and then by txid.
+    ///
+    /// The anchors table is keyed by txid, so this scans the whole table: the cost is linear
+    /// in the number of persisted anchors (of all types), but the metadata of an anchor is only
+    /// decoded if its height is in `range`. An empty result is returned if the table does not
+    /// exist.
+    pub fn anchors_in_height_range<A: AnchorWithMetaData>(
+        &self,
+        range: impl RangeBounds<u32>,
+    ) -> Result<Vec<(A, Txid)>, StoreError> {
+        self.check_anchor_type::<A>()?;
+        let read_tx = self.db.begin_read()?;
+        let Some(table) = open_multimap_table_if_exists(&read_tx, self.anchors_table_defn())?
+        else {
+            return Ok(Vec::new());
+        };
+
+        let mut anchors = Vec::new();
+        for entry in table.iter()? {
+            let (txid, values) = entry?;
+            for value in values {
+                let value = value?;
+                let (block_id_bytes, tag, metadata) = value.value();
+                if tag != A::TAG {
+                    continue;
+                }
+                let block_id = block_id_from_bytes(&block_id_bytes)?;
+                if !range.contains(&block_id.height) {
+                    continue;
+                }
+                anchors.push((
+                    anchor_from_bytes::<A>(block_id, metadata)?,
+                    Txid::from_byte_array(txid.value()),
+                ));
+            }
+        }
+        anchors.sort_by(|(anchor1, txid1), (anchor2, txid2)| {
+            (anchor1.anchor_block().height, txid1).cmp(&(anchor2.anchor_block().height, txid2))
+        });
+        Ok(anchors)
+    }
+
+    /// This function loads the txouts whose transaction is not persisted, i.e. the floating
+    /// txouts (such as the previous outputs of transactions spending from other wallets). Both
+    /// tables are read in a single read transaction.
+    pub fn floating_txouts(&self) -> Result<BTreeMap<OutPoint, TxOut>, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let mut txouts = BTreeMap::new();
+        let Some(table) = open_table_if_exists(&read_tx, self.txouts_table_defn())? else {
+            return Ok(txouts);
+        };
+        let txs_table = open_table_if_exists(&read_tx, self.txs_table_defn())?;
+
+        for entry in table.iter()? {
+            let (outpoint, txout) = entry?;
+            let (txid, vout) = outpoint.value();
+            let tx_persisted = match &txs_table {
+                Some(txs_table) => txs_table.get(txid)?.is_some(),
+                None => false,
+            };
+            if !tx_persisted {
+                let (value, script_pubkey) = txout.value();
+                txouts.insert(
+                    OutPoint {
+                        txid: Txid::from_byte_array(txid),
+                        vout,
+                    },
+                    TxOut {
+                        value: Amount::from_sat(value),
+                        script_pubkey: self.decode_script(
+                            TXOUTS_VALUE,
+                            (txid, vout),
+                            script_pubkey,
+                        )?,
+                    },
+                );
+            }
+        }
+        Ok(txouts)
+    }
+
+    /// This function returns the first_seen timestamp of the transaction with the given txid, or
+    /// `None` if there is none.
+    pub fn get_first_seen(&self, txid: Txid) -> Result<Option<u64>, StoreError> {
+        self.get_timestamp(self.first_seen_table_defn(), txid)
+    }
+
+    /// This function returns the last_seen timestamp of the transaction with the given txid, or
+    /// `None` if there is none.
+    pub fn get_last_seen(&self, txid: Txid) -> Result<Option<u64>, StoreError> {
+        self.get_timestamp(self.last_seen_defn(), txid)
+    }
+
+    /// This function returns the last_evicted timestamp of the transaction with the given txid,
+    /// or `None` if there is none.
+    pub fn get_last_evicted(&self, txid: Txid) -> Result<Option<u64>, StoreError> {
+        self.get_timestamp(self.last_evicted_table_defn(), txid)
+    }
+
+    // This function looks up a single txid in the given table (first_seen, last_seen or
+    // last_evicted).
+    fn get_timestamp(
+        &self,
+        table_defn: TableDefinition<[u8; 32], u64>,
+        txid: Txid,
+    ) -> Result<Option<u64>, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let Some(table) = open_table_if_exists(&read_tx, table_defn)? else {
+            return Ok(None);
+        };
+        Ok(table.get(txid.to_byte_array())?.map(|time| time.value()))
+    }
+
+    /// This function loads the [`indexer`] structures from our db. It loads each
+    /// field by calling corresponding load functions. The spk cache is skipped if disabled with
+    /// [`with_spk_cache_loading`].
+    ///
+    /// [`with_spk_cache_loading`]: Self::with_spk_cache_loading
+    /// [`indexer`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/indexer/index.html>
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(wallet = %self.wallet_name))
+    )]
+    pub fn read_indexer(
+        &self,
+        changeset: &mut keychain_txout::ChangeSet,
+    ) -> Result<(), StoreError> {
+        let read_tx = self.db.begin_read()?;
+        self.read_last_revealed(&read_tx, &mut changeset.last_revealed)?;
+        if self.load_spk_cache {
+            self.read_spks(&read_tx, &mut changeset.spk_cache)?;
+        }
+        Ok(())
+    }
+
+    /// This function loads the cached script pubkeys of the descriptor with the given id whose
+    /// derivation index is in `range` into `spks`.
+    ///
+    /// Unlike [`read_indexer`], only the entries in `range` are read, using a range over the
+    /// (descriptor id, index) keys of the spk table, so a wallet with a large spk cache can load
+    /// only the indices it needs (for instance from its last revealed index minus the lookahead).
+    /// Nothing is loaded if the table does not exist.
+    ///
+    /// [`read_indexer`]: Self::read_indexer
+    pub fn read_spks_range(
+        &self,
+        descriptor_id: DescriptorId,
+        range: impl RangeBounds<u32>,
+        spks: &mut BTreeMap<u32, ScriptBuf>,
+    ) -> Result<(), StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let Some(table) = open_table_if_exists(&read_tx, self.spk_table_defn())? else {
+            return Ok(());
+        };
+        // the keys of a descriptor are contiguous and ordered by index, so the bounds on the index
+        // become bounds on the composite key
+        let id = descriptor_id.to_byte_array();
+        let start = match range.start_bound() {
+            Bound::Included(&index) => Bound::Included((id, index)),
+            Bound::Excluded(&index) => Bound::Excluded((id, index)),
+            Bound::Unbounded => Bound::Included((id, 0)),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&index) => Bound::Included((id, index)),
+            Bound::Excluded(&index) => Bound::Excluded((id, index)),
+            Bound::Unbounded => Bound::Included((id, u32::MAX)),
+        };
+        for entry in table.range((start, end))? {
+            let (key, spk) = entry?;
+            let key = key.value();
+            spks.insert(key.1, self.decode_script(SPKS_VALUE, key, spk.value())?);
+        }
+        Ok(())
+    }
+
+    /// This function loads descriptors from db. The checksum stored with each descriptor is
+    /// verified, [`StoreError::DescriptorChecksumMismatch`] being returned if it does not match,
+    /// and [`StoreError::InvalidDescriptor`] is returned if a descriptor cannot be parsed.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(wallet = %self.wallet_name))
+    )]
+    pub fn read_keychains(
+        &self,
+        desc_changeset: &mut BTreeMap<u64, Descriptor<DescriptorPublicKey>>,
+    ) -> Result<(), StoreError> {
+        self.read_keychains_in(&self.db.begin_read()?, desc_changeset)
+    }
+
+    // Same as read_keychains, in the given read transaction.
+    fn read_keychains_in(
+        &self,
+        read_tx: &ReadTransaction,
+        desc_changeset: &mut BTreeMap<u64, Descriptor<DescriptorPublicKey>>,
+    ) -> Result<(), StoreError> {
+        let table = read_tx.open_table(self.keychains_table_defn())?;
+
+        for entry in table.iter()? {
+            let (label, keychain) = entry?;
+            let desc_str = self.decode_descriptor(label.value(), keychain.value())?;
+            desc_changeset.insert(
+                label.value(),
+                parse_stored_descriptor(label.value(), &desc_str)?,
+            );
+        }
+
+        Ok(())
+    }
+
+    // This function returns the string stored in the keychains table for the descriptor labelled
+    // `label`, encrypting it if an encryption key is set.
+    #[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+    fn encode_descriptor(
+        &self,
+        label: u64,
+        desc: &Descriptor<DescriptorPublicKey>,
+    ) -> Result<String, StoreError> {
+        let desc_str = desc.to_string();
+        #[cfg(feature = "encryption")]
+        let desc_str = match &self.encryption_key {
+            Some(key) => encryption::encrypt(key, label, &desc_str)?,
+            None => desc_str,
+        };
+        Ok(desc_str)
+    }
+
+    // This function compresses `bytes` to be stored in the table identified by `table` (TXS_VALUE
+    // or SPKS_VALUE), if compression is enabled for it.
+    #[cfg_attr(not(feature = "compression"), allow(unused_variables))]
+    fn compress_value(&self, table: u8, bytes: Vec<u8>) -> Result<Vec<u8>, StoreError> {
+        #[cfg(feature = "compression")]
+        if let Some(level) = self.compression_level {
+            if table == TXS_VALUE || self.compress_spks {
+                return compression::compress(level, bytes);
+            }
+        }
+        Ok(bytes)
+    }
+
+    // This function returns `bytes` to be stored under `key` in the table identified by `table`
+    // (one of the *_VALUE constants), encrypting them if an encryption key is set.
+    #[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+    fn seal_value(&self, table: u8, key: &[u8], bytes: Vec<u8>) -> Result<Vec<u8>, StoreError> {
+        #[cfg(feature = "encryption")]
+        if let Some(encryption_key) = &self.encryption_key {
+            return encryption::seal(encryption_key, table, key, bytes);
+        }
+        Ok(bytes)
+    }
+
+    // This function reverses seal_value.
+    #[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+    fn open_value(&self, table: u8, key: &[u8], bytes: Vec<u8>) -> Result<Vec<u8>, StoreError> {
+        #[cfg(feature = "encryption")]
+        if let Some(encryption_key) = &self.encryption_key {
+            return encryption::open(encryption_key, table, key, &bytes);
+        }
+        Ok(bytes)
+    }
+
+    // This function decodes the transaction stored with `codec` in the txs table under `txid`,
+    // decrypting it if an encryption key is set.
+    fn decode_tx(
+        &self,
+        codec: TxCodec,
+        txid: Txid,
+        bytes: Vec<u8>,
+    ) -> Result<Transaction, StoreError> {
+        let bytes = self.open_value(TXS_VALUE, &txid.to_byte_array(), bytes)?;
+        let bytes = compression::decompress(bytes)?;
+        match codec {
+            TxCodec::Cbor => tx_from_bytes(txid, &bytes),
+            TxCodec::Consensus => consensus_tx_from_bytes(txid, &bytes),
+        }
+    }
+
+    // This function decodes a script stored under `key` in the txouts or spk table, decrypting it
+    // if an encryption key is set and decompressing it if it is a compressed spk.
+    fn decode_script(
+        &self,
+        table: u8,
+        key: ([u8; 32], u32),
+        bytes: Vec<u8>,
+    ) -> Result<ScriptBuf, StoreError> {
+        let bytes = self.open_value(table, &row_key(key), bytes)?;
+        if table == SPKS_VALUE {
+            return Ok(ScriptBuf::from_bytes(compression::decompress(bytes)?));
+        }
+        Ok(ScriptBuf::from_bytes(bytes))
+    }
+
+    // This function returns the descriptor string stored in the keychains table under `label`,
+    // decrypting it if an encryption key is set and verifying its checksum if it has one.
+    fn decode_descriptor(&self, label: u64, desc_str: String) -> Result<String, StoreError> {
+        #[cfg(feature = "encryption")]
+        let desc_str = match &self.encryption_key {
+            Some(key) => encryption::decrypt(key, label, &desc_str)?,
+            None => desc_str,
+        };
+        if let Some((desc, checksum)) = desc_str.rsplit_once('#') {
+            if desc_checksum(desc).ok().as_deref() != Some(checksum) {
+                return Err(StoreError::DescriptorChecksumMismatch { label });
+            }
+        }
+        Ok(desc_str)
+    }
+
+    /// This function returns the descriptors of all the keychains persisted in the keychains
+    /// table, mapped by their label.
+    ///
+    /// A [`ChangeSet`] only carries the descriptors labelled 0 (`descriptor`) and 1
+    /// (`change_descriptor`), so [`read_wallet`] ignores descriptors persisted under other labels
+    /// with [`persist_keychains`]. [`persist_wallet`] never removes them, and they can be loaded
+    /// with this function.
+    ///
+    /// [`ChangeSet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.ChangeSet.html>
+    /// [`read_wallet`]: Self::read_wallet
+    /// [`persist_wallet`]: Self::persist_wallet
+    /// [`persist_keychains`]: Self::persist_keychains
+    pub fn read_all_keychains(
+        &self,
+    ) -> Result<BTreeMap<u64, Descriptor<DescriptorPublicKey>>, StoreError> {
+        let mut desc_changeset = BTreeMap::new();
+        self.read_keychains(&mut desc_changeset)?;
+        Ok(desc_changeset)
+    }
+
+    /// This function returns the [`DescriptorId`] of each keychain persisted in the keychains
+    /// table, mapped by its label. This relates the labels of the keychains table to the
+    /// descriptor ids keying the indexer tables (see [`descriptor_ids`]).
+    ///
+    /// [`DescriptorId`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/struct.DescriptorId.html>
+    /// [`descriptor_ids`]: Self::descriptor_ids
+    pub fn keychain_descriptor_ids(&self) -> Result<BTreeMap<u64, DescriptorId>, StoreError> {
+        Ok(self
+            .read_all_keychains()?
+            .into_iter()
+            .map(|(label, desc)| (label, desc.descriptor_id()))
+            .collect())
+    }
+
+    /// This function loads the [`Network`] from our db, whether it is stored as a string or as a
+    /// byte (see [`with_compact_network`]). [`StoreError::UnknownNetwork`] or
+    /// [`StoreError::UnknownNetworkByte`] is returned if the string or byte does not correspond to
+    /// any known network.
+    /// <div class="warning">Warning: Do Not use with MAINNET</div>
+    ///
+    /// [`Network`]: <https://docs.rs/bitcoin/latest/bitcoin/enum.Network.html>
+    /// [`with_compact_network`]: Self::with_compact_network
+    pub fn read_network(&self, network: &mut Option<bitcoin::Network>) -> Result<(), StoreError> {
+        self.read_network_in(&self.db.begin_read()?, network)
+    }
+
+    // Same as read_network, in the given read transaction.
+    fn read_network_in(
+        &self,
+        read_tx: &ReadTransaction,
+        network: &mut Option<bitcoin::Network>,
+    ) -> Result<(), StoreError> {
+        if let Some(byte_table) = open_table_if_exists(read_tx, NETWORK_BYTE)? {
+            if let Some(byte) = byte_table.get(&*self.wallet_name)? {
+                *network = Some(network_from_byte(byte.value())?);
+                return Ok(());
+            }
+        }
+        let table = read_tx.open_table(NETWORK)?;
+        *network = table
+            .get(&*self.wallet_name)?
+            .map(|network| network_from_str(&network.value()))
+            .transpose()?;
+        Ok(())
+    }
+
+    /// This function loads the [`LocalChain`] structure from our db. It loads each
+    /// field by calling corresponding load functions.
+    ///
+    /// [`LocalChain`]: <http://docs.rs/bdk_chain/0.23.0/bdk_chain/local_chain/struct.LocalChain.html>
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(wallet = %self.wallet_name))
+    )]
+    pub fn read_local_chain(
+        &self,
+        changeset: &mut local_chain::ChangeSet,
+    ) -> Result<(), StoreError> {
+        let read_tx = self.db.begin_read()?;
+        self.read_blocks(&read_tx, &mut changeset.blocks)?;
+        Ok(())
+    }
+
+    // This function loads blocks corresponding to local_chain .
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(wallet = %self.wallet_name, table = "blocks", rows = tracing::field::Empty)
+        )
+    )]
+    fn read_blocks(
+        &self,
+        read_tx: &ReadTransaction,
+        blocks: &mut BTreeMap<u32, Option<BlockHash>>,
+    ) -> Result<(), StoreError> {
+        let table = read_tx.open_table(self.blocks_table_defn())?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("rows", table.len()?);
+
+        for entry in table.iter()? {
+            let (height, hash) = entry?;
+            blocks.insert(
+                height.value(),
+                Some(BlockHash::from_byte_array(hash.value())),
+            );
+        }
+
+        Ok(())
+    }
+
+    // This function loads txs corresponding to tx_graph.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(wallet = %self.wallet_name, table = "txs", rows = tracing::field::Empty)
+        )
+    )]
+    fn read_txs(
+        &self,
+        read_tx: &ReadTransaction,
+        txs: &mut BTreeSet<Arc<Transaction>>,
+    ) -> Result<(), StoreError> {
+        let table = read_tx.open_table(self.txs_table_defn())?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("rows", table.len()?);
+
+        let codec = self.tx_codec_in(read_tx)?;
+        for entry in table.iter()? {
+            let (txid, tx_vec) = entry?;
+            txs.insert(Arc::new(self.decode_tx(
+                codec,
+                Txid::from_byte_array(txid.value()),
+                tx_vec.value(),
+            )?));
+        }
+        Ok(())
+    }
+
+    // This function loads txouts corresponding to tx_graph.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(wallet = %self.wallet_name, table = "txouts", rows = tracing::field::Empty)
+        )
+    )]
+    fn read_txouts(
+        &self,
+        read_tx: &ReadTransaction,
+        txouts: &mut BTreeMap<OutPoint, TxOut>,
+    ) -> Result<(), StoreError> {
+        let table = read_tx.open_table(self.txouts_table_defn())?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("rows", table.len()?);
+
+        for entry in table.iter()? {
+            let (outpoint, txout) = entry?;
+            let (value, script_pubkey) = txout.value();
+            txouts.insert(
+                OutPoint {
+                    txid: Txid::from_byte_array(outpoint.value().0),
+                    vout: outpoint.value().1,
+                },
+                TxOut {
+                    value: Amount::from_sat(value),
+                    script_pubkey: self.decode_script(
+                        TXOUTS_VALUE,
+                        outpoint.value(),
+                        script_pubkey,
+                    )?,
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    // This function loads anchors corresponding to tx_graph.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(wallet = %self.wallet_name, table = "anchors", rows = tracing::field::Empty)
+        )
+    )]
+    fn read_anchors<A: AnchorWithMetaData>(
+        &self,
+        read_tx: &ReadTransaction,
+        anchors: &mut BTreeSet<(A, Txid)>,
+    ) -> Result<(), StoreError> {
+        let table = read_tx.open_multimap_table(self.anchors_table_defn())?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("rows", table.len()?);
+
+        for entry in table.iter()? {
+            let (txid, values) = entry?;
+            for value in values {
+                let value = value?;
+                let (block_id_bytes, tag, metadata) = value.value();
+                // skip anchors of other types
+                if tag != A::TAG {
+                    continue;
+                }
+                let block_id = block_id_from_bytes(&block_id_bytes)?;
+                anchors.insert((
+                    anchor_from_bytes(block_id, metadata)?,
+                    Txid::from_byte_array(txid.value()),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // This function loads last_seen flags corresponding to tx_graph.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(wallet = %self.wallet_name, table = "last_seen", rows = tracing::field::Empty)
+        )
+    )]
+    fn read_last_seen(
+        &self,
+        read_tx: &ReadTransaction,
+        last_seen: &mut BTreeMap<Txid, u64>,
+    ) -> Result<(), StoreError> {
+        let table = read_tx.open_table(self.last_seen_defn())?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("rows", table.len()?);
+
+        for entry in table.iter()? {
+            let (txid, last_seen_num) = entry?;
+            last_seen.insert(Txid::from_byte_array(txid.value()), last_seen_num.value());
+        }
+        Ok(())
+    }
+
+    // This function loads last_evicted flags corresponding to tx_graph .
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(wallet = %self.wallet_name, table = "last_evicted", rows = tracing::field::Empty)
+        )
+    )]
+    fn read_last_evicted(
+        &self,
+        read_tx: &ReadTransaction,
+        last_evicted: &mut BTreeMap<Txid, u64>,
+    ) -> Result<(), StoreError> {
+        let table = read_tx.open_table(self.last_evicted_table_defn())?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("rows", table.len()?);
+
+        for entry in table.iter()? {
+            let (txid, last_evicted_num) = entry?;
+            last_evicted.insert(
+                Txid::from_byte_array(txid.value()),
+                last_evicted_num.value(),
+            );
+        }
+        Ok(())
+    }
+
+    // This function loads first_seen flags corresponding to tx_graph.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(wallet = %self.wallet_name, table = "first_seen", rows = tracing::field::Empty)
+        )
+    )]
+    fn read_first_seen(
+        &self,
+        read_tx: &ReadTransaction,
+        first_seen: &mut BTreeMap<Txid, u64>,
+    ) -> Result<(), StoreError> {
+        let table = read_tx.open_table(self.first_seen_table_defn())?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("rows", table.len()?);
+
+        for entry in table.iter()? {
+            let (txid, first_seen_num) = entry?;
+            first_seen.insert(Txid::from_byte_array(txid.value()), first_seen_num.value());
+        }
+        Ok(())
+    }
+
+    // This function loads last_revealed corresponding to keychain_txout .
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(
+                wallet = %self.wallet_name,
+                table = "last_revealed",
+                rows = tracing::field::Empty
+            )
+        )
+    )]
+    fn read_last_revealed(
+        &self,
+        read_tx: &ReadTransaction,
+        last_revealed: &mut BTreeMap<DescriptorId, u32>,
+    ) -> Result<(), StoreError> {
+        let table = read_tx.open_table(self.last_revealed_table_defn())?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("rows", table.len()?);
+
+        for entry in table.iter()? {
+            let (desc, last_revealed_idx) = entry?;
+            last_revealed.insert(
+                DescriptorId::from_byte_array(desc.value()),
+                last_revealed_idx.value(),
+            );
+        }
+        Ok(())
+    }
+
+    // This function loads spk_cache corresponding to keychain_txout .
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(wallet = %self.wallet_name, table = "spks", rows = tracing::field::Empty)
+        )
+    )]
+    fn read_spks(
+        &self,
+        read_tx: &ReadTransaction,
+        spk_cache: &mut BTreeMap<DescriptorId, BTreeMap<u32, ScriptBuf>>,
+    ) -> Result<(), StoreError> {
+        let table = read_tx.open_table(self.spk_table_defn())?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("rows", table.len()?);
+
+        for entry in table.iter()? {
+            let (desc, spk) = entry?;
+            let spk = self.decode_script(SPKS_VALUE, desc.value(), spk.value())?;
+            spk_cache
+                .entry(DescriptorId::from_byte_array(desc.value().0))
+                .or_default()
+                .insert(desc.value().1, spk);
+        }
+        Ok(())
+    }
+}
+
+// This function encodes a BlockId as stored in the anchors table i.e. (height, hash). The height
+// is big-endian so that anchors of a tx are ordered by height. It is the only encoder of block
+// ids, reversed by block_id_from_bytes.
+fn block_id_to_bytes(block_id: &BlockId) -> [u8; BLOCK_ID_LEN] {
+    let mut bytes = [0; BLOCK_ID_LEN];
+    bytes[..HEIGHT_LEN].copy_from_slice(&block_id.height.to_be_bytes());
+    bytes[HEIGHT_LEN..].copy_from_slice(&block_id.hash.to_byte_array());
+    bytes
+}
+
+// This function encodes the metadata of an anchor as stored in the anchors table. If `compact`,
+// the trailing zero bytes of metadata of a fixed width type are dropped.
+fn anchor_metadata_to_bytes<A: AnchorWithMetaData>(anchor: &A, compact: bool) -> Vec<u8> {
+    let metadata = anchor.metadata();
+    let mut bytes = A::MetaDataType::as_bytes(&metadata).as_ref().to_vec();
+    if compact && A::MetaDataType::fixed_width().is_some() {
+        while bytes.last() == Some(&0) {
+            bytes.pop();
+        }
+    }
+    bytes
+}
+
+// This function decodes an anchor from its block and the metadata stored in the anchors table,
+// restoring the trailing zero bytes dropped by the compact encoding. Metadata longer than the
+// width of a fixed width type, which redb would panic on, is a StoreError::AnchorEncoding.
+fn anchor_from_bytes<A: AnchorWithMetaData>(
+    block_id: BlockId,
+    metadata: &[u8],
+) -> Result<A, StoreError> {
+    match A::MetaDataType::fixed_width() {
+        Some(width) if metadata.len() > width => Err(StoreError::AnchorEncoding {
+            expected: width,
+            found: metadata.len(),
+        }),
+        Some(width) if metadata.len() < width => {
+            let mut padded = metadata.to_vec();
+            padded.resize(width, 0);
+            Ok(A::from_id(block_id, A::MetaDataType::from_bytes(&padded)))
+        }
+        _ => Ok(A::from_id(block_id, A::MetaDataType::from_bytes(metadata))),
+    }
+}
+
+// This function inserts an anchor of a transaction in the anchors table, replacing the anchor of
+// the same type in the same block if its metadata is different, as the key (txid, block id, tag)
+// of older versions did.
+fn insert_anchor(
+    table: &mut MultimapTable<[u8; 32], AnchorValue>,
+    txid: [u8; 32],
+    block_id_bytes: [u8; 36],
+    tag: u8,
+    metadata: &[u8],
+) -> Result<(), StoreError> {
+    let mut replaced = Vec::new();
+    for value in table.get(txid)? {
+        let value = value?;
+        let (stored_block_id_bytes, stored_tag, stored_metadata) = value.value();
+        if stored_block_id_bytes == block_id_bytes
+            && stored_tag == tag
+            && stored_metadata != metadata
+        {
+            replaced.push(stored_metadata.to_vec());
+        }
+    }
+    for stored_metadata in replaced {
+        table.remove(txid, (block_id_bytes, tag, stored_metadata.as_slice()))?;
+    }
+    table.insert(txid, (block_id_bytes, tag, metadata))?;
+    Ok(())
+}
+
+// This function encodes the (txid or descriptor id, index) keys of the txouts and spk tables as
+// bytes, for the associated data of their encrypted values.
+fn row_key((id, index): ([u8; 32], u32)) -> [u8; 36] {
+    let mut bytes = [0; 36];
+    bytes[..32].copy_from_slice(&id);
+    bytes[32..].copy_from_slice(&index.to_be_bytes());
+    bytes
+}
+
+// This function parses a descriptor read from the keychains table under `label`, returning
+// StoreError::InvalidDescriptor if it is not valid.
+fn parse_stored_descriptor(
+    label: u64,
+    desc_str: &str,
+) -> Result<Descriptor<DescriptorPublicKey>, StoreError> {
+    Descriptor::from_str(desc_str).map_err(|_| StoreError::InvalidDescriptor { label })
+}
+
+// This function opens a table for reading, returning `None` if it was not created yet.
+fn open_table_if_exists<K: Key + 'static, V: Value + 'static>(
+    read_tx: &ReadTransaction,
+    table_defn: TableDefinition<K, V>,
+) -> Result<Option<ReadOnlyTable<K, V>>, StoreError> {
+    match read_tx.open_table(table_defn) {
+        Ok(table) => Ok(Some(table)),
+        Err(TableError::TableDoesNotExist(_)) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// Same as open_table_if_exists for a multimap table.
+fn open_multimap_table_if_exists<K: Key + 'static, V: Key + 'static>(
+    read_tx: &ReadTransaction,
+    table_defn: MultimapTableDefinition<K, V>,
+) -> Result<Option<ReadOnlyMultimapTable<K, V>>, StoreError> {
+    match read_tx.open_multimap_table(table_defn) {
+        Ok(table) => Ok(Some(table)),
+        Err(TableError::TableDoesNotExist(_)) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(feature = "wallet")]
+// This function sets the descriptor and change descriptor of `changeset` from the descriptors
+// labelled 0 and 1, each independently of the other so that no persisted descriptor is dropped.
+fn set_wallet_descriptors(
+    changeset: &mut ChangeSet,
+    mut desc_changeset: BTreeMap<u64, Descriptor<DescriptorPublicKey>>,
+) {
+    if let Some(desc) = desc_changeset.remove(&0) {
+        changeset.descriptor = Some(desc);
+    }
+    if let Some(change_desc) = desc_changeset.remove(&1) {
+        changeset.change_descriptor = Some(change_desc);
+    }
+}
+
+// This function checks that a wallet name or prefix does not contain the ':' separating prefixes
+// from wallet names.
+fn check_name(name: &str) -> Result<(), StoreError> {
+    if name.contains(':') {
+        return Err(StoreError::InvalidWalletName(name.to_string()));
+    }
+    Ok(())
+}
+
+// This function opens the given table in `read_tx` and reads its first entry.
+fn check_table<K: Key + 'static, V: Value + 'static>(
+    read_tx: &ReadTransaction,
+    table_defn: TableDefinition<K, V>,
+) -> Result<(), StoreError> {
+    read_tx.open_table(table_defn)?.first()?;
+    Ok(())
+}
+
+// This function returns the checksum of the ciborium encoding of a transaction.
+#[cfg(feature = "serde")]
+fn tx_checksum(cbor: &[u8]) -> [u8; TX_CHECKSUM_LEN] {
+    let hash = bitcoin::hashes::sha256::Hash::hash(cbor).to_byte_array();
+    hash[..TX_CHECKSUM_LEN]
+        .try_into()
+        .expect("slice has length TX_CHECKSUM_LEN")
+}
+
+// This function encodes a transaction to be stored in the txs table: CHECKSUMMED_TX_TAG, the
+// checksum and the ciborium encoding.
+#[cfg(feature = "serde")]
+fn tx_to_bytes(tx: &Transaction) -> Result<Vec<u8>, StoreError> {
+    let mut cbor: Vec<u8> = Vec::new();
+    ciborium::into_writer(tx, &mut cbor)?;
+    let mut bytes = Vec::with_capacity(1 + TX_CHECKSUM_LEN + cbor.len());
+    bytes.push(CHECKSUMMED_TX_TAG);
+    bytes.extend_from_slice(&tx_checksum(&cbor));
+    bytes.extend_from_slice(&cbor);
+    Ok(bytes)
+}
+
+// This function decodes the transaction stored in the txs table under `txid`. Checksummed rows are
+// verified, returning StoreError::ChecksumMismatch if they were corrupted. Rows written before
+// checksums were added are plain ciborium encodings, and consensus encoded ones are also accepted
+// so that a table can hold both during a change of format. A ciborium encoded transaction starts
+// with a map header, which is never mistaken for the version at the start of a consensus encoding.
+#[cfg(feature = "serde")]
+fn tx_from_bytes(txid: Txid, bytes: &[u8]) -> Result<Transaction, StoreError> {
+    if let [CHECKSUMMED_TX_TAG, rest @ ..] = bytes {
+        if let Some((checksum, cbor)) = rest.split_first_chunk::<TX_CHECKSUM_LEN>() {
+            if *checksum != tx_checksum(cbor) {
+                return Err(StoreError::ChecksumMismatch { txid });
+            }
+            return Ok(ciborium::from_reader(cbor)?);
+        }
+    }
+    match ciborium::from_reader(bytes) {
+        Ok(tx) => Ok(tx),
+        Err(_) => bitcoin::consensus::deserialize(bytes).map_err(|_| StoreError::UnknownTxEncoding),
+    }
+}
+
+// This function is tx_from_bytes without the serde feature: consensus encoded rows are still read,
+// and the other ones, being ciborium encodings, return StoreError::CborDisabled.
+#[cfg(not(feature = "serde"))]
+fn tx_from_bytes(_txid: Txid, bytes: &[u8]) -> Result<Transaction, StoreError> {
+    if bytes.first() == Some(&CHECKSUMMED_TX_TAG) {
+        return Err(StoreError::CborDisabled);
+    }
+    bitcoin::consensus::deserialize(bytes).map_err(|_| StoreError::CborDisabled)
+}
+
+// This function decodes the consensus encoded transaction stored in the txs table under `txid`.
+// The txid serves as checksum, returning StoreError::ChecksumMismatch if the row was corrupted.
+fn consensus_tx_from_bytes(txid: Txid, bytes: &[u8]) -> Result<Transaction, StoreError> {
+    let tx: Transaction =
+        bitcoin::consensus::deserialize(bytes).map_err(|_| StoreError::UnknownTxEncoding)?;
+    if tx.compute_txid() != txid {
+        return Err(StoreError::ChecksumMismatch { txid });
+    }
+    Ok(tx)
+}
+
+// This function returns the byte storing `network` in the NETWORK_BYTE table. The bytes must never
+// change as they are persisted.
+fn network_to_byte(network: Network) -> u8 {
+    match network {
+        Network::Bitcoin => 0,
+        Network::Testnet => 1,
+        Network::Testnet4 => 2,
+        Network::Signet => 3,
+        Network::Regtest => 4,
+    }
+}
+
+// This function decodes a network stored in the NETWORK_BYTE table.
+fn network_from_byte(byte: u8) -> Result<Network, StoreError> {
+    match byte {
+        0 => Ok(Network::Bitcoin),
+        1 => Ok(Network::Testnet),
+        2 => Ok(Network::Testnet4),
+        3 => Ok(Network::Signet),
+        4 => Ok(Network::Regtest),
+        _ => Err(StoreError::UnknownNetworkByte(byte)),
+    }
+}
+
+// This function decodes a network stored in the NETWORK table.
+fn network_from_str(network: &str) -> Result<Network, StoreError> {
+    Network::from_str(network).map_err(|_| StoreError::UnknownNetwork(network.to_string()))
+}
+
+// This function decodes a BlockId stored in the anchors table, returning
+// StoreError::AnchorEncoding if `bytes` is not BLOCK_ID_LEN long.
+fn block_id_from_bytes(bytes: &[u8]) -> Result<BlockId, StoreError> {
+    let invalid = || StoreError::AnchorEncoding {
+        expected: BLOCK_ID_LEN,
+        found: bytes.len(),
+    };
+    if bytes.len() != BLOCK_ID_LEN {
+        return Err(invalid());
+    }
+    let (height, hash) = bytes.split_at(HEIGHT_LEN);
+    Ok(BlockId {
+        height: u32::from_be_bytes(height.try_into().map_err(|_| invalid())?),
+        hash: BlockHash::from_slice(hash)?,
+    })
+}
+
+/// This function compacts the database file, reclaiming the space freed by deleted entries (for
+/// instance after removing a wallet's tables). It returns `true` if any compaction was performed.
+///
+/// Compaction needs exclusive access to the [`Database`], so `db` must be the only strong
+/// reference to it: every [`Store`] (and any other clone of the `Arc`) sharing the database must be
+/// dropped first, otherwise [`StoreError::DatabaseInUse`] is returned. The database is closed once
+/// compaction is over and has to be opened again to be used.
+///
+/// [`Database`]: <https://docs.rs/redb/2.5.0/redb/struct.Database.html>
+pub fn compact(mut db: Arc<Database>) -> Result<bool, StoreError> {
+    compact_db(&mut db)
+}
+
+/// This function lists the wallets persisted in `db` along with their [`Network`], if one was
+/// persisted. A wallet is listed once its tables were created or its network was persisted, under
+/// the name its tables are keyed by (`<prefix>:<wallet_name>` for a store created with
+/// [`Store::new_with_prefix`]). Only a read transaction is opened.
+///
+/// [`Network`]: <https://docs.rs/bitcoin/latest/bitcoin/enum.Network.html>
+pub fn list_wallets(db: &Database) -> Result<BTreeMap<String, Option<Network>>, StoreError> {
+    let read_tx = db.begin_read()?;
+    let mut wallets = BTreeMap::new();
+    if let Some(table) = open_table_if_exists(&read_tx, FORMAT_VERSION)? {
+        for entry in table.iter()? {
+            wallets.insert(entry?.0.value().to_string(), None);
+        }
+    }
+    if let Some(table) = open_table_if_exists(&read_tx, NETWORK)? {
+        for entry in table.iter()? {
+            let (wallet_name, network) = entry?;
+            let network = network_from_str(&network.value())?;
+            wallets.insert(wallet_name.value().to_string(), Some(network));
+        }
+    }
+    if let Some(table) = open_table_if_exists(&read_tx, NETWORK_BYTE)? {
+        for entry in table.iter()? {
+            let (wallet_name, byte) = entry?;
+            let network = network_from_byte(byte.value())?;
+            wallets.insert(wallet_name.value().to_string(), Some(network));
+        }
+    }
+    Ok(wallets)
+}
+
+// This function removes the row of `wallet_name` from a table shared by all wallets, without
+// creating the table if it does not exist. It returns whether the row existed.
+fn remove_wallet_row<V: Value + 'static>(
+    write_tx: &WriteTransaction,
+    table_defn: TableDefinition<&str, V>,
+    wallet_name: &str,
+) -> Result<bool, StoreError> {
+    if !write_tx
+        .list_tables()?
+        .any(|table| table.name() == table_defn.name())
+    {
+        return Ok(false);
+    }
+    let mut table = write_tx.open_table(table_defn)?;
+    let removed = table.remove(wallet_name)?.is_some();
+    Ok(removed)
+}
+
+// This function compacts the database behind `db` if it is not shared.
+fn compact_db(db: &mut Arc<Database>) -> Result<bool, StoreError> {
+    Ok(exclusive_db(db)?.compact()?)
+}
+
+// This function returns the database behind `db` if no other handle to it is alive.
+fn exclusive_db(db: &mut Arc<Database>) -> Result<&mut Database, StoreError> {
+    let handles = Arc::strong_count(db);
+    Arc::get_mut(db).ok_or(StoreError::DatabaseInUse(handles))
+}
+
+#[cfg(feature = "wallet")]
+impl Store {
+    // This function implements WalletPersister::initialize. The birthday of the wallet is set to
+    // the current time if nothing was persisted yet, i.e. if the wallet is being created.
+    fn initialize_wallet(&self) -> Result<ChangeSet, StoreError> {
+        self.create_tables::<ConfirmationBlockTime>()?;
+        let mut changeset = ChangeSet::default();
+        self.read_wallet(&mut changeset)?;
+        if changeset.is_empty() && self.birthday()?.is_none() {
+            self.set_birthday(self.now(), None)?;
+        }
+        Ok(changeset)
+    }
+}
+
+#[cfg(feature = "wallet")]
+impl WalletPersister for Store {
+    type Error = StoreError;
+
+    fn initialize(persister: &mut Self) -> Result<ChangeSet, Self::Error> {
+        persister.initialize_wallet()
+    }
+
+    fn persist(persister: &mut Self, changeset: &ChangeSet) -> Result<(), Self::Error> {
+        persister.persist_wallet(changeset)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "wallet")]
+impl WalletPersister for &mut Store {
+    type Error = StoreError;
+
+    fn initialize(persister: &mut Self) -> Result<ChangeSet, Self::Error> {
+        persister.initialize_wallet()
+    }
+
+    fn persist(persister: &mut Self, changeset: &ChangeSet) -> Result<(), Self::Error> {
+        persister.persist_wallet(changeset)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod test {
+    use super::*;
+    use bdk_chain::BlockId;
+    use bdk_chain::ConfirmationBlockTime;
+    use bdk_chain::{
+        DescriptorExt, Merge,
+        bitcoin::{
+            self, Amount, BlockHash, OutPoint, ScriptBuf, Transaction, TxIn, TxOut, absolute,
+            hashes::Hash, transaction, transaction::Txid,
+        },
+        keychain_txout, local_chain,
+        miniscript::descriptor::Descriptor,
+    };
+
+    use std::sync::Arc;
+    use std::{collections::BTreeMap, path::Path};
+    use tempfile::NamedTempFile;
+
+    use bdk_testenv::{block_id, hash, utils};
+
+    const DESCRIPTORS: [&str; 4] = [
+        "tr([5940b9b9/86'/0'/0']tpubDDVNqmq75GNPWQ9UNKfP43UwjaHU4GYfoPavojQbfpyfZp2KetWgjGBRRAy4tYCrAA6SB11mhQAkqxjh1VtQHyKwT4oYxpwLaGHvoKmtxZf/1/*)#ypcpw2dr",
+        "tr([5940b9b9/86'/0'/0']tpubDDVNqmq75GNPWQ9UNKfP43UwjaHU4GYfoPavojQbfpyfZp2KetWgjGBRRAy4tYCrAA6SB11mhQAkqxjh1VtQHyKwT4oYxpwLaGHvoKmtxZf/0/*)#44aqnlam",
+        "wpkh([41f2aed0/84h/1h/0h]tpubDDFSdQWw75hk1ewbwnNpPp5DvXFRKt68ioPoyJDY752cNHKkFxPWqkqCyCf4hxrEfpuxh46QisehL3m8Bi6MsAv394QVLopwbtfvryFQNUH/1/*)#emtwewtk",
+        "wpkh([41f2aed0/84h/1h/0h]tpubDDFSdQWw75hk1ewbwnNpPp5DvXFRKt68ioPoyJDY752cNHKkFxPWqkqCyCf4hxrEfpuxh46QisehL3m8Bi6MsAv394QVLopwbtfvryFQNUH/0/*)#g0w0ymmw",
+    ];
+
+    fn create_db(path: impl AsRef<Path>) -> Database {
+        Database::create(path).unwrap()
+    }
+
+    fn create_test_store(db: Arc<Database>, wallet_name: &str) -> Store {
+        Store::new(db, wallet_name.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_network_persistence() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store.create_network_table().unwrap();
+        let network_changeset = Some(Network::Bitcoin);
+        store.persist_network(&network_changeset).unwrap();
+
+        let mut network_changeset = Some(Network::Regtest);
+        store.read_network(&mut network_changeset).unwrap();
+
+        assert_eq!(network_changeset, Some(Network::Bitcoin));
+    }
+
+    #[test]
+    fn test_list_wallets() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        assert!(list_wallets(&db).unwrap().is_empty());
+
+        let store1 = create_test_store(db.clone(), "wallet1");
+        store1.create_tables::<ConfirmationBlockTime>().unwrap();
+        let store2 = create_test_store(db.clone(), "wallet2").with_compact_network(true);
+        store2.create_tables::<ConfirmationBlockTime>().unwrap();
+        store2.persist_network(&Some(Network::Signet)).unwrap();
+        let store3 =
+            Store::new_with_prefix(db.clone(), "app".to_string(), "wallet1".to_string()).unwrap();
+        store3.create_network_table().unwrap();
+        store3.persist_network(&Some(Network::Regtest)).unwrap();
+
+        assert_eq!(
+            list_wallets(&db).unwrap(),
+            [
+                ("app:wallet1".to_string(), Some(Network::Regtest)),
+                ("wallet1".to_string(), None),
+                ("wallet2".to_string(), Some(Network::Signet)),
+            ]
+            .into()
+        );
+    }
+
+    #[test]
+    fn test_delete_wallet() {
+        use redb::MultimapTableHandle;
+
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store1 = create_test_store(db.clone(), "wallet1");
+        assert!(!store1.delete_wallet().unwrap());
+
+        store1.create_tables::<ConfirmationBlockTime>().unwrap();
+        store1.persist_network(&Some(Network::Signet)).unwrap();
+        store1
+            .persist_local_chain(&local_chain::ChangeSet {
+                blocks: [(0, Some(hash!("A")))].into(),
+            })
+            .unwrap();
+        store1.put_meta("label", b"savings").unwrap();
+        let store2 = create_test_store(db.clone(), "wallet2");
+        store2.create_tables::<ConfirmationBlockTime>().unwrap();
+        store2.persist_network(&Some(Network::Regtest)).unwrap();
+
+        assert!(store1.delete_wallet().unwrap());
+        assert_eq!(
+            list_wallets(&db).unwrap(),
+            [("wallet2".to_string(), Some(Network::Regtest))].into()
+        );
+        let read_tx = db.begin_read().unwrap();
+        let names: Vec<String> = read_tx
+            .list_tables()
+            .unwrap()
+            .map(|table| table.name().to_string())
+            .chain(
+                read_tx
+                    .list_multimap_tables()
+                    .unwrap()
+                    .map(|table| table.name().to_string()),
+            )
+            .collect();
+        assert!(names.iter().all(|name| !name.starts_with("wallet1")));
+        drop(read_tx);
+        assert!(!store1.delete_wallet().unwrap());
+
+        // the wallet can be persisted again
+        store1.create_tables::<ConfirmationBlockTime>().unwrap();
+        assert_eq!(store1.chain_tip().unwrap(), None);
+        assert_eq!(store1.get_meta("label").unwrap(), None);
+        store1.persist_network(&Some(Network::Bitcoin)).unwrap();
+        let mut network = None;
+        store2.read_network(&mut network).unwrap();
+        assert_eq!(network, Some(Network::Regtest));
+    }
+
+    #[cfg(feature = "uniffi")]
+    #[test]
+    fn test_uniffi_scaffolding() {
+        use crate::ffi::FfiStats;
+        use std::ffi::c_void;
+        use uniffi::{Lift, Lower, RustBuffer, RustCallStatus, RustCallStatusCode};
+
+        // these are the functions called by the generated Kotlin and Swift bindings
+        let lower = |value: String| <String as Lower<crate::UniFfiTag>>::lower(value);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallets.redb");
+        let mut status = RustCallStatus::default();
+        let store: *const c_void = crate::ffi::uniffi_bdk_redb_fn_constructor_ffistore_open(
+            lower(path.to_str().unwrap().to_string()),
+            lower("wallet1".to_string()),
+            &mut status,
+        );
+        assert_eq!(status.code, RustCallStatusCode::Success);
+
+        let clone = |store| {
+            let mut status = RustCallStatus::default();
+            // SAFETY: `store` was returned by the constructor and is not freed yet
+            let clone =
+                unsafe { crate::ffi::uniffi_bdk_redb_fn_clone_ffistore(store, &mut status) };
+            assert_eq!(status.code, RustCallStatusCode::Success);
+            clone
+        };
+        let mut status = RustCallStatus::default();
+        let changeset = crate::ffi::uniffi_bdk_redb_fn_method_ffistore_read_changeset(
+            clone(store),
+            &mut status,
+        );
+        assert_eq!(status.code, RustCallStatusCode::Success);
+        let changeset = <Vec<u8> as Lift<crate::UniFfiTag>>::try_lift(changeset).unwrap();
+        let changeset: ChangeSet = ciborium::from_reader(changeset.as_slice()).unwrap();
+        assert!(changeset.is_empty());
+
+        let changeset = ChangeSet {
+            network: Some(Network::Signet),
+            local_chain: local_chain::ChangeSet {
+                blocks: [(0, Some(hash!("A")))].into(),
+            },
+            ..Default::default()
+        };
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&changeset, &mut bytes).unwrap();
+        let mut status = RustCallStatus::default();
+        crate::ffi::uniffi_bdk_redb_fn_method_ffistore_persist(
+            clone(store),
+            <Vec<u8> as Lower<crate::UniFfiTag>>::lower(bytes),
+            &mut status,
+        );
+        assert_eq!(status.code, RustCallStatusCode::Success);
+
+        let mut status = RustCallStatus::default();
+        let stats = crate::ffi::uniffi_bdk_redb_fn_method_ffistore_stats(clone(store), &mut status);
+        assert_eq!(status.code, RustCallStatusCode::Success);
+        let stats = <FfiStats as Lift<crate::UniFfiTag>>::try_lift(stats).unwrap();
+        assert_eq!(stats.blocks, 1);
+
+        let mut status = RustCallStatus::default();
+        let wallets =
+            crate::ffi::uniffi_bdk_redb_fn_method_ffistore_list_wallets(clone(store), &mut status);
+        assert_eq!(status.code, RustCallStatusCode::Success);
+        assert_eq!(
+            <Vec<String> as Lift<crate::UniFfiTag>>::try_lift(wallets).unwrap(),
+            ["wallet1".to_string()]
+        );
+
+        // errors are returned as the index of the FfiError variant followed by the message
+        let mut status = RustCallStatus::default();
+        crate::ffi::uniffi_bdk_redb_fn_method_ffistore_persist(
+            clone(store),
+            <Vec<u8> as Lower<crate::UniFfiTag>>::lower(vec![0xff]),
+            &mut status,
+        );
+        assert_eq!(status.code, RustCallStatusCode::Error);
+        let error_buf = std::mem::replace(&mut *status.error_buf, RustBuffer::new());
+        let error = error_buf.destroy_into_vec();
+        // FfiError::Encoding is the third variant
+        assert_eq!(error[..4], 3i32.to_be_bytes());
+
+        let mut status = RustCallStatus::default();
+        let deleted =
+            crate::ffi::uniffi_bdk_redb_fn_method_ffistore_delete_wallet(clone(store), &mut status);
+        assert_eq!(status.code, RustCallStatusCode::Success);
+        assert_eq!(deleted, 1);
+
+        let mut status = RustCallStatus::default();
+        // SAFETY: `store` was returned by the constructor and every method consumed a clone of it
+        unsafe { crate::ffi::uniffi_bdk_redb_fn_free_ffistore(store, &mut status) };
+        assert_eq!(status.code, RustCallStatusCode::Success);
+    }
+
+    #[cfg(feature = "ffi")]
+    #[test]
+    fn test_ffi_store() {
+        use crate::ffi::{FfiError, FfiStore};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallets.redb");
+        let store =
+            FfiStore::open(path.to_str().unwrap().to_string(), "wallet1".to_string()).unwrap();
+
+        let encode = |changeset: &ChangeSet| {
+            let mut bytes = Vec::new();
+            ciborium::into_writer(changeset, &mut bytes).unwrap();
+            bytes
+        };
+        let decode =
+            |bytes: Vec<u8>| -> ChangeSet { ciborium::from_reader(bytes.as_slice()).unwrap() };
+        assert!(decode(store.read_changeset().unwrap()).is_empty());
+
+        let tx = Arc::new(create_one_inp_one_out_tx(hash!("T"), 30_000));
+        let changeset = ChangeSet {
+            descriptor: Some(parse_descriptor(DESCRIPTORS[0])),
+            change_descriptor: Some(parse_descriptor(DESCRIPTORS[1])),
+            network: Some(Network::Signet),
+            local_chain: local_chain::ChangeSet {
+                blocks: [(0, Some(hash!("A"))), (2, Some(hash!("B")))].into(),
+            },
+            tx_graph: tx_graph::ChangeSet {
+                txs: [tx.clone()].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        store.persist(encode(&changeset)).unwrap();
+        let read = decode(store.read_changeset().unwrap());
+        assert_eq!(read.descriptor, changeset.descriptor);
+        assert_eq!(read.network, changeset.network);
+        assert_eq!(read.local_chain, changeset.local_chain);
+        assert_eq!(read.tx_graph.txs, changeset.tx_graph.txs);
+
+        let stats = store.stats().unwrap();
+        assert_eq!((stats.transactions, stats.blocks), (1, 2));
+        assert!(stats.allocated_bytes > 0);
+        assert_eq!(store.list_wallets().unwrap(), ["wallet1".to_string()]);
+
+        assert!(matches!(
+            store.persist(vec![0xff, 0x00]),
+            Err(FfiError::Encoding(_))
+        ));
+        let mut other_descriptor = ChangeSet {
+            descriptor: Some(parse_descriptor(DESCRIPTORS[2])),
+            ..Default::default()
+        };
+        assert!(matches!(
+            store.persist(encode(&other_descriptor)),
+            Err(FfiError::Rejected(_))
+        ));
+        assert_eq!(
+            FfiError::from(StoreError::WalletNotInitialized("wallet1".to_string())),
+            FfiError::NotInitialized("Wallet wallet1 is not initialized".to_string())
+        );
+
+        assert!(store.delete_wallet().unwrap());
+        assert!(store.list_wallets().unwrap().is_empty());
+        assert!(decode(store.read_changeset().unwrap()).is_empty());
+        other_descriptor.network = Some(Network::Bitcoin);
+        store.persist(encode(&other_descriptor)).unwrap();
+        assert_eq!(store.stats().unwrap().transactions, 0);
+    }
+
+    #[test]
+    fn test_compact_network_persistence() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        for (i, network) in [
+            Network::Bitcoin,
+            Network::Testnet,
+            Network::Testnet4,
+            Network::Signet,
+            Network::Regtest,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let store =
+                create_test_store(db.clone(), &format!("wallet{i}")).with_compact_network(true);
+            store.create_network_table().unwrap();
+            store.persist_network(&Some(network)).unwrap();
+
+            let read_tx = db.begin_read().unwrap();
+            let byte_table = read_tx.open_table(NETWORK_BYTE).unwrap();
+            assert_eq!(
+                byte_table
+                    .get(&*store.wallet_name)
+                    .unwrap()
+                    .unwrap()
+                    .value(),
+                i as u8
+            );
+            let table = read_tx.open_table(NETWORK).unwrap();
+            assert!(table.get(&*store.wallet_name).unwrap().is_none());
+
+            let mut network_changeset = None;
+            store.read_network(&mut network_changeset).unwrap();
+            assert_eq!(network_changeset, Some(network));
+        }
+
+        let write_tx = db.begin_write().unwrap();
+        write_tx
+            .open_table(NETWORK_BYTE)
+            .unwrap()
+            .insert("wallet0", 200)
+            .unwrap();
+        write_tx.commit().unwrap();
+        let store = create_test_store(db, "wallet0");
+        match store.read_network(&mut None) {
+            Ok(_) => panic!("should have failed"),
+            Err(StoreError::UnknownNetworkByte(200)) => {}
+            Err(_) => panic!("unexpected error"),
+        }
+    }
+
+    #[test]
+    fn test_unknown_network_string() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let write_tx = db.begin_write().unwrap();
+        write_tx
+            .open_table(NETWORK)
+            .unwrap()
+            .insert("wallet1", "moonnet".to_string())
+            .unwrap();
+        write_tx.commit().unwrap();
+
+        let store = create_test_store(db.clone(), "wallet1");
+        assert!(matches!(
+            store.read_network(&mut None),
+            Err(StoreError::UnknownNetwork(network)) if network == "moonnet"
+        ));
+        assert!(matches!(
+            list_wallets(&db),
+            Err(StoreError::UnknownNetwork(network)) if network == "moonnet"
+        ));
+        // the row is kept when the migration to the compact format fails
+        let compact_store = create_test_store(db.clone(), "wallet1").with_compact_network(true);
+        assert!(matches!(
+            compact_store.create_network_table(),
+            Err(StoreError::UnknownNetwork(network)) if network == "moonnet"
+        ));
+        let read_tx = db.begin_read().unwrap();
+        let table = read_tx.open_table(NETWORK).unwrap();
+        assert_eq!(table.get("wallet1").unwrap().unwrap().value(), "moonnet");
+    }
+
+    #[test]
+    fn test_compact_network_migration() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        store.persist_network(&Some(Network::Signet)).unwrap();
+
+        // the string format is still read once the compact one is enabled
+        let compact_store = create_test_store(db.clone(), "wallet1").with_compact_network(true);
+        let mut network_changeset = None;
+        compact_store.read_network(&mut network_changeset).unwrap();
+        assert_eq!(network_changeset, Some(Network::Signet));
+
+        // the string format is current without the compact option
+        assert!(Store::attach(db.clone(), "wallet1".to_string()).is_ok());
+        assert!(!compact_store.is_initialized().unwrap());
+        compact_store
+            .create_tables::<ConfirmationBlockTime>()
+            .unwrap();
+        assert!(compact_store.is_initialized().unwrap());
+
+        let read_tx = db.begin_read().unwrap();
+        let byte_table = read_tx.open_table(NETWORK_BYTE).unwrap();
+        assert_eq!(byte_table.get("wallet1").unwrap().unwrap().value(), 3);
+        let table = read_tx.open_table(NETWORK).unwrap();
+        assert!(table.get("wallet1").unwrap().is_none());
+
+        // both stores read the migrated network
+        for store in [&store, &compact_store] {
+            let mut network_changeset = None;
+            store.read_network(&mut network_changeset).unwrap();
+            assert_eq!(network_changeset, Some(Network::Signet));
+        }
+    }
+
+    #[test]
+    fn test_keychains_persistence() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store.create_keychains_table().unwrap();
+
+        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[0].parse().unwrap();
+        let change_descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[1].parse().unwrap();
+        let desc_changeset: BTreeMap<u64, Descriptor<DescriptorPublicKey>> =
+            [(0, descriptor.clone()), (1, change_descriptor.clone())].into();
+
+        store.persist_keychains(&desc_changeset).unwrap();
+
+        let mut desc_changeset: BTreeMap<u64, Descriptor<DescriptorPublicKey>> = BTreeMap::new();
+        store.read_keychains(&mut desc_changeset).unwrap();
+
+        assert_eq!(*desc_changeset.get(&0).unwrap(), descriptor);
+        assert_eq!(*desc_changeset.get(&1).unwrap(), change_descriptor);
+    }
+
+    #[test]
+    fn test_descriptor_checksum() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1");
+        store.create_keychains_table().unwrap();
+        let descriptor = parse_descriptor(DESCRIPTORS[0]);
+        let change_descriptor = parse_descriptor(DESCRIPTORS[1]);
+        store
+            .persist_keychains(&[(0, descriptor.clone()), (1, change_descriptor.clone())].into())
+            .unwrap();
+
+        // the checksum is stored along with the descriptor
+        let read_tx = db.begin_read().unwrap();
+        let table = read_tx.open_table(store.keychains_table_defn()).unwrap();
+        let stored = table.get(0).unwrap().unwrap().value();
+        drop(table);
+        read_tx.close().unwrap();
+        let (_, checksum) = stored.rsplit_once('#').unwrap();
+        assert_eq!(checksum, DESCRIPTORS[0].rsplit_once('#').unwrap().1);
+
+        // a flipped character (here a derivation index) is detected
+        let corrupted = stored.replacen("/1/*", "/2/*", 1);
+        let write_tx = db.begin_write().unwrap();
+        write_tx
+            .open_table(store.keychains_table_defn())
+            .unwrap()
+            .insert(0, corrupted)
+            .unwrap();
+        write_tx.commit().unwrap();
+        assert!(matches!(
+            store.read_all_keychains(),
+            Err(StoreError::DescriptorChecksumMismatch { label: 0 })
+        ));
+
+        // rows without a checksum are read, and upgraded once by the migration of a wallet
+        // written by an older version
+        let (unchecksummed, _) = stored.rsplit_once('#').unwrap();
+        let write_tx = db.begin_write().unwrap();
+        write_tx
+            .open_table(store.keychains_table_defn())
+            .unwrap()
+            .insert(0, unchecksummed.to_string())
+            .unwrap();
+        write_tx
+            .open_table(FORMAT_VERSION)
+            .unwrap()
+            .insert("wallet1", 2)
+            .unwrap();
+        write_tx.commit().unwrap();
+        assert_eq!(store.read_all_keychains().unwrap()[&0], descriptor);
+        // persisting does not rewrite the keychains table
+        store.persist_keychains(&BTreeMap::new()).unwrap();
+        let read_tx = db.begin_read().unwrap();
+        let table = read_tx.open_table(store.keychains_table_defn()).unwrap();
+        assert_eq!(table.get(0).unwrap().unwrap().value(), unchecksummed);
+        drop(table);
+        read_tx.close().unwrap();
+        store.create_tx_graph_tables::<BlockId>().unwrap();
+        let read_tx = db.begin_read().unwrap();
+        let table = read_tx.open_table(store.keychains_table_defn()).unwrap();
+        assert_eq!(table.get(0).unwrap().unwrap().value(), stored);
+    }
+
+    #[test]
+    fn test_invalid_descriptor() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1");
+        store.create_keychains_table().unwrap();
+        let write_tx = db.begin_write().unwrap();
+        write_tx
+            .open_table(store.keychains_table_defn())
+            .unwrap()
+            .insert(0, "not a descriptor".to_string())
+            .unwrap();
+        write_tx.commit().unwrap();
+
+        let mut desc_changeset = BTreeMap::new();
+        assert!(matches!(
+            store.read_keychains(&mut desc_changeset),
+            Err(StoreError::InvalidDescriptor { label: 0 })
+        ));
+        assert!(matches!(
+            store.persist_keychains(&[(0, parse_descriptor(DESCRIPTORS[0]))].into()),
+            Err(StoreError::InvalidDescriptor { label: 0 })
+        ));
+        #[cfg(feature = "wallet")]
+        {
+            let changeset = ChangeSet {
+                descriptor: Some(parse_descriptor(DESCRIPTORS[0])),
+                ..Default::default()
+            };
+            assert!(matches!(
+                store.would_change(&changeset),
+                Err(StoreError::InvalidDescriptor { label: 0 })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_replace_descriptor() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store.create_keychains_table().unwrap();
+
+        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[0].parse().unwrap();
+        let change_descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[1].parse().unwrap();
+        let new_descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[2].parse().unwrap();
+        store
+            .persist_keychains(&[(0, descriptor.clone()), (1, change_descriptor.clone())].into())
+            .unwrap();
+
+        // persisting the same descriptors again is fine
+        store
+            .persist_keychains(&[(0, descriptor.clone())].into())
+            .unwrap();
+
+        // overwriting with a different descriptor is rejected and writes nothing
+        assert!(matches!(
+            store.persist_keychains(&[(0, new_descriptor.clone())].into()),
+            Err(StoreError::DescriptorImmutable(0))
+        ));
+        assert!(matches!(
+            store.persist_keychains(
+                &[(0, new_descriptor.clone()), (3, new_descriptor.clone())].into()
+            ),
+            Err(StoreError::DescriptorImmutable(0))
+        ));
+        assert_eq!(
+            store.read_all_keychains().unwrap(),
+            [(0, descriptor.clone()), (1, change_descriptor.clone())].into()
+        );
+
+        // replacing needs confirmation and an existing descriptor
+        assert!(matches!(
+            store.replace_descriptor(0, new_descriptor.clone(), false),
+            Err(StoreError::DescriptorImmutable(0))
+        ));
+        assert!(matches!(
+            store.replace_descriptor(5, new_descriptor.clone(), true),
+            Err(StoreError::DescriptorImmutable(5))
+        ));
+        assert_eq!(
+            store.read_all_keychains().unwrap(),
+            [(0, descriptor.clone()), (1, change_descriptor.clone())].into()
+        );
+
+        store
+            .replace_descriptor(0, new_descriptor.clone(), true)
+            .unwrap();
+        assert_eq!(
+            store.read_all_keychains().unwrap(),
+            [(0, new_descriptor.clone()), (1, change_descriptor)].into()
+        );
+        assert!(matches!(
+            store.persist_keychains(&[(0, descriptor)].into()),
+            Err(StoreError::DescriptorImmutable(0))
+        ));
+    }
+
+    #[test]
+    fn test_keychains_persistence_second() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store.create_keychains_table().unwrap();
+
+        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[1].parse().unwrap();
+        let change_descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[0].parse().unwrap();
+
+        let desc_changeset: BTreeMap<u64, Descriptor<DescriptorPublicKey>> =
+            [(0, descriptor.clone()), (1, change_descriptor.clone())].into();
+
+        store.persist_keychains(&desc_changeset).unwrap();
+
+        let mut desc_changeset: BTreeMap<u64, Descriptor<DescriptorPublicKey>> = BTreeMap::new();
+        store.read_keychains(&mut desc_changeset).unwrap();
+
+        assert_eq!(*desc_changeset.get(&0).unwrap(), descriptor);
+        assert_eq!(*desc_changeset.get(&1).unwrap(), change_descriptor);
+    }
+
+    #[test]
+    fn test_single_desc_persistence() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store.create_keychains_table().unwrap();
+
+        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[0].parse().unwrap();
+
+        store
+            .persist_keychains(&[(0, descriptor.clone())].into())
+            .unwrap();
+
+        let mut desc_changeset: BTreeMap<u64, Descriptor<DescriptorPublicKey>> = BTreeMap::new();
+        store.read_keychains(&mut desc_changeset).unwrap();
+
+        assert_eq!(*desc_changeset.get(&0).unwrap(), descriptor);
+        assert_eq!(desc_changeset.get(&1), None);
+    }
+
+    #[cfg(all(feature = "wallet", feature = "tracing"))]
+    #[test]
+    fn test_persist_wallet_spans() {
+        use std::sync::Mutex;
+        use tracing_subscriber::fmt::{MakeWriter, format::FmtSpan};
+
+        // collects everything written by the subscriber
+        #[derive(Clone, Default)]
+        struct Output(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Output {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        impl<'a> MakeWriter<'a> for Output {
+            type Writer = Output;
+            fn make_writer(&'a self) -> Self::Writer {
+                self.clone()
+            }
+        }
+
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+
+        let tx = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let changeset = ChangeSet {
+            tx_graph: tx_graph::ChangeSet {
+                txs: [tx].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let output = Output::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(output.clone())
+            .with_max_level(tracing::Level::TRACE)
+            .with_span_events(FmtSpan::CLOSE)
+            .with_ansi(false)
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            store.persist_wallet(&changeset).unwrap();
+            store.read_wallet(&mut ChangeSet::default()).unwrap();
+        });
+
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        for span in [
+            "persist_wallet",
+            "persist_tx_graph",
+            "persist_indexer",
+            "persist_local_chain",
+            "read_wallet",
+        ] {
+            assert!(
+                output.contains(&format!("{span}{{wallet=wallet1}}")),
+                "{span}"
+            );
+        }
+        assert!(output.contains(r#"persist_txs{wallet=wallet1 table="txs" rows=1}"#));
+        assert!(output.contains(r#"read_txs{wallet=wallet1 table="txs" rows=1}"#));
+        assert!(output.contains("empty indexer changeset"));
+        assert!(output.contains("empty local_chain changeset"));
+    }
+
+    #[cfg(all(feature = "wallet", feature = "metrics"))]
+    #[test]
+    fn test_persist_wallet_metrics() {
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+
+        let tx1 = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+        let changeset = ChangeSet {
+            tx_graph: tx_graph::ChangeSet {
+                txs: [tx1, tx2].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        metrics::with_local_recorder(&recorder, || {
+            store.persist_wallet(&changeset).unwrap();
+            store.persist_wallet(&changeset).unwrap();
+            store.read_wallet(&mut ChangeSet::default()).unwrap();
+        });
+
+        let metrics: Vec<_> = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| {
+                let key = key.key();
+                let labels: Vec<_> = key
+                    .labels()
+                    .map(|label| (label.key().to_string(), label.value().to_string()))
+                    .collect();
+                (key.name().to_string(), labels, value)
+            })
+            .collect();
+        let find = |name: &str, table: Option<&str>| {
+            let mut labels = vec![("wallet".to_string(), "wallet1".to_string())];
+            if let Some(table) = table {
+                labels.push(("table".to_string(), table.to_string()));
+            }
+            metrics
+                .iter()
+                .find(|(n, l, _)| n == name && *l == labels)
+                .map(|(_, _, value)| value)
+        };
+
+        assert_eq!(
+            find(telemetry::PERSIST_TOTAL, Some("txs")),
+            Some(&DebugValue::Counter(2))
+        );
+        assert_eq!(
+            find(telemetry::ROWS_WRITTEN_TOTAL, Some("txs")),
+            Some(&DebugValue::Counter(4))
+        );
+        // nothing was written to the other tables
+        assert_eq!(find(telemetry::PERSIST_TOTAL, Some("blocks")), None);
+        match find(telemetry::COMMIT_DURATION_SECONDS, None) {
+            // a single commit for each persist
+            Some(DebugValue::Histogram(values)) => assert_eq!(values.len(), 2),
+            other => panic!("unexpected commit durations: {other:?}"),
+        }
+        match find(telemetry::READ_WALLET_DURATION_SECONDS, None) {
+            Some(DebugValue::Histogram(values)) => assert_eq!(values.len(), 1),
+            other => panic!("unexpected read durations: {other:?}"),
+        }
+    }
+
+    #[cfg(feature = "wallet")]
+    #[test]
+    fn test_read_wallet_not_initialized() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+
+        let mut changeset = ChangeSet::default();
+        match store.read_wallet(&mut changeset) {
+            Ok(_) => panic!("should give error since tables were not created"),
+            Err(StoreError::WalletNotInitialized(wallet_name)) => {
+                assert_eq!(wallet_name, "wallet1")
+            }
+            Err(_) => panic!("error should only be due to wallet not being initialized"),
+        }
+
+        // only some of the tables exist
+        store.create_network_table().unwrap();
+        store.create_keychains_table().unwrap();
+        match store.read_wallet(&mut changeset) {
+            Ok(_) => panic!("should give error since tables were not created"),
+            Err(StoreError::WalletNotInitialized(_)) => {}
+            Err(_) => panic!("error should only be due to wallet not being initialized"),
+        }
+
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        let mut changeset = ChangeSet::default();
+        store.read_wallet(&mut changeset).unwrap();
+        assert!(changeset.is_empty());
+    }
+
+    #[cfg(feature = "wallet")]
+    #[test]
+    fn test_read_all_keychains() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+
+        let descriptors: Vec<Descriptor<DescriptorPublicKey>> = DESCRIPTORS[..3]
+            .iter()
+            .map(|desc| desc.parse().unwrap())
+            .collect();
+
+        let changeset = ChangeSet {
+            descriptor: Some(descriptors[0].clone()),
+            change_descriptor: Some(descriptors[1].clone()),
+            ..Default::default()
+        };
+        store.persist_wallet(&changeset).unwrap();
+        store
+            .persist_keychains(&[(2, descriptors[2].clone())].into())
+            .unwrap();
+        // persisting the wallet again must not drop the extra keychain
+        store.persist_wallet(&changeset).unwrap();
+
+        let keychains = store.read_all_keychains().unwrap();
+        assert_eq!(keychains.len(), 3);
+        for (label, desc) in descriptors.iter().enumerate() {
+            assert_eq!(keychains.get(&(label as u64)), Some(desc));
+        }
+
+        let descriptor_ids = store.keychain_descriptor_ids().unwrap();
+        assert_eq!(
+            descriptor_ids,
+            (0..3)
+                .map(|label| (
+                    label as u64,
+                    parse_descriptor(DESCRIPTORS[label]).descriptor_id()
+                ))
+                .collect()
+        );
+
+        let mut changeset_read = ChangeSet::default();
+        store.read_wallet(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read.descriptor, Some(descriptors[0].clone()));
+        assert_eq!(
+            changeset_read.change_descriptor,
+            Some(descriptors[1].clone())
+        );
+    }
+
+    #[cfg(feature = "wallet")]
+    #[test]
+    fn test_wallet_keychain_subsets() {
+        use bdk_wallet::{KeychainKind, Wallet};
+
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let descriptors: Vec<Descriptor<DescriptorPublicKey>> = DESCRIPTORS[..2]
+            .iter()
+            .map(|desc| parse_descriptor(desc))
+            .collect();
+
+        // external only, a watch-only wallet without change keychain
+        let mut store = create_test_store(db.clone(), "external");
+        let wallet = Wallet::create_single(DESCRIPTORS[0])
+            .network(Network::Regtest)
+            .create_wallet(&mut store)
+            .unwrap();
+        let changeset = store.snapshot_changeset().unwrap();
+        assert_eq!(changeset.descriptor, Some(descriptors[0].clone()));
+        assert_eq!(changeset.change_descriptor, None);
+        let loaded = Wallet::load().load_wallet(&mut store).unwrap().unwrap();
+        assert_eq!(
+            loaded.public_descriptor(KeychainKind::External),
+            wallet.public_descriptor(KeychainKind::External)
+        );
+        assert_eq!(loaded.keychains().count(), 1);
+
+        // both
+        let store = create_test_store(db.clone(), "both");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        store
+            .persist_wallet(&ChangeSet {
+                descriptor: Some(descriptors[0].clone()),
+                change_descriptor: Some(descriptors[1].clone()),
+                ..Default::default()
+            })
+            .unwrap();
+        // a later changeset may carry the change descriptor alone
+        store
+            .persist_wallet(&ChangeSet {
+                change_descriptor: Some(descriptors[1].clone()),
+                ..Default::default()
+            })
+            .unwrap();
+        let changeset = store.snapshot_changeset().unwrap();
+        assert_eq!(changeset.descriptor, Some(descriptors[0].clone()));
+        assert_eq!(changeset.change_descriptor, Some(descriptors[1].clone()));
+
+        // change only
+        let store = create_test_store(db.clone(), "change");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        let change_only = ChangeSet {
+            change_descriptor: Some(descriptors[1].clone()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            store.persist_wallet(&change_only),
+            Err(StoreError::MissingExternalDescriptor)
+        ));
+        assert!(matches!(
+            store.persist_wallet_batched(&change_only),
+            Err(StoreError::MissingExternalDescriptor)
+        ));
+        assert!(store.read_all_keychains().unwrap().is_empty());
+        // a change descriptor persisted alone is still read
+        store
+            .persist_keychains(&[(1, descriptors[1].clone())].into())
+            .unwrap();
+        let changeset = store.snapshot_changeset().unwrap();
+        assert_eq!(changeset.descriptor, None);
+        assert_eq!(changeset.change_descriptor, Some(descriptors[1].clone()));
+    }
+
+    #[test]
+    fn test_descriptor_missing() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store.create_keychains_table().unwrap();
+
+        let mut desc_changeset: BTreeMap<u64, Descriptor<DescriptorPublicKey>> = BTreeMap::new();
+        store.read_keychains(&mut desc_changeset).unwrap();
+        assert_eq!(desc_changeset.get(&0), None);
+        assert_eq!(desc_changeset.get(&1), None);
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_encrypted_keychains_persistence() {
+        use crate::encryption::EncryptionKey;
+
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1")
+            .with_encryption_key(EncryptionKey::new([7; 32]));
+        store.create_keychains_table().unwrap();
+
+        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[0].parse().unwrap();
+        let change_descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[1].parse().unwrap();
+        let desc_changeset: BTreeMap<u64, Descriptor<DescriptorPublicKey>> =
+            [(0, descriptor.clone()), (1, change_descriptor.clone())].into();
+
+        store.persist_keychains(&desc_changeset).unwrap();
+
+        // bytes at rest should not contain the plaintext descriptor
+        let read_tx = db.begin_read().unwrap();
+        let table = read_tx.open_table(store.keychains_table_defn()).unwrap();
+        let stored = table.get(0).unwrap().unwrap().value();
+        assert_ne!(stored, descriptor.to_string());
+        assert!(!stored.contains("tpub"));
+        drop(table);
+        read_tx.close().unwrap();
+
+        let mut desc_changeset_read: BTreeMap<u64, Descriptor<DescriptorPublicKey>> =
+            BTreeMap::new();
+        store.read_keychains(&mut desc_changeset_read).unwrap();
+        assert_eq!(desc_changeset_read, desc_changeset);
+
+        // reading with a different key should fail
+        let store =
+            create_test_store(db, "wallet1").with_encryption_key(EncryptionKey::new([8; 32]));
+        let mut desc_changeset_read: BTreeMap<u64, Descriptor<DescriptorPublicKey>> =
+            BTreeMap::new();
+        match store.read_keychains(&mut desc_changeset_read) {
+            Ok(_) => panic!("should give error since key is wrong"),
+            Err(StoreError::Decryption) => {}
+            Err(_) => panic!("error should only be due to failed decryption"),
+        }
+    }
+
+    #[cfg(all(feature = "encryption", feature = "wallet"))]
+    #[test]
+    fn test_encrypted_values_persistence() {
+        use crate::encryption::EncryptionKey;
+
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let mut store = create_test_store(db.clone(), "wallet1").with_encryption_key([7; 32]);
+        store.enable_changelog(true);
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+
+        let descriptor = parse_descriptor(DESCRIPTORS[0]);
+        let descriptor_id = descriptor.descriptor_id();
+        let tx = Arc::new(create_one_inp_one_out_tx(hash!("T"), 30_000));
+        let txout = TxOut {
+            value: Amount::from_sat(5_000),
+            script_pubkey: ScriptBuf::from_bytes(vec![0xaa; 22]),
+        };
+        let spk = ScriptBuf::from_bytes(vec![0xbb; 34]);
+        let changeset = ChangeSet {
+            descriptor: Some(descriptor),
+            network: Some(Network::Signet),
+            tx_graph: tx_graph::ChangeSet {
+                txs: [tx.clone()].into(),
+                txouts: [(OutPoint::new(hash!("F"), 1), txout.clone())].into(),
+                ..Default::default()
+            },
+            indexer: keychain_txout::ChangeSet {
+                last_revealed: [(descriptor_id, 0)].into(),
+                spk_cache: [(descriptor_id, [(0, spk.clone())].into())].into(),
+            },
+            ..Default::default()
+        };
+        store.persist_wallet(&changeset).unwrap();
+
+        // values at rest do not contain the plaintext, keys do
+        let read_tx = db.begin_read().unwrap();
+        let txs_table = read_tx.open_table(store.txs_table_defn()).unwrap();
+        let stored_tx = txs_table
+            .get(tx.compute_txid().to_byte_array())
+            .unwrap()
+            .unwrap()
+            .value();
+        assert_ne!(stored_tx, tx_to_bytes(&tx).unwrap());
+        // the row is the nonce followed by the AES-256-GCM ciphertext, the table and txid being
+        // the associated data
+        let (nonce, sealed) = stored_tx.split_at(ring::aead::NONCE_LEN);
+        let mut aad = vec![TXS_VALUE];
+        aad.extend_from_slice(&tx.compute_txid().to_byte_array());
+        let mut in_out = sealed.to_vec();
+        let plaintext = ring::aead::LessSafeKey::new(
+            ring::aead::UnboundKey::new(&ring::aead::AES_256_GCM, &[7; 32]).unwrap(),
+        )
+        .open_in_place(
+            ring::aead::Nonce::try_assume_unique_for_key(nonce).unwrap(),
+            ring::aead::Aad::from(aad),
+            &mut in_out,
+        )
+        .unwrap();
+        assert_eq!(plaintext, tx_to_bytes(&tx).unwrap());
+        let txouts_table = read_tx.open_table(store.txouts_table_defn()).unwrap();
+        let (value, stored_script) = txouts_table
+            .get((Txid::to_byte_array(hash!("F")), 1))
+            .unwrap()
+            .unwrap()
+            .value();
+        assert_eq!(value, 5_000);
+        assert!(!stored_script.windows(4).any(|window| window == [0xaa; 4]));
+        let spk_table = read_tx.open_table(store.spk_table_defn()).unwrap();
+        let stored_spk = spk_table
+            .get((descriptor_id.to_byte_array(), 0))
+            .unwrap()
+            .unwrap()
+            .value();
+        assert!(!stored_spk.windows(4).any(|window| window == [0xbb; 4]));
+        drop((txs_table, txouts_table, spk_table));
+        read_tx.close().unwrap();
+
+        // round trip with the right key
+        let mut changeset_read = ChangeSet::default();
+        store.read_wallet(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read, changeset);
+        assert_eq!(store.get_tx(tx.compute_txid()).unwrap(), Some(tx.clone()));
+        assert_eq!(
+            store.get_txout(OutPoint::new(hash!("F"), 1)).unwrap(),
+            Some(txout)
+        );
+        assert_eq!(
+            store
+                .iter_spks(descriptor_id)
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap(),
+            [(0, spk)]
+        );
+        assert_eq!(store.read_changelog(0).unwrap()[0].2, changeset);
+
+        // every read fails with the wrong key
+        let store = create_test_store(db.clone(), "wallet1")
+            .with_encryption_key(EncryptionKey::new([8; 32]));
+        assert!(matches!(
+            store.get_tx(tx.compute_txid()),
+            Err(StoreError::Decryption)
+        ));
+        assert!(matches!(
+            store.get_txout(OutPoint::new(hash!("F"), 1)),
+            Err(StoreError::Decryption)
+        ));
+        assert!(matches!(
+            store.iter_spks(descriptor_id).unwrap().next(),
+            Some(Err(StoreError::Decryption))
+        ));
+        assert!(matches!(
+            store.read_changelog(0),
+            Err(StoreError::Decryption)
+        ));
+        assert!(matches!(
+            store.read_tx_graph(&mut tx_graph::ChangeSet::<ConfirmationBlockTime>::default()),
+            Err(StoreError::Decryption)
+        ));
+        assert!(matches!(
+            store.read_indexer(&mut keychain_txout::ChangeSet::default()),
+            Err(StoreError::Decryption)
+        ));
+
+        // and without a key
+        let store = create_test_store(db, "wallet1");
+        assert!(store.get_tx(tx.compute_txid()).is_err());
+    }
+
+    #[cfg(feature = "dangerous")]
+    #[test]
+    fn test_reset_network() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1");
+        store.create_network_table().unwrap();
+        store.persist_network(&Some(Network::Testnet)).unwrap();
+        assert!(matches!(
+            store.persist_network(&Some(Network::Signet)),
+            Err(StoreError::NetworkMismatch { .. })
+        ));
+
+        store.reset_network(Network::Signet).unwrap();
+        let mut network = None;
+        store.read_network(&mut network).unwrap();
+        assert_eq!(network, Some(Network::Signet));
+
+        // the network stays in the table of the format of the store
+        let store = create_test_store(db, "wallet1").with_compact_network(true);
+        store.reset_network(Network::Regtest).unwrap();
+        let mut network = None;
+        store.read_network(&mut network).unwrap();
+        assert_eq!(network, Some(Network::Regtest));
+    }
+
+    #[test]
+    fn test_persist_network_mismatch() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        for compact in [false, true] {
+            let wallet_name = format!("wallet{compact}");
+            let store = create_test_store(db.clone(), &wallet_name).with_compact_network(compact);
+            store.create_network_table().unwrap();
+            store.persist_network(&Some(Network::Testnet)).unwrap();
+
+            // persisting the same network again, or no network, is fine
+            store.persist_network(&Some(Network::Testnet)).unwrap();
+            store.persist_network(&None).unwrap();
+
+            match store.persist_network(&Some(Network::Signet)) {
+                Err(StoreError::NetworkMismatch { ours, theirs }) => {
+                    assert_eq!(ours, Network::Testnet);
+                    assert_eq!(theirs, Network::Signet);
+                }
+                other => panic!("unexpected result: {other:?}"),
+            }
+            let mut network = None;
+            store.read_network(&mut network).unwrap();
+            assert_eq!(network, Some(Network::Testnet));
+        }
+    }
+
+    #[test]
+    fn test_local_chain_persistence() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+
+        // create a local_chain_changeset, persist that and read it
+        let local_chain_changeset = local_chain::ChangeSet {
+            blocks: [
+                (0, Some(hash!("B"))),
+                (1, Some(hash!("D"))),
+                (2, Some(hash!("K"))),
+            ]
+            .into(),
+        };
+        store.create_local_chain_tables().unwrap();
+        store.persist_local_chain(&local_chain_changeset).unwrap();
+
+        let mut changeset = local_chain::ChangeSet::default();
+        store.read_local_chain(&mut changeset).unwrap();
+        assert_eq!(local_chain_changeset, changeset);
+
+        // create another local_chain_changeset, persist that and read it
+        let local_chain_changeset = local_chain::ChangeSet {
+            blocks: [(2, None)].into(),
+        };
+
+        store.persist_local_chain(&local_chain_changeset).unwrap();
+
+        let mut changeset = local_chain::ChangeSet::default();
+        store.read_local_chain(&mut changeset).unwrap();
+
+        let local_chain_changeset = local_chain::ChangeSet {
+            blocks: [(0, Some(hash!("B"))), (1, Some(hash!("D")))].into(),
+        };
+
+        assert_eq!(local_chain_changeset, changeset);
+    }
+
+    #[test]
+    fn test_persist_block_range() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let mut store = create_test_store(Arc::new(db), "wallet1");
+        store.create_local_chain_tables().unwrap();
+        let calls = Arc::new(AtomicU64::new(0));
+        let counter = calls.clone();
+        store.set_on_persist(Box::new(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+
+        let hashes: Vec<BlockHash> = (0..1000u32)
+            .map(|height| BlockHash::hash(&height.to_le_bytes()))
+            .collect();
+        let summary = store.persist_block_range(100, &hashes).unwrap();
+        assert_eq!(summary.blocks.inserted, 1000);
+        assert!(summary.replaced_blocks.is_empty());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let mut changeset = local_chain::ChangeSet::default();
+        store.read_local_chain(&mut changeset).unwrap();
+        assert_eq!(
+            changeset.blocks,
+            (100..).zip(hashes.iter().copied().map(Some)).collect()
+        );
+        assert_eq!(store.get_block_hash(1099).unwrap(), Some(hashes[999]));
+
+        // overwriting blocks reports those whose hash changed
+        let summary = store
+            .persist_block_range(1098, &[hashes[998], hash!("R")])
+            .unwrap();
+        assert_eq!(
+            summary.replaced_blocks,
+            vec![ReplacedBlock {
+                height: 1099,
+                old_hash: hashes[999],
+                new_hash: hash!("R"),
+            }]
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // the end of the range overflows, nothing is written
+        assert!(matches!(
+            store.persist_block_range(u32::MAX - 1, &hashes[..2]),
+            Err(StoreError::BlockRangeOverflow {
+                start_height,
+                len: 2
+            }) if start_height == u32::MAX - 1
+        ));
+        assert_eq!(store.get_block_hash(u32::MAX - 1).unwrap(), None);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        store
+            .persist_block_range(u32::MAX - 1, &hashes[..1])
+            .unwrap();
+        assert_eq!(store.get_block_hash(u32::MAX - 1).unwrap(), Some(hashes[0]));
+    }
+
+    #[test]
+    fn test_blocks_persistence() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        let blocks: BTreeMap<u32, Option<BlockHash>> = [
+            (0, Some(hash!("B"))),
+            (1, Some(hash!("D"))),
+            (2, Some(hash!("K"))),
+        ]
+        .into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let _ = write_tx.open_table(store.blocks_table_defn()).unwrap();
+        store.persist_blocks(&write_tx, &blocks).unwrap();
+        write_tx.commit().unwrap();
+        let read_tx = store.db.begin_read().unwrap();
+        let mut blocks_new: BTreeMap<u32, Option<BlockHash>> = BTreeMap::new();
+        store.read_blocks(&read_tx, &mut blocks_new).unwrap();
+        assert_eq!(blocks_new, blocks);
+
+        let blocks: BTreeMap<u32, Option<BlockHash>> = [(2, None)].into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        store.persist_blocks(&write_tx, &blocks).unwrap();
+        write_tx.commit().unwrap();
+        let read_tx = store.db.begin_read().unwrap();
+        let mut blocks_new: BTreeMap<u32, Option<BlockHash>> = BTreeMap::new();
+        store.read_blocks(&read_tx, &mut blocks_new).unwrap();
+
+        let blocks: BTreeMap<u32, Option<BlockHash>> =
+            [(0, Some(hash!("B"))), (1, Some(hash!("D")))].into();
+
+        assert_eq!(blocks, blocks_new);
+    }
+
+    fn create_one_inp_one_out_tx(txid: Txid, amount: u64) -> Transaction {
+        Transaction {
+            version: transaction::Version::ONE,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(txid, 0),
+                ..TxIn::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(amount),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_persist_last_seen() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+
+        let tx1 = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+        let tx3 = Arc::new(create_one_inp_one_out_tx(tx2.compute_txid(), 19_000));
+
+        // try persisting and reading last_seen
+        let txs: BTreeSet<Arc<Transaction>> = [tx1.clone(), tx2.clone()].into();
+        let mut last_seen: BTreeMap<Txid, u64> =
+            [(tx1.compute_txid(), 100), (tx2.compute_txid(), 120)].into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
+        let _ = write_tx.open_table(store.last_seen_defn()).unwrap();
+        write_tx.commit().unwrap();
+
+        let write_tx = store.db.begin_write().unwrap();
+        store.persist_txs(&write_tx, &txs).unwrap();
+        write_tx.commit().unwrap();
+
+        // to hit the branch for the case when tx is persisted but not in changeset
+        let txs: BTreeSet<Arc<Transaction>> = BTreeSet::new();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let read_tx = store.db.begin_read().unwrap();
+        store
+            .persist_last_seen(&write_tx, &read_tx, &last_seen, &txs)
+            .unwrap();
+        write_tx.commit().unwrap();
+
+        let read_tx = store.db.begin_read().unwrap();
+        let mut last_seen_read: BTreeMap<Txid, u64> = BTreeMap::new();
+        store.read_last_seen(&read_tx, &mut last_seen_read).unwrap();
+        assert_eq!(last_seen_read, last_seen);
+
+        // persist another last_seen and see if what is read is same as merged one
+        let txs_new: BTreeSet<Arc<Transaction>> = [tx3.clone()].into();
+        let last_seen_new: BTreeMap<Txid, u64> = [(tx3.compute_txid(), 200)].into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
+        let _ = write_tx.open_table(store.last_seen_defn()).unwrap();
+        write_tx.commit().unwrap();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let read_tx = store.db.begin_read().unwrap();
+        store
+            .persist_last_seen(&write_tx, &read_tx, &last_seen_new, &txs_new)
+            .unwrap();
+        write_tx.commit().unwrap();
+
+        let read_tx = store.db.begin_read().unwrap();
+        let mut last_seen_read_new: BTreeMap<Txid, u64> = BTreeMap::new();
+        store
+            .read_last_seen(&read_tx, &mut last_seen_read_new)
+            .unwrap();
+        last_seen.merge(last_seen_new);
+        assert_eq!(last_seen_read_new, last_seen);
+    }
+
+    #[test]
+    fn test_last_seen_missing_txn() {
+        // to hit the branch for the panic case in persist_last_seen
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+
+        let tx1 = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+
+        let last_seen: BTreeMap<Txid, u64> = [
+            (hash!("B"), 100),
+            (tx1.compute_txid(), 120),
+            (tx2.compute_txid(), 121),
+        ]
+        .into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
+        let _ = write_tx.open_table(store.last_seen_defn()).unwrap();
+        write_tx.commit().unwrap();
+
+        let txs: BTreeSet<Arc<Transaction>> = [tx1, tx2].into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let read_tx = store.db.begin_read().unwrap();
+        match store.persist_last_seen(&write_tx, &read_tx, &last_seen, &txs) {
+            Ok(_) => panic!("should give error since tx missing"),
+            Err(StoreError::TxMissing(txid)) => assert_eq!(txid, hash!("B")),
+            Err(_) => panic!("error should only be due to missing tx"),
+        }
+        write_tx.commit().unwrap();
+    }
+
+    #[test]
+    fn test_persist_last_evicted() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+
+        let tx1 = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+        let tx3 = Arc::new(create_one_inp_one_out_tx(tx2.compute_txid(), 19_000));
+
+        let txs: BTreeSet<Arc<Transaction>> = [tx1.clone(), tx2.clone()].into();
+        let mut last_evicted: BTreeMap<Txid, u64> =
+            [(tx1.compute_txid(), 100), (tx2.compute_txid(), 120)].into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
+        let _ = write_tx
+            .open_table(store.last_evicted_table_defn())
+            .unwrap();
+        write_tx.commit().unwrap();
+
+        let write_tx = store.db.begin_write().unwrap();
+        store.persist_txs(&write_tx, &txs).unwrap();
+        write_tx.commit().unwrap();
+
+        // to hit the branch for the case when tx is persisted but not in changeset
+        let txs: BTreeSet<Arc<Transaction>> = BTreeSet::new();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let read_tx = store.db.begin_read().unwrap();
+        store
+            .persist_last_evicted(&write_tx, &read_tx, &last_evicted, &txs)
+            .unwrap();
+        write_tx.commit().unwrap();
+
+        let read_tx = store.db.begin_read().unwrap();
+        let mut last_evicted_read: BTreeMap<Txid, u64> = BTreeMap::new();
+        store
+            .read_last_evicted(&read_tx, &mut last_evicted_read)
+            .unwrap();
+        assert_eq!(last_evicted_read, last_evicted);
+
+        let txs_new: BTreeSet<Arc<Transaction>> = [tx3.clone()].into();
+        let last_evicted_new: BTreeMap<Txid, u64> = [(tx3.compute_txid(), 300)].into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
+        let _ = write_tx
+            .open_table(store.last_evicted_table_defn())
+            .unwrap();
+        write_tx.commit().unwrap();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let read_tx = store.db.begin_read().unwrap();
+        store
+            .persist_last_evicted(&write_tx, &read_tx, &last_evicted_new, &txs_new)
+            .unwrap();
+        write_tx.commit().unwrap();
+
+        let read_tx = store.db.begin_read().unwrap();
+        let mut last_evicted_read_new: BTreeMap<Txid, u64> = BTreeMap::new();
+        store
+            .read_last_evicted(&read_tx, &mut last_evicted_read_new)
+            .unwrap();
+        last_evicted.merge(last_evicted_new);
+        assert_eq!(last_evicted_read_new, last_evicted);
+    }
+
+    #[test]
+    fn test_last_evicted_missing_txs() {
+        // to hit the branch for the panic case in persist_last_evicted
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+
+        let tx1 = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+
+        let last_evicted: BTreeMap<Txid, u64> = [
+            (hash!("B"), 100),
+            (tx1.compute_txid(), 120),
+            (tx2.compute_txid(), 132),
+        ]
+        .into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
+        let _ = write_tx
+            .open_table(store.last_evicted_table_defn())
+            .unwrap();
+        write_tx.commit().unwrap();
+
+        let txs: BTreeSet<Arc<Transaction>> = [tx1, tx2].into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let read_tx = store.db.begin_read().unwrap();
+        match store.persist_last_evicted(&write_tx, &read_tx, &last_evicted, &txs) {
+            Ok(_) => panic!("should give error since tx missing"),
+            Err(StoreError::TxMissing(txid)) => assert_eq!(txid, hash!("B")),
+            Err(_) => panic!("error should only be due to missing tx"),
+        }
+        write_tx.commit().unwrap();
+    }
+
+    #[test]
+    fn test_persist_first_seen() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+
+        let tx1 = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+        let tx3 = Arc::new(create_one_inp_one_out_tx(tx2.compute_txid(), 19_000));
+
+        let txs: BTreeSet<Arc<Transaction>> = [tx1.clone(), tx2.clone()].into();
+        let mut first_seen: BTreeMap<Txid, u64> =
+            [(tx1.compute_txid(), 100), (tx2.compute_txid(), 120)].into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
+        let _ = write_tx.open_table(store.first_seen_table_defn()).unwrap();
+        write_tx.commit().unwrap();
+
+        let write_tx = store.db.begin_write().unwrap();
+        store.persist_txs(&write_tx, &txs).unwrap();
+        write_tx.commit().unwrap();
+
+        // to hit the branch for the case when tx is persisted but not in changeset
+        let txs: BTreeSet<Arc<Transaction>> = BTreeSet::new();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let read_tx = store.db.begin_read().unwrap();
+        store
+            .persist_first_seen(&write_tx, &read_tx, &first_seen, &txs)
+            .unwrap();
+        write_tx.commit().unwrap();
+
+        let read_tx = store.db.begin_read().unwrap();
+        let mut first_seen_read: BTreeMap<Txid, u64> = BTreeMap::new();
+        store
+            .read_first_seen(&read_tx, &mut first_seen_read)
+            .unwrap();
+        assert_eq!(first_seen_read, first_seen);
+
+        let txs_new: BTreeSet<Arc<Transaction>> = [tx3.clone()].into();
+        let first_seen_new: BTreeMap<Txid, u64> = [(tx3.compute_txid(), 200)].into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
+        let _ = write_tx.open_table(store.first_seen_table_defn()).unwrap();
+        write_tx.commit().unwrap();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let read_tx = store.db.begin_read().unwrap();
+        store
+            .persist_first_seen(&write_tx, &read_tx, &first_seen_new, &txs_new)
+            .unwrap();
+        write_tx.commit().unwrap();
+
+        let read_tx = store.db.begin_read().unwrap();
+        let mut first_seen_read_new: BTreeMap<Txid, u64> = BTreeMap::new();
+        store
+            .read_first_seen(&read_tx, &mut first_seen_read_new)
+            .unwrap();
+        first_seen.merge(first_seen_new);
+        assert_eq!(first_seen_read_new, first_seen);
+    }
+
+    #[test]
+    fn test_first_seen_missing_tx() {
+        // to hit the branch for the panic case persist_first_seen
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+
+        let tx1 = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+
+        let first_seen: BTreeMap<Txid, u64> = [
+            (hash!("B"), 100),
+            (tx1.compute_txid(), 120),
+            (tx2.compute_txid(), 121),
+        ]
+        .into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
+        let _ = write_tx.open_table(store.first_seen_table_defn()).unwrap();
+        write_tx.commit().unwrap();
+
+        let txs: BTreeSet<Arc<Transaction>> = [tx1, tx2].into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let read_tx = store.db.begin_read().unwrap();
+        match store.persist_first_seen(&write_tx, &read_tx, &first_seen, &txs) {
+            Ok(_) => panic!("should give error since tx missing"),
+            Err(StoreError::TxMissing(txid)) => assert_eq!(txid, hash!("B")),
+            Err(_) => panic!("error should only be due to missing tx"),
+        }
+        write_tx.commit().unwrap();
+    }
+
+    #[test]
+    fn test_persist_txouts() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+
+        let mut txouts: BTreeMap<OutPoint, TxOut> = [
+            (
+                OutPoint::new(Txid::from_byte_array([0; 32]), 0),
+                TxOut {
+                    value: Amount::from_sat(1300),
+                    script_pubkey: ScriptBuf::from_bytes(vec![0]),
+                },
+            ),
+            (
+                OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+                TxOut {
+                    value: Amount::from_sat(1400),
+                    script_pubkey: ScriptBuf::from_bytes(vec![2]),
+                },
+            ),
+        ]
+        .into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let _ = write_tx.open_table(store.txouts_table_defn()).unwrap();
+        store.persist_txouts(&write_tx, &txouts).unwrap();
+        write_tx.commit().unwrap();
+
+        let read_tx = store.db.begin_read().unwrap();
+        let mut txouts_read: BTreeMap<OutPoint, TxOut> = BTreeMap::new();
+        store.read_txouts(&read_tx, &mut txouts_read).unwrap();
+        assert_eq!(txouts, txouts_read);
+
+        let txouts_new: BTreeMap<OutPoint, TxOut> = [(
+            OutPoint::new(Txid::from_byte_array([2; 32]), 0),
+            TxOut {
+                value: Amount::from_sat(10000),
+                script_pubkey: ScriptBuf::from_bytes(vec![1]),
+            },
+        )]
+        .into();
+        let write_tx = store.db.begin_write().unwrap();
+        let _ = write_tx.open_table(store.txouts_table_defn()).unwrap();
+        store.persist_txouts(&write_tx, &txouts_new).unwrap();
+        write_tx.commit().unwrap();
+
+        let read_tx = store.db.begin_read().unwrap();
+        let mut txouts_read_new: BTreeMap<OutPoint, TxOut> = BTreeMap::new();
+        store.read_txouts(&read_tx, &mut txouts_read_new).unwrap();
+
+        txouts.merge(txouts_new);
+        assert_eq!(txouts, txouts_read_new);
+    }
+
+    #[test]
+    fn test_persist_txs() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+
+        let tx1 = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+        let tx3 = Arc::new(create_one_inp_one_out_tx(tx2.compute_txid(), 19_000));
+
+        let mut txs: BTreeSet<Arc<Transaction>> = [tx1, tx2.clone()].into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
+        store.persist_txs(&write_tx, &txs).unwrap();
+        write_tx.commit().unwrap();
+
+        let read_tx = store.db.begin_read().unwrap();
+        let mut txs_read: BTreeSet<Arc<Transaction>> = BTreeSet::new();
+        store.read_txs(&read_tx, &mut txs_read).unwrap();
+        assert_eq!(txs_read, txs);
+
+        let txs_new: BTreeSet<Arc<Transaction>> = [tx3].into();
+        let write_tx = store.db.begin_write().unwrap();
+        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
+        store.persist_txs(&write_tx, &txs_new).unwrap();
+        write_tx.commit().unwrap();
+
+        let read_tx = store.db.begin_read().unwrap();
+        let mut txs_read_new: BTreeSet<Arc<Transaction>> = BTreeSet::new();
+        store.read_txs(&read_tx, &mut txs_read_new).unwrap();
+
+        txs.merge(txs_new);
+        assert_eq!(txs_read_new, txs);
+    }
+
+    #[test]
+    fn test_txids() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+        assert!(store.txids().unwrap().is_empty());
+
+        let tx1 = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+        let txs: BTreeSet<Arc<Transaction>> = [tx1.clone(), tx2.clone()].into();
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: txs.clone(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let txids: BTreeSet<Txid> = txs.iter().map(|tx| tx.compute_txid()).collect();
+        assert_eq!(store.txids().unwrap(), txids);
+    }
+
+    #[test]
+    fn test_contains_tx() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        let tx1 = Arc::new(create_one_inp_one_out_tx(hash!("T"), 30_000));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+        assert!(!store.contains_tx(tx1.compute_txid()).unwrap());
+
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [tx1.clone()].into(),
+                // a floating txout of tx2 does not make it persisted
+                txouts: [(OutPoint::new(tx2.compute_txid(), 0), tx2.output[0].clone())].into(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(store.contains_tx(tx1.compute_txid()).unwrap());
+        assert!(!store.contains_tx(tx2.compute_txid()).unwrap());
+    }
+
+    #[test]
+    fn test_get_tx() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+
+        // the txs table does not exist yet
+        assert_eq!(store.get_tx(hash!("T")).unwrap(), None);
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+
+        let tx1 = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+        let tx3 = Arc::new(create_one_inp_one_out_tx(tx2.compute_txid(), 19_000));
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [tx1, tx2.clone(), tx3].into(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(store.get_tx(tx2.compute_txid()).unwrap(), Some(tx2));
+        assert_eq!(store.get_tx(hash!("T")).unwrap(), None);
+    }
+
+    #[test]
+    fn test_chain_tip_and_get_block_hash() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+
+        // the blocks table does not exist yet
+        assert_eq!(store.chain_tip().unwrap(), None);
+        assert_eq!(store.get_block_hash(0).unwrap(), None);
+
+        store.create_local_chain_tables().unwrap();
+        assert_eq!(store.chain_tip().unwrap(), None);
+        assert_eq!(store.get_block_hash(0).unwrap(), None);
+
+        store
+            .persist_local_chain(&local_chain::ChangeSet {
+                blocks: [
+                    (0, Some(hash!("B0"))),
+                    (1, Some(hash!("B1"))),
+                    (2, Some(hash!("B2"))),
+                    (256, Some(hash!("B256"))),
+                ]
+                .into(),
+            })
+            .unwrap();
+        assert_eq!(store.chain_tip().unwrap(), Some(block_id!(256, "B256")));
+        assert_eq!(store.get_block_hash(1).unwrap(), Some(hash!("B1")));
+        assert_eq!(store.get_block_hash(3).unwrap(), None);
+
+        store
+            .persist_local_chain(&local_chain::ChangeSet {
+                blocks: [(256, None)].into(),
+            })
+            .unwrap();
+        assert_eq!(store.chain_tip().unwrap(), Some(block_id!(2, "B2")));
+        assert_eq!(store.get_block_hash(256).unwrap(), None);
+    }
+
+    #[test]
+    fn test_anchors_for_tx() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        let [tx1, tx2, tx3] = [10_000, 20_000, 30_000]
+            .map(|amount| Arc::new(create_one_inp_one_out_tx(hash!("T"), amount)));
+        assert!(
+            store
+                .anchors_for_tx::<ConfirmationBlockTime>(tx1.compute_txid())
+                .unwrap()
+                .is_empty()
+        );
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+
+        let anchor = |height, hash| ConfirmationBlockTime {
+            block_id: BlockId { height, hash },
+            confirmation_time: 1000 + height as u64,
+        };
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [tx1.clone(), tx2.clone(), tx3.clone()].into(),
+                anchors: [
+                    (anchor(300, hash!("B300")), tx1.compute_txid()),
+                    (anchor(2, hash!("B2")), tx1.compute_txid()),
+                    (anchor(5, hash!("B5")), tx2.compute_txid()),
+                ]
+                .into(),
+                ..Default::default()
+            })
+            .unwrap();
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<BlockId> {
+                anchors: [(block_id!(7, "B7"), tx1.compute_txid())].into(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(
+            store
+                .anchors_for_tx::<ConfirmationBlockTime>(tx1.compute_txid())
+                .unwrap(),
+            [anchor(2, hash!("B2")), anchor(300, hash!("B300"))]
+        );
+        assert_eq!(
+            store.anchors_for_tx::<BlockId>(tx1.compute_txid()).unwrap(),
+            [block_id!(7, "B7")]
+        );
+        assert!(
+            store
+                .anchors_for_tx::<ConfirmationBlockTime>(tx3.compute_txid())
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_tx_node() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        let [tx1, tx2] =
+            [10_000, 20_000].map(|amount| Arc::new(create_one_inp_one_out_tx(hash!("T"), amount)));
+        assert_eq!(store.tx_node(tx1.compute_txid()).unwrap(), None);
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+
+        let anchor = |height, hash| ConfirmationBlockTime {
+            block_id: BlockId { height, hash },
+            confirmation_time: 1000 + height as u64,
+        };
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [tx1.clone(), tx2.clone()].into(),
+                anchors: [
+                    (anchor(300, hash!("B300")), tx1.compute_txid()),
+                    (anchor(2, hash!("B2")), tx1.compute_txid()),
+                    (anchor(5, hash!("B5")), tx2.compute_txid()),
+                ]
+                .into(),
+                first_seen: [(tx1.compute_txid(), 100)].into(),
+                last_seen: [(tx1.compute_txid(), 200), (tx2.compute_txid(), 250)].into(),
+                last_evicted: [(tx1.compute_txid(), 300)].into(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(
+            store.tx_node(tx1.compute_txid()).unwrap(),
+            Some(tx_node::TxNode {
+                tx: tx1.clone(),
+                anchors: vec![(block_id!(2, "B2"), 1002), (block_id!(300, "B300"), 1300)],
+                first_seen: Some(100),
+                last_seen: Some(200),
+                last_evicted: Some(300),
+            })
+        );
+        assert_eq!(
+            store.tx_node(tx2.compute_txid()).unwrap(),
+            Some(tx_node::TxNode {
+                tx: tx2.clone(),
+                anchors: vec![(block_id!(5, "B5"), 1005)],
+                first_seen: None,
+                last_seen: Some(250),
+                last_evicted: None,
+            })
+        );
+        assert_eq!(store.tx_node(Txid::from_byte_array([1; 32])).unwrap(), None);
+    }
+
+    #[test]
+    fn test_compact_anchor_metadata() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        let compact_store =
+            create_test_store(db.clone(), "wallet2").with_compact_anchor_metadata(true);
+        compact_store
+            .create_tables::<ConfirmationBlockTime>()
+            .unwrap();
+
+        let txs: Vec<_> = (0..100u64)
+            .map(|i| Arc::new(create_one_inp_one_out_tx(hash!("T"), 1_000 + i)))
+            .collect();
+        let anchor = |i: u64| ConfirmationBlockTime {
+            block_id: BlockId {
+                height: 840_000 + i as u32,
+                hash: BlockHash::from_byte_array([i as u8; 32]),
+            },
+            // a zero byte in the middle must be kept
+            confirmation_time: 1_713_571_767 + (i << 8),
+        };
+        let mut tx_graph_changeset = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: txs.iter().cloned().collect(),
+            anchors: txs
+                .iter()
+                .enumerate()
+                .map(|(i, tx)| (anchor(i as u64), tx.compute_txid()))
+                .collect(),
+            ..Default::default()
+        };
+        tx_graph_changeset.anchors.insert((
+            ConfirmationBlockTime {
+                block_id: block_id!(1, "B1"),
+                confirmation_time: 0,
+            },
+            txs[0].compute_txid(),
+        ));
+        store.persist_tx_graph(&tx_graph_changeset).unwrap();
+        compact_store.persist_tx_graph(&tx_graph_changeset).unwrap();
+        compact_store
+            .persist_tx_graph(&tx_graph::ChangeSet::<BlockId> {
+                anchors: [(block_id!(2, "B2"), txs[0].compute_txid())].into(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        // the default encoding is exactly the 8 bytes of a u64, the compact one drops the unused
+        // high bytes of confirmation times
+        let metadata_sizes = |store: &Store, tag: u8| {
+            let read_tx = db.begin_read().unwrap();
+            let table = read_tx
+                .open_multimap_table(store.anchors_table_defn())
+                .unwrap();
+            table
+                .iter()
+                .unwrap()
+                .flat_map(|entry| entry.unwrap().1)
+                .map(|value| value.unwrap())
+                .filter(|value| value.value().1 == tag)
+                .map(|value| value.value().2.len())
+                .collect::<Vec<_>>()
+        };
+        let sizes = metadata_sizes(&store, ConfirmationBlockTime::TAG);
+        assert!(sizes.iter().all(|&size| size == 8));
+        let compact_sizes = metadata_sizes(&compact_store, ConfirmationBlockTime::TAG);
+        assert!(compact_sizes.iter().filter(|&&size| size == 0).count() == 1);
+        assert!(compact_sizes.iter().all(|&size| size <= 4));
+        assert!(compact_sizes.iter().sum::<usize>() * 2 <= sizes.iter().sum::<usize>());
+        assert_eq!(metadata_sizes(&compact_store, BlockId::TAG), [0]);
+
+        // anchors round-trip exactly whatever the encoding and setting
+        for store in [
+            &store,
+            &compact_store,
+            &create_test_store(db.clone(), "wallet2"),
+        ] {
+            let mut tx_graph_read = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+            store.read_tx_graph(&mut tx_graph_read).unwrap();
+            assert_eq!(tx_graph_read.anchors, tx_graph_changeset.anchors);
+        }
+        assert_eq!(
+            compact_store
+                .anchors_for_tx::<BlockId>(txs[0].compute_txid())
+                .unwrap(),
+            [block_id!(2, "B2")]
+        );
+        assert_eq!(
+            compact_store
+                .tx_node(txs[1].compute_txid())
+                .unwrap()
+                .unwrap()
+                .anchors,
+            [(anchor(1).block_id, anchor(1).confirmation_time)]
+        );
+
+        // existing anchors are migrated by compact_anchors
+        assert_eq!(
+            store.compact_anchors::<ConfirmationBlockTime>().unwrap(),
+            101
+        );
+        assert_eq!(store.compact_anchors::<ConfirmationBlockTime>().unwrap(), 0);
+        assert_eq!(
+            metadata_sizes(&store, ConfirmationBlockTime::TAG),
+            compact_sizes
+        );
+        let mut tx_graph_read = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        store.read_tx_graph(&mut tx_graph_read).unwrap();
+        assert_eq!(tx_graph_read.anchors, tx_graph_changeset.anchors);
+    }
+
+    #[test]
+    fn test_anchors_in_height_range() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        assert!(
+            store
+                .anchors_in_height_range::<ConfirmationBlockTime>(..)
+                .unwrap()
+                .is_empty()
+        );
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+
+        let txs: Vec<Arc<Transaction>> = (0..3)
+            .map(|i| Arc::new(create_one_inp_one_out_tx(hash!("T"), 10_000 + i)))
+            .collect();
+        let anchor = |height, hash| ConfirmationBlockTime {
+            block_id: BlockId { height, hash },
+            confirmation_time: 100 + height as u64,
+        };
+        let anchors = [
+            (anchor(10, hash!("B10")), txs[0].compute_txid()),
+            (anchor(20, hash!("B20")), txs[1].compute_txid()),
+            (anchor(30, hash!("B30")), txs[2].compute_txid()),
+        ];
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: txs.iter().cloned().collect(),
+                anchors: anchors.into(),
+                ..Default::default()
+            })
+            .unwrap();
+        // anchors of other types are ignored
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<BlockId> {
+                anchors: [(block_id!(20, "B20"), txs[0].compute_txid())].into(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(
+            store
+                .anchors_in_height_range::<ConfirmationBlockTime>(15..=25)
+                .unwrap(),
+            vec![anchors[1]]
+        );
+        assert_eq!(
+            store
+                .anchors_in_height_range::<ConfirmationBlockTime>(20..)
+                .unwrap(),
+            vec![anchors[1], anchors[2]]
+        );
+        assert_eq!(
+            store
+                .anchors_in_height_range::<ConfirmationBlockTime>(..)
+                .unwrap(),
+            anchors.to_vec()
+        );
+        assert!(
+            store
+                .anchors_in_height_range::<ConfirmationBlockTime>(31..)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_get_last_revealed() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        let descriptor_ids = utils::DESCRIPTORS.map(|d| parse_descriptor(d).descriptor_id());
+
+        // the last_revealed table does not exist yet
+        assert_eq!(store.get_last_revealed(descriptor_ids[0]).unwrap(), None);
+        assert!(store.descriptor_ids().unwrap().is_empty());
+
+        store.create_indexer_tables().unwrap();
+        store
+            .persist_indexer(&keychain_txout::ChangeSet {
+                last_revealed: [(descriptor_ids[0], 7), (descriptor_ids[1], 3)].into(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(store.get_last_revealed(descriptor_ids[0]).unwrap(), Some(7));
+        assert_eq!(store.get_last_revealed(descriptor_ids[1]).unwrap(), Some(3));
+        assert_eq!(store.get_last_revealed(descriptor_ids[2]).unwrap(), None);
+
+        let mut expected = vec![descriptor_ids[0], descriptor_ids[1]];
+        expected.sort();
+        assert_eq!(store.descriptor_ids().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_spk_cache_gap() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        assert!(store.spk_cache_gap().unwrap().is_empty());
+
+        store.create_indexer_tables().unwrap();
+        let descriptor_ids = utils::DESCRIPTORS.map(|d| parse_descriptor(d).descriptor_id());
+        let spks = |indices: &[u32]| -> BTreeMap<u32, ScriptBuf> {
+            indices
+                .iter()
+                .map(|&index| (index, ScriptBuf::from_bytes(vec![index as u8])))
+                .collect()
+        };
+        store
+            .persist_indexer(&keychain_txout::ChangeSet {
+                last_revealed: [
+                    (descriptor_ids[0], 5),
+                    (descriptor_ids[1], 30),
+                    (descriptor_ids[2], 0),
+                ]
+                .into(),
+                spk_cache: [
+                    (descriptor_ids[0], spks(&[0, 1, 2, 3, 4, 5, 20, 25])),
+                    (descriptor_ids[1], spks(&[0, 10])),
+                    // cached without a last revealed index, not reported
+                    (descriptor_ids[3], spks(&[0, 7])),
+                ]
+                .into(),
+            })
+            .unwrap();
+
+        assert_eq!(
+            store.spk_cache_gap().unwrap(),
+            [
+                (descriptor_ids[0], 20),
+                (descriptor_ids[1], -20),
+                (descriptor_ids[2], -1),
+            ]
+            .into()
+        );
+    }
+
+    #[test]
+    fn test_iter_spks() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        let descriptor_ids = utils::DESCRIPTORS.map(|d| parse_descriptor(d).descriptor_id());
+        assert_eq!(store.iter_spks(descriptor_ids[0]).unwrap().count(), 0);
+
+        store.create_indexer_tables().unwrap();
+        let spk = |byte| ScriptBuf::from_bytes(vec![byte]);
+        let spk_cache: BTreeMap<u32, ScriptBuf> = [(300, spk(3)), (0, spk(0)), (256, spk(2))]
+            .into_iter()
+            .collect();
+        store
+            .persist_indexer(&keychain_txout::ChangeSet {
+                spk_cache: [
+                    (descriptor_ids[0], [(0, spk(10)), (1, spk(11))].into()),
+                    (descriptor_ids[1], spk_cache.clone()),
+                    (descriptor_ids[2], [(5, spk(20))].into()),
+                ]
+                .into(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let spks: Vec<(u32, ScriptBuf)> = store
+            .iter_spks(descriptor_ids[1])
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(spks, spk_cache.into_iter().collect::<Vec<_>>());
+
+        let first = store
+            .iter_spks(descriptor_ids[1])
+            .unwrap()
+            .take(1)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        assert_eq!(first, [(0, spk(0))]);
+        assert_eq!(store.iter_spks(descriptor_ids[3]).unwrap().count(), 0);
+
+        // the read transaction of a dropped iterator does not block writes
+        drop(store.iter_spks(descriptor_ids[0]).unwrap());
+        store
+            .persist_indexer(&keychain_txout::ChangeSet::default())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_read_combined() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store.create_local_chain_tables().unwrap();
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+        store.create_indexer_tables().unwrap();
+
+        let tx = Arc::new(create_one_inp_one_out_tx(hash!("T"), 25_000));
+        let descriptor_id = parse_descriptor(utils::DESCRIPTORS[0]).descriptor_id();
+        let changeset = combined::CombinedChangeSet {
+            local_chain: local_chain::ChangeSet {
+                blocks: [(0, Some(hash!("B0"))), (1, Some(hash!("B1")))].into(),
+            },
+            tx_graph: tx_graph::ChangeSet {
+                txs: [tx.clone()].into(),
+                anchors: [(
+                    ConfirmationBlockTime {
+                        block_id: block_id!(1, "B1"),
+                        confirmation_time: 123,
+                    },
+                    tx.compute_txid(),
+                )]
+                .into(),
+                last_seen: [(tx.compute_txid(), 100)].into(),
+                ..Default::default()
+            },
+            indexer: keychain_txout::ChangeSet {
+                last_revealed: [(descriptor_id, 4)].into(),
+                spk_cache: [(descriptor_id, [(0, ScriptBuf::from_bytes(vec![1]))].into())].into(),
+            },
+        };
+        store.persist_local_chain(&changeset.local_chain).unwrap();
+        store.persist_tx_graph(&changeset.tx_graph).unwrap();
+        store.persist_indexer(&changeset.indexer).unwrap();
+
+        assert_eq!(store.read_combined().unwrap(), changeset);
+    }
+
+    #[test]
+    fn test_clear_spk_cache() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store.create_indexer_tables().unwrap();
+        let descriptor_ids = utils::DESCRIPTORS.map(|d| parse_descriptor(d).descriptor_id());
+        let spks = |count: u32| -> BTreeMap<u32, ScriptBuf> {
+            (0..count)
+                .map(|index| (index, ScriptBuf::from_bytes(vec![index as u8])))
+                .collect()
+        };
+        let changeset = keychain_txout::ChangeSet {
+            last_revealed: [(descriptor_ids[0], 4), (descriptor_ids[1], 1)].into(),
+            spk_cache: [(descriptor_ids[0], spks(5)), (descriptor_ids[1], spks(2))].into(),
+        };
+        store.persist_indexer(&changeset).unwrap();
+
+        assert_eq!(store.clear_spk_cache(Some(descriptor_ids[0])).unwrap(), 5);
+        assert_eq!(store.clear_spk_cache(Some(descriptor_ids[2])).unwrap(), 0);
+        let mut changeset_read = keychain_txout::ChangeSet::default();
+        store.read_indexer(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read.last_revealed, changeset.last_revealed);
+        assert_eq!(
+            changeset_read.spk_cache,
+            [(descriptor_ids[1], spks(2))].into()
+        );
+
+        store.persist_indexer(&changeset).unwrap();
+        assert_eq!(store.clear_spk_cache(None).unwrap(), 7);
+        let mut changeset_read = keychain_txout::ChangeSet::default();
+        store.read_indexer(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read.last_revealed, changeset.last_revealed);
+        assert!(changeset_read.spk_cache.is_empty());
+    }
+
+    #[test]
+    fn test_prune_spk_cache() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store.create_indexer_tables().unwrap();
+        let descriptor_ids = utils::DESCRIPTORS.map(|d| parse_descriptor(d).descriptor_id());
+        let spks: BTreeMap<u32, ScriptBuf> = (0..20)
+            .chain([u32::MAX])
+            .map(|index| (index, ScriptBuf::from_bytes(index.to_be_bytes().to_vec())))
+            .collect();
+        store
+            .persist_indexer(&keychain_txout::ChangeSet {
+                last_revealed: [(descriptor_ids[0], 5), (descriptor_ids[2], u32::MAX - 1)].into(),
+                spk_cache: [
+                    (descriptor_ids[0], spks.clone()),
+                    (descriptor_ids[1], spks.clone()),
+                    (descriptor_ids[2], spks.clone()),
+                ]
+                .into(),
+            })
+            .unwrap();
+        let cached_indices = |descriptor_id| {
+            let mut spks = BTreeMap::new();
+            store.read_spks_range(descriptor_id, .., &mut spks).unwrap();
+            spks.into_keys().collect::<Vec<u32>>()
+        };
+
+        // indices up to 5 + 10 are kept
+        assert_eq!(store.prune_spk_cache(descriptor_ids[0], 10).unwrap(), 5);
+        assert_eq!(
+            cached_indices(descriptor_ids[0]),
+            (0..=15).collect::<Vec<_>>()
+        );
+        assert_eq!(store.prune_spk_cache(descriptor_ids[0], 10).unwrap(), 0);
+        assert_eq!(store.prune_spk_cache(descriptor_ids[0], 0).unwrap(), 10);
+        assert_eq!(
+            cached_indices(descriptor_ids[0]),
+            (0..=5).collect::<Vec<_>>()
+        );
+
+        // no last revealed index
+        assert_eq!(store.prune_spk_cache(descriptor_ids[1], 0).unwrap(), 0);
+        assert_eq!(cached_indices(descriptor_ids[1]).len(), spks.len());
+
+        // the window reaches the last index
+        assert_eq!(store.prune_spk_cache(descriptor_ids[2], 1).unwrap(), 0);
+        assert_eq!(store.prune_spk_cache(descriptor_ids[2], 0).unwrap(), 1);
+        assert_eq!(
+            cached_indices(descriptor_ids[2]),
+            (0..20).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_vacuum_spk_cache() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store.create_indexer_tables().unwrap();
+        let descriptor_ids = utils::DESCRIPTORS.map(|d| parse_descriptor(d).descriptor_id());
+        let spks: BTreeMap<u32, ScriptBuf> = (0..=10u32)
+            .map(|index| (index, ScriptBuf::from_bytes(index.to_be_bytes().to_vec())))
+            .collect();
+        store
+            .persist_indexer(&keychain_txout::ChangeSet {
+                last_revealed: [(descriptor_ids[0], 5), (descriptor_ids[2], 10)].into(),
+                spk_cache: [
+                    (descriptor_ids[0], spks.clone()),
+                    (descriptor_ids[1], spks.clone()),
+                    (descriptor_ids[2], spks.clone()),
+                ]
+                .into(),
+            })
+            .unwrap();
+        let cached_indices = |descriptor_id| {
+            let mut spks = BTreeMap::new();
+            store.read_spks_range(descriptor_id, .., &mut spks).unwrap();
+            spks.into_keys().collect::<Vec<u32>>()
+        };
+
+        // indices 6 to 10 of the first descriptor are removed
+        assert_eq!(store.vacuum_spk_cache().unwrap(), 5);
+        assert_eq!(
+            cached_indices(descriptor_ids[0]),
+            (0..=5).collect::<Vec<_>>()
+        );
+        // no last revealed index
+        assert_eq!(cached_indices(descriptor_ids[1]).len(), spks.len());
+        // every cached script is revealed
+        assert_eq!(cached_indices(descriptor_ids[2]).len(), spks.len());
+
+        assert_eq!(store.vacuum_spk_cache().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_spk_cache_persisting() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1").with_spk_cache_persisting(false);
+        store.create_indexer_tables().unwrap();
+        let descriptor_id = parse_descriptor(utils::DESCRIPTORS[0]).descriptor_id();
+        let changeset = |index: u32| keychain_txout::ChangeSet {
+            last_revealed: [(descriptor_id, index)].into(),
+            spk_cache: [(
+                descriptor_id,
+                [(index, ScriptBuf::from_bytes(vec![index as u8]))].into(),
+            )]
+            .into(),
+        };
+
+        for index in 0..3 {
+            let summary = store.persist_indexer(&changeset(index)).unwrap();
+            assert!(summary.spks.is_empty());
+        }
+        let mut changeset_read = keychain_txout::ChangeSet::default();
+        store.read_indexer(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read.last_revealed, [(descriptor_id, 2)].into());
+        assert!(changeset_read.spk_cache.is_empty());
+
+        // enabling it again fills the cache from the next changesets
+        let store = create_test_store(db, "wallet1").with_spk_cache_persisting(true);
+        store.persist_indexer(&changeset(3)).unwrap();
+        let mut changeset_read = keychain_txout::ChangeSet::default();
+        store.read_indexer(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read, changeset(3));
+    }
+
+    #[test]
+    fn test_read_spks_range() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        let descriptor_ids = utils::DESCRIPTORS.map(|d| parse_descriptor(d).descriptor_id());
+
+        let mut spks = BTreeMap::new();
+        store
+            .read_spks_range(descriptor_ids[0], .., &mut spks)
+            .unwrap();
+        assert!(spks.is_empty());
+
+        store.create_indexer_tables().unwrap();
+        let spk_cache: BTreeMap<u32, ScriptBuf> = (0..10)
+            .chain([u32::MAX])
+            .map(|index| (index, ScriptBuf::from_bytes(index.to_be_bytes().to_vec())))
+            .collect();
+        store
+            .persist_indexer(&keychain_txout::ChangeSet {
+                spk_cache: [
+                    (descriptor_ids[0], spk_cache.clone()),
+                    (descriptor_ids[1], spk_cache.clone()),
+                ]
+                .into(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let read_range = |range: (Bound<u32>, Bound<u32>)| {
+            let mut spks = BTreeMap::new();
+            store
+                .read_spks_range(descriptor_ids[0], range, &mut spks)
+                .unwrap();
+            spks.into_keys().collect::<Vec<u32>>()
+        };
+        use Bound::{Excluded, Included, Unbounded};
+        assert_eq!(read_range((Included(3), Included(5))), [3, 4, 5]);
+        assert_eq!(read_range((Excluded(3), Excluded(5))), [4]);
+        assert_eq!(read_range((Included(8), Unbounded)), [8, 9, u32::MAX]);
+        assert_eq!(read_range((Unbounded, Excluded(2))), [0, 1]);
+        assert_eq!(read_range((Unbounded, Unbounded)).len(), spk_cache.len());
+        assert!(read_range((Included(10), Excluded(u32::MAX))).is_empty());
+        assert!(read_range((Excluded(5), Excluded(6))).is_empty());
+
+        let mut spks = BTreeMap::new();
+        store
+            .read_spks_range(descriptor_ids[0], 2..4, &mut spks)
+            .unwrap();
+        assert_eq!(
+            spks,
+            spk_cache
+                .range(2..4)
+                .map(|(&index, spk)| (index, spk.clone()))
+                .collect()
+        );
+        let mut spks = BTreeMap::new();
+        store
+            .read_spks_range(descriptor_ids[2], .., &mut spks)
+            .unwrap();
+        assert!(spks.is_empty());
+    }
+
+    #[test]
+    fn test_get_txout() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+
+        // the tables do not exist yet
+        assert_eq!(store.get_txout(OutPoint::new(hash!("F"), 0)).unwrap(), None);
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+
+        let tx = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let floating_txout = TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: ScriptBuf::from_bytes(vec![2]),
+        };
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [tx.clone()].into(),
+                txouts: [(OutPoint::new(hash!("F"), 1), floating_txout.clone())].into(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        // from the txouts table
+        assert_eq!(
+            store.get_txout(OutPoint::new(hash!("F"), 1)).unwrap(),
+            Some(floating_txout)
+        );
+        // from the tx in the txs table
+        assert_eq!(
+            store
+                .get_txout(OutPoint::new(tx.compute_txid(), 0))
+                .unwrap(),
+            Some(tx.output[0].clone())
+        );
+        // missing vout, missing txout and missing tx
+        assert_eq!(
+            store
+                .get_txout(OutPoint::new(tx.compute_txid(), 1))
+                .unwrap(),
+            None
+        );
+        assert_eq!(store.get_txout(OutPoint::new(hash!("F"), 0)).unwrap(), None);
+        assert_eq!(store.get_txout(OutPoint::new(hash!("G"), 0)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_txouts_for_tx() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        assert!(store.txouts_for_tx(hash!("A")).unwrap().is_empty());
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+
+        let txout = |amount| TxOut {
+            value: Amount::from_sat(amount),
+            script_pubkey: ScriptBuf::from_bytes(vec![amount as u8; 22]),
+        };
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txouts: [
+                    (OutPoint::new(hash!("A"), 0), txout(1_000)),
+                    (OutPoint::new(hash!("A"), 3), txout(3_000)),
+                    (OutPoint::new(hash!("A"), u32::MAX), txout(4_000)),
+                    (OutPoint::new(hash!("B"), 1), txout(2_000)),
+                ]
+                .into(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(
+            store.txouts_for_tx(hash!("A")).unwrap(),
+            [
+                (0, txout(1_000)),
+                (3, txout(3_000)),
+                (u32::MAX, txout(4_000))
+            ]
+            .into()
+        );
+        assert_eq!(
+            store.txouts_for_tx(hash!("B")).unwrap(),
+            [(1, txout(2_000))].into()
+        );
+        assert!(store.txouts_for_tx(hash!("C")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_list_utxos() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+
+        let descriptor_ids = utils::DESCRIPTORS.map(|d| parse_descriptor(d).descriptor_id());
+        let [spk0, spk1, foreign] = [1, 2, 3].map(|byte| ScriptBuf::from_bytes(vec![byte]));
+        let txout = |value, script_pubkey: &ScriptBuf| TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: script_pubkey.clone(),
+        };
+        let tx = |previous_outputs: Vec<OutPoint>, output: Vec<TxOut>| {
+            Arc::new(Transaction {
+                version: transaction::Version::ONE,
+                lock_time: absolute::LockTime::ZERO,
+                input: previous_outputs
+                    .into_iter()
+                    .map(|previous_output| TxIn {
+                        previous_output,
+                        ..TxIn::default()
+                    })
+                    .collect(),
+                output,
+            })
+        };
+
+        // tx0 pays the wallet and a foreign script, tx1 (unconfirmed) spends the wallet output of
+        // tx0 and a floating txout back to the wallet, tx2 pays the wallet
+        let floating_spent = OutPoint::new(hash!("S"), 0);
+        let floating_unspent = OutPoint::new(hash!("U"), 1);
+        let tx0 = tx(
+            vec![OutPoint::new(hash!("P"), 0)],
+            vec![txout(50_000, &spk0), txout(10_000, &foreign)],
+        );
+        let tx1 = tx(
+            vec![OutPoint::new(tx0.compute_txid(), 0), floating_spent],
+            vec![txout(45_000, &spk1)],
+        );
+        let tx2 = tx(
+            vec![OutPoint::new(hash!("Q"), 0)],
+            vec![txout(7_000, &spk0)],
+        );
+        let anchor = |height, hash| ConfirmationBlockTime {
+            block_id: BlockId { height, hash },
+            confirmation_time: 100,
+        };
+
+        store
+            .persist_local_chain(&local_chain::ChangeSet {
+                blocks: [
+                    (1, Some(hash!("B1"))),
+                    (2, Some(hash!("B2"))),
+                    (3, Some(hash!("B3"))),
+                ]
+                .into(),
+            })
+            .unwrap();
+        store
+            .persist_indexer(&keychain_txout::ChangeSet {
+                spk_cache: [(
+                    descriptor_ids[0],
+                    [(0, spk0.clone()), (1, spk1.clone())].into(),
+                )]
+                .into(),
+                ..Default::default()
+            })
+            .unwrap();
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [tx0.clone(), tx1.clone(), tx2.clone()].into(),
+                txouts: [
+                    (floating_spent, txout(1_000, &spk0)),
+                    (floating_unspent, txout(2_000, &spk1)),
+                ]
+                .into(),
+                // the anchor at height 5 is not in the local chain
+                anchors: [
+                    (anchor(1, hash!("B1")), tx0.compute_txid()),
+                    (anchor(3, hash!("B3")), tx2.compute_txid()),
+                    (anchor(2, hash!("B2")), tx2.compute_txid()),
+                    (anchor(5, hash!("B5")), tx2.compute_txid()),
+                ]
+                .into(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let mut expected = vec![
+            (
+                OutPoint::new(tx1.compute_txid(), 0),
+                txout(45_000, &spk1),
+                None,
+            ),
+            (
+                OutPoint::new(tx2.compute_txid(), 0),
+                txout(7_000, &spk0),
+                Some(anchor(2, hash!("B2"))),
+            ),
+            (floating_unspent, txout(2_000, &spk1), None),
+        ];
+        expected.sort_by_key(|(outpoint, _, _)| *outpoint);
+        assert_eq!(
+            store.list_utxos::<ConfirmationBlockTime>().unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_evict_tx() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+
+        // tx3 spends tx2 which spends tx1
+        let tx1 = Arc::new(create_one_inp_one_out_tx(hash!("T"), 30_000));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+        let tx3 = Arc::new(create_one_inp_one_out_tx(tx2.compute_txid(), 10_000));
+        let [txid1, txid2, txid3] = [&tx1, &tx2, &tx3].map(|tx| tx.compute_txid());
+        let txout = |value| TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: ScriptBuf::new(),
+        };
+        let anchor = |height| ConfirmationBlockTime {
+            block_id: BlockId {
+                height,
+                hash: hash!("B"),
+            },
+            confirmation_time: 100,
+        };
+        let changeset = |evicted: bool| {
+            let keep = |txid: &Txid| !evicted || *txid != txid2;
+            tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [tx1.clone(), tx2.clone(), tx3.clone()]
+                    .into_iter()
+                    .filter(|tx| keep(&tx.compute_txid()))
+                    .collect(),
+                // txid2 has a txout which is not an output of tx2
+                txouts: [
+                    (OutPoint::new(txid2, 5), txout(1_000)),
+                    (OutPoint::new(hash!("F"), 0), txout(2_000)),
+                ]
+                .into_iter()
+                .filter(|(outpoint, _)| keep(&outpoint.txid))
+                .collect(),
+                anchors: [
+                    (anchor(1), txid1),
+                    (anchor(2), txid2),
+                    (anchor(3), txid2),
+                    (anchor(4), txid3),
+                ]
+                .into_iter()
+                .filter(|(_, txid)| keep(txid))
+                .collect(),
+                last_seen: [(txid1, 10), (txid2, 20), (txid3, 30)]
+                    .into_iter()
+                    .filter(|(txid, _)| keep(txid))
+                    .collect(),
+                first_seen: [(txid2, 15), (txid3, 25)]
+                    .into_iter()
+                    .filter(|(txid, _)| keep(txid))
+                    .collect(),
+                last_evicted: [(txid2, 18)]
+                    .into_iter()
+                    .filter(|(txid, _)| keep(txid))
+                    .collect(),
+            }
+        };
+        store.persist_tx_graph(&changeset(false)).unwrap();
+        // an anchor of another type is removed too
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<BlockId> {
+                anchors: [(block_id!(2, "B"), txid2)].into(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert!(store.evict_tx(txid2).unwrap());
+        assert!(!store.evict_tx(txid2).unwrap());
+
+        let mut changeset_read = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        store.read_tx_graph(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read, changeset(true));
+        assert!(store.anchors_for_tx::<BlockId>(txid2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_floating_txouts() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        // the tables were not created yet
+        assert!(store.floating_txouts().unwrap().is_empty());
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+        assert!(store.floating_txouts().unwrap().is_empty());
+
+        let tx = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let owned_txout = TxOut {
+            value: Amount::from_sat(30_000),
+            script_pubkey: ScriptBuf::from_bytes(vec![1]),
+        };
+        let floating_txout = TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: ScriptBuf::from_bytes(vec![2]),
+        };
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [tx.clone()].into(),
+                txouts: [
+                    (OutPoint::new(tx.compute_txid(), 0), owned_txout),
+                    (OutPoint::new(hash!("F"), 1), floating_txout.clone()),
+                ]
+                .into(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(
+            store.floating_txouts().unwrap(),
+            [(OutPoint::new(hash!("F"), 1), floating_txout)].into()
+        );
+    }
+
+    #[test]
+    fn test_get_timestamps() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+
+        let tx1 = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+        let txid1 = tx1.compute_txid();
+        let txid2 = tx2.compute_txid();
+
+        // the tables were not created yet
+        assert_eq!(store.get_first_seen(txid1).unwrap(), None);
+        assert_eq!(store.get_last_seen(txid1).unwrap(), None);
+        assert_eq!(store.get_last_evicted(txid1).unwrap(), None);
+
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+
+        // only tx1 has timestamps
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [tx1, tx2].into(),
+                first_seen: [(txid1, 100)].into(),
+                last_seen: [(txid1, 200)].into(),
+                last_evicted: [(txid1, 300)].into(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(store.get_first_seen(txid1).unwrap(), Some(100));
+        assert_eq!(store.get_first_seen(txid2).unwrap(), None);
+
+        assert_eq!(store.get_last_seen(txid1).unwrap(), Some(200));
+        assert_eq!(store.get_last_seen(txid2).unwrap(), None);
+
+        assert_eq!(store.get_last_evicted(txid1).unwrap(), Some(300));
+        assert_eq!(store.get_last_evicted(txid2).unwrap(), None);
+    }
+
+    #[test]
+    fn test_block_id_bytes_round_trip() {
+        let block_ids = [
+            block_id!(0, "A"),
+            block_id!(1, "B"),
+            block_id!(255, "C"),
+            block_id!(256, "D"),
+            block_id!(65_536, "E"),
+            block_id!(u32::MAX, "F"),
+        ];
+        let bytes: Vec<[u8; BLOCK_ID_LEN]> = block_ids.iter().map(block_id_to_bytes).collect();
+        for (block_id, bytes) in block_ids.iter().zip(&bytes) {
+            assert_eq!(&block_id_from_bytes(bytes).unwrap(), block_id);
+        }
+        // the byte order of the encodings is the height order
+        assert!(bytes.is_sorted());
+
+        // encodings of another length are rejected instead of panicking
+        for len in [0, HEIGHT_LEN, BLOCK_ID_LEN - 1, BLOCK_ID_LEN + 1] {
+            let bytes = vec![0; len];
+            assert!(matches!(
+                block_id_from_bytes(&bytes),
+                Err(StoreError::AnchorEncoding {
+                    expected: BLOCK_ID_LEN,
+                    found,
+                }) if found == len
+            ));
+        }
+    }
+
+    #[test]
+    fn test_anchor_bytes_round_trip() {
+        let anchor = ConfirmationBlockTime {
+            block_id: block_id!(u32::MAX, "A"),
+            confirmation_time: u64::MAX,
+        };
+        for compact in [false, true] {
+            let metadata = anchor_metadata_to_bytes(&anchor, compact);
+            assert_eq!(
+                anchor_from_bytes::<ConfirmationBlockTime>(anchor.block_id, &metadata).unwrap(),
+                anchor
+            );
+        }
+        let anchor = ConfirmationBlockTime {
+            confirmation_time: 1,
+            ..anchor
+        };
+        let metadata = anchor_metadata_to_bytes(&anchor, true);
+        assert_eq!(metadata, [1]);
+        assert_eq!(
+            anchor_from_bytes::<ConfirmationBlockTime>(anchor.block_id, &metadata).unwrap(),
+            anchor
+        );
+        assert_eq!(
+            anchor_from_bytes::<BlockId>(anchor.block_id, &[]).unwrap(),
+            anchor.block_id
+        );
+
+        // metadata longer than the fixed width is rejected instead of panicking
+        assert!(matches!(
+            anchor_from_bytes::<ConfirmationBlockTime>(anchor.block_id, &[0; 9]),
+            Err(StoreError::AnchorEncoding {
+                expected: 8,
+                found: 9
+            })
+        ));
+    }
+
+    #[test]
+    fn test_anchors_ordered_by_height() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+
+        let tx = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        // in little-endian 256 and 65_536 would sort before 1
+        let heights = [65_536, 1, 300_000, 256, 2];
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<BlockId> {
+                txs: [tx.clone()].into(),
+                anchors: heights
+                    .iter()
+                    .map(|&height| {
+                        (
+                            BlockId {
+                                height,
+                                hash: hash!("B"),
+                            },
+                            tx.compute_txid(),
+                        )
+                    })
+                    .collect(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let read_tx = store.db.begin_read().unwrap();
+        let table = read_tx
+            .open_multimap_table(store.anchors_table_defn())
+            .unwrap();
+        let heights_read: Vec<u32> = table
+            .get(tx.compute_txid().to_byte_array())
+            .unwrap()
+            .map(|value| {
+                block_id_from_bytes(&value.unwrap().value().0)
+                    .unwrap()
+                    .height
+            })
+            .collect();
+        assert_eq!(heights_read, [1, 2, 256, 65_536, 300_000]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_tx_checksum() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+
+        let txs: Vec<Arc<Transaction>> = [10_000, 20_000]
+            .map(|amount| Arc::new(create_one_inp_one_out_tx(hash!("T"), amount)))
+            .into();
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: txs.iter().cloned().collect(),
+                ..Default::default()
+            })
+            .unwrap();
+        let corrupted_txid = txs[1].compute_txid();
+        assert_eq!(store.get_tx(corrupted_txid).unwrap(), Some(txs[1].clone()));
+
+        // flip a bit of the last byte of the ciborium encoding
+        let write_tx = store.db.begin_write().unwrap();
+        {
+            let mut table = write_tx.open_table(store.txs_table_defn()).unwrap();
+            let mut bytes = table
+                .get(corrupted_txid.to_byte_array())
+                .unwrap()
+                .unwrap()
+                .value();
+            assert_eq!(bytes[0], CHECKSUMMED_TX_TAG);
+            *bytes.last_mut().unwrap() ^= 1;
+            table.insert(corrupted_txid.to_byte_array(), bytes).unwrap();
+        }
+        write_tx.commit().unwrap();
+
+        let mut changeset = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        match store.read_tx_graph(&mut changeset) {
+            Ok(_) => panic!("should give error since a tx was corrupted"),
+            Err(StoreError::ChecksumMismatch { txid }) => assert_eq!(txid, corrupted_txid),
+            Err(_) => panic!("error should only be due to the checksum mismatch"),
+        }
+        match store.get_tx(corrupted_txid) {
+            Ok(_) => panic!("should give error since the tx was corrupted"),
+            Err(StoreError::ChecksumMismatch { txid }) => assert_eq!(txid, corrupted_txid),
+            Err(_) => panic!("error should only be due to the checksum mismatch"),
+        }
+        assert_eq!(
+            store.get_tx(txs[0].compute_txid()).unwrap(),
+            Some(txs[0].clone())
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_read_txs_encodings() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+
+        let cbor_tx = Arc::new(create_one_inp_one_out_tx(hash!("A"), 30_000));
+        let consensus_tx = Arc::new(create_one_inp_one_out_tx(hash!("B"), 20_000));
+
+        let write_tx = store.db.begin_write().unwrap();
+        {
+            let mut table = write_tx.open_table(store.txs_table_defn()).unwrap();
+            let mut vec: Vec<u8> = Vec::new();
+            ciborium::into_writer(&cbor_tx, &mut vec).unwrap();
+            table
+                .insert(cbor_tx.compute_txid().to_byte_array(), vec)
+                .unwrap();
+            table
+                .insert(
+                    consensus_tx.compute_txid().to_byte_array(),
+                    bitcoin::consensus::serialize(&*consensus_tx),
+                )
+                .unwrap();
+        }
+        write_tx.commit().unwrap();
+
+        let mut changeset = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        store.read_tx_graph(&mut changeset).unwrap();
+        assert_eq!(
+            changeset.txs,
+            [cbor_tx.clone(), consensus_tx.clone()].into()
+        );
+        assert_eq!(
+            store.get_tx(consensus_tx.compute_txid()).unwrap(),
+            Some(consensus_tx)
+        );
+
+        let write_tx = store.db.begin_write().unwrap();
+        {
+            let mut table = write_tx.open_table(store.txs_table_defn()).unwrap();
+            table.insert([0; 32], vec![0xff, 0x00, 0x01]).unwrap();
+        }
+        write_tx.commit().unwrap();
+
+        let mut changeset = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        match store.read_tx_graph(&mut changeset) {
+            Ok(_) => panic!("should give error since a tx is not decodable"),
+            Err(StoreError::UnknownTxEncoding) => {}
+            Err(_) => panic!("error should only be due to the unknown tx encoding"),
+        }
+    }
+
+    // This function returns a transaction spending a segwit output, with a witness.
+    fn create_segwit_tx(txid: Txid, amount: u64) -> Transaction {
+        let mut tx = create_one_inp_one_out_tx(txid, amount);
+        tx.version = transaction::Version::TWO;
+        tx.input[0].witness = bitcoin::Witness::from_slice(&[vec![0x30; 72], vec![0x02; 33]]);
+        tx.output[0].script_pubkey =
+            ScriptBuf::from_bytes([vec![0x00, 0x14], vec![0xaa; 20]].concat());
+        tx
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_tx_codec() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let cbor_store = create_test_store(db.clone(), "wallet1");
+        let consensus_store =
+            create_test_store(db.clone(), "wallet2").with_tx_codec(TxCodec::Consensus);
+
+        let segwit_tx = Arc::new(create_segwit_tx(hash!("S"), 30_000));
+        let legacy_tx = Arc::new(create_one_inp_one_out_tx(hash!("L"), 20_000));
+        let changeset = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: [segwit_tx.clone(), legacy_tx.clone()].into(),
+            ..Default::default()
+        };
+        for store in [&cbor_store, &consensus_store] {
+            store
+                .create_tx_graph_tables::<ConfirmationBlockTime>()
+                .unwrap();
+            assert_eq!(store.persisted_tx_codec().unwrap(), None);
+            store.persist_tx_graph(&changeset).unwrap();
+
+            let mut changeset_read = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+            store.read_tx_graph(&mut changeset_read).unwrap();
+            assert_eq!(changeset_read.txs, changeset.txs);
+            assert_eq!(
+                store.get_tx(segwit_tx.compute_txid()).unwrap(),
+                Some(segwit_tx.clone())
+            );
+        }
+        assert_eq!(
+            cbor_store.persisted_tx_codec().unwrap(),
+            Some(TxCodec::Cbor)
+        );
+        assert_eq!(
+            consensus_store.persisted_tx_codec().unwrap(),
+            Some(TxCodec::Consensus)
+        );
+
+        // consensus encoded rows are the raw transactions, smaller than the ciborium ones
+        let read_tx = db.begin_read().unwrap();
+        for tx in [&segwit_tx, &legacy_tx] {
+            let txid = tx.compute_txid().to_byte_array();
+            let cbor_row = read_tx
+                .open_table(cbor_store.txs_table_defn())
+                .unwrap()
+                .get(txid)
+                .unwrap()
+                .unwrap()
+                .value();
+            let consensus_row = read_tx
+                .open_table(consensus_store.txs_table_defn())
+                .unwrap()
+                .get(txid)
+                .unwrap()
+                .unwrap()
+                .value();
+            assert_eq!(consensus_row, bitcoin::consensus::serialize(tx.as_ref()));
+            assert!(consensus_row.len() < cbor_row.len());
+        }
+        drop(read_tx);
+
+        // reads use the codec of the wallet, persisting needs the store to use it too
+        let other_store = create_test_store(db.clone(), "wallet2");
+        assert_eq!(
+            other_store.get_tx(legacy_tx.compute_txid()).unwrap(),
+            Some(legacy_tx.clone())
+        );
+        match other_store.persist_tx_graph(&changeset) {
+            Err(StoreError::TxCodecMismatch { stored, requested }) => {
+                assert_eq!(stored, TxCodec::Consensus);
+                assert_eq!(requested, TxCodec::Cbor);
+            }
+            _ => panic!("should give error since the codecs differ"),
+        }
+
+        // the txid serves as checksum of consensus encoded rows
+        let corrupted_txid = legacy_tx.compute_txid();
+        let write_tx = db.begin_write().unwrap();
+        {
+            let mut table = write_tx
+                .open_table(consensus_store.txs_table_defn())
+                .unwrap();
+            let mut bytes = table
+                .get(corrupted_txid.to_byte_array())
+                .unwrap()
+                .unwrap()
+                .value();
+            *bytes.last_mut().unwrap() ^= 1;
+            table.insert(corrupted_txid.to_byte_array(), bytes).unwrap();
+        }
+        write_tx.commit().unwrap();
+        match consensus_store.get_tx(corrupted_txid) {
+            Err(StoreError::ChecksumMismatch { txid }) => assert_eq!(txid, corrupted_txid),
+            _ => panic!("should give error since the tx was corrupted"),
+        }
+    }
+
+    #[cfg(not(feature = "serde"))]
+    #[test]
+    fn test_persist_without_serde() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1");
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+        store.create_local_chain_tables().unwrap();
+        store.create_indexer_tables().unwrap();
+
+        // the chain, tx graph and indexer are persisted with the consensus codec
+        let tx = Arc::new(create_segwit_tx(hash!("S"), 30_000));
+        let txid = tx.compute_txid();
+        let local_chain_changeset = local_chain::ChangeSet {
+            blocks: [(0, Some(hash!("B0"))), (1, Some(hash!("B1")))].into(),
+        };
+        let tx_graph_changeset = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: [tx.clone()].into(),
+            anchors: [(
+                ConfirmationBlockTime {
+                    block_id: block_id!(1, "B1"),
+                    confirmation_time: 1000,
+                },
+                txid,
+            )]
+            .into(),
+            last_seen: [(txid, 100)].into(),
+            ..Default::default()
+        };
+        let indexer_changeset = keychain_txout::ChangeSet {
+            last_revealed: [(DescriptorId::from_byte_array([1; 32]), 4)].into(),
+            ..Default::default()
+        };
+        store.persist_local_chain(&local_chain_changeset).unwrap();
+        store.persist_tx_graph(&tx_graph_changeset).unwrap();
+        store.persist_indexer(&indexer_changeset).unwrap();
+        assert_eq!(
+            store.persisted_tx_codec().unwrap(),
+            Some(TxCodec::Consensus)
+        );
+
+        let store = create_test_store(db.clone(), "wallet1");
+        let mut local_chain_read = local_chain::ChangeSet::default();
+        store.read_local_chain(&mut local_chain_read).unwrap();
+        assert_eq!(local_chain_read, local_chain_changeset);
+        let mut tx_graph_read = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        store.read_tx_graph(&mut tx_graph_read).unwrap();
+        assert_eq!(tx_graph_read, tx_graph_changeset);
+        let mut indexer_read = keychain_txout::ChangeSet::default();
+        store.read_indexer(&mut indexer_read).unwrap();
+        assert_eq!(indexer_read, indexer_changeset);
+
+        // ciborium encoded transactions can neither be persisted nor read
+        let cbor_store = create_test_store(db.clone(), "wallet2").with_tx_codec(TxCodec::Cbor);
+        cbor_store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+        assert!(matches!(
+            cbor_store.persist_tx_graph(&tx_graph_changeset),
+            Err(StoreError::CborDisabled)
+        ));
+        assert_eq!(cbor_store.persisted_tx_codec().unwrap(), None);
+        let write_tx = db.begin_write().unwrap();
+        write_tx
+            .open_table(cbor_store.txs_table_defn())
+            .unwrap()
+            .insert(
+                txid.to_byte_array(),
+                vec![CHECKSUMMED_TX_TAG, 0, 0, 0, 0, 0xa0],
+            )
+            .unwrap();
+        write_tx.commit().unwrap();
+        assert!(matches!(
+            cbor_store.get_tx(txid),
+            Err(StoreError::CborDisabled)
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_migrate_tx_codec() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1");
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+
+        // a transaction written before codecs were recorded
+        let segwit_tx = Arc::new(create_segwit_tx(hash!("S"), 30_000));
+        let write_tx = db.begin_write().unwrap();
+        write_tx
+            .open_table(store.txs_table_defn())
+            .unwrap()
+            .insert(
+                segwit_tx.compute_txid().to_byte_array(),
+                tx_to_bytes(&segwit_tx).unwrap(),
+            )
+            .unwrap();
+        write_tx.commit().unwrap();
+
+        let consensus_store =
+            create_test_store(db.clone(), "wallet1").with_tx_codec(TxCodec::Consensus);
+        let legacy_tx = Arc::new(create_one_inp_one_out_tx(hash!("L"), 20_000));
+        let changeset = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: [legacy_tx.clone()].into(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            consensus_store.persist_tx_graph(&changeset),
+            Err(StoreError::TxCodecMismatch { .. })
+        ));
+        store.persist_tx_graph(&changeset).unwrap();
+        assert_eq!(store.persisted_tx_codec().unwrap(), Some(TxCodec::Cbor));
+
+        assert_eq!(store.migrate_tx_codec(TxCodec::Consensus).unwrap(), 2);
+        assert_eq!(store.migrate_tx_codec(TxCodec::Consensus).unwrap(), 0);
+        assert_eq!(
+            store.persisted_tx_codec().unwrap(),
+            Some(TxCodec::Consensus)
+        );
+        let read_tx = db.begin_read().unwrap();
+        let table = read_tx.open_table(store.txs_table_defn()).unwrap();
+        for tx in [&segwit_tx, &legacy_tx] {
+            assert_eq!(
+                table
+                    .get(tx.compute_txid().to_byte_array())
+                    .unwrap()
+                    .unwrap()
+                    .value(),
+                bitcoin::consensus::serialize(tx.as_ref())
+            );
+        }
+        drop(read_tx);
+
+        let mut changeset_read = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        store.read_tx_graph(&mut changeset_read).unwrap();
+        assert_eq!(
+            changeset_read.txs,
+            [segwit_tx.clone(), legacy_tx.clone()].into()
+        );
+        let new_tx = Arc::new(create_one_inp_one_out_tx(hash!("N"), 10_000));
+        consensus_store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [new_tx.clone()].into(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(store.get_tx(new_tx.compute_txid()).unwrap(), Some(new_tx));
+
+        // and back
+        assert_eq!(store.migrate_tx_codec(TxCodec::Cbor).unwrap(), 3);
+        let mut changeset_read = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        store.read_tx_graph(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read.txs.len(), 3);
+        store.persist_tx_graph(&changeset).unwrap();
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_compression() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let raw_store = create_test_store(db.clone(), "wallet1");
+        let store = create_test_store(db.clone(), "wallet2").with_compression_level(Some(3));
+
+        // transactions with several inputs and outputs paying the same script, like the ones of a
+        // consolidating wallet
+        let txs: BTreeSet<Arc<Transaction>> = (0..500u32)
+            .map(|i| {
+                let mut tx = create_segwit_tx(Txid::from_byte_array([i as u8; 32]), 1_000);
+                tx.input = (0..5)
+                    .map(|vout| TxIn {
+                        previous_output: OutPoint::new(Txid::from_byte_array([i as u8; 32]), vout),
+                        witness: tx.input[0].witness.clone(),
+                        ..TxIn::default()
+                    })
+                    .collect();
+                tx.output = (0..5)
+                    .map(|j| TxOut {
+                        value: Amount::from_sat(u64::from(i * 5 + j)),
+                        script_pubkey: tx.output[0].script_pubkey.clone(),
+                    })
+                    .collect();
+                Arc::new(tx)
+            })
+            .collect();
+        let changeset = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: txs.clone(),
+            ..Default::default()
+        };
+        for store in [&raw_store, &store] {
+            store
+                .create_tx_graph_tables::<ConfirmationBlockTime>()
+                .unwrap();
+            store.persist_tx_graph(&changeset).unwrap();
+            let mut changeset_read = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+            store.read_tx_graph(&mut changeset_read).unwrap();
+            assert_eq!(changeset_read.txs, txs);
+        }
+        let raw_bytes = raw_store.stats().unwrap().txs.stored_bytes;
+        let compressed_bytes = store.stats().unwrap().txs.stored_bytes;
+        assert!(
+            compressed_bytes * 10 < raw_bytes * 8,
+            "{compressed_bytes} bytes compressed, {raw_bytes} raw"
+        );
+
+        let txid = txs.first().unwrap().compute_txid();
+        let read_tx = db.begin_read().unwrap();
+        let row = read_tx
+            .open_table(store.txs_table_defn())
+            .unwrap()
+            .get(txid.to_byte_array())
+            .unwrap()
+            .unwrap()
+            .value();
+        assert_eq!(row[0], compression::COMPRESSED_TAG);
+        drop(read_tx);
+
+        // compression can be toggled, both kinds of rows are read
+        let toggled_store = create_test_store(db.clone(), "wallet2");
+        let tx = Arc::new(create_segwit_tx(hash!("T"), 30_000));
+        toggled_store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [tx.clone()].into(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(toggled_store.get_tx(txid).unwrap(), txs.first().cloned());
+        assert_eq!(store.get_tx(tx.compute_txid()).unwrap(), Some(tx));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_spk_compression() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1")
+            .with_compression_level(Some(3))
+            .with_spk_compression(true);
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+
+        let descriptor_id = parse_descriptor(DESCRIPTORS[0]).descriptor_id();
+        let short_spk = ScriptBuf::from_bytes(
+            [vec![0x00, 0x20], Txid::to_byte_array(hash!("P")).to_vec()].concat(),
+        );
+        let long_spk = ScriptBuf::from_bytes(vec![0x51; 200]);
+        let spk_cache = [(
+            descriptor_id,
+            [(0u32, short_spk.clone()), (1u32, long_spk.clone())].into(),
+        )]
+        .into();
+        store
+            .persist_indexer(&keychain_txout::ChangeSet {
+                spk_cache,
+                ..Default::default()
+            })
+            .unwrap();
+
+        // only the scripts which shrink are stored compressed
+        let read_tx = db.begin_read().unwrap();
+        let table = read_tx.open_table(store.spk_table_defn()).unwrap();
+        let key = descriptor_id.to_byte_array();
+        assert_eq!(
+            table.get((key, 0)).unwrap().unwrap().value(),
+            short_spk.to_bytes()
+        );
+        let row = table.get((key, 1)).unwrap().unwrap().value();
+        assert_eq!(row[0], compression::COMPRESSED_TAG);
+        assert!(row.len() < long_spk.len());
+
+        for store in [store, create_test_store(db.clone(), "wallet1")] {
+            let mut spk_cache_read = BTreeMap::new();
+            store.read_spks(&read_tx, &mut spk_cache_read).unwrap();
+            assert_eq!(
+                spk_cache_read,
+                [(
+                    descriptor_id,
+                    [(0, short_spk.clone()), (1, long_spk.clone())].into()
+                )]
+                .into()
+            );
+
+            // the lazy iterators decompress the scripts too
+            let spks: Vec<_> = store
+                .iter_spks(descriptor_id)
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(spks, [(0, short_spk.clone()), (1, long_spk.clone())]);
+            let all_spks: Vec<_> = store
+                .iter_all_spks()
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(
+                all_spks,
+                [
+                    (descriptor_id, 0, short_spk.clone()),
+                    (descriptor_id, 1, long_spk.clone())
+                ]
+            );
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_migrate_anchors_to_big_endian() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+
+        let tx = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let txid = tx.compute_txid();
+        let anchors = [
+            block_id!(1, "A"),
+            block_id!(256, "B"),
+            block_id!(70_000, "C"),
+        ];
+
+        // write the tables as an older version of the crate did: no format version and
+        // little-endian heights
+        let write_tx = store.db.begin_write().unwrap();
+        {
+            let mut vec: Vec<u8> = Vec::new();
+            ciborium::into_writer(&tx, &mut vec).unwrap();
+            let mut txs_table = write_tx.open_table(store.txs_table_defn()).unwrap();
+            txs_table.insert(txid.to_byte_array(), vec).unwrap();
+
+            let mut table = write_tx
+                .open_table(store.legacy_anchors_table_defn())
+                .unwrap();
+            for anchor in &anchors {
+                let mut bytes = [0; 36];
+                bytes[0..4].copy_from_slice(&anchor.height.to_le_bytes());
+                bytes[4..].copy_from_slice(&anchor.hash.to_byte_array());
+                table
+                    .insert(
+                        (txid.to_byte_array(), bytes, BlockId::TAG),
+                        <Option<()>>::as_bytes(&None),
+                    )
+                    .unwrap();
+            }
+        }
+        write_tx.commit().unwrap();
+
+        // migrating twice must not swap the heights back
+        store.create_tx_graph_tables::<BlockId>().unwrap();
+        store.create_tx_graph_tables::<BlockId>().unwrap();
+
+        let mut changeset = tx_graph::ChangeSet::<BlockId>::default();
+        store.read_tx_graph(&mut changeset).unwrap();
+        assert_eq!(
+            changeset.anchors,
+            anchors.iter().map(|anchor| (*anchor, txid)).collect()
+        );
+
+        let read_tx = store.db.begin_read().unwrap();
+        let version_table = read_tx.open_table(FORMAT_VERSION).unwrap();
+        assert_eq!(
+            version_table.get("wallet1").unwrap().unwrap().value(),
+            CURRENT_FORMAT_VERSION
+        );
+    }
+
+    #[test]
+    fn test_migrate_anchors_to_multimap() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1");
+
+        let txs = [10_000, 20_000].map(|amount| {
+            Arc::new(create_one_inp_one_out_tx(
+                Txid::from_byte_array([0; 32]),
+                amount,
+            ))
+        });
+        let changeset = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: txs.iter().cloned().collect(),
+            anchors: [
+                (
+                    ConfirmationBlockTime {
+                        block_id: block_id!(256, "B"),
+                        confirmation_time: 300,
+                    },
+                    txs[0].compute_txid(),
+                ),
+                (
+                    ConfirmationBlockTime {
+                        block_id: block_id!(1, "A"),
+                        confirmation_time: 100,
+                    },
+                    txs[0].compute_txid(),
+                ),
+                (
+                    ConfirmationBlockTime {
+                        block_id: block_id!(1, "A"),
+                        confirmation_time: 100,
+                    },
+                    txs[1].compute_txid(),
+                ),
+            ]
+            .into(),
+            ..Default::default()
+        };
+
+        // write the anchors as version 1 of the format did: keyed by (txid, block id, tag) with
+        // big-endian heights
+        let write_tx = db.begin_write().unwrap();
+        {
+            store.persist_txs(&write_tx, &changeset.txs).unwrap();
+            let mut table = write_tx
+                .open_table(store.legacy_anchors_table_defn())
+                .unwrap();
+            for (anchor, txid) in &changeset.anchors {
+                table
+                    .insert(
+                        (
+                            txid.to_byte_array(),
+                            block_id_to_bytes(&anchor.block_id),
+                            ConfirmationBlockTime::TAG,
+                        ),
+                        anchor_metadata_to_bytes(anchor, false),
+                    )
+                    .unwrap();
+            }
+            write_tx
+                .open_table(FORMAT_VERSION)
+                .unwrap()
+                .insert("wallet1", 1)
+                .unwrap();
+        }
+        write_tx.commit().unwrap();
+
+        // the legacy table cannot be read as a multimap before the migration
+        assert!(!store.is_initialized().unwrap());
+        assert!(
+            store
+                .read_tx_graph(&mut tx_graph::ChangeSet::<ConfirmationBlockTime>::default())
+                .is_err()
+        );
+
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        assert!(store.is_initialized().unwrap());
+        let mut changeset_read = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        store.read_tx_graph(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read, changeset);
+        assert_eq!(store.anchor_count().unwrap(), 3);
+        assert_eq!(
+            store
+                .anchors_for_tx::<ConfirmationBlockTime>(txs[0].compute_txid())
+                .unwrap()
+                .iter()
+                .map(|anchor| anchor.block_id.height)
+                .collect::<Vec<_>>(),
+            [1, 256]
+        );
+    }
+
+    #[test]
+    fn test_multiple_anchors_per_tx() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+
+        let tx = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            10_000,
+        ));
+        let txid = tx.compute_txid();
+        let anchors = [
+            ConfirmationBlockTime {
+                block_id: block_id!(7, "A"),
+                confirmation_time: 700,
+            },
+            ConfirmationBlockTime {
+                block_id: block_id!(5, "B"),
+                confirmation_time: 500,
+            },
+            ConfirmationBlockTime {
+                block_id: block_id!(5, "C"),
+                confirmation_time: 501,
+            },
+        ];
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet {
+                txs: [tx.clone()].into(),
+                anchors: anchors.iter().map(|anchor| (*anchor, txid)).collect(),
+                ..Default::default()
+            })
+            .unwrap();
+        // an anchor of another type in the same block
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<BlockId> {
+                anchors: [(block_id!(7, "A"), txid)].into(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(store.anchor_count().unwrap(), 4);
+        assert_eq!(
+            store.anchors_for_tx::<ConfirmationBlockTime>(txid).unwrap(),
+            [anchors[1], anchors[2], anchors[0]]
+        );
+        assert_eq!(
+            store.anchors_for_tx::<BlockId>(txid).unwrap(),
+            [block_id!(7, "A")]
+        );
+
+        // an anchor persisted again in the same block with other metadata replaces the stored one
+        let updated = ConfirmationBlockTime {
+            confirmation_time: 702,
+            ..anchors[0]
+        };
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet {
+                anchors: [(updated, txid)].into(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(store.anchor_count().unwrap(), 4);
+        assert_eq!(
+            store.anchors_for_tx::<ConfirmationBlockTime>(txid).unwrap(),
+            [anchors[1], anchors[2], updated]
+        );
+
+        // anchors of all types are removed with the tx
+        assert!(store.evict_tx(txid).unwrap());
+        assert_eq!(store.anchor_count().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_persist_anchors() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+
+        let tx1 = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+        let tx3 = Arc::new(create_one_inp_one_out_tx(tx2.compute_txid(), 19_000));
+
+        let anchor1 = ConfirmationBlockTime {
+            block_id: block_id!(23, "BTC"),
+            confirmation_time: 1756838400,
+        };
+
+        let anchor2 = ConfirmationBlockTime {
+            block_id: block_id!(25, "BDK"),
+            confirmation_time: 1756839600,
+        };
+
+        let txs: BTreeSet<Arc<Transaction>> = [tx1.clone(), tx2.clone()].into();
+        let mut anchors = [(anchor1, tx1.compute_txid()), (anchor2, tx2.compute_txid())].into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
+        let _ = write_tx
+            .open_multimap_table(store.anchors_table_defn())
+            .unwrap();
+        write_tx.commit().unwrap();
+
+        let write_tx = store.db.begin_write().unwrap();
+        store.persist_txs(&write_tx, &txs).unwrap();
+        write_tx.commit().unwrap();
+
+        // to hit the branch for the case when tx is persisted but not in changeset
+        let txs: BTreeSet<Arc<Transaction>> = BTreeSet::new();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let read_tx = store.db.begin_read().unwrap();
+        store
+            .persist_anchors(&write_tx, &read_tx, &anchors, &txs)
+            .unwrap();
+        read_tx.close().unwrap();
+        write_tx.commit().unwrap();
+
+        let read_tx = store.db.begin_read().unwrap();
+        let mut anchors_read: BTreeSet<(ConfirmationBlockTime, Txid)> = BTreeSet::new();
+        store.read_anchors(&read_tx, &mut anchors_read).unwrap();
+        assert_eq!(anchors_read, anchors);
+
+        let txs_new: BTreeSet<Arc<Transaction>> = [tx3.clone()].into();
+        let anchors_new: BTreeSet<(ConfirmationBlockTime, Txid)> =
+            [(anchor2, tx3.compute_txid())].into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let read_tx = store.db.begin_read().unwrap();
+        store
+            .persist_anchors(&write_tx, &read_tx, &anchors_new, &txs_new)
+            .unwrap();
+        read_tx.close().unwrap();
+        write_tx.commit().unwrap();
+
+        let read_tx = store.db.begin_read().unwrap();
+        let mut anchors_read_new: BTreeSet<(ConfirmationBlockTime, Txid)> = BTreeSet::new();
+        store.read_anchors(&read_tx, &mut anchors_read_new).unwrap();
+
+        anchors.merge(anchors_new);
+        assert_eq!(anchors_read_new, anchors);
+
+        // to hit the branch for the case when tx is persisted and is also in changeset (can this
+        // happen though?)
+        let tx4 = Arc::new(create_one_inp_one_out_tx(tx3.compute_txid(), 14_000));
+
+        let txs_new: BTreeSet<Arc<Transaction>> = [tx4.clone()].into();
+        let anchors_new: BTreeSet<(ConfirmationBlockTime, Txid)> =
+            [(anchor2, tx4.compute_txid())].into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        store.persist_txs(&write_tx, &txs_new).unwrap();
+        write_tx.commit().unwrap();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let read_tx = store.db.begin_read().unwrap();
+        store
+            .persist_anchors(&write_tx, &read_tx, &anchors_new, &txs_new)
+            .unwrap();
+        read_tx.close().unwrap();
+        write_tx.commit().unwrap();
+
+        let read_tx = store.db.begin_read().unwrap();
+        let mut anchors_read_new: BTreeSet<(ConfirmationBlockTime, Txid)> = BTreeSet::new();
+        store.read_anchors(&read_tx, &mut anchors_read_new).unwrap();
+
+        anchors.merge(anchors_new);
+        assert_eq!(anchors_read_new, anchors);
+    }
+
+    #[test]
+    fn test_anchors_missing_tx() {
+        // to hit the branch for the panic case in persist_anchors
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+
+        let anchor1 = ConfirmationBlockTime {
+            block_id: block_id!(23, "BTC"),
+            confirmation_time: 1756838400,
+        };
+
+        let write_tx = store.db.begin_write().unwrap();
+        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
+        let _ = write_tx
+            .open_multimap_table(store.anchors_table_defn())
+            .unwrap();
+        write_tx.commit().unwrap();
+
+        let anchors_missing_txs: BTreeSet<(ConfirmationBlockTime, Txid)> =
+            [(anchor1, hash!("B"))].into();
+        let txs: BTreeSet<Arc<Transaction>> = BTreeSet::new();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let read_tx = store.db.begin_read().unwrap();
+        match store.persist_anchors(&write_tx, &read_tx, &anchors_missing_txs, &txs) {
+            Ok(_) => panic!("should give error since tx missing"),
+            Err(StoreError::TxMissing(txid)) => assert_eq!(txid, hash!("B")),
+            Err(_) => panic!("error should only be due to missing tx"),
+        }
+        read_tx.close().unwrap();
+        write_tx.commit().unwrap();
+    }
+
+    #[test]
+    fn test_persist_anchors_blockid() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
         let store = create_test_store(Arc::new(db), "wallet1");
-        store.create_network_table().unwrap();
-        let network_changeset = Some(Network::Bitcoin);
-        store.persist_network(&network_changeset).unwrap();
 
-        let mut network_changeset = Some(Network::Regtest);
-        store.read_network(&mut network_changeset).unwrap();
+        let tx1 = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+        let tx3 = Arc::new(create_one_inp_one_out_tx(tx2.compute_txid(), 19_000));
 
-        assert_eq!(network_changeset, Some(Network::Bitcoin));
+        let anchor1 = block_id!(23, "BTC");
+
+        let anchor2 = block_id!(25, "BDK");
+
+        let txs: BTreeSet<Arc<Transaction>> = [tx1.clone(), tx2.clone()].into();
+        let mut anchors = [(anchor1, tx1.compute_txid()), (anchor2, tx2.compute_txid())].into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
+        let _ = write_tx
+            .open_multimap_table(store.anchors_table_defn())
+            .unwrap();
+        write_tx.commit().unwrap();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let read_tx = store.db.begin_read().unwrap();
+        store
+            .persist_anchors(&write_tx, &read_tx, &anchors, &txs)
+            .unwrap();
+        read_tx.close().unwrap();
+        write_tx.commit().unwrap();
+
+        let read_tx = store.db.begin_read().unwrap();
+        let mut anchors_read: BTreeSet<(BlockId, Txid)> = BTreeSet::new();
+        store.read_anchors(&read_tx, &mut anchors_read).unwrap();
+        assert_eq!(anchors_read, anchors);
+
+        let txs_new: BTreeSet<Arc<Transaction>> = [tx3.clone()].into();
+        let anchors_new: BTreeSet<(BlockId, Txid)> = [(anchor2, tx3.compute_txid())].into();
+
+        let write_tx = store.db.begin_write().unwrap();
+        let read_tx = store.db.begin_read().unwrap();
+        store
+            .persist_anchors(&write_tx, &read_tx, &anchors_new, &txs_new)
+            .unwrap();
+        read_tx.close().unwrap();
+        write_tx.commit().unwrap();
+
+        let read_tx = store.db.begin_read().unwrap();
+        let mut anchors_read_new: BTreeSet<(BlockId, Txid)> = BTreeSet::new();
+        store.read_anchors(&read_tx, &mut anchors_read_new).unwrap();
+
+        anchors.merge(anchors_new);
+        assert_eq!(anchors_read_new, anchors);
     }
 
     #[test]
-    fn test_keychains_persistence() {
+    fn test_persist_mixed_anchors() {
         let tmpfile = NamedTempFile::new().unwrap();
         let db = create_db(tmpfile.path());
         let store = create_test_store(Arc::new(db), "wallet1");
-        store.create_keychains_table().unwrap();
+        store.create_tx_graph_tables::<BlockId>().unwrap();
 
-        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[0].parse().unwrap();
-        let change_descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[1].parse().unwrap();
-        let desc_changeset: BTreeMap<u64, Descriptor<DescriptorPublicKey>> =
-            [(0, descriptor.clone()), (1, change_descriptor.clone())].into();
+        let tx1 = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+
+        let block_id_anchor = block_id!(23, "BTC");
+        let conf_anchor = ConfirmationBlockTime {
+            block_id: block_id!(23, "BTC"),
+            confirmation_time: 1756838400,
+        };
+
+        let block_id_changeset = tx_graph::ChangeSet::<BlockId> {
+            txs: [tx1.clone()].into(),
+            anchors: [(block_id_anchor, tx1.compute_txid())].into(),
+            ..Default::default()
+        };
+        let conf_changeset = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            anchors: [(conf_anchor, tx1.compute_txid())].into(),
+            ..Default::default()
+        };
+
+        store.persist_tx_graph(&block_id_changeset).unwrap();
+        store.persist_tx_graph(&conf_changeset).unwrap();
+
+        let mut changeset = tx_graph::ChangeSet::<BlockId>::default();
+        store.read_tx_graph(&mut changeset).unwrap();
+        assert_eq!(changeset, block_id_changeset);
+
+        let mut changeset = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        store.read_tx_graph(&mut changeset).unwrap();
+        assert_eq!(changeset.anchors, conf_changeset.anchors);
+    }
+
+    #[test]
+    fn test_strict_txouts() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1");
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+        let outpoint = OutPoint::new(hash!("F"), 0);
+        let txout = TxOut {
+            value: Amount::from_sat(1_000),
+            script_pubkey: ScriptBuf::from_bytes(vec![1, 2, 3]),
+        };
+        let conflicting = TxOut {
+            value: Amount::from_sat(1_000),
+            script_pubkey: ScriptBuf::from_bytes(vec![4, 5, 6]),
+        };
+        let changeset = |txout: &TxOut| tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txouts: [(outpoint, txout.clone())].into(),
+            ..Default::default()
+        };
+        store.persist_tx_graph(&changeset(&txout)).unwrap();
+
+        // strict mode rejects a different txout but accepts the same one
+        let strict_store = create_test_store(db, "wallet1").with_strict_txouts(true);
+        strict_store.persist_tx_graph(&changeset(&txout)).unwrap();
+        assert!(matches!(
+            strict_store.persist_tx_graph(&changeset(&conflicting)),
+            Err(StoreError::ConflictingTxOut { outpoint: op }) if op == outpoint
+        ));
+        assert_eq!(strict_store.get_txout(outpoint).unwrap(), Some(txout));
+
+        // the default mode overwrites it
+        store.persist_tx_graph(&changeset(&conflicting)).unwrap();
+        assert_eq!(store.get_txout(outpoint).unwrap(), Some(conflicting));
+    }
+
+    #[test]
+    fn test_anchor_type_check() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1").with_anchor_type_check(true);
+        store.create_tx_graph_tables::<BlockId>().unwrap();
+
+        let tx = Arc::new(create_one_inp_one_out_tx(hash!("T"), 10_000));
+        let block_id_changeset = tx_graph::ChangeSet::<BlockId> {
+            txs: [tx.clone()].into(),
+            anchors: [(block_id!(1, "B1"), tx.compute_txid())].into(),
+            ..Default::default()
+        };
+        store.persist_tx_graph(&block_id_changeset).unwrap();
+        let mut changeset = tx_graph::ChangeSet::<BlockId>::default();
+        store.read_tx_graph(&mut changeset).unwrap();
+        assert_eq!(changeset, block_id_changeset);
+
+        let is_mismatch = |err| {
+            matches!(
+                err,
+                StoreError::AnchorTypeMismatch { stored, requested }
+                    if stored == std::any::type_name::<BlockId>()
+                        && requested == std::any::type_name::<ConfirmationBlockTime>()
+            )
+        };
+        let mut changeset = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        assert!(is_mismatch(
+            store.read_tx_graph(&mut changeset).unwrap_err()
+        ));
+        assert!(is_mismatch(
+            store
+                .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                    anchors: [(
+                        ConfirmationBlockTime {
+                            block_id: block_id!(1, "B1"),
+                            confirmation_time: 100,
+                        },
+                        tx.compute_txid()
+                    )]
+                    .into(),
+                    ..Default::default()
+                })
+                .unwrap_err()
+        ));
+        assert!(is_mismatch(
+            store
+                .anchors_for_tx::<ConfirmationBlockTime>(tx.compute_txid())
+                .unwrap_err()
+        ));
+        assert!(is_mismatch(
+            store.create_tables::<ConfirmationBlockTime>().unwrap_err()
+        ));
+
+        // without the check, anchors of other types can be used as before
+        let store = create_test_store(db, "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        let mut changeset = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        store.read_tx_graph(&mut changeset).unwrap();
+        assert!(changeset.anchors.is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_anchor() {
+        use crate::anchor_trait::SerdeAnchor;
+
+        #[derive(
+            Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+        )]
+        struct CustomAnchor {
+            block_id: BlockId,
+            confirmations: u32,
+        }
+
+        impl bdk_chain::Anchor for CustomAnchor {
+            fn anchor_block(&self) -> BlockId {
+                self.block_id
+            }
+        }
+
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store
+            .create_tx_graph_tables::<SerdeAnchor<CustomAnchor>>()
+            .unwrap();
+
+        let tx = Arc::new(create_one_inp_one_out_tx(hash!("T"), 10_000));
+        let anchors: [SerdeAnchor<CustomAnchor>; 2] = [
+            SerdeAnchor(CustomAnchor {
+                block_id: block_id!(2, "B2"),
+                confirmations: 6,
+            }),
+            SerdeAnchor(CustomAnchor {
+                block_id: block_id!(1, "B1"),
+                confirmations: 0,
+            }),
+        ];
+        let changeset = tx_graph::ChangeSet {
+            txs: [tx.clone()].into(),
+            anchors: anchors
+                .iter()
+                .map(|anchor| (anchor.clone(), tx.compute_txid()))
+                .collect(),
+            ..Default::default()
+        };
+        let write_tx = store.db.begin_write().unwrap();
+        let read_tx = store.db.begin_read().unwrap();
+        store.persist_txs(&write_tx, &changeset.txs).unwrap();
+        store
+            .persist_anchors(&write_tx, &read_tx, &changeset.anchors, &changeset.txs)
+            .unwrap();
+        write_tx.commit().unwrap();
+
+        let mut anchors_read = BTreeSet::new();
+        let read_tx = store.db.begin_read().unwrap();
+        store.read_anchors(&read_tx, &mut anchors_read).unwrap();
+        assert_eq!(anchors_read, changeset.anchors);
+        assert_eq!(
+            store
+                .anchors_for_tx::<SerdeAnchor<CustomAnchor>>(tx.compute_txid())
+                .unwrap(),
+            [anchors[1].clone(), anchors[0].clone()]
+        );
+
+        // the adapter does not see the anchors of other types
+        let mut changeset_read = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        store.read_tx_graph(&mut changeset_read).unwrap();
+        assert!(changeset_read.anchors.is_empty());
+    }
+
+    #[test]
+    fn test_store_clone_concurrent() {
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let mut store = create_test_store(Arc::new(db), "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        let persists = Arc::new(AtomicUsize::new(0));
+        let counter = persists.clone();
+        store.set_on_persist(Box::new(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+
+        let txs: Vec<Arc<Transaction>> = (0..20)
+            .map(|i| Arc::new(create_one_inp_one_out_tx(hash!("T"), 1_000 + i)))
+            .collect();
+        let done = Arc::new(AtomicBool::new(false));
+        let readers: Vec<_> = (0..2)
+            .map(|_| {
+                let store = store.clone();
+                let done = done.clone();
+                std::thread::spawn(move || {
+                    // each read sees a committed state, which only grows
+                    let mut seen = 0;
+                    loop {
+                        let finished = done.load(Ordering::SeqCst);
+                        let mut changeset = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+                        store.read_tx_graph(&mut changeset).unwrap();
+                        assert!(changeset.txs.len() >= seen);
+                        seen = changeset.txs.len();
+                        if finished {
+                            return seen;
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let writer = store.clone();
+        for tx in &txs {
+            writer
+                .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                    txs: [tx.clone()].into(),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        done.store(true, Ordering::SeqCst);
+        for reader in readers {
+            assert_eq!(reader.join().unwrap(), txs.len());
+        }
+        // the callback is shared by the clones
+        assert_eq!(persists.load(Ordering::SeqCst), txs.len());
+    }
+
+    #[test]
+    fn test_store_debug() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1");
+
+        let debug = format!("{store:?}");
+        assert!(debug.starts_with("Store {"));
+        assert!(debug.contains(r#"wallet_name: "wallet1""#));
+        assert!(debug.contains(r#"txs_table_name: "wallet1_txs""#));
+        assert!(!debug.contains("Database"));
+        assert!(!debug.contains(&format!("{:p}", Arc::as_ptr(&db))));
+        assert!(debug.ends_with(", .. }"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_cbor_value() {
+        use crate::wrapper::CborValue;
+
+        #[derive(
+            Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+        )]
+        struct Contact {
+            name: String,
+            address: Option<String>,
+        }
+
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        let contacts: TableDefinition<i64, CborValue<Contact>> = TableDefinition::new("contacts");
+        let by_contact: TableDefinition<CborValue<Contact>, u32> =
+            TableDefinition::new("by_contact");
+
+        let alice = Contact {
+            name: "alice".to_string(),
+            address: Some("bc1qalice".to_string()),
+        };
+        let bob = Contact {
+            name: "bob".to_string(),
+            address: None,
+        };
+        let write_tx = db.begin_write().unwrap();
+        {
+            let mut table = write_tx.open_table(contacts).unwrap();
+            table.insert(-1, &alice).unwrap();
+            table.insert(0, &bob).unwrap();
+            let mut table = write_tx.open_table(by_contact).unwrap();
+            // "bob" would come first if keys were ordered by their encoding, which starts with
+            // the length of the name
+            table.insert(&bob, 1).unwrap();
+            table.insert(&alice, 0).unwrap();
+        }
+        write_tx.commit().unwrap();
+
+        let read_tx = db.begin_read().unwrap();
+        let table = read_tx.open_table(contacts).unwrap();
+        assert_eq!(table.get(-1).unwrap().unwrap().value(), alice);
+        assert_eq!(table.get(0).unwrap().unwrap().value(), bob);
+        let table = read_tx.open_table(by_contact).unwrap();
+        assert_eq!(table.get(&bob).unwrap().unwrap().value(), 1);
+        let keys: Vec<Contact> = table
+            .iter()
+            .unwrap()
+            .map(|entry| entry.unwrap().0.value())
+            .collect();
+        assert_eq!(keys, [alice, bob]);
+
+        // the wallet tables are unaffected
+        let mut changeset_read = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        store.read_tx_graph(&mut changeset_read).unwrap();
+        assert!(changeset_read.is_empty());
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn test_derive_anchor_with_metadata() {
+        use crate::anchor_trait::AnchorWithMetaData;
+
+        #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, AnchorWithMetaData)]
+        #[anchor(tag = 3)]
+        struct CustomAnchor {
+            block_id: BlockId,
+            confirmations: u32,
+            source: String,
+        }
+
+        impl bdk_chain::Anchor for CustomAnchor {
+            fn anchor_block(&self) -> BlockId {
+                self.block_id
+            }
+        }
+
+        assert_eq!(<CustomAnchor as AnchorWithMetaData>::TAG, 3);
+
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        store.create_tx_graph_tables::<CustomAnchor>().unwrap();
+
+        let tx = Arc::new(create_one_inp_one_out_tx(hash!("T"), 10_000));
+        let anchors = [
+            CustomAnchor {
+                block_id: block_id!(2, "B2"),
+                confirmations: 6,
+                source: "electrum".to_string(),
+            },
+            CustomAnchor {
+                block_id: block_id!(1, "B1"),
+                confirmations: 0,
+                source: String::new(),
+            },
+        ];
+        let changeset = tx_graph::ChangeSet {
+            txs: [tx.clone()].into(),
+            anchors: anchors
+                .iter()
+                .map(|anchor| (anchor.clone(), tx.compute_txid()))
+                .collect(),
+            ..Default::default()
+        };
+        store.persist_tx_graph(&changeset).unwrap();
+
+        let mut changeset_read = tx_graph::ChangeSet::<CustomAnchor>::default();
+        store.read_tx_graph(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read, changeset);
+        assert_eq!(
+            store
+                .anchors_for_tx::<CustomAnchor>(tx.compute_txid())
+                .unwrap(),
+            [anchors[1].clone(), anchors[0].clone()]
+        );
+    }
+
+    #[test]
+    fn test_tx_graph_persistence() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+        let tx1 = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+        let block_id = block_id!(100, "B");
+
+        let conf_anchor: ConfirmationBlockTime = ConfirmationBlockTime {
+            block_id,
+            confirmation_time: 1,
+        };
+
+        let mut tx_graph_changeset1 = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: [tx1.clone()].into(),
+            txouts: [].into(),
+            anchors: [(conf_anchor, tx1.compute_txid())].into(),
+            last_seen: [(tx1.compute_txid(), 100)].into(),
+            first_seen: [(tx1.compute_txid(), 50)].into(),
+            last_evicted: [(tx1.compute_txid(), 150)].into(),
+        };
+
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+
+        store.persist_tx_graph(&tx_graph_changeset1).unwrap();
+
+        let mut changeset = tx_graph::ChangeSet::default();
+        store.read_tx_graph(&mut changeset).unwrap();
+        assert_eq!(changeset, tx_graph_changeset1);
+
+        let block_id = block_id!(101, "REDB");
+
+        let conf_anchor: ConfirmationBlockTime = ConfirmationBlockTime {
+            block_id,
+            confirmation_time: 1,
+        };
+
+        let tx_graph_changeset2 = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: [tx2.clone()].into(),
+            txouts: [].into(),
+            anchors: [(conf_anchor, tx2.compute_txid())].into(),
+            last_seen: [(tx2.compute_txid(), 200)].into(),
+            first_seen: [(tx2.compute_txid(), 100)].into(),
+            last_evicted: [(tx2.compute_txid(), 150)].into(),
+        };
+
+        store.persist_tx_graph(&tx_graph_changeset2).unwrap();
+
+        let mut changeset = tx_graph::ChangeSet::default();
+        store.read_tx_graph(&mut changeset).unwrap();
 
-        store.persist_keychains(&desc_changeset).unwrap();
+        tx_graph_changeset1.merge(tx_graph_changeset2);
 
-        let mut desc_changeset: BTreeMap<u64, Descriptor<DescriptorPublicKey>> = BTreeMap::new();
-        store.read_keychains(&mut desc_changeset).unwrap();
+        assert_eq!(tx_graph_changeset1, changeset);
+    }
 
-        assert_eq!(*desc_changeset.get(&0).unwrap(), descriptor);
-        assert_eq!(*desc_changeset.get(&1).unwrap(), change_descriptor);
+    fn parse_descriptor(descriptor: &str) -> Descriptor<DescriptorPublicKey> {
+        let secp = bdk_chain::bitcoin::secp256k1::Secp256k1::signing_only();
+        Descriptor::<DescriptorPublicKey>::parse_descriptor(&secp, descriptor)
+            .unwrap()
+            .0
     }
 
     #[test]
-    fn test_keychains_persistence_second() {
+    fn test_last_revealed_persistence() {
         let tmpfile = NamedTempFile::new().unwrap();
         let db = create_db(tmpfile.path());
         let store = create_test_store(Arc::new(db), "wallet1");
-        store.create_keychains_table().unwrap();
 
-        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[1].parse().unwrap();
-        let change_descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[0].parse().unwrap();
+        let descriptor_ids = utils::DESCRIPTORS.map(|d| parse_descriptor(d).descriptor_id());
 
-        let desc_changeset: BTreeMap<u64, Descriptor<DescriptorPublicKey>> =
-            [(0, descriptor.clone()), (1, change_descriptor.clone())].into();
+        let mut last_revealed: BTreeMap<DescriptorId, u32> =
+            [(descriptor_ids[0], 1), (descriptor_ids[1], 100)].into();
 
-        store.persist_keychains(&desc_changeset).unwrap();
+        let write_tx = store.db.begin_write().unwrap();
+        let _ = write_tx
+            .open_table(store.last_revealed_table_defn())
+            .unwrap();
+        store
+            .persist_last_revealed(&write_tx, &last_revealed)
+            .unwrap();
+        write_tx.commit().unwrap();
 
-        let mut desc_changeset: BTreeMap<u64, Descriptor<DescriptorPublicKey>> = BTreeMap::new();
-        store.read_keychains(&mut desc_changeset).unwrap();
+        let mut last_revealed_read: BTreeMap<DescriptorId, u32> = BTreeMap::new();
+        let read_tx = store.db.begin_read().unwrap();
+        store
+            .read_last_revealed(&read_tx, &mut last_revealed_read)
+            .unwrap();
 
-        assert_eq!(*desc_changeset.get(&0).unwrap(), descriptor);
-        assert_eq!(*desc_changeset.get(&1).unwrap(), change_descriptor);
-    }
+        assert_eq!(last_revealed, last_revealed_read);
 
-    #[test]
-    fn test_single_desc_persistence() {
-        let tmpfile = NamedTempFile::new().unwrap();
-        let db = create_db(tmpfile.path());
-        let store = create_test_store(Arc::new(db), "wallet1");
-        store.create_keychains_table().unwrap();
+        let last_revealed_new: BTreeMap<DescriptorId, u32> = [(descriptor_ids[0], 2)].into();
 
-        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[0].parse().unwrap();
+        let write_tx = store.db.begin_write().unwrap();
+        store
+            .persist_last_revealed(&write_tx, &last_revealed_new)
+            .unwrap();
+        write_tx.commit().unwrap();
 
+        let mut last_revealed_read_new: BTreeMap<DescriptorId, u32> = BTreeMap::new();
+        let read_tx = store.db.begin_read().unwrap();
         store
-            .persist_keychains(&[(0, descriptor.clone())].into())
+            .read_last_revealed(&read_tx, &mut last_revealed_read_new)
             .unwrap();
 
-        let mut desc_changeset: BTreeMap<u64, Descriptor<DescriptorPublicKey>> = BTreeMap::new();
-        store.read_keychains(&mut desc_changeset).unwrap();
+        last_revealed.merge(last_revealed_new);
 
-        assert_eq!(*desc_changeset.get(&0).unwrap(), descriptor);
-        assert_eq!(desc_changeset.get(&1), None);
+        assert_eq!(last_revealed, last_revealed_read_new);
     }
 
     #[test]
-    fn test_descriptor_missing() {
+    fn test_set_last_revealed() {
         let tmpfile = NamedTempFile::new().unwrap();
         let db = create_db(tmpfile.path());
         let store = create_test_store(Arc::new(db), "wallet1");
-        store.create_keychains_table().unwrap();
+        store.create_indexer_tables().unwrap();
 
-        let mut desc_changeset: BTreeMap<u64, Descriptor<DescriptorPublicKey>> = BTreeMap::new();
-        store.read_keychains(&mut desc_changeset).unwrap();
-        assert_eq!(desc_changeset.get(&0), None);
-        assert_eq!(desc_changeset.get(&1), None);
+        let descriptor_ids = utils::DESCRIPTORS.map(|d| parse_descriptor(d).descriptor_id());
+        let read_last_revealed = || {
+            let mut changeset = keychain_txout::ChangeSet::default();
+            store.read_indexer(&mut changeset).unwrap();
+            changeset.last_revealed
+        };
+
+        // raise from missing and from an existing index
+        store.set_last_revealed(descriptor_ids[0], 10).unwrap();
+        store.set_last_revealed(descriptor_ids[1], 3).unwrap();
+        store.set_last_revealed(descriptor_ids[0], 20).unwrap();
+        assert_eq!(
+            read_last_revealed(),
+            [(descriptor_ids[0], 20), (descriptor_ids[1], 3)].into()
+        );
+
+        // equal index is a no-op
+        store.set_last_revealed(descriptor_ids[0], 20).unwrap();
+        assert_eq!(read_last_revealed()[&descriptor_ids[0]], 20);
+
+        // lowering is rejected
+        match store.set_last_revealed(descriptor_ids[0], 5) {
+            Ok(_) => panic!("should give error since the index is lowered"),
+            Err(StoreError::NonMonotonicRevealedIndex {
+                descriptor_id,
+                current,
+                index,
+            }) => {
+                assert_eq!(descriptor_id, descriptor_ids[0]);
+                assert_eq!(current, 20);
+                assert_eq!(index, 5);
+            }
+            Err(_) => panic!("error should only be due to the lowered index"),
+        }
+        assert_eq!(read_last_revealed()[&descriptor_ids[0]], 20);
     }
 
     #[test]
-    fn test_local_chain_persistence() {
+    fn test_persist_spks_skips_unchanged() {
         let tmpfile = NamedTempFile::new().unwrap();
         let db = create_db(tmpfile.path());
         let store = create_test_store(Arc::new(db), "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
 
-        // create a local_chain_changeset, persist that and read it
-        let local_chain_changeset = local_chain::ChangeSet {
-            blocks: [
-                (0, Some(hash!("B"))),
-                (1, Some(hash!("D"))),
-                (2, Some(hash!("K"))),
+        let descriptor_ids = utils::DESCRIPTORS.map(|d| parse_descriptor(d).descriptor_id());
+        let mut changeset = keychain_txout::ChangeSet {
+            spk_cache: [
+                (
+                    descriptor_ids[0],
+                    [
+                        (0u32, ScriptBuf::from_bytes(vec![1, 2, 3])),
+                        (1u32, ScriptBuf::from_bytes(vec![4, 5])),
+                    ]
+                    .into(),
+                ),
+                (
+                    descriptor_ids[1],
+                    [(0u32, ScriptBuf::from_bytes(vec![6]))].into(),
+                ),
             ]
             .into(),
-        };
-        store.create_local_chain_tables().unwrap();
-        store.persist_local_chain(&local_chain_changeset).unwrap();
-
-        let mut changeset = local_chain::ChangeSet::default();
-        store.read_local_chain(&mut changeset).unwrap();
-        assert_eq!(local_chain_changeset, changeset);
-
-        // create another local_chain_changeset, persist that and read it
-        let local_chain_changeset = local_chain::ChangeSet {
-            blocks: [(2, None)].into(),
+            ..Default::default()
         };
 
-        store.persist_local_chain(&local_chain_changeset).unwrap();
+        let summary = store.persist_indexer(&changeset).unwrap();
+        assert_eq!(summary.spks.inserted, 3);
+        assert_eq!(summary.spks.skipped, 0);
 
-        let mut changeset = local_chain::ChangeSet::default();
-        store.read_local_chain(&mut changeset).unwrap();
+        // persisting the same cache again writes nothing
+        let summary = store.persist_indexer(&changeset).unwrap();
+        assert_eq!(summary.spks.inserted, 0);
+        assert_eq!(summary.spks.skipped, 3);
+        assert!(summary.spks.is_empty());
 
-        let local_chain_changeset = local_chain::ChangeSet {
-            blocks: [(0, Some(hash!("B"))), (1, Some(hash!("D")))].into(),
-        };
+        // only the new and changed scripts are written
+        changeset
+            .spk_cache
+            .get_mut(&descriptor_ids[0])
+            .unwrap()
+            .insert(1, ScriptBuf::from_bytes(vec![7]));
+        changeset
+            .spk_cache
+            .get_mut(&descriptor_ids[1])
+            .unwrap()
+            .insert(1, ScriptBuf::from_bytes(vec![8]));
+        let summary = store.persist_indexer(&changeset).unwrap();
+        assert_eq!(summary.spks.inserted, 2);
+        assert_eq!(summary.spks.skipped, 2);
 
-        assert_eq!(local_chain_changeset, changeset);
+        let mut spk_cache_read = BTreeMap::new();
+        let read_tx = store.db.begin_read().unwrap();
+        store.read_spks(&read_tx, &mut spk_cache_read).unwrap();
+        assert_eq!(spk_cache_read, changeset.spk_cache);
     }
 
     #[test]
-    fn test_blocks_persistence() {
+    fn test_spks_persistence() {
         let tmpfile = NamedTempFile::new().unwrap();
         let db = create_db(tmpfile.path());
         let store = create_test_store(Arc::new(db), "wallet1");
-        let blocks: BTreeMap<u32, Option<BlockHash>> = [
-            (0, Some(hash!("B"))),
-            (1, Some(hash!("D"))),
-            (2, Some(hash!("K"))),
+
+        let descriptor_ids = utils::DESCRIPTORS.map(|d| parse_descriptor(d).descriptor_id());
+
+        let spk_cache: BTreeMap<DescriptorId, BTreeMap<u32, ScriptBuf>> = [
+            (
+                descriptor_ids[0],
+                [(0u32, ScriptBuf::from_bytes(vec![1, 2, 3]))].into(),
+            ),
+            (
+                descriptor_ids[1],
+                [
+                    (100u32, ScriptBuf::from_bytes(vec![3])),
+                    (1000u32, ScriptBuf::from_bytes(vec![5, 6, 8])),
+                ]
+                .into(),
+            ),
         ]
         .into();
 
         let write_tx = store.db.begin_write().unwrap();
-        let _ = write_tx.open_table(store.blocks_table_defn()).unwrap();
-        store.persist_blocks(&write_tx, &blocks).unwrap();
+        let _ = write_tx.open_table(store.spk_table_defn()).unwrap();
+        store.persist_spks(&write_tx, &spk_cache).unwrap();
         write_tx.commit().unwrap();
+
+        let mut spk_cache_read: BTreeMap<DescriptorId, BTreeMap<u32, ScriptBuf>> = BTreeMap::new();
         let read_tx = store.db.begin_read().unwrap();
-        let mut blocks_new: BTreeMap<u32, Option<BlockHash>> = BTreeMap::new();
-        store.read_blocks(&read_tx, &mut blocks_new).unwrap();
-        assert_eq!(blocks_new, blocks);
+        store.read_spks(&read_tx, &mut spk_cache_read).unwrap();
 
-        let blocks: BTreeMap<u32, Option<BlockHash>> = [(2, None)].into();
+        assert_eq!(spk_cache, spk_cache_read);
+
+        let spk_cache_new: BTreeMap<DescriptorId, BTreeMap<u32, ScriptBuf>> = [(
+            descriptor_ids[0],
+            [(1u32, ScriptBuf::from_bytes(vec![1, 2, 3, 4]))].into(),
+        )]
+        .into();
 
         let write_tx = store.db.begin_write().unwrap();
-        store.persist_blocks(&write_tx, &blocks).unwrap();
+        let _ = write_tx.open_table(store.spk_table_defn()).unwrap();
+        store.persist_spks(&write_tx, &spk_cache_new).unwrap();
         write_tx.commit().unwrap();
+
+        let mut spk_cache_read_new: BTreeMap<DescriptorId, BTreeMap<u32, ScriptBuf>> =
+            BTreeMap::new();
         let read_tx = store.db.begin_read().unwrap();
-        let mut blocks_new: BTreeMap<u32, Option<BlockHash>> = BTreeMap::new();
-        store.read_blocks(&read_tx, &mut blocks_new).unwrap();
+        store.read_spks(&read_tx, &mut spk_cache_read_new).unwrap();
 
-        let blocks: BTreeMap<u32, Option<BlockHash>> =
-            [(0, Some(hash!("B"))), (1, Some(hash!("D")))].into();
+        let spk_cache: BTreeMap<DescriptorId, BTreeMap<u32, ScriptBuf>> = [
+            (
+                descriptor_ids[0],
+                [
+                    (0u32, ScriptBuf::from_bytes(vec![1, 2, 3])),
+                    (1u32, ScriptBuf::from_bytes(vec![1, 2, 3, 4])),
+                ]
+                .into(),
+            ),
+            (
+                descriptor_ids[1],
+                [
+                    (100u32, ScriptBuf::from_bytes(vec![3])),
+                    (1000u32, ScriptBuf::from_bytes(vec![5, 6, 8])),
+                ]
+                .into(),
+            ),
+        ]
+        .into();
 
-        assert_eq!(blocks, blocks_new);
+        assert_eq!(spk_cache, spk_cache_read_new);
+
+        let spks: Vec<(DescriptorId, u32, ScriptBuf)> = store
+            .iter_all_spks()
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let expected: Vec<(DescriptorId, u32, ScriptBuf)> = spk_cache
+            .into_iter()
+            .flat_map(|(id, spks)| spks.into_iter().map(move |(index, spk)| (id, index, spk)))
+            .collect();
+        assert_eq!(spks, expected);
     }
 
-    fn create_one_inp_one_out_tx(txid: Txid, amount: u64) -> Transaction {
-        Transaction {
-            version: transaction::Version::ONE,
-            lock_time: absolute::LockTime::ZERO,
-            input: vec![TxIn {
-                previous_output: OutPoint::new(txid, 0),
-                ..TxIn::default()
-            }],
-            output: vec![TxOut {
-                value: Amount::from_sat(amount),
-                script_pubkey: ScriptBuf::new(),
-            }],
-        }
+    #[test]
+    fn test_indexer_persistence() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
+
+        let descriptor_ids = utils::DESCRIPTORS.map(|d| parse_descriptor(d).descriptor_id());
+
+        let mut keychain_txout_changeset = keychain_txout::ChangeSet {
+            last_revealed: [(descriptor_ids[0], 1), (descriptor_ids[1], 100)].into(),
+            spk_cache: [
+                (
+                    descriptor_ids[0],
+                    [(0u32, ScriptBuf::from_bytes(vec![1, 2, 3]))].into(),
+                ),
+                (
+                    descriptor_ids[1],
+                    [
+                        (100u32, ScriptBuf::from_bytes(vec![3])),
+                        (1000u32, ScriptBuf::from_bytes(vec![5, 6, 8])),
+                    ]
+                    .into(),
+                ),
+            ]
+            .into(),
+        };
+
+        store.create_indexer_tables().unwrap();
+        store.persist_indexer(&keychain_txout_changeset).unwrap();
+
+        let mut changeset = keychain_txout::ChangeSet::default();
+        store.read_indexer(&mut changeset).unwrap();
+
+        let keychain_txout_changeset_new = keychain_txout::ChangeSet {
+            last_revealed: [(descriptor_ids[0], 2)].into(),
+            spk_cache: [(
+                descriptor_ids[0],
+                [(1u32, ScriptBuf::from_bytes(vec![1, 2, 3]))].into(),
+            )]
+            .into(),
+        };
+
+        store
+            .persist_indexer(&keychain_txout_changeset_new)
+            .unwrap();
+
+        let mut changeset_new = keychain_txout::ChangeSet::default();
+        store.read_indexer(&mut changeset_new).unwrap();
+        keychain_txout_changeset.merge(keychain_txout_changeset_new);
+
+        assert_eq!(changeset_new, keychain_txout_changeset);
     }
 
+    #[cfg(feature = "wallet")]
     #[test]
-    fn test_persist_last_seen() {
+    fn test_persist_wallet() {
         let tmpfile = NamedTempFile::new().unwrap();
-        let db = create_db(tmpfile.path());
-        let store = create_test_store(Arc::new(db), "wallet1");
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db, "wallet1");
+
+        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[0].parse().unwrap();
+        let change_descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[1].parse().unwrap();
+
+        let mut blocks: BTreeMap<u32, Option<BlockHash>> = BTreeMap::new();
+        blocks.insert(0u32, Some(hash!("B")));
+        blocks.insert(1u32, Some(hash!("T")));
+        blocks.insert(2u32, Some(hash!("C")));
+        let local_chain_changeset = local_chain::ChangeSet { blocks };
 
         let tx1 = Arc::new(create_one_inp_one_out_tx(
             Txid::from_byte_array([0; 32]),
             30_000,
         ));
         let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
-        let tx3 = Arc::new(create_one_inp_one_out_tx(tx2.compute_txid(), 19_000));
 
-        // try persisting and reading last_seen
-        let txs: BTreeSet<Arc<Transaction>> = [tx1.clone(), tx2.clone()].into();
-        let mut last_seen: BTreeMap<Txid, u64> =
-            [(tx1.compute_txid(), 100), (tx2.compute_txid(), 120)].into();
+        let block_id = block_id!(1, "BDK");
 
-        let write_tx = store.db.begin_write().unwrap();
-        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
-        let _ = write_tx.open_table(store.last_seen_defn()).unwrap();
-        write_tx.commit().unwrap();
+        let conf_anchor: ConfirmationBlockTime = ConfirmationBlockTime {
+            block_id,
+            confirmation_time: 123,
+        };
 
-        let write_tx = store.db.begin_write().unwrap();
-        store.persist_txs(&write_tx, &txs).unwrap();
-        write_tx.commit().unwrap();
+        let tx_graph_changeset = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: [tx1.clone()].into(),
+            txouts: [].into(),
+            anchors: [(conf_anchor, tx1.compute_txid())].into(),
+            last_seen: [(tx1.compute_txid(), 100)].into(),
+            first_seen: [(tx1.compute_txid(), 80)].into(),
+            last_evicted: [(tx1.compute_txid(), 150)].into(),
+        };
 
-        // to hit the branch for the case when tx is persisted but not in changeset
-        let txs: BTreeSet<Arc<Transaction>> = BTreeSet::new();
+        let keychain_txout_changeset = keychain_txout::ChangeSet {
+            last_revealed: [
+                (descriptor.descriptor_id(), 12),
+                (change_descriptor.descriptor_id(), 10),
+            ]
+            .into(),
+            spk_cache: [
+                (
+                    descriptor.descriptor_id(),
+                    [(0u32, ScriptBuf::from_bytes(vec![245, 123, 112]))].into(),
+                ),
+                (
+                    change_descriptor.descriptor_id(),
+                    [
+                        (100u32, ScriptBuf::from_bytes(vec![145, 234, 98])),
+                        (1000u32, ScriptBuf::from_bytes(vec![5, 6, 8])),
+                    ]
+                    .into(),
+                ),
+            ]
+            .into(),
+        };
 
-        let write_tx = store.db.begin_write().unwrap();
-        let read_tx = store.db.begin_read().unwrap();
-        store
-            .persist_last_seen(&write_tx, &read_tx, &last_seen, &txs)
-            .unwrap();
-        write_tx.commit().unwrap();
+        let mut changeset = ChangeSet {
+            descriptor: Some(descriptor.clone()),
+            change_descriptor: Some(change_descriptor.clone()),
+            network: Some(Network::Bitcoin),
+            local_chain: local_chain_changeset,
+            tx_graph: tx_graph_changeset,
+            indexer: keychain_txout_changeset,
+        };
 
-        let read_tx = store.db.begin_read().unwrap();
-        let mut last_seen_read: BTreeMap<Txid, u64> = BTreeMap::new();
-        store.read_last_seen(&read_tx, &mut last_seen_read).unwrap();
-        assert_eq!(last_seen_read, last_seen);
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
 
-        // persist another last_seen and see if what is read is same as merged one
-        let txs_new: BTreeSet<Arc<Transaction>> = [tx3.clone()].into();
-        let last_seen_new: BTreeMap<Txid, u64> = [(tx3.compute_txid(), 200)].into();
+        store.persist_wallet(&changeset).unwrap();
+        let mut changeset_read = ChangeSet::default();
+        store.read_wallet(&mut changeset_read).unwrap();
 
-        let write_tx = store.db.begin_write().unwrap();
-        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
-        let _ = write_tx.open_table(store.last_seen_defn()).unwrap();
-        write_tx.commit().unwrap();
+        assert_eq!(changeset, changeset_read);
 
-        let write_tx = store.db.begin_write().unwrap();
-        let read_tx = store.db.begin_read().unwrap();
-        store
-            .persist_last_seen(&write_tx, &read_tx, &last_seen_new, &txs_new)
-            .unwrap();
-        write_tx.commit().unwrap();
+        let mut blocks: BTreeMap<u32, Option<BlockHash>> = BTreeMap::new();
+        blocks.insert(4u32, Some(hash!("RE")));
+        blocks.insert(5u32, Some(hash!("DB")));
+        let local_chain_changeset = local_chain::ChangeSet { blocks };
 
-        let read_tx = store.db.begin_read().unwrap();
-        let mut last_seen_read_new: BTreeMap<Txid, u64> = BTreeMap::new();
-        store
-            .read_last_seen(&read_tx, &mut last_seen_read_new)
-            .unwrap();
-        last_seen.merge(last_seen_new);
-        assert_eq!(last_seen_read_new, last_seen);
-    }
+        let block_id = block_id!(2, "Bitcoin");
 
-    #[test]
-    fn test_last_seen_missing_txn() {
-        // to hit the branch for the panic case in persist_last_seen
-        let tmpfile = NamedTempFile::new().unwrap();
-        let db = create_db(tmpfile.path());
-        let store = create_test_store(Arc::new(db), "wallet1");
+        let conf_anchor: ConfirmationBlockTime = ConfirmationBlockTime {
+            block_id,
+            confirmation_time: 214,
+        };
 
-        let tx1 = Arc::new(create_one_inp_one_out_tx(
-            Txid::from_byte_array([0; 32]),
-            30_000,
-        ));
-        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+        let tx_graph_changeset = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: [tx2.clone()].into(),
+            txouts: [].into(),
+            anchors: [(conf_anchor, tx2.compute_txid())].into(),
+            last_seen: [(tx2.compute_txid(), 200)].into(),
+            first_seen: [(tx2.compute_txid(), 160)].into(),
+            last_evicted: [(tx2.compute_txid(), 300)].into(),
+        };
 
-        let last_seen: BTreeMap<Txid, u64> = [
-            (hash!("B"), 100),
-            (tx1.compute_txid(), 120),
-            (tx2.compute_txid(), 121),
-        ]
-        .into();
+        let keychain_txout_changeset = keychain_txout::ChangeSet {
+            last_revealed: [(descriptor.descriptor_id(), 14)].into(),
+            spk_cache: [(
+                change_descriptor.descriptor_id(),
+                [
+                    (102u32, ScriptBuf::from_bytes(vec![8, 45, 78])),
+                    (1001u32, ScriptBuf::from_bytes(vec![29, 56, 47])),
+                ]
+                .into(),
+            )]
+            .into(),
+        };
 
-        let write_tx = store.db.begin_write().unwrap();
-        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
-        let _ = write_tx.open_table(store.last_seen_defn()).unwrap();
-        write_tx.commit().unwrap();
+        let changeset_new = ChangeSet {
+            descriptor: Some(descriptor),
+            change_descriptor: Some(change_descriptor),
+            network: Some(Network::Bitcoin),
+            local_chain: local_chain_changeset,
+            tx_graph: tx_graph_changeset,
+            indexer: keychain_txout_changeset,
+        };
 
-        let txs: BTreeSet<Arc<Transaction>> = [tx1, tx2].into();
+        store.persist_wallet(&changeset_new).unwrap();
+        let mut changeset_read_new = ChangeSet::default();
+        store.read_wallet(&mut changeset_read_new).unwrap();
 
-        let write_tx = store.db.begin_write().unwrap();
-        let read_tx = store.db.begin_read().unwrap();
-        match store.persist_last_seen(&write_tx, &read_tx, &last_seen, &txs) {
-            Ok(_) => panic!("should give error since tx missing"),
-            Err(StoreError::TxMissing(txid)) => assert_eq!(txid, hash!("B")),
-            Err(_) => panic!("error should only be due to missing tx"),
-        }
-        write_tx.commit().unwrap();
+        changeset.merge(changeset_new);
+
+        assert_eq!(changeset, changeset_read_new);
+        assert_eq!(store.snapshot_changeset().unwrap(), changeset);
     }
 
+    #[cfg(feature = "wallet")]
     #[test]
-    fn test_persist_last_evicted() {
+    fn test_persist_wallet_in_tx() {
         let tmpfile = NamedTempFile::new().unwrap();
-        let db = create_db(tmpfile.path());
-        let store = create_test_store(Arc::new(db), "wallet1");
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        let custom_table: TableDefinition<&str, u64> = TableDefinition::new("app_balances");
 
-        let tx1 = Arc::new(create_one_inp_one_out_tx(
+        let tx = Arc::new(create_one_inp_one_out_tx(
             Txid::from_byte_array([0; 32]),
             30_000,
         ));
-        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
-        let tx3 = Arc::new(create_one_inp_one_out_tx(tx2.compute_txid(), 19_000));
-
-        let txs: BTreeSet<Arc<Transaction>> = [tx1.clone(), tx2.clone()].into();
-        let mut last_evicted: BTreeMap<Txid, u64> =
-            [(tx1.compute_txid(), 100), (tx2.compute_txid(), 120)].into();
-
-        let write_tx = store.db.begin_write().unwrap();
-        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
-        let _ = write_tx
-            .open_table(store.last_evicted_table_defn())
-            .unwrap();
-        write_tx.commit().unwrap();
-
-        let write_tx = store.db.begin_write().unwrap();
-        store.persist_txs(&write_tx, &txs).unwrap();
-        write_tx.commit().unwrap();
-
-        // to hit the branch for the case when tx is persisted but not in changeset
-        let txs: BTreeSet<Arc<Transaction>> = BTreeSet::new();
-
-        let write_tx = store.db.begin_write().unwrap();
-        let read_tx = store.db.begin_read().unwrap();
-        store
-            .persist_last_evicted(&write_tx, &read_tx, &last_evicted, &txs)
-            .unwrap();
-        write_tx.commit().unwrap();
+        let changeset = ChangeSet {
+            descriptor: Some(DESCRIPTORS[0].parse().unwrap()),
+            network: Some(Network::Bitcoin),
+            local_chain: local_chain::ChangeSet {
+                blocks: [(0, Some(hash!("B")))].into(),
+            },
+            tx_graph: tx_graph::ChangeSet {
+                txs: [tx.clone()].into(),
+                anchors: [(
+                    ConfirmationBlockTime {
+                        block_id: block_id!(0, "B"),
+                        confirmation_time: 100,
+                    },
+                    tx.compute_txid(),
+                )]
+                .into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
 
-        let read_tx = store.db.begin_read().unwrap();
-        let mut last_evicted_read: BTreeMap<Txid, u64> = BTreeMap::new();
-        store
-            .read_last_evicted(&read_tx, &mut last_evicted_read)
+        // nothing is written when the transaction is aborted
+        let write_tx = db.begin_write().unwrap();
+        write_tx
+            .open_table(custom_table)
+            .unwrap()
+            .insert("wallet1", 30_000)
             .unwrap();
-        assert_eq!(last_evicted_read, last_evicted);
-
-        let txs_new: BTreeSet<Arc<Transaction>> = [tx3.clone()].into();
-        let last_evicted_new: BTreeMap<Txid, u64> = [(tx3.compute_txid(), 300)].into();
-
-        let write_tx = store.db.begin_write().unwrap();
-        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
-        let _ = write_tx
-            .open_table(store.last_evicted_table_defn())
+        let summary = store.persist_wallet_in_tx(&write_tx, &changeset).unwrap();
+        assert_eq!(summary.txs.inserted, 1);
+        write_tx.abort().unwrap();
+        assert_eq!(store.snapshot_changeset().unwrap(), ChangeSet::default());
+        let read_tx = db.begin_read().unwrap();
+        assert!(
+            open_table_if_exists(&read_tx, custom_table)
+                .unwrap()
+                .is_none()
+        );
+        drop(read_tx);
+
+        // both the wallet and the custom table are committed by the caller
+        let write_tx = db.begin_write().unwrap();
+        write_tx
+            .open_table(custom_table)
+            .unwrap()
+            .insert("wallet1", 30_000)
             .unwrap();
+        store.persist_wallet_in_tx(&write_tx, &changeset).unwrap();
         write_tx.commit().unwrap();
+        assert_eq!(store.snapshot_changeset().unwrap(), changeset);
+        let read_tx = db.begin_read().unwrap();
+        let table = read_tx.open_table(custom_table).unwrap();
+        assert_eq!(table.get("wallet1").unwrap().unwrap().value(), 30_000);
+    }
 
-        let write_tx = store.db.begin_write().unwrap();
-        let read_tx = store.db.begin_read().unwrap();
-        store
-            .persist_last_evicted(&write_tx, &read_tx, &last_evicted_new, &txs_new)
-            .unwrap();
-        write_tx.commit().unwrap();
+    #[test]
+    fn test_persist_waits_for_write_transaction() {
+        use std::sync::mpsc;
+        use std::time::Duration;
 
-        let read_tx = store.db.begin_read().unwrap();
-        let mut last_evicted_read_new: BTreeMap<Txid, u64> = BTreeMap::new();
-        store
-            .read_last_evicted(&read_tx, &mut last_evicted_read_new)
-            .unwrap();
-        last_evicted.merge(last_evicted_new);
-        assert_eq!(last_evicted_read_new, last_evicted);
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1");
+        store.create_local_chain_tables().unwrap();
+
+        let write_tx = db.begin_write().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let changeset = local_chain::ChangeSet {
+            blocks: [(0, Some(hash!("B0")))].into(),
+        };
+        let thread_changeset = changeset.clone();
+        let handle = std::thread::spawn(move || {
+            let result = store.persist_local_chain(&thread_changeset);
+            sender.send(()).unwrap();
+            result.map(|_| store)
+        });
+
+        // the persist is blocked, not failed, while the write transaction is open
+        assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+        write_tx.commit().unwrap();
+        receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+        let store = handle.join().unwrap().unwrap();
+
+        let mut read_changeset = local_chain::ChangeSet::default();
+        store.read_local_chain(&mut read_changeset).unwrap();
+        assert_eq!(read_changeset, changeset);
     }
 
     #[test]
-    fn test_last_evicted_missing_txs() {
-        // to hit the branch for the panic case in persist_last_evicted
+    fn test_health_check() {
         let tmpfile = NamedTempFile::new().unwrap();
-        let db = create_db(tmpfile.path());
-        let store = create_test_store(Arc::new(db), "wallet1");
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1");
 
-        let tx1 = Arc::new(create_one_inp_one_out_tx(
-            Txid::from_byte_array([0; 32]),
-            30_000,
+        // nothing is created
+        assert!(matches!(
+            store.health_check(),
+            Err(StoreError::RedbTable(TableError::TableDoesNotExist(_)))
         ));
-        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+        assert_eq!(db.begin_read().unwrap().list_tables().unwrap().count(), 0);
 
-        let last_evicted: BTreeMap<Txid, u64> = [
-            (hash!("B"), 100),
-            (tx1.compute_txid(), 120),
-            (tx2.compute_txid(), 132),
-        ]
-        .into();
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        store.health_check().unwrap();
 
-        let write_tx = store.db.begin_write().unwrap();
-        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
-        let _ = write_tx
-            .open_table(store.last_evicted_table_defn())
+        // a missing table
+        let write_tx = db.begin_write().unwrap();
+        write_tx
+            .delete_table(store.first_seen_table_defn())
             .unwrap();
         write_tx.commit().unwrap();
-
-        let txs: BTreeSet<Arc<Transaction>> = [tx1, tx2].into();
-
-        let write_tx = store.db.begin_write().unwrap();
-        let read_tx = store.db.begin_read().unwrap();
-        match store.persist_last_evicted(&write_tx, &read_tx, &last_evicted, &txs) {
-            Ok(_) => panic!("should give error since tx missing"),
-            Err(StoreError::TxMissing(txid)) => assert_eq!(txid, hash!("B")),
-            Err(_) => panic!("error should only be due to missing tx"),
+        match store.health_check() {
+            Err(StoreError::RedbTable(TableError::TableDoesNotExist(name))) => {
+                assert_eq!(name, "wallet1_first_seen")
+            }
+            _ => panic!("should give error since the first_seen table is missing"),
         }
+
+        // a table of the wrong type
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        store.health_check().unwrap();
+        let write_tx = db.begin_write().unwrap();
+        write_tx.delete_table(store.txs_table_defn()).unwrap();
+        write_tx
+            .open_table(TableDefinition::<u64, u64>::new("wallet1_txs"))
+            .unwrap();
         write_tx.commit().unwrap();
+        assert!(matches!(
+            store.health_check(),
+            Err(StoreError::RedbTable(TableError::TableTypeMismatch { .. }))
+        ));
     }
 
     #[test]
-    fn test_persist_first_seen() {
-        let tmpfile = NamedTempFile::new().unwrap();
-        let db = create_db(tmpfile.path());
-        let store = create_test_store(Arc::new(db), "wallet1");
+    fn test_attach() {
+        use std::sync::mpsc;
+        use std::time::Duration;
 
-        let tx1 = Arc::new(create_one_inp_one_out_tx(
-            Txid::from_byte_array([0; 32]),
-            30_000,
-        ));
-        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
-        let tx3 = Arc::new(create_one_inp_one_out_tx(tx2.compute_txid(), 19_000));
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
 
-        let txs: BTreeSet<Arc<Transaction>> = [tx1.clone(), tx2.clone()].into();
-        let mut first_seen: BTreeMap<Txid, u64> =
-            [(tx1.compute_txid(), 100), (tx2.compute_txid(), 120)].into();
+        match Store::attach(db.clone(), "wallet1".to_string()) {
+            Ok(_) => panic!("should give error since tables were not created"),
+            Err(StoreError::WalletNotInitialized(wallet_name)) => {
+                assert_eq!(wallet_name, "wallet1")
+            }
+            Err(_) => panic!("error should only be due to wallet not being initialized"),
+        }
 
-        let write_tx = store.db.begin_write().unwrap();
-        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
-        let _ = write_tx.open_table(store.first_seen_table_defn()).unwrap();
-        write_tx.commit().unwrap();
+        let store = create_test_store(db.clone(), "wallet1");
+        store.create_local_chain_tables().unwrap();
+        match Store::attach(db.clone(), "wallet1".to_string()) {
+            Ok(_) => panic!("should give error since only some tables were created"),
+            Err(StoreError::WalletNotInitialized(_)) => {}
+            Err(_) => panic!("error should only be due to wallet not being initialized"),
+        }
 
-        let write_tx = store.db.begin_write().unwrap();
-        store.persist_txs(&write_tx, &txs).unwrap();
-        write_tx.commit().unwrap();
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        let changeset = local_chain::ChangeSet {
+            blocks: [(0, Some(hash!("B0")))].into(),
+        };
+        store.persist_local_chain(&changeset).unwrap();
+
+        // while a write transaction is open, opening another one blocks, so attaching (and
+        // creating the already existing tables) must only read
+        let write_tx = db.begin_write().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let thread_db = db.clone();
+        std::thread::spawn(move || {
+            let store = Store::attach(thread_db, "wallet1".to_string()).unwrap();
+            store.create_tables::<ConfirmationBlockTime>().unwrap();
+            let mut read_changeset = local_chain::ChangeSet::default();
+            store.read_local_chain(&mut read_changeset).unwrap();
+            sender.send(read_changeset).unwrap();
+        });
+        let read_changeset = receiver
+            .recv_timeout(Duration::from_secs(10))
+            .expect("attach should not open a write transaction");
+        assert_eq!(read_changeset, changeset);
+        write_tx.abort().unwrap();
+
+        // a store attached to another wallet of the file is independent
+        match Store::attach(db, "wallet2".to_string()) {
+            Ok(_) => panic!("should give error since tables of wallet2 were not created"),
+            Err(StoreError::WalletNotInitialized(wallet_name)) => {
+                assert_eq!(wallet_name, "wallet2")
+            }
+            Err(_) => panic!("error should only be due to wallet not being initialized"),
+        }
+    }
 
-        // to hit the branch for the case when tx is persisted but not in changeset
-        let txs: BTreeSet<Arc<Transaction>> = BTreeSet::new();
+    #[cfg(feature = "wallet")]
+    #[test]
+    fn test_merge_from() {
+        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[0].parse().unwrap();
+        let change_descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[1].parse().unwrap();
+        let descriptor_id = descriptor.descriptor_id();
+        let changeset =
+            |network, txs: &[Arc<Transaction>], blocks: &[(u32, BlockHash)], index| ChangeSet {
+                descriptor: Some(descriptor.clone()),
+                change_descriptor: Some(change_descriptor.clone()),
+                network: Some(network),
+                local_chain: local_chain::ChangeSet {
+                    blocks: blocks.iter().map(|&(ht, hash)| (ht, Some(hash))).collect(),
+                },
+                tx_graph: tx_graph::ChangeSet {
+                    txs: txs.iter().cloned().collect(),
+                    ..Default::default()
+                },
+                indexer: keychain_txout::ChangeSet {
+                    last_revealed: [(descriptor_id, index)].into(),
+                    ..Default::default()
+                },
+            };
+        let new_store = |tmpfile: &NamedTempFile, changeset: &ChangeSet| {
+            let store = create_test_store(Arc::new(create_db(tmpfile.path())), "wallet1");
+            store.create_tables::<ConfirmationBlockTime>().unwrap();
+            store.persist_wallet(changeset).unwrap();
+            store
+        };
 
-        let write_tx = store.db.begin_write().unwrap();
-        let read_tx = store.db.begin_read().unwrap();
-        store
-            .persist_first_seen(&write_tx, &read_tx, &first_seen, &txs)
-            .unwrap();
-        write_tx.commit().unwrap();
+        let [tx_a, tx_b, tx_c] = [10_000, 20_000, 30_000]
+            .map(|amount| Arc::new(create_one_inp_one_out_tx(hash!("T"), amount)));
+        let tmpfile1 = NamedTempFile::new().unwrap();
+        let store1 = new_store(
+            &tmpfile1,
+            &changeset(
+                Network::Bitcoin,
+                &[tx_a.clone(), tx_b.clone()],
+                &[(0, hash!("B0")), (1, hash!("B1"))],
+                5,
+            ),
+        );
+        let tmpfile2 = NamedTempFile::new().unwrap();
+        let store2 = new_store(
+            &tmpfile2,
+            &changeset(
+                Network::Bitcoin,
+                &[tx_b.clone(), tx_c.clone()],
+                &[(0, hash!("B0")), (2, hash!("B2"))],
+                3,
+            ),
+        );
 
-        let read_tx = store.db.begin_read().unwrap();
-        let mut first_seen_read: BTreeMap<Txid, u64> = BTreeMap::new();
-        store
-            .read_first_seen(&read_tx, &mut first_seen_read)
-            .unwrap();
-        assert_eq!(first_seen_read, first_seen);
+        store1.merge_from(&store2).unwrap();
+        let mut changeset_read = ChangeSet::default();
+        store1.read_wallet(&mut changeset_read).unwrap();
+        assert_eq!(
+            changeset_read,
+            changeset(
+                Network::Bitcoin,
+                &[tx_a, tx_b, tx_c],
+                &[(0, hash!("B0")), (1, hash!("B1")), (2, hash!("B2"))],
+                5,
+            )
+        );
+
+        let tmpfile3 = NamedTempFile::new().unwrap();
+        let store3 = new_store(&tmpfile3, &changeset(Network::Testnet, &[], &[], 0));
+        match store1.merge_from(&store3) {
+            Ok(_) => panic!("should give error since networks differ"),
+            Err(StoreError::NetworkMismatch { ours, theirs }) => {
+                assert_eq!(ours, Network::Bitcoin);
+                assert_eq!(theirs, Network::Testnet);
+            }
+            Err(_) => panic!("error should only be due to the network mismatch"),
+        }
 
-        let txs_new: BTreeSet<Arc<Transaction>> = [tx3.clone()].into();
-        let first_seen_new: BTreeMap<Txid, u64> = [(tx3.compute_txid(), 200)].into();
+        // A store of another wallet, on the same network, is rejected without writing anything.
+        let tmpfile4 = NamedTempFile::new().unwrap();
+        let store4 = new_store(
+            &tmpfile4,
+            &ChangeSet {
+                descriptor: Some(descriptor.clone()),
+                change_descriptor: Some(DESCRIPTORS[3].parse().unwrap()),
+                network: Some(Network::Bitcoin),
+                local_chain: local_chain::ChangeSet {
+                    blocks: [(3, Some(hash!("B3")))].into(),
+                },
+                ..Default::default()
+            },
+        );
+        assert!(matches!(
+            store1.merge_from(&store4),
+            Err(StoreError::DescriptorMismatch { label: 1 })
+        ));
+        let mut changeset_after = ChangeSet::default();
+        store1.read_wallet(&mut changeset_after).unwrap();
+        assert_eq!(changeset_after, changeset_read);
+
+        // A store without descriptors can be merged, this store keeps its own ones.
+        let tmpfile5 = NamedTempFile::new().unwrap();
+        let store5 = new_store(
+            &tmpfile5,
+            &ChangeSet {
+                network: Some(Network::Bitcoin),
+                ..Default::default()
+            },
+        );
+        store1.merge_from(&store5).unwrap();
+        let mut changeset_after = ChangeSet::default();
+        store1.read_wallet(&mut changeset_after).unwrap();
+        assert_eq!(changeset_after, changeset_read);
+    }
 
-        let write_tx = store.db.begin_write().unwrap();
-        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
-        let _ = write_tx.open_table(store.first_seen_table_defn()).unwrap();
-        write_tx.commit().unwrap();
+    #[cfg(feature = "wallet")]
+    #[test]
+    fn test_spk_cache_loading() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
 
-        let write_tx = store.db.begin_write().unwrap();
-        let read_tx = store.db.begin_read().unwrap();
-        store
-            .persist_first_seen(&write_tx, &read_tx, &first_seen_new, &txs_new)
-            .unwrap();
-        write_tx.commit().unwrap();
+        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[0].parse().unwrap();
+        let descriptor_id = descriptor.descriptor_id();
+        let changeset = ChangeSet {
+            descriptor: Some(descriptor),
+            network: Some(Network::Bitcoin),
+            indexer: keychain_txout::ChangeSet {
+                last_revealed: [(descriptor_id, 2)].into(),
+                spk_cache: [(
+                    descriptor_id,
+                    (0..3)
+                        .map(|index| (index, ScriptBuf::from_bytes(vec![index as u8])))
+                        .collect(),
+                )]
+                .into(),
+            },
+            ..Default::default()
+        };
+        store.persist_wallet(&changeset).unwrap();
 
-        let read_tx = store.db.begin_read().unwrap();
-        let mut first_seen_read_new: BTreeMap<Txid, u64> = BTreeMap::new();
-        store
-            .read_first_seen(&read_tx, &mut first_seen_read_new)
-            .unwrap();
-        first_seen.merge(first_seen_new);
-        assert_eq!(first_seen_read_new, first_seen);
+        let mut store = create_test_store(db.clone(), "wallet1").with_spk_cache_loading(false);
+        let changeset_read = WalletPersister::initialize(&mut store).unwrap();
+        assert_eq!(
+            changeset_read.indexer.last_revealed,
+            changeset.indexer.last_revealed
+        );
+        assert!(changeset_read.indexer.spk_cache.is_empty());
+        assert!(store.read_combined().unwrap().indexer.spk_cache.is_empty());
+
+        // the persisted cache is untouched
+        let store = create_test_store(db, "wallet1").with_spk_cache_loading(true);
+        let mut changeset_read = ChangeSet::default();
+        store.read_wallet(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read, changeset);
     }
 
+    #[cfg(feature = "wallet")]
     #[test]
-    fn test_first_seen_missing_tx() {
-        // to hit the branch for the panic case persist_first_seen
+    fn test_would_change() {
         let tmpfile = NamedTempFile::new().unwrap();
         let db = create_db(tmpfile.path());
         let store = create_test_store(Arc::new(db), "wallet1");
 
-        let tx1 = Arc::new(create_one_inp_one_out_tx(
-            Txid::from_byte_array([0; 32]),
-            30_000,
-        ));
-        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+        let descriptor = parse_descriptor(DESCRIPTORS[0]);
+        let descriptor_id = descriptor.descriptor_id();
+        let tx = Arc::new(create_one_inp_one_out_tx(hash!("T"), 30_000));
+        let txid = tx.compute_txid();
+        let anchor = ConfirmationBlockTime {
+            block_id: block_id!(2, "B"),
+            confirmation_time: 100,
+        };
+        let changeset = ChangeSet {
+            descriptor: Some(descriptor.clone()),
+            change_descriptor: Some(parse_descriptor(DESCRIPTORS[1])),
+            network: Some(Network::Signet),
+            local_chain: local_chain::ChangeSet {
+                blocks: [(0, Some(hash!("A"))), (2, Some(hash!("B")))].into(),
+            },
+            tx_graph: tx_graph::ChangeSet {
+                txs: [tx.clone()].into(),
+                txouts: [(
+                    OutPoint::new(hash!("F"), 0),
+                    TxOut {
+                        value: Amount::from_sat(1_000),
+                        script_pubkey: ScriptBuf::from_bytes(vec![1, 2]),
+                    },
+                )]
+                .into(),
+                anchors: [(anchor, txid)].into(),
+                last_seen: [(txid, 200)].into(),
+                first_seen: [(txid, 150)].into(),
+                ..Default::default()
+            },
+            indexer: keychain_txout::ChangeSet {
+                last_revealed: [(descriptor_id, 1)].into(),
+                spk_cache: [(descriptor_id, [(0, ScriptBuf::from_bytes(vec![3]))].into())].into(),
+            },
+        };
+        assert!(!store.would_change(&ChangeSet::default()).unwrap());
+        assert!(store.would_change(&changeset).unwrap());
 
-        let first_seen: BTreeMap<Txid, u64> = [
-            (hash!("B"), 100),
-            (tx1.compute_txid(), 120),
-            (tx2.compute_txid(), 121),
-        ]
-        .into();
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        store.persist_wallet(&changeset).unwrap();
+        assert!(!store.would_change(&changeset).unwrap());
+        assert!(!store.would_change(&ChangeSet::default()).unwrap());
+        // removing a block which is not persisted is a no-op
+        assert!(
+            !store
+                .would_change(&ChangeSet {
+                    local_chain: local_chain::ChangeSet {
+                        blocks: [(1, None)].into(),
+                    },
+                    ..Default::default()
+                })
+                .unwrap()
+        );
+
+        let modifications: [fn(&mut ChangeSet); 8] = [
+            |changeset| changeset.network = Some(Network::Regtest),
+            |changeset| changeset.descriptor = Some(parse_descriptor(DESCRIPTORS[2])),
+            |changeset| {
+                changeset.local_chain.blocks.insert(2, None);
+            },
+            |changeset| {
+                changeset
+                    .tx_graph
+                    .txs
+                    .insert(Arc::new(create_one_inp_one_out_tx(hash!("U"), 10_000)));
+            },
+            |changeset| {
+                let (mut anchor, txid) = changeset.tx_graph.anchors.pop_first().unwrap();
+                anchor.confirmation_time += 1;
+                changeset.tx_graph.anchors.insert((anchor, txid));
+            },
+            |changeset| {
+                for time in changeset.tx_graph.last_seen.values_mut() {
+                    *time += 1;
+                }
+            },
+            |changeset| {
+                for index in changeset.indexer.last_revealed.values_mut() {
+                    *index += 1;
+                }
+            },
+            |changeset| {
+                for spks in changeset.indexer.spk_cache.values_mut() {
+                    spks.insert(0, ScriptBuf::from_bytes(vec![4]));
+                }
+            },
+        ];
+        for modify in modifications {
+            let mut modified = changeset.clone();
+            modify(&mut modified);
+            assert!(store.would_change(&modified).unwrap());
+        }
+    }
 
-        let write_tx = store.db.begin_write().unwrap();
-        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
-        let _ = write_tx.open_table(store.first_seen_table_defn()).unwrap();
-        write_tx.commit().unwrap();
+    #[cfg(feature = "wallet")]
+    #[test]
+    fn test_shared_store_persister() {
+        use crate::shared::SharedStore;
+        use bdk_wallet::{KeychainKind, Wallet};
 
-        let txs: BTreeSet<Arc<Transaction>> = [tx1, tx2].into();
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
 
-        let write_tx = store.db.begin_write().unwrap();
-        let read_tx = store.db.begin_read().unwrap();
-        match store.persist_first_seen(&write_tx, &read_tx, &first_seen, &txs) {
-            Ok(_) => panic!("should give error since tx missing"),
-            Err(StoreError::TxMissing(txid)) => assert_eq!(txid, hash!("B")),
-            Err(_) => panic!("error should only be due to missing tx"),
+        // two wallets of the same file persisted from two threads
+        let handles: Vec<_> = [("wallet1", 0), ("wallet2", 2)]
+            .map(|(wallet_name, first)| {
+                let mut store = SharedStore::from(create_test_store(db.clone(), wallet_name));
+                std::thread::spawn(move || {
+                    let mut wallet = Wallet::create(DESCRIPTORS[first], DESCRIPTORS[first + 1])
+                        .network(Network::Regtest)
+                        .create_wallet(&mut store)
+                        .unwrap();
+                    for _ in 0..5 {
+                        wallet.reveal_next_address(KeychainKind::External);
+                        assert!(wallet.persist(&mut store).unwrap());
+                    }
+                    wallet.derivation_index(KeychainKind::External)
+                })
+            })
+            .into();
+        for (handle, wallet_name) in handles.into_iter().zip(["wallet1", "wallet2"]) {
+            let index = handle.join().unwrap();
+            assert_eq!(index, Some(4));
+            let mut store = SharedStore::from(create_test_store(db.clone(), wallet_name));
+            let wallet = Wallet::load().load_wallet(&mut store).unwrap().unwrap();
+            assert_eq!(wallet.derivation_index(KeychainKind::External), index);
         }
-        write_tx.commit().unwrap();
+
+        // persisting through a mutable reference
+        let mut store = create_test_store(db.clone(), "wallet3");
+        let mut persister = &mut store;
+        let mut wallet = Wallet::create(DESCRIPTORS[0], DESCRIPTORS[1])
+            .network(Network::Regtest)
+            .create_wallet(&mut persister)
+            .unwrap();
+        wallet.reveal_next_address(KeychainKind::Internal);
+        assert!(wallet.persist(&mut persister).unwrap());
+        let wallet = Wallet::load().load_wallet(&mut store).unwrap().unwrap();
+        assert_eq!(wallet.derivation_index(KeychainKind::Internal), Some(0));
     }
 
     #[test]
-    fn test_persist_txouts() {
+    fn test_with_wallet_name() {
         let tmpfile = NamedTempFile::new().unwrap();
-        let db = create_db(tmpfile.path());
-        let store = create_test_store(Arc::new(db), "wallet1");
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store1 = create_test_store(db.clone(), "wallet1");
+        let store2 = store1.with_wallet_name("wallet2".to_string()).unwrap();
+        assert!(Arc::ptr_eq(&store2.database(), &db));
 
-        let mut txouts: BTreeMap<OutPoint, TxOut> = [
-            (
-                OutPoint::new(Txid::from_byte_array([0; 32]), 0),
-                TxOut {
-                    value: Amount::from_sat(1300),
-                    script_pubkey: ScriptBuf::from_bytes(vec![0]),
-                },
-            ),
-            (
-                OutPoint::new(Txid::from_byte_array([1; 32]), 0),
-                TxOut {
-                    value: Amount::from_sat(1400),
-                    script_pubkey: ScriptBuf::from_bytes(vec![2]),
-                },
-            ),
-        ]
-        .into();
+        let changeset1 = local_chain::ChangeSet {
+            blocks: [(0, Some(hash!("A0"))), (1, Some(hash!("A1")))].into(),
+        };
+        let changeset2 = local_chain::ChangeSet {
+            blocks: [(0, Some(hash!("B0")))].into(),
+        };
+        for (store, changeset) in [(&store1, &changeset1), (&store2, &changeset2)] {
+            store.create_local_chain_tables().unwrap();
+            store.persist_local_chain(changeset).unwrap();
+        }
 
-        let write_tx = store.db.begin_write().unwrap();
-        let _ = write_tx.open_table(store.txouts_table_defn()).unwrap();
-        store.persist_txouts(&write_tx, &txouts).unwrap();
-        write_tx.commit().unwrap();
+        for (store, changeset) in [(&store1, &changeset1), (&store2, &changeset2)] {
+            let mut changeset_read = local_chain::ChangeSet::default();
+            store.read_local_chain(&mut changeset_read).unwrap();
+            assert_eq!(&changeset_read, changeset);
+        }
+    }
 
-        let read_tx = store.db.begin_read().unwrap();
-        let mut txouts_read: BTreeMap<OutPoint, TxOut> = BTreeMap::new();
-        store.read_txouts(&read_tx, &mut txouts_read).unwrap();
-        assert_eq!(txouts, txouts_read);
+    #[test]
+    fn test_new_with_prefix() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store1 =
+            Store::new_with_prefix(db.clone(), "app1".to_string(), "default".to_string()).unwrap();
+        let store2 =
+            Store::new_with_prefix(db.clone(), "app2".to_string(), "default".to_string()).unwrap();
+        let unprefixed_store = create_test_store(db.clone(), "default");
+
+        let tx1 = Arc::new(create_one_inp_one_out_tx(hash!("T1"), 10_000));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(hash!("T2"), 20_000));
+        for (store, network, tx) in [
+            (&store1, Network::Signet, &tx1),
+            (&store2, Network::Regtest, &tx2),
+        ] {
+            store.create_tables::<ConfirmationBlockTime>().unwrap();
+            store.persist_network(&Some(network)).unwrap();
+            store
+                .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                    txs: [tx.clone()].into(),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
 
-        let txouts_new: BTreeMap<OutPoint, TxOut> = [(
-            OutPoint::new(Txid::from_byte_array([2; 32]), 0),
-            TxOut {
-                value: Amount::from_sat(10000),
-                script_pubkey: ScriptBuf::from_bytes(vec![1]),
-            },
-        )]
-        .into();
-        let write_tx = store.db.begin_write().unwrap();
-        let _ = write_tx.open_table(store.txouts_table_defn()).unwrap();
-        store.persist_txouts(&write_tx, &txouts_new).unwrap();
-        write_tx.commit().unwrap();
+        for (store, network, tx) in [
+            (&store1, Network::Signet, &tx1),
+            (&store2, Network::Regtest, &tx2),
+        ] {
+            let mut network_read = None;
+            store.read_network(&mut network_read).unwrap();
+            assert_eq!(network_read, Some(network));
+            assert_eq!(store.txids().unwrap(), [tx.compute_txid()].into());
+        }
+
+        // the wallet named "default" without a prefix is distinct from both
+        unprefixed_store.create_network_table().unwrap();
+        let mut network_read = None;
+        unprefixed_store.read_network(&mut network_read).unwrap();
+        assert_eq!(network_read, None);
+        assert_eq!(unprefixed_store.transaction_count().unwrap(), 0);
+
+        // stores created from a prefixed store share its prefix
+        let other_store = store1.with_wallet_name("default".to_string()).unwrap();
+        assert_eq!(other_store.txids().unwrap(), [tx1.compute_txid()].into());
+
+        // the wallet of "app1" named "default" cannot be reached under another prefix or without
+        // one, as names containing ':' are rejected
+        for result in [
+            Store::new(db.clone(), "app1:default".to_string()),
+            Store::new_with_prefix(db.clone(), "app1:".to_string(), "default".to_string()),
+            Store::new_with_prefix(db.clone(), "app".to_string(), "1:default".to_string()),
+        ] {
+            assert!(matches!(result, Err(StoreError::InvalidWalletName(_))));
+        }
+        assert!(matches!(
+            store1.with_wallet_name("a:b".to_string()),
+            Err(StoreError::InvalidWalletName(name)) if name == "a:b"
+        ));
+    }
+
+    #[cfg(feature = "wallet")]
+    #[test]
+    fn test_rollback_to_height() {
+        use bdk_wallet::Wallet;
+        use bdk_wallet::chain::ChainPosition;
+
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let mut store = create_test_store(Arc::new(db), "wallet1");
+        let network = Network::Regtest;
+        let genesis_hash = bitcoin::constants::genesis_block(network).block_hash();
+        Wallet::create(DESCRIPTORS[0], DESCRIPTORS[1])
+            .network(network)
+            .create_wallet(&mut store)
+            .unwrap();
+
+        let tx1 = Arc::new(create_one_inp_one_out_tx(hash!("T1"), 10_000));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(hash!("T2"), 20_000));
+        let anchor = |height, hash| ConfirmationBlockTime {
+            block_id: BlockId { height, hash },
+            confirmation_time: 100,
+        };
+        store
+            .persist_local_chain(&local_chain::ChangeSet {
+                blocks: (1..=5).map(|ht| (ht, Some(hash!("B")))).collect(),
+            })
+            .unwrap();
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [tx1.clone(), tx2.clone()].into(),
+                anchors: [
+                    (anchor(2, hash!("B")), tx1.compute_txid()),
+                    (anchor(4, hash!("B")), tx2.compute_txid()),
+                ]
+                .into(),
+                last_seen: [(tx2.compute_txid(), 200)].into(),
+                ..Default::default()
+            })
+            .unwrap();
+        // anchors of another type are kept
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<BlockId> {
+                anchors: [(block_id!(4, "B"), tx2.compute_txid())].into(),
+                ..Default::default()
+            })
+            .unwrap();
 
-        let read_tx = store.db.begin_read().unwrap();
-        let mut txouts_read_new: BTreeMap<OutPoint, TxOut> = BTreeMap::new();
-        store.read_txouts(&read_tx, &mut txouts_read_new).unwrap();
+        assert_eq!(
+            store
+                .rollback_to_height::<ConfirmationBlockTime>(5)
+                .unwrap(),
+            rollback::RollbackStats::default()
+        );
+        assert_eq!(
+            store
+                .rollback_to_height::<ConfirmationBlockTime>(3)
+                .unwrap(),
+            rollback::RollbackStats {
+                blocks: 2,
+                anchors: 1
+            }
+        );
 
-        txouts.merge(txouts_new);
-        assert_eq!(txouts, txouts_read_new);
+        let wallet = Wallet::load().load_wallet(&mut store).unwrap().unwrap();
+        assert_eq!(wallet.latest_checkpoint().height(), 3);
+        assert!(matches!(
+            wallet.get_tx(tx1.compute_txid()).unwrap().chain_position,
+            ChainPosition::Confirmed { .. }
+        ));
+        assert!(matches!(
+            wallet.get_tx(tx2.compute_txid()).unwrap().chain_position,
+            ChainPosition::Unconfirmed { .. }
+        ));
+        assert_eq!(
+            store.anchors_for_tx::<BlockId>(tx2.compute_txid()).unwrap(),
+            [block_id!(4, "B")]
+        );
+
+        assert_eq!(
+            store
+                .rollback_to_height::<ConfirmationBlockTime>(0)
+                .unwrap(),
+            rollback::RollbackStats {
+                blocks: 3,
+                anchors: 1
+            }
+        );
+        let mut changeset = ChangeSet::default();
+        store.read_wallet(&mut changeset).unwrap();
+        assert_eq!(
+            changeset.local_chain.blocks,
+            [(0, Some(genesis_hash))].into()
+        );
+        assert!(changeset.tx_graph.anchors.is_empty());
+        assert_eq!(changeset.tx_graph.txs.len(), 2);
+        let wallet = Wallet::load().load_wallet(&mut store).unwrap().unwrap();
+        assert_eq!(
+            wallet.latest_checkpoint().block_id(),
+            BlockId {
+                height: 0,
+                hash: genesis_hash
+            }
+        );
     }
 
+    #[cfg(feature = "wallet")]
     #[test]
-    fn test_persist_txs() {
+    fn test_prune_blocks_below() {
+        use bdk_wallet::Wallet;
+        use bdk_wallet::chain::ChainPosition;
+
         let tmpfile = NamedTempFile::new().unwrap();
         let db = create_db(tmpfile.path());
-        let store = create_test_store(Arc::new(db), "wallet1");
-
-        let tx1 = Arc::new(create_one_inp_one_out_tx(
-            Txid::from_byte_array([0; 32]),
-            30_000,
-        ));
-        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
-        let tx3 = Arc::new(create_one_inp_one_out_tx(tx2.compute_txid(), 19_000));
-
-        let mut txs: BTreeSet<Arc<Transaction>> = [tx1, tx2.clone()].into();
+        let mut store = create_test_store(Arc::new(db), "wallet1");
+        Wallet::create(DESCRIPTORS[0], DESCRIPTORS[1])
+            .network(Network::Regtest)
+            .create_wallet(&mut store)
+            .unwrap();
 
-        let write_tx = store.db.begin_write().unwrap();
-        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
-        store.persist_txs(&write_tx, &txs).unwrap();
-        write_tx.commit().unwrap();
+        store
+            .persist_local_chain(&local_chain::ChangeSet {
+                blocks: (1..=30).map(|ht| (ht, Some(hash!("B")))).collect(),
+            })
+            .unwrap();
+        let tx = Arc::new(create_one_inp_one_out_tx(hash!("T"), 10_000));
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [tx.clone()].into(),
+                anchors: [(
+                    ConfirmationBlockTime {
+                        block_id: block_id!(7, "B"),
+                        confirmation_time: 100,
+                    },
+                    tx.compute_txid(),
+                )]
+                .into(),
+                ..Default::default()
+            })
+            .unwrap();
+        let heights = |store: &Store| {
+            let mut changeset = local_chain::ChangeSet::default();
+            store.read_local_chain(&mut changeset).unwrap();
+            changeset.blocks.into_keys().collect::<Vec<u32>>()
+        };
 
-        let read_tx = store.db.begin_read().unwrap();
-        let mut txs_read: BTreeSet<Arc<Transaction>> = BTreeSet::new();
-        store.read_txs(&read_tx, &mut txs_read).unwrap();
-        assert_eq!(txs_read, txs);
+        // 5, 10 and 15 are checkpoints and 7 anchors the tx
+        assert_eq!(store.prune_blocks_below(20, Some(5)).unwrap(), 15);
+        assert_eq!(
+            heights(&store),
+            [0, 5, 7, 10, 15]
+                .into_iter()
+                .chain(20..=30)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(store.prune_blocks_below(20, Some(5)).unwrap(), 0);
+        assert_eq!(store.prune_blocks_below(25, Some(0)).unwrap(), 8);
+        assert_eq!(
+            heights(&store),
+            [0, 7].into_iter().chain(25..=30).collect::<Vec<_>>()
+        );
+
+        let wallet = Wallet::load().load_wallet(&mut store).unwrap().unwrap();
+        assert_eq!(wallet.latest_checkpoint().height(), 30);
+        assert!(matches!(
+            wallet.get_tx(tx.compute_txid()).unwrap().chain_position,
+            ChainPosition::Confirmed { .. }
+        ));
+    }
 
-        let txs_new: BTreeSet<Arc<Transaction>> = [tx3].into();
-        let write_tx = store.db.begin_write().unwrap();
-        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
-        store.persist_txs(&write_tx, &txs_new).unwrap();
-        write_tx.commit().unwrap();
+    #[cfg(feature = "wallet")]
+    #[test]
+    fn test_changelog() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let mut store = create_test_store(Arc::new(db), "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
 
-        let read_tx = store.db.begin_read().unwrap();
-        let mut txs_read_new: BTreeSet<Arc<Transaction>> = BTreeSet::new();
-        store.read_txs(&read_tx, &mut txs_read_new).unwrap();
+        // nothing is logged while the changelog is disabled
+        store
+            .persist_local_chain(&local_chain::ChangeSet {
+                blocks: [(0, Some(hash!("B0")))].into(),
+            })
+            .unwrap();
+        store.enable_changelog(true);
 
-        txs.merge(txs_new);
-        assert_eq!(txs_read_new, txs);
+        let changeset1 = ChangeSet {
+            descriptor: Some(parse_descriptor(DESCRIPTORS[0])),
+            change_descriptor: Some(parse_descriptor(DESCRIPTORS[1])),
+            network: Some(Network::Regtest),
+            local_chain: local_chain::ChangeSet {
+                blocks: [(1, Some(hash!("B1")))].into(),
+            },
+            ..Default::default()
+        };
+        let tx = Arc::new(create_one_inp_one_out_tx(hash!("T"), 10_000));
+        let tx_graph_changeset = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: [tx.clone()].into(),
+            anchors: [(
+                ConfirmationBlockTime {
+                    block_id: block_id!(1, "B1"),
+                    confirmation_time: 100,
+                },
+                tx.compute_txid(),
+            )]
+            .into(),
+            last_seen: [(tx.compute_txid(), 50)].into(),
+            ..Default::default()
+        };
+        let changeset3 = ChangeSet {
+            local_chain: local_chain::ChangeSet {
+                blocks: [(2, Some(hash!("B2")))].into(),
+            },
+            indexer: keychain_txout::ChangeSet {
+                last_revealed: [(parse_descriptor(DESCRIPTORS[0]).descriptor_id(), 3)].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        store.persist_wallet(&changeset1).unwrap();
+        store.persist_tx_graph(&tx_graph_changeset).unwrap();
+        // empty changesets and anchors of other types are not logged
+        store.persist_wallet(&ChangeSet::default()).unwrap();
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<BlockId> {
+                anchors: [(block_id!(2, "B2"), tx.compute_txid())].into(),
+                ..Default::default()
+            })
+            .unwrap();
+        store.persist_wallet(&changeset3).unwrap();
+
+        let log = store.read_changelog(0).unwrap();
+        assert_eq!(
+            log.iter().map(|(seq, _, _)| *seq).collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+        assert!(log.iter().all(|(_, timestamp, _)| *timestamp > 0));
+        assert_eq!(log[1].2.tx_graph, tx_graph_changeset);
+
+        let mut replayed = ChangeSet {
+            local_chain: local_chain::ChangeSet {
+                blocks: [(0, Some(hash!("B0")))].into(),
+            },
+            ..Default::default()
+        };
+        for (_, _, changeset) in log {
+            replayed.merge(changeset);
+        }
+        let mut aggregate = ChangeSet::default();
+        store.read_wallet(&mut aggregate).unwrap();
+        assert_eq!(replayed, aggregate);
+
+        assert_eq!(store.read_changelog(2).unwrap().len(), 1);
+        assert_eq!(store.truncate_changelog(2).unwrap(), 2);
+        assert_eq!(store.truncate_changelog(3).unwrap(), 1);
+        assert!(store.read_changelog(0).unwrap().is_empty());
+
+        // sequence numbers are not reused after truncation
+        store.persist_wallet(&changeset3).unwrap();
+        let log = store.read_changelog(0).unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].0, 3);
     }
 
+    #[cfg(feature = "wallet")]
     #[test]
-    fn test_persist_anchors() {
+    fn test_persist_multi_wallet() {
         let tmpfile = NamedTempFile::new().unwrap();
-        let db = create_db(tmpfile.path());
-        let store = create_test_store(Arc::new(db), "wallet1");
+        let db = Arc::new(create_db(tmpfile.path()));
 
-        let tx1 = Arc::new(create_one_inp_one_out_tx(
-            Txid::from_byte_array([0; 32]),
-            30_000,
-        ));
-        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
-        let tx3 = Arc::new(create_one_inp_one_out_tx(tx2.compute_txid(), 19_000));
+        let store1 = create_test_store(db.clone(), "wallet1");
 
-        let anchor1 = ConfirmationBlockTime {
-            block_id: block_id!(23, "BTC"),
-            confirmation_time: 1756838400,
+        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[0].parse().unwrap();
+        let change_descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[1].parse().unwrap();
+
+        let changeset1 = ChangeSet {
+            descriptor: Some(descriptor.clone()),
+            change_descriptor: Some(change_descriptor.clone()),
+            network: Some(Network::Bitcoin),
+            ..ChangeSet::default()
         };
 
-        let anchor2 = ConfirmationBlockTime {
-            block_id: block_id!(25, "BDK"),
-            confirmation_time: 1756839600,
+        store1.create_tables::<ConfirmationBlockTime>().unwrap();
+        store1.persist_wallet(&changeset1).unwrap();
+
+        let store2 = create_test_store(db, "wallet2");
+
+        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[2].parse().unwrap();
+        let change_descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[3].parse().unwrap();
+
+        let changeset2 = ChangeSet {
+            descriptor: Some(descriptor.clone()),
+            change_descriptor: Some(change_descriptor.clone()),
+            network: Some(Network::Bitcoin),
+            ..ChangeSet::default()
         };
 
-        let txs: BTreeSet<Arc<Transaction>> = [tx1.clone(), tx2.clone()].into();
-        let mut anchors = [(anchor1, tx1.compute_txid()), (anchor2, tx2.compute_txid())].into();
+        store2.create_tables::<ConfirmationBlockTime>().unwrap();
+        store2.persist_wallet(&changeset2).unwrap();
 
-        let write_tx = store.db.begin_write().unwrap();
-        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
-        let _ = write_tx
-            .open_table(store.anchors_table_defn::<ConfirmationBlockTime>())
-            .unwrap();
-        write_tx.commit().unwrap();
+        let mut changeset_read = ChangeSet::default();
+        store1.read_wallet(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read, changeset1);
 
-        let write_tx = store.db.begin_write().unwrap();
-        store.persist_txs(&write_tx, &txs).unwrap();
-        write_tx.commit().unwrap();
+        let mut changeset_read = ChangeSet::default();
+        store2.read_wallet(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read, changeset2);
+    }
 
-        // to hit the branch for the case when tx is persisted but not in changeset
-        let txs: BTreeSet<Arc<Transaction>> = BTreeSet::new();
+    #[cfg(feature = "wallet")]
+    #[test]
+    fn wallets_missing_descriptors() {
+        // to increase branch coverage for if-let statements
+        // in persist_wallet functions
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
 
-        let write_tx = store.db.begin_write().unwrap();
-        let read_tx = store.db.begin_read().unwrap();
-        store
-            .persist_anchors(&write_tx, &read_tx, &anchors, &txs)
-            .unwrap();
-        read_tx.close().unwrap();
-        write_tx.commit().unwrap();
+        let store1 = create_test_store(db.clone(), "wallet1");
 
-        let read_tx = store.db.begin_read().unwrap();
-        let mut anchors_read: BTreeSet<(ConfirmationBlockTime, Txid)> = BTreeSet::new();
-        store.read_anchors(&read_tx, &mut anchors_read).unwrap();
-        assert_eq!(anchors_read, anchors);
+        let changeset1 = ChangeSet {
+            network: Some(Network::Bitcoin),
+            ..ChangeSet::default()
+        };
 
-        let txs_new: BTreeSet<Arc<Transaction>> = [tx3.clone()].into();
-        let anchors_new: BTreeSet<(ConfirmationBlockTime, Txid)> =
-            [(anchor2, tx3.compute_txid())].into();
+        store1.create_tables::<ConfirmationBlockTime>().unwrap();
+        store1.persist_wallet(&changeset1).unwrap();
 
-        let write_tx = store.db.begin_write().unwrap();
-        let read_tx = store.db.begin_read().unwrap();
-        store
-            .persist_anchors(&write_tx, &read_tx, &anchors_new, &txs_new)
-            .unwrap();
-        read_tx.close().unwrap();
-        write_tx.commit().unwrap();
+        let store2 = create_test_store(db, "wallet2");
 
-        let read_tx = store.db.begin_read().unwrap();
-        let mut anchors_read_new: BTreeSet<(ConfirmationBlockTime, Txid)> = BTreeSet::new();
-        store.read_anchors(&read_tx, &mut anchors_read_new).unwrap();
+        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[2].parse().unwrap();
 
-        anchors.merge(anchors_new);
-        assert_eq!(anchors_read_new, anchors);
+        let changeset2 = ChangeSet {
+            descriptor: Some(descriptor.clone()),
+            ..ChangeSet::default()
+        };
 
-        // to hit the branch for the case when tx is persisted and is also in changeset (can this
-        // happen though?)
-        let tx4 = Arc::new(create_one_inp_one_out_tx(tx3.compute_txid(), 14_000));
+        store2.create_tables::<ConfirmationBlockTime>().unwrap();
+        store2.persist_wallet(&changeset2).unwrap();
 
-        let txs_new: BTreeSet<Arc<Transaction>> = [tx4.clone()].into();
-        let anchors_new: BTreeSet<(ConfirmationBlockTime, Txid)> =
-            [(anchor2, tx4.compute_txid())].into();
+        let mut changeset_read = ChangeSet::default();
+        store1.read_wallet(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read, changeset1);
 
-        let write_tx = store.db.begin_write().unwrap();
-        store.persist_txs(&write_tx, &txs_new).unwrap();
-        write_tx.commit().unwrap();
+        let mut changeset_read = ChangeSet::default();
+        store2.read_wallet(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read, changeset2);
+    }
 
-        let write_tx = store.db.begin_write().unwrap();
-        let read_tx = store.db.begin_read().unwrap();
-        store
-            .persist_anchors(&write_tx, &read_tx, &anchors_new, &txs_new)
-            .unwrap();
-        read_tx.close().unwrap();
-        write_tx.commit().unwrap();
+    #[cfg(feature = "wallet")]
+    #[test]
+    fn test_persist_wallet_rollback() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        let persisted = ChangeSet {
+            network: Some(Network::Bitcoin),
+            local_chain: local_chain::ChangeSet {
+                blocks: [(0, Some(hash!("A")))].into(),
+            },
+            ..ChangeSet::default()
+        };
+        store.persist_wallet(&persisted).unwrap();
 
-        let read_tx = store.db.begin_read().unwrap();
-        let mut anchors_read_new: BTreeSet<(ConfirmationBlockTime, Txid)> = BTreeSet::new();
-        store.read_anchors(&read_tx, &mut anchors_read_new).unwrap();
+        let anchor = ConfirmationBlockTime {
+            block_id: block_id!(1, "BDK"),
+            confirmation_time: 123,
+        };
+        // the blocks are written before the tx_graph, which fails since the anchored tx is missing
+        let changeset = ChangeSet {
+            local_chain: local_chain::ChangeSet {
+                blocks: [(0, Some(hash!("B"))), (1, Some(hash!("BDK")))].into(),
+            },
+            tx_graph: tx_graph::ChangeSet {
+                anchors: [(anchor, hash!("T"))].into(),
+                ..Default::default()
+            },
+            ..ChangeSet::default()
+        };
 
-        anchors.merge(anchors_new);
-        assert_eq!(anchors_read_new, anchors);
+        // the write transaction is dropped without being committed, for every persist function
+        // writing the blocks
+        match store.persist_wallet(&changeset) {
+            Ok(_) => panic!("should give error since tx missing"),
+            Err(StoreError::TxMissing(txid)) => assert_eq!(txid, hash!("T")),
+            Err(_) => panic!("error should only be due to missing tx"),
+        }
+        assert!(matches!(
+            store.persist_wallet_batched(&changeset),
+            Err(StoreError::TxMissing(_))
+        ));
+        let write_tx = db.begin_write().unwrap();
+        assert!(matches!(
+            store.persist_wallet_in_tx(&write_tx, &changeset),
+            Err(StoreError::TxMissing(_))
+        ));
+        write_tx.abort().unwrap();
+
+        let mut changeset_read = ChangeSet::default();
+        store.read_wallet(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read, persisted);
+
+        // a failed persist does not revert the commits of other stores sharing the database
+        let other = create_test_store(db, "wallet2");
+        other.create_tables::<ConfirmationBlockTime>().unwrap();
+        other.persist_wallet(&persisted).unwrap();
+        assert!(store.persist_wallet(&changeset).is_err());
+        let mut changeset_read = ChangeSet::default();
+        other.read_wallet(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read, persisted);
     }
 
+    #[cfg(feature = "wallet")]
     #[test]
-    fn test_anchors_missing_tx() {
-        // to hit the branch for the panic case in persist_anchors
+    fn test_persist_summary() {
         let tmpfile = NamedTempFile::new().unwrap();
         let db = create_db(tmpfile.path());
         let store = create_test_store(Arc::new(db), "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+
+        let descriptor_ids = utils::DESCRIPTORS.map(|d| parse_descriptor(d).descriptor_id());
+        let tx1 = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+        let anchor = ConfirmationBlockTime {
+            block_id: block_id!(1, "BDK"),
+            confirmation_time: 123,
+        };
+
+        let changeset = ChangeSet {
+            descriptor: Some(parse_descriptor(DESCRIPTORS[0])),
+            change_descriptor: Some(parse_descriptor(DESCRIPTORS[1])),
+            network: Some(Network::Bitcoin),
+            local_chain: local_chain::ChangeSet {
+                blocks: [
+                    (0, Some(hash!("B"))),
+                    (1, Some(hash!("BDK"))),
+                    (2, Some(hash!("R"))),
+                ]
+                .into(),
+            },
+            tx_graph: tx_graph::ChangeSet {
+                txs: [tx1.clone(), tx2.clone()].into(),
+                txouts: [(
+                    OutPoint::new(hash!("F"), 0),
+                    TxOut {
+                        value: Amount::from_sat(1_000),
+                        script_pubkey: ScriptBuf::new(),
+                    },
+                )]
+                .into(),
+                anchors: [(anchor, tx1.compute_txid())].into(),
+                last_seen: [(tx1.compute_txid(), 100), (tx2.compute_txid(), 120)].into(),
+                first_seen: [(tx2.compute_txid(), 110)].into(),
+                ..Default::default()
+            },
+            indexer: keychain_txout::ChangeSet {
+                last_revealed: [(descriptor_ids[0], 2)].into(),
+                spk_cache: [(
+                    descriptor_ids[0],
+                    [
+                        (0, ScriptBuf::from_bytes(vec![1])),
+                        (1, ScriptBuf::from_bytes(vec![2])),
+                        (2, ScriptBuf::from_bytes(vec![3])),
+                    ]
+                    .into(),
+                )]
+                .into(),
+            },
+        };
 
-        let anchor1 = ConfirmationBlockTime {
-            block_id: block_id!(23, "BTC"),
-            confirmation_time: 1756838400,
+        let summary = store.persist_wallet(&changeset).unwrap();
+        let inserted = |rows| TableChanges {
+            inserted: rows,
+            removed: 0,
+            skipped: 0,
         };
+        assert_eq!(
+            summary,
+            PersistSummary {
+                network: inserted(1),
+                keychains: inserted(2),
+                blocks: inserted(3),
+                txs: inserted(2),
+                txouts: inserted(1),
+                anchors: inserted(1),
+                last_seen: inserted(2),
+                first_seen: inserted(1),
+                last_revealed: inserted(1),
+                spks: inserted(3),
+                ..Default::default()
+            }
+        );
 
-        let write_tx = store.db.begin_write().unwrap();
-        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
-        let _ = write_tx
-            .open_table(store.anchors_table_defn::<ConfirmationBlockTime>())
+        // only the tombstone of a persisted block counts as a removal
+        let summary = store
+            .persist_local_chain(&local_chain::ChangeSet {
+                blocks: [(2, None), (5, None), (3, Some(hash!("D")))].into(),
+            })
             .unwrap();
-        write_tx.commit().unwrap();
-
-        let anchors_missing_txs: BTreeSet<(ConfirmationBlockTime, Txid)> =
-            [(anchor1, hash!("B"))].into();
-        let txs: BTreeSet<Arc<Transaction>> = BTreeSet::new();
+        assert_eq!(
+            summary,
+            PersistSummary {
+                blocks: TableChanges {
+                    inserted: 1,
+                    removed: 1,
+                    skipped: 0,
+                },
+                ..Default::default()
+            }
+        );
 
-        let write_tx = store.db.begin_write().unwrap();
-        let read_tx = store.db.begin_read().unwrap();
-        match store.persist_anchors(&write_tx, &read_tx, &anchors_missing_txs, &txs) {
-            Ok(_) => panic!("should give error since tx missing"),
-            Err(StoreError::TxMissing(txid)) => assert_eq!(txid, hash!("B")),
-            Err(_) => panic!("error should only be due to missing tx"),
-        }
-        read_tx.close().unwrap();
-        write_tx.commit().unwrap();
+        // overwriting a block with a different hash is reported, rewriting the same hash is not
+        let summary = store
+            .persist_local_chain(&local_chain::ChangeSet {
+                blocks: [(0, Some(hash!("B"))), (1, Some(hash!("BDK2")))].into(),
+            })
+            .unwrap();
+        assert_eq!(summary.blocks, inserted(2));
+        assert_eq!(
+            summary.replaced_blocks,
+            [ReplacedBlock {
+                height: 1,
+                old_hash: hash!("BDK"),
+                new_hash: hash!("BDK2"),
+            }]
+        );
+
+        assert!(
+            store
+                .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime>::default())
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            store
+                .persist_indexer(&keychain_txout::ChangeSet::default())
+                .unwrap()
+                .is_empty()
+        );
+
+        assert_eq!(
+            store
+                .persist_keychains(&[(2, parse_descriptor(DESCRIPTORS[2]))].into())
+                .unwrap(),
+            PersistSummary {
+                keychains: inserted(1),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            store.persist_network(&Some(Network::Bitcoin)).unwrap(),
+            PersistSummary {
+                network: inserted(1),
+                ..Default::default()
+            }
+        );
+        assert!(store.persist_network(&None).unwrap().is_empty());
     }
 
     #[test]
-    fn test_persist_anchors_blockid() {
+    fn test_prune_confirmed_below() {
         let tmpfile = NamedTempFile::new().unwrap();
         let db = create_db(tmpfile.path());
         let store = create_test_store(Arc::new(db), "wallet1");
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
 
         let tx1 = Arc::new(create_one_inp_one_out_tx(
             Txid::from_byte_array([0; 32]),
@@ -1650,507 +10085,1676 @@ mod test {
         ));
         let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
         let tx3 = Arc::new(create_one_inp_one_out_tx(tx2.compute_txid(), 19_000));
+        let tx4 = Arc::new(create_one_inp_one_out_tx(tx3.compute_txid(), 18_000));
+        let [txid1, txid2, txid3, txid4] = [&tx1, &tx2, &tx3, &tx4].map(|tx| tx.compute_txid());
+        let anchor = |height, hash| ConfirmationBlockTime {
+            block_id: BlockId { height, hash },
+            confirmation_time: 100,
+        };
 
-        let anchor1 = block_id!(23, "BTC");
-
-        let anchor2 = block_id!(25, "BDK");
-
-        let txs: BTreeSet<Arc<Transaction>> = [tx1.clone(), tx2.clone()].into();
-        let mut anchors = [(anchor1, tx1.compute_txid()), (anchor2, tx2.compute_txid())].into();
+        // tx1: deeply confirmed, tx2: confirmed in a block below and one above the threshold,
+        // tx3: confirmed at the threshold, tx4: unconfirmed
+        let changeset = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: [tx1, tx2, tx3, tx4].into(),
+            txouts: [(
+                OutPoint::new(txid1, 1),
+                TxOut {
+                    value: Amount::from_sat(1_000),
+                    script_pubkey: ScriptBuf::new(),
+                },
+            )]
+            .into(),
+            anchors: [
+                (anchor(5, hash!("A")), txid1),
+                (anchor(8, hash!("B")), txid1),
+                (anchor(5, hash!("A")), txid2),
+                (anchor(12, hash!("C")), txid2),
+                (anchor(10, hash!("D")), txid3),
+            ]
+            .into(),
+            last_seen: [(txid1, 100), (txid4, 200)].into(),
+            first_seen: [(txid1, 90)].into(),
+            last_evicted: [(txid1, 110)].into(),
+        };
+        store.persist_tx_graph(&changeset).unwrap();
 
-        let write_tx = store.db.begin_write().unwrap();
-        let _ = write_tx.open_table(store.txs_table_defn()).unwrap();
-        let _ = write_tx
-            .open_table(store.anchors_table_defn::<BlockId>())
+        // BlockId anchors are taken into account too
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<BlockId> {
+                anchors: [(block_id!(15, "E"), txid1)].into(),
+                ..Default::default()
+            })
             .unwrap();
-        write_tx.commit().unwrap();
+        assert_eq!(store.prune_confirmed_below(10).unwrap(), 0);
 
         let write_tx = store.db.begin_write().unwrap();
-        let read_tx = store.db.begin_read().unwrap();
-        store
-            .persist_anchors(&write_tx, &read_tx, &anchors, &txs)
+        write_tx
+            .open_multimap_table(store.anchors_table_defn())
+            .unwrap()
+            .remove(
+                txid1.to_byte_array(),
+                (
+                    block_id_to_bytes(&block_id!(15, "E")),
+                    BlockId::TAG,
+                    anchor_metadata_to_bytes(&block_id!(15, "E"), false).as_slice(),
+                ),
+            )
             .unwrap();
-        read_tx.close().unwrap();
         write_tx.commit().unwrap();
 
-        let read_tx = store.db.begin_read().unwrap();
-        let mut anchors_read: BTreeSet<(BlockId, Txid)> = BTreeSet::new();
-        store.read_anchors(&read_tx, &mut anchors_read).unwrap();
-        assert_eq!(anchors_read, anchors);
-
-        let txs_new: BTreeSet<Arc<Transaction>> = [tx3.clone()].into();
-        let anchors_new: BTreeSet<(BlockId, Txid)> = [(anchor2, tx3.compute_txid())].into();
+        assert_eq!(store.prune_confirmed_below(10).unwrap(), 1);
+        assert_eq!(store.prune_confirmed_below(10).unwrap(), 0);
+
+        let mut changeset_read = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        store.read_tx_graph(&mut changeset_read).unwrap();
+        assert_eq!(
+            store.txids().unwrap(),
+            [txid2, txid3, txid4].into_iter().collect()
+        );
+        assert!(changeset_read.txouts.is_empty());
+        assert!(
+            changeset_read
+                .anchors
+                .iter()
+                .all(|(_, txid)| *txid != txid1)
+        );
+        assert_eq!(changeset_read.anchors.len(), 3);
+        assert_eq!(changeset_read.last_seen, [(txid4, 200)].into());
+        assert!(changeset_read.first_seen.is_empty());
+        assert!(changeset_read.last_evicted.is_empty());
+    }
 
-        let write_tx = store.db.begin_write().unwrap();
-        let read_tx = store.db.begin_read().unwrap();
+    #[test]
+    fn test_prune_keeps_unspent_outputs() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = create_db(tmpfile.path());
+        let store = create_test_store(Arc::new(db), "wallet1");
         store
-            .persist_anchors(&write_tx, &read_tx, &anchors_new, &txs_new)
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
             .unwrap();
-        read_tx.close().unwrap();
-        write_tx.commit().unwrap();
 
-        let read_tx = store.db.begin_read().unwrap();
-        let mut anchors_read_new: BTreeSet<(BlockId, Txid)> = BTreeSet::new();
-        store.read_anchors(&read_tx, &mut anchors_read_new).unwrap();
+        // tx_a has two outputs, only the first one being spent by tx_b, whose output is spent by
+        // the unconfirmed tx_c
+        let mut tx_a = create_one_inp_one_out_tx(Txid::from_byte_array([0; 32]), 30_000);
+        tx_a.output.push(TxOut {
+            value: Amount::from_sat(5_000),
+            script_pubkey: ScriptBuf::new(),
+        });
+        let tx_a = Arc::new(tx_a);
+        let tx_b = Arc::new(create_one_inp_one_out_tx(tx_a.compute_txid(), 20_000));
+        let tx_c = Arc::new(create_one_inp_one_out_tx(tx_b.compute_txid(), 19_000));
+        let [txid_a, txid_b, txid_c] = [&tx_a, &tx_b, &tx_c].map(|tx| tx.compute_txid());
+        let anchor = ConfirmationBlockTime {
+            block_id: block_id!(5, "A"),
+            confirmation_time: 100,
+        };
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [tx_a, tx_b, tx_c].into(),
+                anchors: [(anchor, txid_a), (anchor, txid_b)].into(),
+                ..Default::default()
+            })
+            .unwrap();
 
-        anchors.merge(anchors_new);
-        assert_eq!(anchors_read_new, anchors);
+        // tx_a is kept since its second output is unspent
+        assert_eq!(store.prune_confirmed_below(10).unwrap(), 1);
+        assert_eq!(store.txids().unwrap(), [txid_a, txid_c].into());
     }
 
+    #[cfg(feature = "wallet")]
     #[test]
-    fn test_tx_graph_persistence() {
+    fn test_on_persist() {
+        use std::sync::OnceLock;
+        use std::sync::atomic::{AtomicU64, Ordering};
+
         let tmpfile = NamedTempFile::new().unwrap();
         let db = create_db(tmpfile.path());
-        let store = create_test_store(Arc::new(db), "wallet1");
-        let tx1 = Arc::new(create_one_inp_one_out_tx(
-            Txid::from_byte_array([0; 32]),
-            30_000,
-        ));
-        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
-        let block_id = block_id!(100, "B");
+        let mut store = create_test_store(Arc::new(db), "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
 
-        let conf_anchor: ConfirmationBlockTime = ConfirmationBlockTime {
-            block_id,
-            confirmation_time: 1,
+        // counts the calls and the blocks inserted, persisting a block from within the callback
+        let calls = Arc::new(AtomicU64::new(0));
+        let blocks = Arc::new(AtomicU64::new(0));
+        let this: Arc<OnceLock<Arc<Store>>> = Arc::new(OnceLock::new());
+        store.set_on_persist(Box::new({
+            let (calls, blocks, this) = (calls.clone(), blocks.clone(), this.clone());
+            move |summary| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                blocks.fetch_add(summary.blocks.inserted, Ordering::SeqCst);
+                let nested = this
+                    .get()
+                    .unwrap()
+                    .persist_local_chain(&local_chain::ChangeSet {
+                        blocks: [(100, Some(hash!("N")))].into(),
+                    })
+                    .unwrap();
+                assert_eq!(nested.blocks.inserted, 1);
+            }
+        }));
+        let store = Arc::new(store);
+        this.set(store.clone()).unwrap();
+
+        let changeset = ChangeSet {
+            local_chain: local_chain::ChangeSet {
+                blocks: [(0, Some(hash!("B"))), (1, Some(hash!("BDK")))].into(),
+            },
+            ..ChangeSet::default()
         };
+        store.persist_wallet(&changeset).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(blocks.load(Ordering::SeqCst), 2);
 
-        let mut tx_graph_changeset1 = tx_graph::ChangeSet::<ConfirmationBlockTime> {
-            txs: [tx1.clone()].into(),
-            txouts: [].into(),
-            anchors: [(conf_anchor, tx1.compute_txid())].into(),
-            last_seen: [(tx1.compute_txid(), 100)].into(),
-            first_seen: [(tx1.compute_txid(), 50)].into(),
-            last_evicted: [(tx1.compute_txid(), 150)].into(),
+        // not invoked when the persist fails
+        let anchor = ConfirmationBlockTime {
+            block_id: block_id!(1, "BDK"),
+            confirmation_time: 123,
         };
+        assert!(
+            store
+                .persist_tx_graph(&tx_graph::ChangeSet {
+                    anchors: [(anchor, hash!("T"))].into(),
+                    ..Default::default()
+                })
+                .is_err()
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
 
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime>::default())
+            .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(blocks.load(Ordering::SeqCst), 2);
+
+        let mut changeset_read = ChangeSet::default();
+        store.read_wallet(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read.local_chain.blocks.len(), 3);
+    }
+
+    // This function creates the tx graph tables of the store and persists 2000 txouts with 1000
+    // bytes scripts, so that the database file shrinks noticeably once they are deleted and the
+    // file is compacted.
+    fn persist_large_txouts(store: &Store) {
         store
             .create_tx_graph_tables::<ConfirmationBlockTime>()
             .unwrap();
 
-        store.persist_tx_graph(&tx_graph_changeset1).unwrap();
+        let txouts: BTreeMap<OutPoint, TxOut> = (0..2000)
+            .map(|vout| {
+                (
+                    OutPoint::new(Txid::from_byte_array([0; 32]), vout),
+                    TxOut {
+                        value: Amount::from_sat(vout as u64),
+                        script_pubkey: ScriptBuf::from_bytes(vec![1; 1000]),
+                    },
+                )
+            })
+            .collect();
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txouts,
+                ..Default::default()
+            })
+            .unwrap();
+    }
 
-        let mut changeset = tx_graph::ChangeSet::default();
-        store.read_tx_graph(&mut changeset).unwrap();
-        assert_eq!(changeset, tx_graph_changeset1);
+    #[test]
+    fn test_compact() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1");
+        persist_large_txouts(&store);
+
+        // the database is shared with the store
+        match compact(db.clone()) {
+            Ok(_) => panic!("should give error since db is shared"),
+            Err(StoreError::DatabaseInUse(handles)) => assert_eq!(handles, 3),
+            Err(_) => panic!("error should only be due to db being in use"),
+        }
 
-        let block_id = block_id!(101, "REDB");
+        // delete the wallet
+        let write_tx = db.begin_write().unwrap();
+        write_tx.delete_table(store.txouts_table_defn()).unwrap();
+        write_tx.commit().unwrap();
+        drop(store);
 
-        let conf_anchor: ConfirmationBlockTime = ConfirmationBlockTime {
-            block_id,
-            confirmation_time: 1,
-        };
+        let size_before = std::fs::metadata(tmpfile.path()).unwrap().len();
+        assert!(compact(db).unwrap());
+        let size_after = std::fs::metadata(tmpfile.path()).unwrap().len();
+        assert!(size_after < size_before);
+    }
 
-        let tx_graph_changeset2 = tx_graph::ChangeSet::<ConfirmationBlockTime> {
-            txs: [tx2.clone()].into(),
-            txouts: [].into(),
-            anchors: [(conf_anchor, tx2.compute_txid())].into(),
-            last_seen: [(tx2.compute_txid(), 200)].into(),
-            first_seen: [(tx2.compute_txid(), 100)].into(),
-            last_evicted: [(tx2.compute_txid(), 150)].into(),
-        };
+    #[test]
+    fn test_store_compact() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let mut store = create_test_store(db.clone(), "wallet1");
+        persist_large_txouts(&store);
 
-        store.persist_tx_graph(&tx_graph_changeset2).unwrap();
+        let write_tx = store.db.begin_write().unwrap();
+        {
+            let mut table = write_tx.open_table(store.txouts_table_defn()).unwrap();
+            table.retain(|_, _| false).unwrap();
+        }
+        write_tx.commit().unwrap();
 
-        let mut changeset = tx_graph::ChangeSet::default();
+        // another handle to the database is alive
+        match store.compact() {
+            Ok(_) => panic!("should give error since db is shared"),
+            Err(StoreError::DatabaseInUse(handles)) => assert_eq!(handles, 2),
+            Err(_) => panic!("error should only be due to db being in use"),
+        }
+        drop(db);
+
+        let size_before = std::fs::metadata(tmpfile.path()).unwrap().len();
+        assert!(store.compact().unwrap());
+        let size_after = std::fs::metadata(tmpfile.path()).unwrap().len();
+        assert!(size_after < size_before);
+
+        // the store is still usable after compaction
+        let mut changeset = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
         store.read_tx_graph(&mut changeset).unwrap();
+        assert!(changeset.txouts.is_empty());
+    }
 
-        tx_graph_changeset1.merge(tx_graph_changeset2);
+    // This backend counts the calls to sync_data, i.e. the fsyncs made by the commits with
+    // Durability::Immediate.
+    #[cfg(feature = "wallet")]
+    #[derive(Debug, Default, Clone)]
+    struct SyncCountingBackend {
+        inner: crate::backend::MemoryBackend,
+        syncs: Arc<std::sync::atomic::AtomicUsize>,
+    }
 
-        assert_eq!(tx_graph_changeset1, changeset);
+    #[cfg(feature = "wallet")]
+    impl SyncCountingBackend {
+        fn syncs(&self) -> usize {
+            self.syncs.load(std::sync::atomic::Ordering::SeqCst)
+        }
     }
 
-    fn parse_descriptor(descriptor: &str) -> Descriptor<DescriptorPublicKey> {
-        let secp = bdk_chain::bitcoin::secp256k1::Secp256k1::signing_only();
-        Descriptor::<DescriptorPublicKey>::parse_descriptor(&secp, descriptor)
-            .unwrap()
-            .0
+    #[cfg(feature = "wallet")]
+    impl StorageBackend for SyncCountingBackend {
+        fn len(&self) -> Result<u64, std::io::Error> {
+            self.inner.len()
+        }
+
+        fn read(&self, offset: u64, len: usize) -> Result<Vec<u8>, std::io::Error> {
+            self.inner.read(offset, len)
+        }
+
+        fn set_len(&self, len: u64) -> Result<(), std::io::Error> {
+            self.inner.set_len(len)
+        }
+
+        fn sync_data(&self, eventual: bool) -> Result<(), std::io::Error> {
+            self.syncs.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            self.inner.sync_data(eventual)
+        }
+
+        fn write(&self, offset: u64, data: &[u8]) -> Result<(), std::io::Error> {
+            self.inner.write(offset, data)
+        }
     }
 
+    #[cfg(feature = "wallet")]
     #[test]
-    fn test_last_revealed_persistence() {
+    fn test_persist_wallet_batched() {
+        // one changeset per block, as persisted while syncing a wallet
+        let changesets: Vec<ChangeSet> = (0..20)
+            .map(|height| {
+                let tx = create_one_inp_one_out_tx(Txid::from_byte_array([0; 32]), height.into());
+                ChangeSet {
+                    local_chain: local_chain::ChangeSet {
+                        blocks: [(height, Some(hash!("B")))].into(),
+                    },
+                    tx_graph: tx_graph::ChangeSet {
+                        last_seen: [(tx.compute_txid(), height.into())].into(),
+                        txs: [Arc::new(tx)].into(),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                }
+            })
+            .collect();
+        let mut expected = ChangeSet {
+            descriptor: Some(parse_descriptor(DESCRIPTORS[0])),
+            network: Some(Network::Signet),
+            ..Default::default()
+        };
+        for changeset in &changesets {
+            expected.merge(changeset.clone());
+        }
+
+        // persist_wallet waits for the disk once per changeset
+        let backend = SyncCountingBackend::default();
+        let store = Store::from_backend(backend.clone(), "wallet1").unwrap();
+        store
+            .persist_wallet(&ChangeSet {
+                descriptor: expected.descriptor.clone(),
+                network: expected.network,
+                ..Default::default()
+            })
+            .unwrap();
+        let start = backend.syncs();
+        for changeset in &changesets {
+            store.persist_wallet(changeset).unwrap();
+        }
+        let immediate_syncs = backend.syncs() - start;
+        assert!(immediate_syncs >= changesets.len());
+
+        // persist_wallet_batched does not wait for the disk, a single sync makes all the
+        // changesets durable
+        let backend = SyncCountingBackend::default();
+        let store = Store::from_backend(backend.clone(), "wallet1").unwrap();
+        store
+            .persist_wallet(&ChangeSet {
+                descriptor: expected.descriptor.clone(),
+                network: expected.network,
+                ..Default::default()
+            })
+            .unwrap();
+        let start = backend.syncs();
+        for changeset in &changesets {
+            store.persist_wallet_batched(changeset).unwrap();
+        }
+        assert_eq!(backend.syncs(), start);
+        // the changesets are visible before being durable
+        let mut changeset_read = ChangeSet::default();
+        store.read_wallet(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read, expected);
+        store.sync().unwrap();
+        let batched_syncs = backend.syncs() - start;
+        assert!(batched_syncs > 0);
+        assert!(batched_syncs < immediate_syncs);
+
+        // a failing changeset is not committed
+        let changeset_err = ChangeSet {
+            local_chain: local_chain::ChangeSet {
+                blocks: [(1000, Some(hash!("T")))].into(),
+            },
+            tx_graph: tx_graph::ChangeSet {
+                anchors: [(
+                    ConfirmationBlockTime {
+                        block_id: block_id!(1, "BDK"),
+                        confirmation_time: 123,
+                    },
+                    hash!("T"),
+                )]
+                .into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(store.persist_wallet_batched(&changeset_err).is_err());
+        let mut changeset_read = ChangeSet::default();
+        store.read_wallet(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read, expected);
+    }
+
+    #[test]
+    fn test_close() {
         let tmpfile = NamedTempFile::new().unwrap();
-        let db = create_db(tmpfile.path());
-        let store = create_test_store(Arc::new(db), "wallet1");
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db, "wallet1");
+        store.create_local_chain_tables().unwrap();
+        let changeset = local_chain::ChangeSet {
+            blocks: [(0, Some(hash!("B0"))), (1, Some(hash!("B1")))].into(),
+        };
+        store.persist_local_chain(&changeset).unwrap();
+        store.close().unwrap();
+
+        // the file is closed, so it can be opened again
+        let db = Arc::new(Database::open(tmpfile.path()).unwrap());
+        let store = create_test_store(db.clone(), "wallet1");
+        let mut read_changeset = local_chain::ChangeSet::default();
+        store.read_local_chain(&mut read_changeset).unwrap();
+        assert_eq!(read_changeset, changeset);
+
+        // closing a store sharing the database leaves it open for the other handles
+        store.close().unwrap();
+        let store = create_test_store(db, "wallet1");
+        let mut read_changeset = local_chain::ChangeSet::default();
+        store.read_local_chain(&mut read_changeset).unwrap();
+        assert_eq!(read_changeset, changeset);
+    }
 
-        let descriptor_ids = utils::DESCRIPTORS.map(|d| parse_descriptor(d).descriptor_id());
+    #[test]
+    fn test_check_integrity() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let mut store = create_test_store(db.clone(), "wallet1");
+        store.create_local_chain_tables().unwrap();
+        store
+            .persist_local_chain(&local_chain::ChangeSet {
+                blocks: (0..1000).map(|ht| (ht, Some(hash!("B")))).collect(),
+            })
+            .unwrap();
 
-        let mut last_revealed: BTreeMap<DescriptorId, u32> =
-            [(descriptor_ids[0], 1), (descriptor_ids[1], 100)].into();
+        match store.check_integrity() {
+            Ok(_) => panic!("should give error since db is shared"),
+            Err(StoreError::DatabaseInUse(handles)) => assert_eq!(handles, 2),
+            Err(_) => panic!("error should only be due to db being in use"),
+        }
+        drop(db);
+
+        assert!(store.check_integrity().unwrap());
+
+        // a copy of the file taken while the database is open looks like a crashed database, it is
+        // repaired when opened and passes the check afterwards
+        let copy = NamedTempFile::new().unwrap();
+        std::fs::copy(tmpfile.path(), copy.path()).unwrap();
+        drop(store);
+
+        let repaired = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let repaired_clone = repaired.clone();
+        let db = Database::builder()
+            .set_repair_callback(move |_| {
+                repaired_clone.store(true, std::sync::atomic::Ordering::SeqCst)
+            })
+            .open(copy.path())
+            .unwrap();
+        assert!(repaired.load(std::sync::atomic::Ordering::SeqCst));
 
-        let write_tx = store.db.begin_write().unwrap();
-        let _ = write_tx
-            .open_table(store.last_revealed_table_defn())
+        let mut store = create_test_store(Arc::new(db), "wallet1");
+        assert!(store.check_integrity().unwrap());
+        let mut changeset = local_chain::ChangeSet::default();
+        store.read_local_chain(&mut changeset).unwrap();
+        assert_eq!(changeset.blocks.len(), 1000);
+    }
+
+    #[test]
+    fn test_verify_references() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db, "wallet1");
+        store.create_local_chain_tables().unwrap();
+        store
+            .create_tx_graph_tables::<ConfirmationBlockTime>()
+            .unwrap();
+        store.create_indexer_tables().unwrap();
+
+        let tx1 = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+        let anchor1 = ConfirmationBlockTime {
+            block_id: block_id!(1, "B"),
+            confirmation_time: 100,
+        };
+        let anchor2 = ConfirmationBlockTime {
+            block_id: block_id!(2, "C"),
+            confirmation_time: 200,
+        };
+        let descriptor_ids = utils::DESCRIPTORS.map(|d| parse_descriptor(d).descriptor_id());
+
+        store
+            .persist_local_chain(&local_chain::ChangeSet {
+                blocks: [
+                    (0, Some(hash!("A"))),
+                    (1, Some(hash!("B"))),
+                    (2, Some(hash!("C"))),
+                ]
+                .into(),
+            })
             .unwrap();
         store
-            .persist_last_revealed(&write_tx, &last_revealed)
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [tx1.clone(), tx2.clone()].into(),
+                anchors: [(anchor1, tx1.compute_txid()), (anchor2, tx2.compute_txid())].into(),
+                last_seen: [(tx1.compute_txid(), 100), (tx2.compute_txid(), 120)].into(),
+                first_seen: [(tx1.compute_txid(), 50), (tx2.compute_txid(), 60)].into(),
+                last_evicted: [(tx1.compute_txid(), 150)].into(),
+                ..Default::default()
+            })
             .unwrap();
-        write_tx.commit().unwrap();
-
-        let mut last_revealed_read: BTreeMap<DescriptorId, u32> = BTreeMap::new();
-        let read_tx = store.db.begin_read().unwrap();
         store
-            .read_last_revealed(&read_tx, &mut last_revealed_read)
+            .persist_indexer(&keychain_txout::ChangeSet {
+                last_revealed: [(descriptor_ids[0], 3)].into(),
+                spk_cache: [(
+                    descriptor_ids[0],
+                    (0..4)
+                        .map(|idx| (idx, ScriptBuf::from_bytes(vec![idx as u8])))
+                        .collect(),
+                )]
+                .into(),
+            })
             .unwrap();
 
-        assert_eq!(last_revealed, last_revealed_read);
-
-        let last_revealed_new: BTreeMap<DescriptorId, u32> = [(descriptor_ids[0], 2)].into();
+        let report = store.verify_references::<ConfirmationBlockTime>().unwrap();
+        assert!(report.is_consistent());
 
+        // corrupt the store with raw writes
         let write_tx = store.db.begin_write().unwrap();
-        store
-            .persist_last_revealed(&write_tx, &last_revealed_new)
-            .unwrap();
+        {
+            // remove tx1 which is referenced by an anchor and all the timestamps
+            let mut txs_table = write_tx.open_table(store.txs_table_defn()).unwrap();
+            txs_table
+                .remove(tx1.compute_txid().to_byte_array())
+                .unwrap();
+
+            // reorg block 2 without updating the anchor of tx2
+            let mut blocks_table = write_tx.open_table(store.blocks_table_defn()).unwrap();
+            let hash: BlockHash = hash!("D");
+            blocks_table.insert(2, hash.to_byte_array()).unwrap();
+
+            // make holes in the spk cache
+            let mut spk_table = write_tx.open_table(store.spk_table_defn()).unwrap();
+            spk_table
+                .remove((descriptor_ids[0].to_byte_array(), 1))
+                .unwrap();
+            spk_table
+                .remove((descriptor_ids[0].to_byte_array(), 2))
+                .unwrap();
+            spk_table
+                .insert((descriptor_ids[1].to_byte_array(), 5), vec![5])
+                .unwrap();
+        }
         write_tx.commit().unwrap();
 
-        let mut last_revealed_read_new: BTreeMap<DescriptorId, u32> = BTreeMap::new();
-        let read_tx = store.db.begin_read().unwrap();
-        store
-            .read_last_revealed(&read_tx, &mut last_revealed_read_new)
-            .unwrap();
+        let report = store.verify_references::<ConfirmationBlockTime>().unwrap();
+        assert!(!report.is_consistent());
+        assert_eq!(
+            report.anchors_missing_tx,
+            vec![(anchor1.block_id, tx1.compute_txid())]
+        );
+        assert_eq!(
+            report.anchors_conflicting_block,
+            vec![(anchor2.block_id, hash!("D"))]
+        );
+        assert_eq!(report.last_seen_missing_tx, vec![tx1.compute_txid()]);
+        assert_eq!(report.first_seen_missing_tx, vec![tx1.compute_txid()]);
+        assert_eq!(report.last_evicted_missing_tx, vec![tx1.compute_txid()]);
+        let mut expected_gaps = vec![(descriptor_ids[0], 1..3), (descriptor_ids[1], 0..5)];
+        expected_gaps.sort_by_key(|(desc, _)| *desc);
+        assert_eq!(report.spk_cache_gaps, expected_gaps);
+    }
 
-        last_revealed.merge(last_revealed_new);
+    #[cfg(feature = "wallet")]
+    #[test]
+    fn test_custom_table_alongside_wallet() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db, "wallet1");
 
-        assert_eq!(last_revealed, last_revealed_read_new);
+        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[0].parse().unwrap();
+        let changeset = ChangeSet {
+            descriptor: Some(descriptor),
+            network: Some(Network::Bitcoin),
+            local_chain: local_chain::ChangeSet {
+                blocks: [(0, Some(hash!("B")))].into(),
+            },
+            ..ChangeSet::default()
+        };
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        store.persist_wallet(&changeset).unwrap();
+
+        const APP_TABLE: TableDefinition<&str, u64> = TableDefinition::new("my_app_data");
+        let db = store.database();
+        let write_tx = db.begin_write().unwrap();
+        {
+            let mut table = write_tx.open_table(APP_TABLE).unwrap();
+            table.insert("counter", 42).unwrap();
+        }
+        write_tx.commit().unwrap();
+
+        let read_tx = db.begin_read().unwrap();
+        let table = read_tx.open_table(APP_TABLE).unwrap();
+        assert_eq!(table.get("counter").unwrap().unwrap().value(), 42);
+
+        let mut changeset_read = ChangeSet::default();
+        store.read_wallet(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read, changeset);
     }
 
     #[test]
-    fn test_spks_persistence() {
+    fn test_repair_dangling() {
+        use crate::verify::RepairStats;
+
         let tmpfile = NamedTempFile::new().unwrap();
-        let db = create_db(tmpfile.path());
-        let store = create_test_store(Arc::new(db), "wallet1");
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db, "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
 
-        let descriptor_ids = utils::DESCRIPTORS.map(|d| parse_descriptor(d).descriptor_id());
+        let tx1 = Arc::new(create_one_inp_one_out_tx(
+            Txid::from_byte_array([0; 32]),
+            30_000,
+        ));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
+        let anchor = ConfirmationBlockTime {
+            block_id: block_id!(1, "B"),
+            confirmation_time: 100,
+        };
 
-        let spk_cache: BTreeMap<DescriptorId, BTreeMap<u32, ScriptBuf>> = [
-            (
-                descriptor_ids[0],
-                [(0u32, ScriptBuf::from_bytes(vec![1, 2, 3]))].into(),
-            ),
-            (
-                descriptor_ids[1],
-                [
-                    (100u32, ScriptBuf::from_bytes(vec![3])),
-                    (1000u32, ScriptBuf::from_bytes(vec![5, 6, 8])),
-                ]
-                .into(),
-            ),
-        ]
-        .into();
+        let tx_graph_changeset = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: [tx1.clone(), tx2.clone()].into(),
+            anchors: [(anchor, tx1.compute_txid()), (anchor, tx2.compute_txid())].into(),
+            last_seen: [(tx1.compute_txid(), 100), (tx2.compute_txid(), 120)].into(),
+            first_seen: [(tx1.compute_txid(), 50), (tx2.compute_txid(), 60)].into(),
+            last_evicted: [(tx1.compute_txid(), 150)].into(),
+            ..Default::default()
+        };
+        store.persist_tx_graph(&tx_graph_changeset).unwrap();
 
+        // make the rows of tx1 dangling
         let write_tx = store.db.begin_write().unwrap();
-        let _ = write_tx.open_table(store.spk_table_defn()).unwrap();
-        store.persist_spks(&write_tx, &spk_cache).unwrap();
+        {
+            let mut table = write_tx.open_table(store.txs_table_defn()).unwrap();
+            table.remove(tx1.compute_txid().to_byte_array()).unwrap();
+        }
         write_tx.commit().unwrap();
 
-        let mut spk_cache_read: BTreeMap<DescriptorId, BTreeMap<u32, ScriptBuf>> = BTreeMap::new();
-        let read_tx = store.db.begin_read().unwrap();
-        store.read_spks(&read_tx, &mut spk_cache_read).unwrap();
+        let report = store.verify_references::<ConfirmationBlockTime>().unwrap();
+        assert!(!report.is_consistent());
 
-        assert_eq!(spk_cache, spk_cache_read);
+        let stats = store
+            .repair_dangling::<ConfirmationBlockTime>(&report)
+            .unwrap();
+        assert_eq!(
+            stats,
+            RepairStats {
+                anchors: 1,
+                last_seen: 1,
+                first_seen: 1,
+                last_evicted: 1,
+            }
+        );
+
+        assert!(
+            store
+                .verify_references::<ConfirmationBlockTime>()
+                .unwrap()
+                .is_consistent()
+        );
+
+        // repairing again with the stale report removes nothing
+        let stats = store
+            .repair_dangling::<ConfirmationBlockTime>(&report)
+            .unwrap();
+        assert_eq!(stats, RepairStats::default());
 
-        let spk_cache_new: BTreeMap<DescriptorId, BTreeMap<u32, ScriptBuf>> = [(
-            descriptor_ids[0],
-            [(1u32, ScriptBuf::from_bytes(vec![1, 2, 3, 4]))].into(),
-        )]
-        .into();
+        let mut changeset = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        store.read_tx_graph(&mut changeset).unwrap();
+        let expected = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: [tx2.clone()].into(),
+            anchors: [(anchor, tx2.compute_txid())].into(),
+            last_seen: [(tx2.compute_txid(), 120)].into(),
+            first_seen: [(tx2.compute_txid(), 60)].into(),
+            ..Default::default()
+        };
+        assert_eq!(changeset, expected);
 
-        let write_tx = store.db.begin_write().unwrap();
-        let _ = write_tx.open_table(store.spk_table_defn()).unwrap();
-        store.persist_spks(&write_tx, &spk_cache_new).unwrap();
-        write_tx.commit().unwrap();
+        // the repaired store can be persisted to again
+        store.persist_tx_graph(&changeset).unwrap();
+    }
 
-        let mut spk_cache_read_new: BTreeMap<DescriptorId, BTreeMap<u32, ScriptBuf>> =
-            BTreeMap::new();
-        let read_tx = store.db.begin_read().unwrap();
-        store.read_spks(&read_tx, &mut spk_cache_read_new).unwrap();
+    #[test]
+    fn test_stats() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db, "wallet1");
 
-        let spk_cache: BTreeMap<DescriptorId, BTreeMap<u32, ScriptBuf>> = [
-            (
-                descriptor_ids[0],
-                [
-                    (0u32, ScriptBuf::from_bytes(vec![1, 2, 3])),
-                    (1u32, ScriptBuf::from_bytes(vec![1, 2, 3, 4])),
-                ]
-                .into(),
-            ),
-            (
-                descriptor_ids[1],
-                [
-                    (100u32, ScriptBuf::from_bytes(vec![3])),
-                    (1000u32, ScriptBuf::from_bytes(vec![5, 6, 8])),
-                ]
+        // no table exists yet
+        let stats = store.stats().unwrap();
+        assert_eq!(stats.txs.entries, 0);
+        assert_eq!(stats.blocks.entries, 0);
+        assert_eq!(stats.spks.entries, 0);
+
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+
+        let mut txs: BTreeSet<Arc<Transaction>> = BTreeSet::new();
+        let mut prev_txid = Txid::from_byte_array([0; 32]);
+        for amount in 0..7 {
+            let tx = Arc::new(create_one_inp_one_out_tx(prev_txid, amount));
+            prev_txid = tx.compute_txid();
+            txs.insert(tx);
+        }
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs,
+                ..Default::default()
+            })
+            .unwrap();
+        store
+            .persist_local_chain(&local_chain::ChangeSet {
+                blocks: (0..5).map(|ht| (ht, Some(hash!("B")))).collect(),
+            })
+            .unwrap();
+        let descriptor_ids = utils::DESCRIPTORS.map(|d| parse_descriptor(d).descriptor_id());
+        store
+            .persist_indexer(&keychain_txout::ChangeSet {
+                spk_cache: [(
+                    descriptor_ids[0],
+                    (0..11)
+                        .map(|idx| (idx, ScriptBuf::from_bytes(vec![1, 2, 3])))
+                        .collect(),
+                )]
                 .into(),
-            ),
-        ]
-        .into();
+                ..Default::default()
+            })
+            .unwrap();
 
-        assert_eq!(spk_cache, spk_cache_read_new);
+        let stats = store.stats().unwrap();
+        assert_eq!(stats.txs.entries, 7);
+        assert!(stats.txs.stored_bytes > 0);
+        assert_eq!(stats.blocks.entries, 5);
+        assert_eq!(stats.blocks.stored_bytes, 5 * (4 + 32));
+        assert_eq!(stats.spks.entries, 11);
+        assert_eq!(stats.anchors.entries, 0);
+        assert!(stats.allocated_bytes > 0);
     }
 
     #[test]
-    fn test_indexer_persistence() {
+    fn test_counts() {
         let tmpfile = NamedTempFile::new().unwrap();
-        let db = create_db(tmpfile.path());
-        let store = create_test_store(Arc::new(db), "wallet1");
-
-        let descriptor_ids = utils::DESCRIPTORS.map(|d| parse_descriptor(d).descriptor_id());
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db, "wallet1");
+        assert_eq!(store.transaction_count().unwrap(), 0);
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
 
-        let mut keychain_txout_changeset = keychain_txout::ChangeSet {
-            last_revealed: [(descriptor_ids[0], 1), (descriptor_ids[1], 100)].into(),
-            spk_cache: [
+        let tx1 = Arc::new(create_one_inp_one_out_tx(hash!("T1"), 10_000));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(hash!("T2"), 20_000));
+        let changeset = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: [tx1.clone(), tx2.clone()].into(),
+            txouts: [
+                (OutPoint::new(hash!("F"), 0), TxOut::NULL),
+                (OutPoint::new(hash!("F"), 1), TxOut::NULL),
+                (OutPoint::new(hash!("G"), 0), TxOut::NULL),
+            ]
+            .into(),
+            anchors: [
                 (
-                    descriptor_ids[0],
-                    [(0u32, ScriptBuf::from_bytes(vec![1, 2, 3]))].into(),
+                    ConfirmationBlockTime {
+                        block_id: block_id!(1, "B1"),
+                        confirmation_time: 100,
+                    },
+                    tx1.compute_txid(),
                 ),
                 (
-                    descriptor_ids[1],
-                    [
-                        (100u32, ScriptBuf::from_bytes(vec![3])),
-                        (1000u32, ScriptBuf::from_bytes(vec![5, 6, 8])),
-                    ]
-                    .into(),
+                    ConfirmationBlockTime {
+                        block_id: block_id!(2, "B2"),
+                        confirmation_time: 200,
+                    },
+                    tx1.compute_txid(),
                 ),
             ]
             .into(),
+            ..Default::default()
         };
+        store.persist_tx_graph(&changeset).unwrap();
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<BlockId> {
+                anchors: [(block_id!(2, "B2"), tx2.compute_txid())].into(),
+                ..Default::default()
+            })
+            .unwrap();
+        store
+            .persist_local_chain(&local_chain::ChangeSet {
+                blocks: (0..4).map(|ht| (ht, Some(hash!("B")))).collect(),
+            })
+            .unwrap();
 
-        store.create_indexer_tables().unwrap();
-        store.persist_indexer(&keychain_txout_changeset).unwrap();
-
-        let mut changeset = keychain_txout::ChangeSet::default();
-        store.read_indexer(&mut changeset).unwrap();
+        assert_eq!(store.transaction_count().unwrap(), 2);
+        assert_eq!(store.txout_count().unwrap(), 3);
+        assert_eq!(store.anchor_count().unwrap(), 3);
+        assert_eq!(store.block_count().unwrap(), 4);
+    }
 
-        let keychain_txout_changeset_new = keychain_txout::ChangeSet {
-            last_revealed: [(descriptor_ids[0], 2)].into(),
-            spk_cache: [(
-                descriptor_ids[0],
-                [(1u32, ScriptBuf::from_bytes(vec![1, 2, 3]))].into(),
-            )]
-            .into(),
-        };
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_stats_json() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db, "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
 
         store
-            .persist_indexer(&keychain_txout_changeset_new)
+            .persist_local_chain(&local_chain::ChangeSet {
+                blocks: (0..5).map(|ht| (ht, Some(hash!("B")))).collect(),
+            })
+            .unwrap();
+        let descriptor_ids = utils::DESCRIPTORS.map(|d| parse_descriptor(d).descriptor_id());
+        store
+            .persist_indexer(&keychain_txout::ChangeSet {
+                last_revealed: [(descriptor_ids[0], 3), (descriptor_ids[1], 7)].into(),
+                ..Default::default()
+            })
             .unwrap();
 
-        let mut changeset_new = keychain_txout::ChangeSet::default();
-        store.read_indexer(&mut changeset_new).unwrap();
-        keychain_txout_changeset.merge(keychain_txout_changeset_new);
-
-        assert_eq!(changeset_new, keychain_txout_changeset);
+        let json = store.stats_json().unwrap();
+        let stats: stats::StoreStats = serde_json::from_str(&json).unwrap();
+        assert_eq!(stats.blocks.entries, 5);
+        assert_eq!(stats.last_revealed.entries, 2);
+        assert_eq!(stats.txs.entries, 0);
+        assert_eq!(stats, store.stats().unwrap());
     }
 
-    #[cfg(feature = "wallet")]
+    #[cfg(feature = "json")]
     #[test]
-    fn test_persist_wallet() {
+    fn test_export_json() {
         let tmpfile = NamedTempFile::new().unwrap();
         let db = Arc::new(create_db(tmpfile.path()));
         let store = create_test_store(db, "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
 
-        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[0].parse().unwrap();
-        let change_descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[1].parse().unwrap();
+        let tx = Arc::new(create_one_inp_one_out_tx(hash!("T"), 30_000));
+        let txid = tx.compute_txid();
+        let (b0, b2): (BlockHash, BlockHash) = (hash!("B0"), hash!("B2"));
+        let floating_txid: Txid = hash!("F");
+        let descriptor = parse_descriptor(DESCRIPTORS[0]);
+        let descriptor_id = descriptor.descriptor_id();
+        store.persist_network(&Some(Network::Signet)).unwrap();
+        store
+            .persist_keychains(&[(0, descriptor.clone())].into())
+            .unwrap();
+        store
+            .persist_local_chain(&local_chain::ChangeSet {
+                blocks: [(0, Some(b0)), (2, Some(b2))].into(),
+            })
+            .unwrap();
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [tx.clone()].into(),
+                txouts: [(
+                    OutPoint::new(floating_txid, 1),
+                    TxOut {
+                        value: Amount::from_sat(1_234),
+                        script_pubkey: ScriptBuf::from_bytes(vec![0x51]),
+                    },
+                )]
+                .into(),
+                anchors: [(
+                    ConfirmationBlockTime {
+                        block_id: BlockId {
+                            height: 2,
+                            hash: b2,
+                        },
+                        confirmation_time: 1_000,
+                    },
+                    txid,
+                )]
+                .into(),
+                last_seen: [(txid, 100)].into(),
+                first_seen: [(txid, 50)].into(),
+                ..Default::default()
+            })
+            .unwrap();
+        store
+            .persist_indexer(&keychain_txout::ChangeSet {
+                last_revealed: [(descriptor_id, 4)].into(),
+                spk_cache: [(
+                    descriptor_id,
+                    [(0, ScriptBuf::from_bytes(vec![0xab, 0xcd]))].into(),
+                )]
+                .into(),
+            })
+            .unwrap();
 
-        let mut blocks: BTreeMap<u32, Option<BlockHash>> = BTreeMap::new();
-        blocks.insert(0u32, Some(hash!("B")));
-        blocks.insert(1u32, Some(hash!("T")));
-        blocks.insert(2u32, Some(hash!("C")));
-        let local_chain_changeset = local_chain::ChangeSet { blocks };
+        let mut bytes = Vec::new();
+        store
+            .export_json::<ConfirmationBlockTime>(&mut bytes)
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+
+        assert_eq!(json["format_version"], export::EXPORT_FORMAT_VERSION);
+        assert_eq!(json["wallet_name"], "wallet1");
+        assert_eq!(json["network"], "signet");
+        assert_eq!(
+            json["descriptors"],
+            serde_json::json!([{"keychain": 0, "descriptor": descriptor.to_string()}])
+        );
+        assert_eq!(
+            json["blocks"],
+            serde_json::json!([
+                {"height": 0, "hash": b0.to_string()},
+                {"height": 2, "hash": b2.to_string()},
+            ])
+        );
+        assert_eq!(
+            json["transactions"],
+            serde_json::json!([{
+                "txid": txid.to_string(),
+                "raw": bitcoin::consensus::encode::serialize_hex(&*tx),
+            }])
+        );
+        assert_eq!(
+            json["txouts"],
+            serde_json::json!([{
+                "txid": floating_txid.to_string(),
+                "vout": 1,
+                "value": 1_234,
+                "script_pubkey": "51",
+            }])
+        );
+        assert_eq!(
+            json["anchors"],
+            serde_json::json!([{
+                "txid": txid.to_string(),
+                "anchor": {
+                    "block_id": {"height": 2, "hash": b2.to_string()},
+                    "confirmation_time": 1_000,
+                },
+            }])
+        );
+        assert_eq!(
+            json["last_seen"],
+            serde_json::json!([{"txid": txid.to_string(), "timestamp": 100}])
+        );
+        assert_eq!(
+            json["first_seen"],
+            serde_json::json!([{"txid": txid.to_string(), "timestamp": 50}])
+        );
+        assert_eq!(json["last_evicted"], serde_json::json!([]));
+        assert_eq!(
+            json["last_revealed"],
+            serde_json::json!([{"descriptor_id": descriptor_id.to_string(), "index": 4}])
+        );
+        assert_eq!(
+            json["spks"],
+            serde_json::json!([{
+                "descriptor_id": descriptor_id.to_string(),
+                "index": 0,
+                "script_pubkey": "abcd",
+            }])
+        );
+    }
+
+    #[cfg(all(feature = "json", feature = "wallet"))]
+    #[test]
+    fn test_import_json() {
+        let descriptor = parse_descriptor(DESCRIPTORS[0]);
+        let change_descriptor = parse_descriptor(DESCRIPTORS[1]);
+        let descriptor_id = descriptor.descriptor_id();
+        let tx1 = Arc::new(create_one_inp_one_out_tx(hash!("T1"), 10_000));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(hash!("T2"), 20_000));
+        let changeset = ChangeSet {
+            descriptor: Some(descriptor.clone()),
+            change_descriptor: Some(change_descriptor),
+            network: Some(Network::Signet),
+            local_chain: local_chain::ChangeSet {
+                blocks: [(0, Some(hash!("B0"))), (3, Some(hash!("B3")))].into(),
+            },
+            tx_graph: tx_graph::ChangeSet {
+                txs: [tx1.clone(), tx2.clone()].into(),
+                txouts: [(OutPoint::new(hash!("F"), 0), TxOut::NULL)].into(),
+                anchors: [(
+                    ConfirmationBlockTime {
+                        block_id: block_id!(3, "B3"),
+                        confirmation_time: 300,
+                    },
+                    tx1.compute_txid(),
+                )]
+                .into(),
+                last_seen: [(tx2.compute_txid(), 400)].into(),
+                first_seen: [(tx2.compute_txid(), 350)].into(),
+                last_evicted: [(tx2.compute_txid(), 500)].into(),
+            },
+            indexer: keychain_txout::ChangeSet {
+                last_revealed: [(descriptor_id, 2)].into(),
+                spk_cache: [(
+                    descriptor_id,
+                    (0..3)
+                        .map(|index| (index, ScriptBuf::from_bytes(vec![index as u8])))
+                        .collect(),
+                )]
+                .into(),
+            },
+        };
+        let tmpfile = NamedTempFile::new().unwrap();
+        let store = create_test_store(Arc::new(create_db(tmpfile.path())), "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        store.persist_wallet(&changeset).unwrap();
+        let mut export = Vec::new();
+        store
+            .export_json::<ConfirmationBlockTime>(&mut export)
+            .unwrap();
+
+        // round trip into a fresh database
+        let tmpfile = NamedTempFile::new().unwrap();
+        let imported = create_test_store(Arc::new(create_db(tmpfile.path())), "imported");
+        let stats = imported
+            .import_json::<ConfirmationBlockTime>(export.as_slice())
+            .unwrap();
+        assert_eq!(stats.wallet_name, "wallet1");
+        assert_eq!(stats.summary.txs.inserted, 2);
+        assert_eq!(stats.summary.spks.inserted, 3);
+        let mut changeset_read = ChangeSet::default();
+        imported.read_wallet(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read, changeset);
+
+        // importing again changes nothing
+        imported
+            .import_json::<ConfirmationBlockTime>(export.as_slice())
+            .unwrap();
+        let mut changeset_read = ChangeSet::default();
+        imported.read_wallet(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read, changeset);
+
+        // the network of an existing wallet must match
+        let other = imported.with_wallet_name("other".to_string()).unwrap();
+        other.create_tables::<ConfirmationBlockTime>().unwrap();
+        other.persist_network(&Some(Network::Regtest)).unwrap();
+        match other.import_json::<ConfirmationBlockTime>(export.as_slice()) {
+            Err(StoreError::NetworkMismatch { ours, theirs }) => {
+                assert_eq!(ours, Network::Regtest);
+                assert_eq!(theirs, Network::Signet);
+            }
+            _ => panic!("should give error since networks differ"),
+        }
+        assert_eq!(other.transaction_count().unwrap(), 0);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_import_json_malformed() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let store = create_test_store(Arc::new(create_db(tmpfile.path())), "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [Arc::new(create_one_inp_one_out_tx(hash!("T"), 10_000))].into(),
+                ..Default::default()
+            })
+            .unwrap();
+        let mut export = Vec::new();
+        store
+            .export_json::<ConfirmationBlockTime>(&mut export)
+            .unwrap();
+        let export: serde_json::Value = serde_json::from_slice(&export).unwrap();
+        let import = |json: &[u8]| {
+            let tmpfile = NamedTempFile::new().unwrap();
+            let store = create_test_store(Arc::new(create_db(tmpfile.path())), "wallet1");
+            store.import_json::<ConfirmationBlockTime>(json)
+        };
 
-        let tx1 = Arc::new(create_one_inp_one_out_tx(
-            Txid::from_byte_array([0; 32]),
-            30_000,
+        assert!(matches!(
+            import(b"{\"format_version\": 1, "),
+            Err(StoreError::Json(_))
+        ));
+        assert!(matches!(
+            import(b"{\"blocks\": []}"),
+            Err(StoreError::InvalidExport(_))
         ));
-        let tx2 = Arc::new(create_one_inp_one_out_tx(tx1.compute_txid(), 20_000));
 
-        let block_id = block_id!(1, "BDK");
+        let mut newer = export.clone();
+        newer["format_version"] = serde_json::json!(export::EXPORT_FORMAT_VERSION + 1);
+        assert!(matches!(
+            import(&serde_json::to_vec(&newer).unwrap()),
+            Err(StoreError::UnsupportedExportVersion(version))
+                if version == u64::from(export::EXPORT_FORMAT_VERSION) + 1
+        ));
 
-        let conf_anchor: ConfirmationBlockTime = ConfirmationBlockTime {
-            block_id,
-            confirmation_time: 123,
-        };
+        let mut missing_field = export.clone();
+        missing_field.as_object_mut().unwrap().remove("txouts");
+        assert!(matches!(
+            import(&serde_json::to_vec(&missing_field).unwrap()),
+            Err(StoreError::Json(_))
+        ));
 
-        let tx_graph_changeset = tx_graph::ChangeSet::<ConfirmationBlockTime> {
-            txs: [tx1.clone()].into(),
-            txouts: [].into(),
-            anchors: [(conf_anchor, tx1.compute_txid())].into(),
-            last_seen: [(tx1.compute_txid(), 100)].into(),
-            first_seen: [(tx1.compute_txid(), 80)].into(),
-            last_evicted: [(tx1.compute_txid(), 150)].into(),
-        };
+        let mut wrong_txid = export.clone();
+        wrong_txid["transactions"][0]["txid"] =
+            serde_json::json!(Txid::from_byte_array([1; 32]).to_string());
+        assert!(matches!(
+            import(&serde_json::to_vec(&wrong_txid).unwrap()),
+            Err(StoreError::InvalidExport(_))
+        ));
 
-        let keychain_txout_changeset = keychain_txout::ChangeSet {
-            last_revealed: [
-                (descriptor.descriptor_id(), 12),
-                (change_descriptor.descriptor_id(), 10),
-            ]
-            .into(),
-            spk_cache: [
-                (
-                    descriptor.descriptor_id(),
-                    [(0u32, ScriptBuf::from_bytes(vec![245, 123, 112]))].into(),
-                ),
-                (
-                    change_descriptor.descriptor_id(),
-                    [
-                        (100u32, ScriptBuf::from_bytes(vec![145, 234, 98])),
-                        (1000u32, ScriptBuf::from_bytes(vec![5, 6, 8])),
-                    ]
-                    .into(),
-                ),
-            ]
-            .into(),
-        };
+        let mut bad_hex = export;
+        bad_hex["transactions"][0]["raw"] = serde_json::json!("zz");
+        assert!(matches!(
+            import(&serde_json::to_vec(&bad_hex).unwrap()),
+            Err(StoreError::InvalidExport(_))
+        ));
+    }
 
-        let mut changeset = ChangeSet {
+    #[cfg(feature = "wallet")]
+    #[test]
+    fn test_changeset_cbor() {
+        let descriptor = parse_descriptor(DESCRIPTORS[0]);
+        let change_descriptor = parse_descriptor(DESCRIPTORS[1]);
+        let descriptor_id = descriptor.descriptor_id();
+        let tx1 = Arc::new(create_one_inp_one_out_tx(hash!("T1"), 10_000));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(hash!("T2"), 20_000));
+        let changeset = ChangeSet {
             descriptor: Some(descriptor.clone()),
             change_descriptor: Some(change_descriptor.clone()),
-            network: Some(Network::Bitcoin),
-            local_chain: local_chain_changeset,
-            tx_graph: tx_graph_changeset,
-            indexer: keychain_txout_changeset,
+            network: Some(Network::Signet),
+            local_chain: local_chain::ChangeSet {
+                blocks: [(0, Some(hash!("B0"))), (3, Some(hash!("B3")))].into(),
+            },
+            tx_graph: tx_graph::ChangeSet {
+                txs: [tx1.clone()].into(),
+                anchors: [(
+                    ConfirmationBlockTime {
+                        block_id: block_id!(3, "B3"),
+                        confirmation_time: 300,
+                    },
+                    tx1.compute_txid(),
+                )]
+                .into(),
+                last_seen: [(tx1.compute_txid(), 400)].into(),
+                ..Default::default()
+            },
+            indexer: keychain_txout::ChangeSet {
+                last_revealed: [(descriptor_id, 2)].into(),
+                spk_cache: [(
+                    descriptor_id,
+                    (0..3)
+                        .map(|index| (index, ScriptBuf::from_bytes(vec![index as u8])))
+                        .collect(),
+                )]
+                .into(),
+            },
         };
-
+        let tmpfile = NamedTempFile::new().unwrap();
+        let store = create_test_store(Arc::new(create_db(tmpfile.path())), "wallet1");
         store.create_tables::<ConfirmationBlockTime>().unwrap();
-
         store.persist_wallet(&changeset).unwrap();
-        let mut changeset_read = ChangeSet::default();
-        store.read_wallet(&mut changeset_read).unwrap();
+        let mut backup = Vec::new();
+        store.export_changeset_cbor(&mut backup).unwrap();
+        assert_eq!(backup[..4], cbor_backup::CBOR_BACKUP_MAGIC);
+        assert_eq!(backup[4], cbor_backup::CBOR_BACKUP_VERSION);
 
-        assert_eq!(changeset, changeset_read);
+        // round trip into a fresh database
+        let tmpfile = NamedTempFile::new().unwrap();
+        let imported = create_test_store(Arc::new(create_db(tmpfile.path())), "wallet1");
+        let summary = imported.import_changeset_cbor(backup.as_slice()).unwrap();
+        assert_eq!(summary.txs.inserted, 1);
+        assert_eq!(summary.spks.inserted, 3);
+        let mut changeset_read = ChangeSet::default();
+        imported.read_wallet(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read, changeset);
 
-        let mut blocks: BTreeMap<u32, Option<BlockHash>> = BTreeMap::new();
-        blocks.insert(4u32, Some(hash!("RE")));
-        blocks.insert(5u32, Some(hash!("DB")));
-        let local_chain_changeset = local_chain::ChangeSet { blocks };
+        // importing into a wallet with data merges the backup into it
+        let newer = ChangeSet {
+            descriptor: Some(descriptor.clone()),
+            change_descriptor: Some(change_descriptor),
+            network: Some(Network::Signet),
+            local_chain: local_chain::ChangeSet {
+                blocks: [(5, Some(hash!("B5")))].into(),
+            },
+            tx_graph: tx_graph::ChangeSet {
+                txs: [tx1.clone(), tx2.clone()].into(),
+                last_seen: [(tx1.compute_txid(), 300)].into(),
+                ..Default::default()
+            },
+            indexer: keychain_txout::ChangeSet {
+                last_revealed: [(descriptor_id, 5)].into(),
+                ..Default::default()
+            },
+        };
+        let tmpfile = NamedTempFile::new().unwrap();
+        let merged = create_test_store(Arc::new(create_db(tmpfile.path())), "wallet1");
+        merged.create_tables::<ConfirmationBlockTime>().unwrap();
+        merged.persist_wallet(&newer).unwrap();
+        merged.import_changeset_cbor(backup.as_slice()).unwrap();
+        let mut expected = changeset.clone();
+        expected.merge(newer);
+        let mut changeset_read = ChangeSet::default();
+        merged.read_wallet(&mut changeset_read).unwrap();
+        assert_eq!(changeset_read, expected);
+        assert_eq!(changeset_read.indexer.last_revealed[&descriptor_id], 5);
+        assert_eq!(changeset_read.tx_graph.last_seen[&tx1.compute_txid()], 400);
+
+        // the network of an existing wallet must match and nothing is written otherwise
+        let other = merged.with_wallet_name("other".to_string()).unwrap();
+        other.create_tables::<ConfirmationBlockTime>().unwrap();
+        other
+            .persist_wallet(&ChangeSet {
+                network: Some(Network::Bitcoin),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(matches!(
+            other.import_changeset_cbor(backup.as_slice()),
+            Err(StoreError::NetworkMismatch {
+                ours: Network::Bitcoin,
+                theirs: Network::Signet
+            })
+        ));
+        let mut changeset_read = ChangeSet::default();
+        other.read_wallet(&mut changeset_read).unwrap();
+        assert!(changeset_read.tx_graph.txs.is_empty());
+    }
 
-        let block_id = block_id!(2, "Bitcoin");
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_changeset_cbor_malformed() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let store = create_test_store(Arc::new(create_db(tmpfile.path())), "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [Arc::new(create_one_inp_one_out_tx(hash!("T"), 10_000))].into(),
+                ..Default::default()
+            })
+            .unwrap();
+        let mut backup = Vec::new();
+        store.export_changeset_cbor(&mut backup).unwrap();
 
-        let conf_anchor: ConfirmationBlockTime = ConfirmationBlockTime {
-            block_id,
-            confirmation_time: 214,
-        };
+        let tmpfile = NamedTempFile::new().unwrap();
+        let imported = create_test_store(Arc::new(create_db(tmpfile.path())), "wallet1");
+        imported.create_tables::<ConfirmationBlockTime>().unwrap();
+        assert!(matches!(
+            imported.import_changeset_cbor(&backup[..3]),
+            Err(StoreError::Io(_))
+        ));
+        assert!(matches!(
+            imported.import_changeset_cbor(&backup[..backup.len() - 10]),
+            Err(StoreError::Deser(_))
+        ));
+        let mut wrong_magic = backup.clone();
+        wrong_magic[0] = b'X';
+        assert!(matches!(
+            imported.import_changeset_cbor(wrong_magic.as_slice()),
+            Err(StoreError::InvalidBackupMagic)
+        ));
+        let mut newer = backup.clone();
+        newer[4] = cbor_backup::CBOR_BACKUP_VERSION + 1;
+        assert!(matches!(
+            imported.import_changeset_cbor(newer.as_slice()),
+            Err(StoreError::UnsupportedBackupVersion(version))
+                if version == cbor_backup::CBOR_BACKUP_VERSION + 1
+        ));
 
-        let tx_graph_changeset = tx_graph::ChangeSet::<ConfirmationBlockTime> {
-            txs: [tx2.clone()].into(),
-            txouts: [].into(),
-            anchors: [(conf_anchor, tx2.compute_txid())].into(),
-            last_seen: [(tx2.compute_txid(), 200)].into(),
-            first_seen: [(tx2.compute_txid(), 160)].into(),
-            last_evicted: [(tx2.compute_txid(), 300)].into(),
-        };
+        // failed imports write nothing
+        let mut tx_graph_read = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        imported.read_tx_graph(&mut tx_graph_read).unwrap();
+        assert!(tx_graph_read.txs.is_empty());
 
-        let keychain_txout_changeset = keychain_txout::ChangeSet {
-            last_revealed: [(descriptor.descriptor_id(), 14)].into(),
-            spk_cache: [(
-                change_descriptor.descriptor_id(),
-                [
-                    (102u32, ScriptBuf::from_bytes(vec![8, 45, 78])),
-                    (1001u32, ScriptBuf::from_bytes(vec![29, 56, 47])),
-                ]
-                .into(),
-            )]
-            .into(),
-        };
+        imported.import_changeset_cbor(backup.as_slice()).unwrap();
+        let mut tx_graph_read = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        imported.read_tx_graph(&mut tx_graph_read).unwrap();
+        assert_eq!(tx_graph_read.txs.len(), 1);
+    }
 
-        let changeset_new = ChangeSet {
-            descriptor: Some(descriptor),
-            change_descriptor: Some(change_descriptor),
-            network: Some(Network::Bitcoin),
-            local_chain: local_chain_changeset,
-            tx_graph: tx_graph_changeset,
-            indexer: keychain_txout_changeset,
-        };
+    #[test]
+    fn test_meta() {
+        use crate::meta::RESERVED_META_PREFIX;
 
-        store.persist_wallet(&changeset_new).unwrap();
-        let mut changeset_read_new = ChangeSet::default();
-        store.read_wallet(&mut changeset_read_new).unwrap();
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1");
+        let other_store = create_test_store(db, "wallet2");
+
+        // nothing is set before the table exists
+        assert_eq!(store.get_meta("name").unwrap(), None);
+        assert_eq!(store.iter_meta().unwrap().count(), 0);
+
+        store.put_meta("name", b"savings").unwrap();
+        store.put_meta("color", &[0xff, 0, 0]).unwrap();
+        other_store.put_meta("name", b"spending").unwrap();
+        assert_eq!(store.get_meta("name").unwrap(), Some(b"savings".to_vec()));
+        assert_eq!(
+            other_store.get_meta("name").unwrap(),
+            Some(b"spending".to_vec())
+        );
+
+        // overwrite
+        store.put_meta("name", b"cold storage").unwrap();
+        assert_eq!(
+            store.get_meta("name").unwrap(),
+            Some(b"cold storage".to_vec())
+        );
+
+        // reserved keys cannot be written or deleted, and are not iterated over
+        let reserved = format!("{RESERVED_META_PREFIX}birthday");
+        assert!(matches!(
+            store.put_meta(&reserved, b"0"),
+            Err(StoreError::ReservedMetaKey(key)) if key == reserved
+        ));
+        assert!(matches!(
+            store.delete_meta(&reserved),
+            Err(StoreError::ReservedMetaKey(_))
+        ));
+        let write_tx = store.db.begin_write().unwrap();
+        store.put_meta_in(&write_tx, &reserved, b"0").unwrap();
+        write_tx.commit().unwrap();
+        assert_eq!(store.get_meta(&reserved).unwrap(), Some(b"0".to_vec()));
+        assert_eq!(
+            store
+                .iter_meta()
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap(),
+            [
+                ("color".to_string(), vec![0xff, 0, 0]),
+                ("name".to_string(), b"cold storage".to_vec())
+            ]
+        );
 
-        changeset.merge(changeset_new);
+        // delete
+        assert!(store.delete_meta("color").unwrap());
+        assert!(!store.delete_meta("color").unwrap());
+        assert_eq!(store.get_meta("color").unwrap(), None);
+        assert_eq!(store.iter_meta().unwrap().count(), 1);
+    }
 
-        assert_eq!(changeset, changeset_read_new);
+    #[test]
+    fn test_birthday() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1");
+        assert_eq!(store.birthday().unwrap(), None);
+
+        store.set_birthday(1_700_000_000, Some(800_000)).unwrap();
+        assert_eq!(
+            store.birthday().unwrap(),
+            Some((1_700_000_000, Some(800_000)))
+        );
+        // setting the same birthday again is a no-op, a different one is rejected
+        store.set_birthday(1_700_000_000, Some(800_000)).unwrap();
+        assert!(matches!(
+            store.set_birthday(1_700_000_000, None),
+            Err(StoreError::BirthdayImmutable)
+        ));
+        assert!(matches!(
+            store.set_birthday(1_800_000_000, Some(800_000)),
+            Err(StoreError::BirthdayImmutable)
+        ));
+        assert_eq!(
+            store.birthday().unwrap(),
+            Some((1_700_000_000, Some(800_000)))
+        );
+        // it is not part of the user metadata
+        assert_eq!(store.iter_meta().unwrap().count(), 0);
+
+        let other_store = create_test_store(db, "wallet2");
+        other_store.set_birthday(1_600_000_000, None).unwrap();
+        assert_eq!(other_store.birthday().unwrap(), Some((1_600_000_000, None)));
     }
 
     #[cfg(feature = "wallet")]
     #[test]
-    fn test_persist_multi_wallet() {
+    fn test_birthday_set_on_create_wallet() {
+        use bdk_wallet::Wallet;
+
         let tmpfile = NamedTempFile::new().unwrap();
         let db = Arc::new(create_db(tmpfile.path()));
+        let mut store = create_test_store(db.clone(), "wallet1");
+        let before = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut wallet = Wallet::create(DESCRIPTORS[0], DESCRIPTORS[1])
+            .network(Network::Signet)
+            .create_wallet(&mut store)
+            .unwrap();
+        let (unix_time, height) = store.birthday().unwrap().unwrap();
+        assert!(unix_time >= before);
+        assert_eq!(height, None);
+
+        // loading the wallet keeps the birthday
+        wallet.reveal_next_address(bdk_wallet::KeychainKind::External);
+        wallet.persist(&mut store).unwrap();
+        let mut store = create_test_store(db.clone(), "wallet1");
+        Wallet::load()
+            .load_wallet(&mut store)
+            .unwrap()
+            .expect("wallet must exist");
+        assert_eq!(store.birthday().unwrap(), Some((unix_time, None)));
+
+        // a birthday set before creating the wallet is kept
+        let mut store = create_test_store(db, "wallet2");
+        store.set_birthday(1_600_000_000, Some(100)).unwrap();
+        Wallet::create(DESCRIPTORS[2], DESCRIPTORS[3])
+            .network(Network::Signet)
+            .create_wallet(&mut store)
+            .unwrap();
+        assert_eq!(store.birthday().unwrap(), Some((1_600_000_000, Some(100))));
+    }
 
-        let store1 = create_test_store(db.clone(), "wallet1");
+    #[test]
+    fn test_last_persist_at() {
+        use std::sync::atomic::{AtomicU64, Ordering};
 
-        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[0].parse().unwrap();
-        let change_descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[1].parse().unwrap();
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let time = Arc::new(AtomicU64::new(1_000));
+        let clock_time = time.clone();
+        let store = create_test_store(db, "wallet1")
+            .with_clock(Box::new(move || clock_time.load(Ordering::SeqCst)));
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        assert_eq!(store.last_persist_at().unwrap(), None);
 
-        let changeset1 = ChangeSet {
-            descriptor: Some(descriptor.clone()),
-            change_descriptor: Some(change_descriptor.clone()),
-            network: Some(Network::Bitcoin),
-            ..ChangeSet::default()
-        };
+        let tx = Arc::new(create_one_inp_one_out_tx(hash!("T"), 10_000));
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [tx.clone()].into(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(store.last_persist_at().unwrap(), Some(1_000));
 
-        store1.create_tables::<ConfirmationBlockTime>().unwrap();
-        store1.persist_wallet(&changeset1).unwrap();
+        // reads do not update it
+        time.store(2_000, Ordering::SeqCst);
+        let mut changeset = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        store.read_tx_graph(&mut changeset).unwrap();
+        store.get_tx(tx.compute_txid()).unwrap();
+        assert_eq!(store.last_persist_at().unwrap(), Some(1_000));
+
+        // nor do failed persists
+        assert!(
+            store
+                .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                    last_seen: [(hash!("missing"), 100)].into(),
+                    ..Default::default()
+                })
+                .is_err()
+        );
+        assert_eq!(store.last_persist_at().unwrap(), Some(1_000));
+
+        #[cfg(feature = "wallet")]
+        {
+            store.persist_wallet(&ChangeSet::default()).unwrap();
+            assert_eq!(store.last_persist_at().unwrap(), Some(2_000));
+        }
+    }
 
-        let store2 = create_test_store(db, "wallet2");
+    #[test]
+    fn test_redb_format_upgrade_required() {
+        // redb returns this error when opening a file written by redb 1.x
+        let err = StoreError::from(redb::DatabaseError::UpgradeRequired(1));
+        assert!(matches!(err, StoreError::RedbFormatUpgradeRequired(1)));
+        assert!(err.to_string().contains("manual upgrade"));
+
+        let err = StoreError::from(redb::DatabaseError::DatabaseAlreadyOpen);
+        assert!(matches!(
+            err,
+            StoreError::RedbDatabase(redb::DatabaseError::DatabaseAlreadyOpen)
+        ));
+    }
 
-        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[2].parse().unwrap();
-        let change_descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[3].parse().unwrap();
+    #[cfg(feature = "wallet")]
+    #[test]
+    fn test_from_backend() {
+        use crate::backend::MemoryBackend;
 
-        let changeset2 = ChangeSet {
-            descriptor: Some(descriptor.clone()),
-            change_descriptor: Some(change_descriptor.clone()),
-            network: Some(Network::Bitcoin),
-            ..ChangeSet::default()
+        let backend = MemoryBackend::new();
+        let store = Store::from_backend(backend.clone(), "wallet1")
+            .unwrap()
+            .with_clock(Box::new(|| 1_700_000_000));
+        let changeset = ChangeSet {
+            descriptor: Some(parse_descriptor(DESCRIPTORS[0])),
+            network: Some(Network::Signet),
+            local_chain: local_chain::ChangeSet {
+                blocks: [(0, Some(hash!("A")))].into(),
+            },
+            tx_graph: tx_graph::ChangeSet {
+                txs: [Arc::new(create_one_inp_one_out_tx(hash!("T"), 30_000))].into(),
+                ..Default::default()
+            },
+            ..Default::default()
         };
+        store.persist_wallet(&changeset).unwrap();
+        drop(store);
+
+        // the bytes of the database are restored in a new backend
+        let bytes = backend.to_bytes();
+        assert!(!bytes.is_empty());
+        let store = Store::from_backend(MemoryBackend::from_bytes(bytes), "wallet1").unwrap();
+        let mut read = ChangeSet::default();
+        store.read_wallet(&mut read).unwrap();
+        assert_eq!(read, changeset);
+        assert_eq!(store.last_persist_at().unwrap(), Some(1_700_000_000));
+    }
 
-        store2.create_tables::<ConfirmationBlockTime>().unwrap();
-        store2.persist_wallet(&changeset2).unwrap();
-
-        let mut changeset_read = ChangeSet::default();
-        store1.read_wallet(&mut changeset_read).unwrap();
-        assert_eq!(changeset_read, changeset1);
-
-        let mut changeset_read = ChangeSet::default();
-        store2.read_wallet(&mut changeset_read).unwrap();
-        assert_eq!(changeset_read, changeset2);
+    #[test]
+    fn test_from_path_str() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallets.redb");
+        let path = path.to_str().unwrap();
+
+        let store = Store::from_path_str(path, "wallet1").unwrap();
+        store.persist_network(&Some(Network::Signet)).unwrap();
+        drop(store);
+
+        // the existing file is opened
+        let store = Store::from_path_str(path, "wallet1").unwrap();
+        let mut network = None;
+        store.read_network(&mut network).unwrap();
+        assert_eq!(network, Some(Network::Signet));
+        drop(store);
+
+        let missing_dir = dir.path().join("missing").join("wallets.redb");
+        assert!(matches!(
+            Store::from_path_str(missing_dir.to_str().unwrap(), "wallet1"),
+            Err(StoreError::Io(_))
+        ));
     }
 
-    #[cfg(feature = "wallet")]
+    #[cfg(feature = "serde")]
     #[test]
-    fn wallets_missing_descriptors() {
-        // to increase branch coverage for if-let statements
-        // in persist_wallet functions
+    fn test_sync_state() {
+        use crate::sync_state::SyncState;
+
         let tmpfile = NamedTempFile::new().unwrap();
         let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db, "wallet1");
+        assert_eq!(store.sync_state("electrum").unwrap(), None);
 
-        let store1 = create_test_store(db.clone(), "wallet1");
-
-        let changeset1 = ChangeSet {
-            network: Some(Network::Bitcoin),
-            ..ChangeSet::default()
+        let electrum = SyncState {
+            source: "electrum".to_string(),
+            last_height: Some(100),
+            last_block_hash: Some(hash!("B100")),
+            last_time: 1_000,
         };
+        let esplora = SyncState {
+            source: "esplora".to_string(),
+            last_height: None,
+            last_block_hash: None,
+            last_time: 2_000,
+        };
+        store.put_sync_state(&electrum).unwrap();
+        store.put_sync_state(&esplora).unwrap();
+        assert_eq!(store.sync_state("electrum").unwrap(), Some(electrum));
+        assert_eq!(store.sync_state("esplora").unwrap(), Some(esplora.clone()));
+
+        // overwriting a source leaves the other one untouched
+        let electrum = SyncState {
+            source: "electrum".to_string(),
+            last_height: Some(105),
+            last_block_hash: Some(hash!("B105")),
+            last_time: 3_000,
+        };
+        store.put_sync_state(&electrum).unwrap();
+        assert_eq!(store.sync_state("electrum").unwrap(), Some(electrum));
+        assert_eq!(store.sync_state("esplora").unwrap(), Some(esplora));
+        assert_eq!(store.iter_meta().unwrap().count(), 0);
+    }
 
-        store1.create_tables::<ConfirmationBlockTime>().unwrap();
-        store1.persist_wallet(&changeset1).unwrap();
-
-        let store2 = create_test_store(db, "wallet2");
-
-        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTORS[2].parse().unwrap();
+    #[test]
+    fn test_backup_to() {
+        let tmpfile = NamedTempFile::new().unwrap();
+        let db = Arc::new(create_db(tmpfile.path()));
+        let store = create_test_store(db.clone(), "wallet1");
+        store.create_tables::<ConfirmationBlockTime>().unwrap();
+        let other_store = create_test_store(db, "wallet2");
+        other_store
+            .create_tables::<ConfirmationBlockTime>()
+            .unwrap();
 
-        let changeset2 = ChangeSet {
-            descriptor: Some(descriptor.clone()),
-            ..ChangeSet::default()
+        let tx1 = Arc::new(create_one_inp_one_out_tx(hash!("T1"), 10_000));
+        let tx2 = Arc::new(create_one_inp_one_out_tx(hash!("T2"), 20_000));
+        let tx_graph_changeset = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: [tx1.clone()].into(),
+            txouts: [(OutPoint::new(hash!("F"), 0), TxOut::NULL)].into(),
+            anchors: [(
+                ConfirmationBlockTime {
+                    block_id: block_id!(1, "B1"),
+                    confirmation_time: 100,
+                },
+                tx1.compute_txid(),
+            )]
+            .into(),
+            last_seen: [(tx1.compute_txid(), 100)].into(),
+            ..Default::default()
         };
+        let local_chain_changeset = local_chain::ChangeSet {
+            blocks: [(0, Some(hash!("B0"))), (1, Some(hash!("B1")))].into(),
+        };
+        let keychains: BTreeMap<u64, Descriptor<DescriptorPublicKey>> =
+            [(0, parse_descriptor(DESCRIPTORS[0]))].into();
+        store.persist_network(&Some(Network::Signet)).unwrap();
+        store.persist_keychains(&keychains).unwrap();
+        store.persist_tx_graph(&tx_graph_changeset).unwrap();
+        store.persist_local_chain(&local_chain_changeset).unwrap();
+        other_store
+            .persist_network(&Some(Network::Bitcoin))
+            .unwrap();
 
-        store2.create_tables::<ConfirmationBlockTime>().unwrap();
-        store2.persist_wallet(&changeset2).unwrap();
-
-        let mut changeset_read = ChangeSet::default();
-        store1.read_wallet(&mut changeset_read).unwrap();
-        assert_eq!(changeset_read, changeset1);
+        // mutations made after the snapshot began are not part of the backup
+        let read_tx = store.db.begin_read().unwrap();
+        store
+            .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [tx2].into(),
+                ..Default::default()
+            })
+            .unwrap();
+        store
+            .persist_local_chain(&local_chain::ChangeSet {
+                blocks: [(1, None), (2, Some(hash!("B2")))].into(),
+            })
+            .unwrap();
 
-        let mut changeset_read = ChangeSet::default();
-        store2.read_wallet(&mut changeset_read).unwrap();
-        assert_eq!(changeset_read, changeset2);
+        let backup_file = NamedTempFile::new().unwrap();
+        store.backup_snapshot(&read_tx, backup_file.path()).unwrap();
+        drop(read_tx);
+
+        let backup = Store::attach(
+            Arc::new(create_db(backup_file.path())),
+            "wallet1".to_string(),
+        )
+        .unwrap();
+        let mut network = None;
+        backup.read_network(&mut network).unwrap();
+        assert_eq!(network, Some(Network::Signet));
+        let mut keychains_read = BTreeMap::new();
+        backup.read_keychains(&mut keychains_read).unwrap();
+        assert_eq!(keychains_read, keychains);
+        let mut tx_graph_read = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        backup.read_tx_graph(&mut tx_graph_read).unwrap();
+        assert_eq!(tx_graph_read, tx_graph_changeset);
+        let mut local_chain_read = local_chain::ChangeSet::default();
+        backup.read_local_chain(&mut local_chain_read).unwrap();
+        assert_eq!(local_chain_read, local_chain_changeset);
+
+        // other wallets are not copied
+        let other_backup = backup.with_wallet_name("wallet2".to_string()).unwrap();
+        let mut network = None;
+        other_backup.read_network(&mut network).unwrap();
+        assert_eq!(network, None);
+        assert_eq!(other_backup.transaction_count().unwrap(), 0);
+
+        // backup_to takes its own snapshot
+        let backup_file = NamedTempFile::new().unwrap();
+        store.backup_to(backup_file.path()).unwrap();
+        let backup = Store::attach(
+            Arc::new(create_db(backup_file.path())),
+            "wallet1".to_string(),
+        )
+        .unwrap();
+        assert_eq!(backup.transaction_count().unwrap(), 2);
+        assert_eq!(backup.chain_tip().unwrap(), Some(block_id!(2, "B2")));
     }
 }
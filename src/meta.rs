@@ -0,0 +1,261 @@
+#![warn(missing_docs)]
+//! This module contains the metadata of a wallet, a key-value map for the settings applications
+//! keep next to the wallet (display name, color, preferred fee rate...), see [`Store::put_meta`].
+//!
+//! Keys starting with [`RESERVED_META_PREFIX`] are used by this crate and cannot be written or
+//! deleted through [`Store::put_meta`] and [`Store::delete_meta`]. The birthday of the wallet (see
+//! [`Store::set_birthday`]), the time of its last persist (see [`Store::last_persist_at`]), its
+//! sync states (see [`Store::put_sync_state`]), its anchor type (see
+//! [`Store::with_anchor_type_check`]) and the codec of its transactions (see
+//! [`Store::with_tx_codec`]) are stored under them.
+use crate::anchor_trait::AnchorWithMetaData;
+use crate::error::StoreError;
+use crate::{Store, open_table_if_exists};
+use redb::{Durability, Range, ReadableTable, TableDefinition, WriteTransaction};
+use std::any::type_name;
+
+/// Prefix of the metadata keys reserved for this crate.
+pub const RESERVED_META_PREFIX: &str = "bdk_redb:";
+
+// Key of the birthday of the wallet, stored as the big-endian unix time followed by the big-endian
+// height if any.
+const BIRTHDAY_KEY: &str = "bdk_redb:birthday";
+
+// Key of the anchor type of the wallet, stored as its AnchorWithMetaData::TAG followed by its
+// type name.
+const ANCHOR_TYPE_KEY: &str = "bdk_redb:anchor_type";
+
+// Key of the unix time of the last persist, stored in big-endian.
+const LAST_PERSIST_AT_KEY: &str = "bdk_redb:last_persist_at";
+
+// Range over the (key, value) entries of the meta table.
+type MetaRange = Range<'static, &'static str, Vec<u8>>;
+
+/// Iterator over the (key, value) metadata entries of a wallet, in ascending order of key. Entries
+/// under [`RESERVED_META_PREFIX`] are skipped.
+///
+/// It owns the read transaction it was created from, so it sees the metadata as it was when
+/// [`Store::iter_meta`] was called, and entries are only read as it advances.
+pub struct MetaIter {
+    // None if the meta table does not exist.
+    range: Option<MetaRange>,
+}
+
+impl Iterator for MetaIter {
+    type Item = Result<(String, Vec<u8>), StoreError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for entry in self.range.as_mut()? {
+            match entry {
+                Ok((key, value)) => {
+                    let key = key.value();
+                    if !key.starts_with(RESERVED_META_PREFIX) {
+                        return Some(Ok((key.to_string(), value.value())));
+                    }
+                }
+                Err(err) => return Some(Err(err.into())),
+            }
+        }
+        None
+    }
+}
+
+impl Store {
+    // This table stores (key, value) pairs of metadata.
+    pub(crate) fn meta_table_defn(&self) -> TableDefinition<&'static str, Vec<u8>> {
+        TableDefinition::new(&self.meta_table_name)
+    }
+
+    /// This function sets the metadata entry `key` of the wallet to `value`, overwriting any
+    /// previous value. The `<wallet_name>_meta` table is created if needed, and is copied along
+    /// with the other tables of the wallet by [`backup_to`]. Values are stored as is, even if an
+    /// encryption key is set.
+    ///
+    /// [`StoreError::ReservedMetaKey`] is returned if `key` starts with [`RESERVED_META_PREFIX`].
+    ///
+    /// [`backup_to`]: Self::backup_to
+    pub fn put_meta(&self, key: &str, value: &[u8]) -> Result<(), StoreError> {
+        check_meta_key(key)?;
+        let write_tx = self.begin_write(Durability::Immediate)?;
+        self.put_meta_in(&write_tx, key, value)?;
+        self.commit(write_tx)?;
+        Ok(())
+    }
+
+    /// This function returns the value of the metadata entry `key` of the wallet, or `None` if it
+    /// is not set. Keys under [`RESERVED_META_PREFIX`] can be read.
+    pub fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let Some(table) = open_table_if_exists(&read_tx, self.meta_table_defn())? else {
+            return Ok(None);
+        };
+        Ok(table.get(key)?.map(|value| value.value()))
+    }
+
+    /// This function removes the metadata entry `key` of the wallet and returns whether it was
+    /// set.
+    ///
+    /// [`StoreError::ReservedMetaKey`] is returned if `key` starts with [`RESERVED_META_PREFIX`].
+    pub fn delete_meta(&self, key: &str) -> Result<bool, StoreError> {
+        check_meta_key(key)?;
+        let write_tx = self.begin_write(Durability::Immediate)?;
+        let removed = write_tx
+            .open_table(self.meta_table_defn())?
+            .remove(key)?
+            .is_some();
+        self.commit(write_tx)?;
+        Ok(removed)
+    }
+
+    /// This function returns an iterator over the metadata entries of the wallet, see
+    /// [`MetaIter`]. The iterator is empty if the table does not exist.
+    pub fn iter_meta(&self) -> Result<MetaIter, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let Some(table) = open_table_if_exists(&read_tx, self.meta_table_defn())? else {
+            return Ok(MetaIter { range: None });
+        };
+        Ok(MetaIter {
+            range: Some(table.range::<&str>(..)?),
+        })
+    }
+
+    /// This function records the birthday of the wallet, the unix time at which it was created and
+    /// optionally the height of the chain tip at that time, so that scans can start from it.
+    ///
+    /// The birthday is written once: setting it again with the same value is a no-op, and with a
+    /// different one returns [`StoreError::BirthdayImmutable`] and leaves it unchanged. With the
+    /// `wallet` feature, it is set to the current time when a new wallet is initialized (see
+    /// [`WalletPersister::initialize`]).
+    ///
+    /// [`WalletPersister::initialize`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/trait.WalletPersister.html#tymethod.initialize>
+    pub fn set_birthday(&self, unix_time: u64, height: Option<u32>) -> Result<(), StoreError> {
+        let write_tx = self.begin_write(Durability::Immediate)?;
+        {
+            let table = write_tx.open_table(self.meta_table_defn())?;
+            if let Some(bytes) = table.get(BIRTHDAY_KEY)? {
+                if decode_birthday(&bytes.value())? == (unix_time, height) {
+                    return Ok(());
+                }
+                return Err(StoreError::BirthdayImmutable);
+            }
+        }
+        let mut bytes = unix_time.to_be_bytes().to_vec();
+        bytes.extend(height.map(u32::to_be_bytes).into_iter().flatten());
+        self.put_meta_in(&write_tx, BIRTHDAY_KEY, &bytes)?;
+        self.commit(write_tx)?;
+        Ok(())
+    }
+
+    /// This function returns the birthday of the wallet as a (unix time, height) pair, or `None`
+    /// if it was never set (see [`set_birthday`]).
+    ///
+    /// [`set_birthday`]: Self::set_birthday
+    pub fn birthday(&self) -> Result<Option<(u64, Option<u32>)>, StoreError> {
+        self.get_meta(BIRTHDAY_KEY)?
+            .map(|bytes| decode_birthday(&bytes))
+            .transpose()
+    }
+
+    /// This function returns the unix time of the last successful call to `persist_wallet` (with
+    /// the `wallet` feature) or [`persist_tx_graph`], or `None` if the wallet was never persisted
+    /// through them.
+    ///
+    /// The time is written in the write transaction persisting the [`TxGraph`], so it is committed
+    /// if and only if the changeset is, and is read from the clock of the store (see
+    /// [`with_clock`]). Reads never update it, unlike the modification time of the database file.
+    ///
+    /// [`persist_tx_graph`]: Self::persist_tx_graph
+    /// [`with_clock`]: Self::with_clock
+    /// [`TxGraph`]: <http://docs.rs/bdk_chain/0.23.0/bdk_chain/tx_graph/struct.TxGraph.html>
+    pub fn last_persist_at(&self) -> Result<Option<u64>, StoreError> {
+        let Some(bytes) = self.get_meta(LAST_PERSIST_AT_KEY)? else {
+            return Ok(None);
+        };
+        let bytes = bytes
+            .try_into()
+            .map_err(|_| StoreError::InvalidMeta(LAST_PERSIST_AT_KEY.to_string()))?;
+        Ok(Some(u64::from_be_bytes(bytes)))
+    }
+
+    // This function records the current time as the time of the last persist in the given write
+    // transaction.
+    pub(crate) fn record_last_persist(
+        &self,
+        write_tx: &WriteTransaction,
+    ) -> Result<(), StoreError> {
+        self.put_meta_in(write_tx, LAST_PERSIST_AT_KEY, &self.now().to_be_bytes())
+    }
+
+    // This function records `A` as the anchor type of the wallet in the given write transaction,
+    // unless one is already recorded.
+    pub(crate) fn record_anchor_type_in<A: AnchorWithMetaData>(
+        &self,
+        write_tx: &WriteTransaction,
+    ) -> Result<(), StoreError> {
+        let table = write_tx.open_table(self.meta_table_defn())?;
+        if table.get(ANCHOR_TYPE_KEY)?.is_some() {
+            return Ok(());
+        }
+        drop(table);
+        let mut bytes = vec![A::TAG];
+        bytes.extend_from_slice(type_name::<A>().as_bytes());
+        self.put_meta_in(write_tx, ANCHOR_TYPE_KEY, &bytes)
+    }
+
+    // This function checks that `A` is the anchor type recorded for the wallet, if the check is
+    // enabled and a type is recorded.
+    pub(crate) fn check_anchor_type<A: AnchorWithMetaData>(&self) -> Result<(), StoreError> {
+        if !self.check_anchor_type {
+            return Ok(());
+        }
+        let Some(bytes) = self.get_meta(ANCHOR_TYPE_KEY)? else {
+            return Ok(());
+        };
+        let Some((&tag, name)) = bytes.split_first() else {
+            return Err(StoreError::InvalidMeta(ANCHOR_TYPE_KEY.to_string()));
+        };
+        if tag != A::TAG {
+            return Err(StoreError::AnchorTypeMismatch {
+                stored: String::from_utf8_lossy(name).into_owned(),
+                requested: type_name::<A>().to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    // This function sets a metadata entry in the given write transaction, without checking the
+    // key, so that the crate can write its reserved keys.
+    pub(crate) fn put_meta_in(
+        &self,
+        write_tx: &WriteTransaction,
+        key: &str,
+        value: &[u8],
+    ) -> Result<(), StoreError> {
+        write_tx
+            .open_table(self.meta_table_defn())?
+            .insert(key, value.to_vec())?;
+        Ok(())
+    }
+}
+
+// This function decodes a birthday written by `Store::set_birthday`.
+fn decode_birthday(bytes: &[u8]) -> Result<(u64, Option<u32>), StoreError> {
+    let invalid = || StoreError::InvalidMeta(BIRTHDAY_KEY.to_string());
+    let (unix_time, height) = bytes.split_at_checked(8).ok_or_else(invalid)?;
+    let unix_time = u64::from_be_bytes(unix_time.try_into().map_err(|_| invalid())?);
+    let height = match height {
+        [] => None,
+        height => Some(u32::from_be_bytes(
+            height.try_into().map_err(|_| invalid())?,
+        )),
+    };
+    Ok((unix_time, height))
+}
+
+// This function rejects the keys reserved for this crate.
+fn check_meta_key(key: &str) -> Result<(), StoreError> {
+    if key.starts_with(RESERVED_META_PREFIX) {
+        return Err(StoreError::ReservedMetaKey(key.to_string()));
+    }
+    Ok(())
+}
@@ -0,0 +1,60 @@
+#![warn(missing_docs)]
+//! This module contains [`RollbackStats`] which is returned by [`Store::rollback_to_height`].
+use crate::anchor_trait::AnchorWithMetaData;
+use crate::error::StoreError;
+use crate::{Store, block_id_from_bytes};
+use redb::{Durability, ReadableMultimapTable};
+
+/// Number of rows removed from each table by [`Store::rollback_to_height`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RollbackStats {
+    /// Number of blocks removed.
+    pub blocks: u64,
+    /// Number of anchors removed.
+    pub anchors: u64,
+}
+
+impl Store {
+    /// This function rolls the wallet back to `height`, for instance after a reorg deeper than the
+    /// chain source could report or to force a rescan from that height. It removes the blocks above
+    /// `height` and the anchors of type `A` whose block is above it, in a single write transaction.
+    ///
+    /// Transactions and their last_seen, first_seen and last_evicted entries are kept, so the
+    /// transactions which lost their anchors are loaded as unconfirmed. Anchors of other types
+    /// are not removed. A `height` at or above the tip removes nothing, and a `height` of 0 keeps
+    /// only the genesis block.
+    pub fn rollback_to_height<A: AnchorWithMetaData>(
+        &self,
+        height: u32,
+    ) -> Result<RollbackStats, StoreError> {
+        self.check_anchor_type::<A>()?;
+        let write_tx = self.begin_write(Durability::Immediate)?;
+        let mut stats = RollbackStats::default();
+        if let Some(first_removed) = height.checked_add(1) {
+            let mut blocks_table = write_tx.open_table(self.blocks_table_defn())?;
+            blocks_table.retain_in(first_removed.., |_, _| {
+                stats.blocks += 1;
+                false
+            })?;
+
+            let mut anchors_table = write_tx.open_multimap_table(self.anchors_table_defn())?;
+            let mut removed = Vec::new();
+            for entry in anchors_table.iter()? {
+                let (txid, values) = entry?;
+                for value in values {
+                    let value = value?;
+                    let (block_id_bytes, tag, metadata) = value.value();
+                    if tag == A::TAG && block_id_from_bytes(&block_id_bytes)?.height > height {
+                        removed.push((txid.value(), block_id_bytes, metadata.to_vec()));
+                    }
+                }
+            }
+            for (txid, block_id_bytes, metadata) in removed {
+                anchors_table.remove(txid, (block_id_bytes, A::TAG, metadata.as_slice()))?;
+                stats.anchors += 1;
+            }
+        }
+        self.commit(write_tx)?;
+        Ok(stats)
+    }
+}
@@ -0,0 +1,56 @@
+#![warn(missing_docs)]
+//! This module contains [`SharedStore`], a [`WalletPersister`] for a [`Store`] shared through an
+//! [`Arc`].
+//!
+//! [`WalletPersister`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/trait.WalletPersister.html>
+use crate::Store;
+use crate::error::StoreError;
+use bdk_wallet::{ChangeSet, WalletPersister};
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// A [`Store`] shared through an [`Arc`] which implements [`WalletPersister`], so that a
+/// [`Wallet`] can be persisted while other tasks hold the same store.
+///
+/// [`WalletPersister`] takes the persister by `&mut`, but the persist functions of [`Store`] only
+/// need `&self` as redb handles the synchronization, so persisting through one [`SharedStore`]
+/// does not block the holders of its other clones. Writes still serialize on redb's writer lock,
+/// see [`Store`]. The store is reachable through [`Deref`].
+///
+/// [`WalletPersister`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/trait.WalletPersister.html>
+/// [`Wallet`]: <https://docs.rs/bdk_wallet/2.0.0/bdk_wallet/struct.Wallet.html>
+#[derive(Debug, Clone)]
+pub struct SharedStore(pub Arc<Store>);
+
+impl From<Arc<Store>> for SharedStore {
+    fn from(store: Arc<Store>) -> Self {
+        SharedStore(store)
+    }
+}
+
+impl From<Store> for SharedStore {
+    fn from(store: Store) -> Self {
+        SharedStore(Arc::new(store))
+    }
+}
+
+impl Deref for SharedStore {
+    type Target = Store;
+
+    fn deref(&self) -> &Store {
+        &self.0
+    }
+}
+
+impl WalletPersister for SharedStore {
+    type Error = StoreError;
+
+    fn initialize(persister: &mut Self) -> Result<ChangeSet, Self::Error> {
+        persister.0.initialize_wallet()
+    }
+
+    fn persist(persister: &mut Self, changeset: &ChangeSet) -> Result<(), Self::Error> {
+        persister.0.persist_wallet(changeset)?;
+        Ok(())
+    }
+}
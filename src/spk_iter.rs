@@ -0,0 +1,119 @@
+#![warn(missing_docs)]
+//! This module contains [`SpkIter`] which lazily iterates over the spk cache of one descriptor,
+//! see [`Store::iter_spks`], and [`AllSpkIter`] which lazily iterates over the whole spk cache,
+//! see [`Store::iter_all_spks`].
+use crate::error::StoreError;
+use crate::{SPKS_VALUE, Store, open_table_if_exists};
+use bdk_chain::DescriptorId;
+use bdk_chain::bitcoin::{ScriptBuf, hashes::Hash};
+use redb::{AccessGuard, Range};
+
+// Range over the ((DescriptorId, index), ScriptPubKey) entries of the spk table.
+type SpkRange = Range<'static, ([u8; 32], u32), Vec<u8>>;
+
+// An entry of the spk table.
+type SpkEntry = (
+    AccessGuard<'static, ([u8; 32], u32)>,
+    AccessGuard<'static, Vec<u8>>,
+);
+
+/// Iterator over the cached (index, script pubkey) pairs of a descriptor, in ascending order of
+/// index.
+///
+/// It owns the read transaction it was created from, so it sees the spk cache as it was when
+/// [`Store::iter_spks`] was called, and entries are only read as it advances. Dropping it early
+/// (for instance after [`Iterator::take`]) ends the read transaction.
+pub struct SpkIter {
+    // None if the spk table does not exist.
+    range: Option<SpkRange>,
+    // The store the iterator was created from, decoding the scripts.
+    store: Store,
+}
+
+impl Iterator for SpkIter {
+    type Item = Result<(u32, ScriptBuf), StoreError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = decode_entry(&self.store, self.range.as_mut()?.next()?);
+        Some(entry.map(|(_, index, spk)| (index, spk)))
+    }
+}
+
+/// Iterator over the whole spk cache as (descriptor id, index, script pubkey) triples, ordered by
+/// descriptor id (as bytes) and then by index.
+///
+/// Like [`SpkIter`], it owns the read transaction it was created from, so it sees the spk cache
+/// as it was when [`Store::iter_all_spks`] was called, and only one entry is held in memory at a
+/// time.
+pub struct AllSpkIter {
+    // None if the spk table does not exist.
+    range: Option<SpkRange>,
+    // The store the iterator was created from, decoding the scripts.
+    store: Store,
+}
+
+impl Iterator for AllSpkIter {
+    type Item = Result<(DescriptorId, u32, ScriptBuf), StoreError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = decode_entry(&self.store, self.range.as_mut()?.next()?);
+        Some(entry)
+    }
+}
+
+// This function decodes an entry of the spk table like read_spks does, decrypting and
+// decompressing the script if needed.
+fn decode_entry(
+    store: &Store,
+    entry: Result<SpkEntry, redb::StorageError>,
+) -> Result<(DescriptorId, u32, ScriptBuf), StoreError> {
+    let (key, spk) = entry?;
+    let key = key.value();
+    let spk = store.decode_script(SPKS_VALUE, key, spk.value())?;
+    Ok((DescriptorId::from_byte_array(key.0), key.1, spk))
+}
+
+impl Store {
+    /// This function returns an iterator over the cached script pubkeys of the descriptor with the
+    /// given id, along with their derivation index.
+    ///
+    /// Unlike [`read_indexer`], only the entries of that descriptor are read, lazily, using a range
+    /// over the (descriptor id, index) keys of the spk table. The iterator is empty if nothing is
+    /// cached for the descriptor or if the table does not exist.
+    ///
+    /// [`read_indexer`]: Self::read_indexer
+    pub fn iter_spks(&self, descriptor_id: DescriptorId) -> Result<SpkIter, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let Some(table) = open_table_if_exists(&read_tx, self.spk_table_defn())? else {
+            return Ok(SpkIter {
+                range: None,
+                store: self.clone(),
+            });
+        };
+        let id = descriptor_id.to_byte_array();
+        Ok(SpkIter {
+            range: Some(table.range((id, 0)..=(id, u32::MAX))?),
+            store: self.clone(),
+        })
+    }
+
+    /// This function returns an iterator over the whole spk cache, yielding one (descriptor id,
+    /// index, script pubkey) triple at a time, see [`AllSpkIter`].
+    ///
+    /// Unlike [`read_indexer`], the cache is never loaded in memory at once, which bounds the
+    /// memory used to go over wallets with very large caches. The iterator is empty if the table
+    /// does not exist.
+    ///
+    /// [`read_indexer`]: Self::read_indexer
+    pub fn iter_all_spks(&self) -> Result<AllSpkIter, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let range = match open_table_if_exists(&read_tx, self.spk_table_defn())? {
+            Some(table) => Some(table.range::<([u8; 32], u32)>(..)?),
+            None => None,
+        };
+        Ok(AllSpkIter {
+            range,
+            store: self.clone(),
+        })
+    }
+}
@@ -0,0 +1,172 @@
+#![warn(missing_docs)]
+//! This module contains [`StoreStats`] which is returned by [`Store::stats`] and reports the
+//! storage used by a wallet's tables and by the whole database file, along with functions counting
+//! the entries of individual tables such as [`Store::transaction_count`].
+//!
+//! [`Store::stats`]: crate::Store::stats
+//! [`Store::transaction_count`]: crate::Store::transaction_count
+use crate::error::StoreError;
+use crate::{Store, open_multimap_table_if_exists, open_table_if_exists};
+use redb::ReadableTableMetadata;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Number of entries and storage used by a redb table.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TableStats {
+    /// Number of entries in the table.
+    pub entries: u64,
+    /// Number of bytes taken by the keys and values of the table.
+    pub stored_bytes: u64,
+    /// Number of bytes taken by the table's internal metadata.
+    pub metadata_bytes: u64,
+    /// Number of bytes allocated to the table but not used.
+    pub fragmented_bytes: u64,
+}
+
+/// Storage statistics of a wallet's tables and of the database file.
+///
+/// Tables which were not created yet are reported with zero entries.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StoreStats {
+    /// Stats of the keychains table.
+    pub keychains: TableStats,
+    /// Stats of the blocks table.
+    pub blocks: TableStats,
+    /// Stats of the txs table.
+    pub txs: TableStats,
+    /// Stats of the txouts table.
+    pub txouts: TableStats,
+    /// Stats of the anchors table.
+    pub anchors: TableStats,
+    /// Stats of the last_seen table.
+    pub last_seen: TableStats,
+    /// Stats of the last_evicted table.
+    pub last_evicted: TableStats,
+    /// Stats of the first_seen table.
+    pub first_seen: TableStats,
+    /// Stats of the last_revealed table.
+    pub last_revealed: TableStats,
+    /// Stats of the spk cache table.
+    pub spks: TableStats,
+    /// Number of bytes allocated in the database file, for all wallets and tables. The file itself
+    /// can be larger since redb grows it in chunks.
+    pub allocated_bytes: u64,
+    /// Number of bytes allocated in the database file but not used, i.e. an estimate of the free
+    /// space which [`Store::compact`] could reclaim.
+    ///
+    /// [`Store::compact`]: crate::Store::compact
+    pub fragmented_bytes: u64,
+}
+
+impl Store {
+    /// This function returns the number of entries and storage used by each table of the wallet
+    /// along with the storage used by the database file as a whole.
+    ///
+    /// The size of the file on disk is not known to the [`Store`]; it can be obtained with
+    /// [`std::fs::metadata`] on the path used to create the [`Database`].
+    ///
+    /// [`Database`]: <https://docs.rs/redb/2.5.0/redb/struct.Database.html>
+    pub fn stats(&self) -> Result<StoreStats, StoreError> {
+        // database wide stats are only available from a write transaction
+        let write_tx = self.db.begin_write()?;
+        let db_stats = write_tx.stats()?;
+        write_tx.abort()?;
+
+        let read_tx = self.db.begin_read()?;
+        Ok(StoreStats {
+            keychains: table_stats(open_table_if_exists(&read_tx, self.keychains_table_defn())?)?,
+            blocks: table_stats(open_table_if_exists(&read_tx, self.blocks_table_defn())?)?,
+            txs: table_stats(open_table_if_exists(&read_tx, self.txs_table_defn())?)?,
+            txouts: table_stats(open_table_if_exists(&read_tx, self.txouts_table_defn())?)?,
+            anchors: table_stats(open_multimap_table_if_exists(
+                &read_tx,
+                self.anchors_table_defn(),
+            )?)?,
+            last_seen: table_stats(open_table_if_exists(&read_tx, self.last_seen_defn())?)?,
+            last_evicted: table_stats(open_table_if_exists(
+                &read_tx,
+                self.last_evicted_table_defn(),
+            )?)?,
+            first_seen: table_stats(open_table_if_exists(
+                &read_tx,
+                self.first_seen_table_defn(),
+            )?)?,
+            last_revealed: table_stats(open_table_if_exists(
+                &read_tx,
+                self.last_revealed_table_defn(),
+            )?)?,
+            spks: table_stats(open_table_if_exists(&read_tx, self.spk_table_defn())?)?,
+            allocated_bytes: db_stats.allocated_pages() * db_stats.page_size() as u64,
+            fragmented_bytes: db_stats.fragmented_bytes(),
+        })
+    }
+
+    /// This function returns the number of transactions persisted in the txs table. Like the other
+    /// count functions, it opens a read transaction and returns the length of the table, which
+    /// redb keeps track of, without iterating over it. 0 is returned if the table does not exist.
+    pub fn transaction_count(&self) -> Result<u64, StoreError> {
+        table_len(open_table_if_exists(
+            &self.db.begin_read()?,
+            self.txs_table_defn(),
+        )?)
+    }
+
+    /// This function returns the number of floating txouts persisted in the txouts table. Outputs
+    /// of full transactions are not counted.
+    pub fn txout_count(&self) -> Result<u64, StoreError> {
+        table_len(open_table_if_exists(
+            &self.db.begin_read()?,
+            self.txouts_table_defn(),
+        )?)
+    }
+
+    /// This function returns the number of anchors persisted in the anchors table, for all anchor
+    /// types.
+    pub fn anchor_count(&self) -> Result<u64, StoreError> {
+        table_len(open_multimap_table_if_exists(
+            &self.db.begin_read()?,
+            self.anchors_table_defn(),
+        )?)
+    }
+
+    /// This function returns the number of blocks persisted in the blocks table.
+    pub fn block_count(&self) -> Result<u64, StoreError> {
+        table_len(open_table_if_exists(
+            &self.db.begin_read()?,
+            self.blocks_table_defn(),
+        )?)
+    }
+
+    #[cfg(feature = "json")]
+    /// This function returns the [`StoreStats`] of [`stats`] as a pretty printed JSON string.
+    ///
+    /// [`stats`]: Self::stats
+    pub fn stats_json(&self) -> Result<String, StoreError> {
+        Ok(serde_json::to_string_pretty(&self.stats()?)?)
+    }
+}
+
+// This function returns the stats of a table, or zeroes if the table does not exist.
+fn table_stats(table: Option<impl ReadableTableMetadata>) -> Result<TableStats, StoreError> {
+    let Some(table) = table else {
+        return Ok(TableStats::default());
+    };
+    let stats = table.stats()?;
+    Ok(TableStats {
+        entries: table.len()?,
+        stored_bytes: stats.stored_bytes(),
+        metadata_bytes: stats.metadata_bytes(),
+        fragmented_bytes: stats.fragmented_bytes(),
+    })
+}
+
+// This function returns the number of entries of a table, or 0 if the table does not exist.
+fn table_len(table: Option<impl ReadableTableMetadata>) -> Result<u64, StoreError> {
+    match table {
+        Some(table) => Ok(table.len()?),
+        None => Ok(0),
+    }
+}
@@ -0,0 +1,117 @@
+#![warn(missing_docs)]
+//! This module contains [`PersistSummary`] which is returned by the persist methods of [`Store`]
+//! and describes the rows they wrote.
+//!
+//! [`Store`]: crate::Store
+use bdk_chain::bitcoin::BlockHash;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+/// Number of rows written to and removed from a redb table.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct TableChanges {
+    /// Number of rows inserted, including rows which overwrote an existing entry.
+    pub inserted: u64,
+    /// Number of rows removed.
+    pub removed: u64,
+    /// Number of rows which were not written because the same value was already stored. Only the
+    /// spk cache table skips such rows.
+    pub skipped: u64,
+}
+
+impl TableChanges {
+    // This function returns the changes of a table to which `rows` rows were inserted.
+    pub(crate) fn from_inserted(rows: usize) -> Self {
+        TableChanges {
+            inserted: rows as u64,
+            removed: 0,
+            skipped: 0,
+        }
+    }
+
+    /// This function returns `true` if no row was inserted or removed, skipped rows not being
+    /// changes.
+    pub fn is_empty(&self) -> bool {
+        self.inserted == 0 && self.removed == 0
+    }
+
+    // This function adds the changes of `other` to `self`.
+    fn merge(&mut self, other: TableChanges) {
+        self.inserted += other.inserted;
+        self.removed += other.removed;
+        self.skipped += other.skipped;
+    }
+}
+
+/// A block of the blocks table whose hash was overwritten by a different one, i.e. a reorg.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ReplacedBlock {
+    /// Height of the block.
+    pub height: u32,
+    /// Hash persisted before.
+    pub old_hash: BlockHash,
+    /// Hash persisted instead.
+    pub new_hash: BlockHash,
+}
+
+/// Rows written by a persist call of [`Store`], per table.
+///
+/// Only blocks can be removed, when the [`local_chain::ChangeSet`] maps a height to `None`; the
+/// other tables only have inserted rows.
+///
+/// [`Store`]: crate::Store
+/// [`local_chain::ChangeSet`]: <http://docs.rs/bdk_chain/0.23.0/bdk_chain/local_chain/struct.ChangeSet.html>
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct PersistSummary {
+    /// Changes to the network table.
+    pub network: TableChanges,
+    /// Changes to the keychains table.
+    pub keychains: TableChanges,
+    /// Changes to the blocks table.
+    pub blocks: TableChanges,
+    /// Changes to the txs table.
+    pub txs: TableChanges,
+    /// Changes to the txouts table.
+    pub txouts: TableChanges,
+    /// Changes to the anchors table.
+    pub anchors: TableChanges,
+    /// Changes to the last_seen table.
+    pub last_seen: TableChanges,
+    /// Changes to the last_evicted table.
+    pub last_evicted: TableChanges,
+    /// Changes to the first_seen table.
+    pub first_seen: TableChanges,
+    /// Changes to the last_revealed table.
+    pub last_revealed: TableChanges,
+    /// Changes to the spk cache table.
+    pub spks: TableChanges,
+    /// Blocks whose persisted hash was replaced by a different one, in ascending order of height.
+    /// These are also counted as inserted in `blocks`.
+    pub replaced_blocks: Vec<ReplacedBlock>,
+}
+
+impl PersistSummary {
+    /// This function returns `true` if nothing was written.
+    pub fn is_empty(&self) -> bool {
+        self == &PersistSummary::default()
+    }
+
+    /// This function adds the changes of `other` to `self`.
+    pub fn merge(&mut self, other: PersistSummary) {
+        self.network.merge(other.network);
+        self.keychains.merge(other.keychains);
+        self.blocks.merge(other.blocks);
+        self.txs.merge(other.txs);
+        self.txouts.merge(other.txouts);
+        self.anchors.merge(other.anchors);
+        self.last_seen.merge(other.last_seen);
+        self.last_evicted.merge(other.last_evicted);
+        self.first_seen.merge(other.first_seen);
+        self.last_revealed.merge(other.last_revealed);
+        self.spks.merge(other.spks);
+        self.replaced_blocks.extend(other.replaced_blocks);
+    }
+}
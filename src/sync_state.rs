@@ -0,0 +1,56 @@
+#![warn(missing_docs)]
+//! This module contains [`SyncState`] which records the last sync of a wallet with a chain source,
+//! see [`Store::put_sync_state`]. It is available with the `serde` feature.
+use crate::Store;
+use crate::error::StoreError;
+use crate::meta::RESERVED_META_PREFIX;
+use bdk_chain::bitcoin::BlockHash;
+use redb::Durability;
+use serde::{Deserialize, Serialize};
+
+/// The state of the last sync of a wallet with a chain source (an Electrum or Esplora server, a
+/// bitcoind node...), so that the next sync can resume from it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncState {
+    /// The name of the chain source, chosen by the application.
+    pub source: String,
+    /// The height of the last block synced, if any.
+    pub last_height: Option<u32>,
+    /// The hash of the last block synced, if any.
+    pub last_block_hash: Option<BlockHash>,
+    /// The unix time of the last sync.
+    pub last_time: u64,
+}
+
+impl Store {
+    /// This function persists the sync state of the wallet for `state.source`, overwriting the
+    /// previous one of that source. Each source has its own state.
+    ///
+    /// The states are stored in the metadata table of the wallet under keys reserved for this
+    /// crate (see [`meta`]), so they are copied along with the wallet by [`backup_to`].
+    ///
+    /// [`meta`]: crate::meta
+    /// [`backup_to`]: Self::backup_to
+    pub fn put_sync_state(&self, state: &SyncState) -> Result<(), StoreError> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(state, &mut bytes)?;
+        let write_tx = self.begin_write(Durability::Immediate)?;
+        self.put_meta_in(&write_tx, &sync_state_key(&state.source), &bytes)?;
+        self.commit(write_tx)?;
+        Ok(())
+    }
+
+    /// This function returns the sync state of the wallet for the chain source named `source`, or
+    /// `None` if none was persisted.
+    pub fn sync_state(&self, source: &str) -> Result<Option<SyncState>, StoreError> {
+        let Some(bytes) = self.get_meta(&sync_state_key(source))? else {
+            return Ok(None);
+        };
+        Ok(Some(ciborium::from_reader(bytes.as_slice())?))
+    }
+}
+
+// This function returns the metadata key of the sync state of `source`.
+fn sync_state_key(source: &str) -> String {
+    format!("{RESERVED_META_PREFIX}sync_state:{source}")
+}
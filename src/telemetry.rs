@@ -0,0 +1,45 @@
+#![warn(missing_docs)]
+//! This module contains the names of the metrics emitted through the [`metrics`] facade when the
+//! `metrics` feature is enabled. An exporter (e.g. for Prometheus) has to be installed by the
+//! application for them to be collected.
+//!
+//! Every metric carries a `wallet` label set to the wallet name of the [`Store`]. Metrics about a
+//! single table also carry a `table` label set to the name of the table without the wallet prefix
+//! (e.g. `txs`, `last_seen`).
+//!
+//! [`metrics`]: <https://docs.rs/metrics/0.24/metrics/>
+//! [`Store`]: crate::Store
+use crate::summary::TableChanges;
+use metrics::{counter, histogram};
+use std::time::Duration;
+
+/// Counter of the persist operations which wrote at least one row to a table. Labels: `wallet`,
+/// `table`.
+pub const PERSIST_TOTAL: &str = "bdk_redb_persist_total";
+/// Counter of the rows inserted into or removed from a table. Labels: `wallet`, `table`.
+pub const ROWS_WRITTEN_TOTAL: &str = "bdk_redb_rows_written_total";
+/// Histogram of the time taken to commit write transactions, in seconds. Labels: `wallet`.
+pub const COMMIT_DURATION_SECONDS: &str = "bdk_redb_commit_duration_seconds";
+/// Histogram of the time taken to load a wallet, in seconds. Labels: `wallet`.
+pub const READ_WALLET_DURATION_SECONDS: &str = "bdk_redb_read_wallet_duration_seconds";
+
+// This function records the rows persisted into `table`. Nothing is recorded if no row was
+// written.
+pub(crate) fn record_persist(wallet: &str, table: &'static str, changes: TableChanges) {
+    if changes.is_empty() {
+        return;
+    }
+    let labels = [("wallet", wallet.to_string()), ("table", table.to_string())];
+    counter!(PERSIST_TOTAL, &labels).increment(1);
+    counter!(ROWS_WRITTEN_TOTAL, &labels).increment(changes.inserted + changes.removed);
+}
+
+// This function records the duration of a commit.
+pub(crate) fn record_commit(wallet: &str, duration: Duration) {
+    histogram!(COMMIT_DURATION_SECONDS, "wallet" => wallet.to_string()).record(duration);
+}
+
+// This function records the duration of a wallet load.
+pub(crate) fn record_read_wallet(wallet: &str, duration: Duration) {
+    histogram!(READ_WALLET_DURATION_SECONDS, "wallet" => wallet.to_string()).record(duration);
+}
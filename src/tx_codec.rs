@@ -0,0 +1,168 @@
+#![warn(missing_docs)]
+//! This module contains [`TxCodec`], the encoding of the transactions persisted in the txs table,
+//! see [`Store::with_tx_codec`].
+use crate::error::StoreError;
+use crate::{Store, TXS_VALUE, open_table_if_exists};
+use bdk_chain::bitcoin::{Transaction, Txid, hashes::Hash};
+use redb::{Durability, ReadTransaction, ReadableTable, ReadableTableMetadata, WriteTransaction};
+
+// Key of the codec of the wallet's transactions, stored as the byte of the TxCodec.
+const TX_CODEC_KEY: &str = "bdk_redb:tx_codec";
+
+/// The encoding of the transactions persisted in the txs table of a wallet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TxCodec {
+    /// The [`ciborium`] encoding of the transaction, preceded by a checksum. This is the encoding
+    /// of wallets persisted before codecs could be chosen, and the default one with the `serde`
+    /// feature. Without it, these transactions can neither be read nor persisted, which returns
+    /// [`StoreError::CborDisabled`].
+    ///
+    /// [`ciborium`]: <https://docs.rs/ciborium/0.2.2/ciborium/index.html>
+    #[cfg_attr(feature = "serde", default)]
+    Cbor,
+    /// The consensus encoding of the transaction, as broadcast on the network. It is smaller than
+    /// the [`Cbor`](TxCodec::Cbor) one, and the txid keying the row serves as checksum. It is the
+    /// default codec without the `serde` feature.
+    #[cfg_attr(not(feature = "serde"), default)]
+    Consensus,
+}
+
+impl TxCodec {
+    // This function returns the byte recording the codec in the metadata. The bytes must never
+    // change as they are persisted.
+    fn to_byte(self) -> u8 {
+        match self {
+            TxCodec::Cbor => 0,
+            TxCodec::Consensus => 1,
+        }
+    }
+
+    // This function decodes the codec recorded in the metadata, if any.
+    fn from_meta(bytes: Option<Vec<u8>>) -> Result<Option<Self>, StoreError> {
+        match bytes.as_deref() {
+            None => Ok(None),
+            Some([0]) => Ok(Some(TxCodec::Cbor)),
+            Some([1]) => Ok(Some(TxCodec::Consensus)),
+            Some(_) => Err(StoreError::InvalidMeta(TX_CODEC_KEY.to_string())),
+        }
+    }
+}
+
+impl Store {
+    /// This function returns the codec recorded for the transactions of the wallet, or `None` if
+    /// none is recorded, in which case they are read as [`TxCodec::Cbor`].
+    ///
+    /// The codec of the store (see [`with_tx_codec`]) is recorded the first time transactions are
+    /// persisted, or [`TxCodec::Cbor`] if the wallet already has transactions written before
+    /// codecs were recorded. It is only changed by [`migrate_tx_codec`].
+    ///
+    /// [`with_tx_codec`]: Self::with_tx_codec
+    /// [`migrate_tx_codec`]: Self::migrate_tx_codec
+    pub fn persisted_tx_codec(&self) -> Result<Option<TxCodec>, StoreError> {
+        TxCodec::from_meta(self.get_meta(TX_CODEC_KEY)?)
+    }
+
+    /// This function rewrites all the persisted transactions of the wallet with `codec` and
+    /// records it as the codec of the wallet, in a single write transaction. It returns the number
+    /// of transactions rewritten, i.e. 0 if the wallet already uses `codec`.
+    ///
+    /// It is the migration path for wallets persisted with another codec: the transactions are
+    /// still read whatever the codec of the store, but persisting new ones returns
+    /// [`StoreError::TxCodecMismatch`] until the store is created with the codec of the wallet
+    /// (see [`with_tx_codec`]).
+    ///
+    /// [`with_tx_codec`]: Self::with_tx_codec
+    pub fn migrate_tx_codec(&self, codec: TxCodec) -> Result<usize, StoreError> {
+        let write_tx = self.begin_write(Durability::Immediate)?;
+        let stored = TxCodec::from_meta(
+            write_tx
+                .open_table(self.meta_table_defn())?
+                .get(TX_CODEC_KEY)?
+                .map(|value| value.value()),
+        )?
+        .unwrap_or(TxCodec::Cbor);
+        let mut rewritten = 0;
+        if stored != codec {
+            let mut table = write_tx.open_table(self.txs_table_defn())?;
+            let mut txs = Vec::new();
+            for entry in table.iter()? {
+                let (txid, tx_vec) = entry?;
+                let txid = Txid::from_byte_array(txid.value());
+                txs.push(self.decode_tx(stored, txid, tx_vec.value())?);
+            }
+            for tx in &txs {
+                let txid = tx.compute_txid().to_byte_array();
+                table.insert(txid, self.encode_tx(codec, tx)?)?;
+            }
+            rewritten = txs.len();
+        }
+        self.put_meta_in(&write_tx, TX_CODEC_KEY, &[codec.to_byte()])?;
+        self.commit(write_tx)?;
+        Ok(rewritten)
+    }
+
+    // This function returns the codec of the wallet's transactions in `read_tx`, i.e. the
+    // recorded one or TxCodec::Cbor if none is recorded.
+    pub(crate) fn tx_codec_in(&self, read_tx: &ReadTransaction) -> Result<TxCodec, StoreError> {
+        let Some(table) = open_table_if_exists(read_tx, self.meta_table_defn())? else {
+            return Ok(TxCodec::Cbor);
+        };
+        Ok(
+            TxCodec::from_meta(table.get(TX_CODEC_KEY)?.map(|value| value.value()))?
+                .unwrap_or(TxCodec::Cbor),
+        )
+    }
+
+    // This function checks that the codec of the store is the one of the wallet before
+    // transactions are persisted in the given write transaction, recording it if the wallet has
+    // none.
+    pub(crate) fn check_tx_codec_in(&self, write_tx: &WriteTransaction) -> Result<(), StoreError> {
+        let stored = TxCodec::from_meta(
+            write_tx
+                .open_table(self.meta_table_defn())?
+                .get(TX_CODEC_KEY)?
+                .map(|value| value.value()),
+        )?;
+        let stored = match stored {
+            Some(stored) => stored,
+            None => {
+                // transactions persisted before codecs were recorded are ciborium encoded
+                let codec = if write_tx.open_table(self.txs_table_defn())?.is_empty()? {
+                    self.tx_codec
+                } else {
+                    TxCodec::Cbor
+                };
+                self.put_meta_in(write_tx, TX_CODEC_KEY, &[codec.to_byte()])?;
+                codec
+            }
+        };
+        if stored != self.tx_codec {
+            return Err(StoreError::TxCodecMismatch {
+                stored,
+                requested: self.tx_codec,
+            });
+        }
+        Ok(())
+    }
+
+    // This function encodes a transaction to be stored in the txs table with `codec`, compressing
+    // and encrypting it if enabled.
+    pub(crate) fn encode_tx(
+        &self,
+        codec: TxCodec,
+        tx: &Transaction,
+    ) -> Result<Vec<u8>, StoreError> {
+        let bytes = match codec {
+            #[cfg(feature = "serde")]
+            TxCodec::Cbor => crate::tx_to_bytes(tx)?,
+            #[cfg(not(feature = "serde"))]
+            TxCodec::Cbor => return Err(StoreError::CborDisabled),
+            TxCodec::Consensus => bdk_chain::bitcoin::consensus::serialize(tx),
+        };
+        self.seal_value(
+            TXS_VALUE,
+            &tx.compute_txid().to_byte_array(),
+            self.compress_value(TXS_VALUE, bytes)?,
+        )
+    }
+}
@@ -0,0 +1,88 @@
+#![warn(missing_docs)]
+//! This module contains [`TxNode`] which is returned by [`Store::tx_node`] and bundles a persisted
+//! transaction with its anchors and timestamps.
+use crate::anchor_trait::AnchorWithMetaData;
+use crate::error::StoreError;
+use crate::{
+    Store, anchor_from_bytes, block_id_from_bytes, open_multimap_table_if_exists,
+    open_table_if_exists,
+};
+use bdk_chain::bitcoin::{Transaction, Txid, hashes::Hash};
+use bdk_chain::{BlockId, ConfirmationBlockTime};
+use redb::{ReadTransaction, TableDefinition};
+use std::sync::Arc;
+
+/// A persisted transaction along with everything persisted about it in the transaction graph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxNode {
+    /// The transaction.
+    pub tx: Arc<Transaction>,
+    /// The [`ConfirmationBlockTime`] anchors of the transaction as (block, confirmation time)
+    /// pairs, ordered by height.
+    ///
+    /// [`ConfirmationBlockTime`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/struct.ConfirmationBlockTime.html>
+    pub anchors: Vec<(BlockId, u64)>,
+    /// The first_seen timestamp of the transaction, if any.
+    pub first_seen: Option<u64>,
+    /// The last_seen timestamp of the transaction, if any.
+    pub last_seen: Option<u64>,
+    /// The last_evicted timestamp of the transaction, if any.
+    pub last_evicted: Option<u64>,
+}
+
+impl Store {
+    /// This function loads the transaction with the given txid along with its anchors and its
+    /// first_seen, last_seen and last_evicted timestamps, or returns `None` if the transaction is
+    /// not persisted.
+    ///
+    /// Everything is read in a single read transaction, so the [`TxNode`] is consistent even if
+    /// the store is written to concurrently. Only the entries of that transaction are read from
+    /// each table, and tables which do not exist are treated as empty. Anchors of types other
+    /// than [`ConfirmationBlockTime`] are not included.
+    ///
+    /// [`ConfirmationBlockTime`]: <https://docs.rs/bdk_chain/0.23.0/bdk_chain/struct.ConfirmationBlockTime.html>
+    pub fn tx_node(&self, txid: Txid) -> Result<Option<TxNode>, StoreError> {
+        let read_tx = self.db.begin_read()?;
+        let Some(txs_table) = open_table_if_exists(&read_tx, self.txs_table_defn())? else {
+            return Ok(None);
+        };
+        let Some(tx_vec) = txs_table.get(txid.to_byte_array())? else {
+            return Ok(None);
+        };
+        let tx = Arc::new(self.decode_tx(self.tx_codec_in(&read_tx)?, txid, tx_vec.value())?);
+
+        let mut anchors = Vec::new();
+        if let Some(table) = open_multimap_table_if_exists(&read_tx, self.anchors_table_defn())? {
+            for value in table.get(txid.to_byte_array())? {
+                let value = value?;
+                let (block_id_bytes, tag, metadata) = value.value();
+                if tag != ConfirmationBlockTime::TAG {
+                    continue;
+                }
+                let anchor: ConfirmationBlockTime =
+                    anchor_from_bytes(block_id_from_bytes(&block_id_bytes)?, metadata)?;
+                anchors.push((anchor.block_id, anchor.confirmation_time));
+            }
+        }
+
+        Ok(Some(TxNode {
+            tx,
+            anchors,
+            first_seen: read_timestamp_in(&read_tx, self.first_seen_table_defn(), txid)?,
+            last_seen: read_timestamp_in(&read_tx, self.last_seen_defn(), txid)?,
+            last_evicted: read_timestamp_in(&read_tx, self.last_evicted_table_defn(), txid)?,
+        }))
+    }
+}
+
+// This function looks up a single txid in the given timestamp table of `read_tx`.
+fn read_timestamp_in(
+    read_tx: &ReadTransaction,
+    table_defn: TableDefinition<[u8; 32], u64>,
+    txid: Txid,
+) -> Result<Option<u64>, StoreError> {
+    let Some(table) = open_table_if_exists(read_tx, table_defn)? else {
+        return Ok(None);
+    };
+    Ok(table.get(txid.to_byte_array())?.map(|time| time.value()))
+}
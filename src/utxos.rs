@@ -0,0 +1,80 @@
+//! This module contains [`Store::list_utxos`] which computes the wallet's unspent outputs from the
+//! persisted transaction graph.
+use crate::Store;
+use crate::anchor_trait::AnchorWithMetaData;
+use crate::error::StoreError;
+use bdk_chain::bitcoin::{OutPoint, ScriptBuf, TxOut, Txid};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+
+impl Store {
+    /// This function returns the unspent outputs of the wallet, i.e. the persisted outputs whose
+    /// script is in the spk cache and which are not spent by any persisted transaction, along with
+    /// the best anchor of type `A` of their transaction.
+    ///
+    /// Outputs are taken from the persisted transactions and from the floating txouts. Spends by
+    /// unconfirmed transactions count, as do spends by transactions which conflict with others.
+    /// The best anchor is the one with the lowest height among the anchors whose block is in the
+    /// persisted local chain; `None` is returned if there is no such anchor.
+    ///
+    /// It only reads from the database, but loads the whole transaction graph in memory.
+    pub fn list_utxos<A: AnchorWithMetaData>(
+        &self,
+    ) -> Result<Vec<(OutPoint, TxOut, Option<A>)>, StoreError> {
+        self.check_anchor_type::<A>()?;
+        let read_tx = self.db.begin_read()?;
+        let mut txs = BTreeSet::new();
+        self.read_txs(&read_tx, &mut txs)?;
+        let mut txouts = BTreeMap::new();
+        self.read_txouts(&read_tx, &mut txouts)?;
+        let mut anchors = BTreeSet::new();
+        self.read_anchors::<A>(&read_tx, &mut anchors)?;
+        let mut blocks = BTreeMap::new();
+        self.read_blocks(&read_tx, &mut blocks)?;
+        let mut spk_cache = BTreeMap::new();
+        self.read_spks(&read_tx, &mut spk_cache)?;
+
+        let spks: BTreeSet<&ScriptBuf> = spk_cache.values().flat_map(BTreeMap::values).collect();
+        let spent: BTreeSet<OutPoint> = txs
+            .iter()
+            .flat_map(|tx| tx.input.iter().map(|txin| txin.previous_output))
+            .collect();
+
+        // outputs of full transactions take precedence over floating txouts
+        for tx in &txs {
+            let txid = tx.compute_txid();
+            for (vout, txout) in tx.output.iter().enumerate() {
+                txouts.insert(OutPoint::new(txid, vout as u32), txout.clone());
+            }
+        }
+
+        let mut best_anchors: BTreeMap<Txid, A> = BTreeMap::new();
+        for (anchor, txid) in anchors {
+            let block_id = anchor.anchor_block();
+            if blocks.get(&block_id.height) != Some(&Some(block_id.hash)) {
+                continue;
+            }
+            match best_anchors.entry(txid) {
+                Entry::Vacant(entry) => {
+                    entry.insert(anchor);
+                }
+                Entry::Occupied(mut entry) => {
+                    if block_id.height < entry.get().anchor_block().height {
+                        entry.insert(anchor);
+                    }
+                }
+            }
+        }
+
+        Ok(txouts
+            .into_iter()
+            .filter(|(outpoint, txout)| {
+                spks.contains(&txout.script_pubkey) && !spent.contains(outpoint)
+            })
+            .map(|(outpoint, txout)| {
+                let anchor = best_anchors.get(&outpoint.txid).cloned();
+                (outpoint, txout, anchor)
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,236 @@
+#![warn(missing_docs)]
+//! This module contains [`VerifyReport`] which is returned by [`Store::verify_references`] and
+//! lists the rows of a wallet's tables which do not satisfy the referential constraints that
+//! [`Store`] enforces while persisting, along with [`RepairStats`] returned by
+//! [`Store::repair_dangling`].
+//!
+//! [`Store`]: crate::Store
+//! [`Store::verify_references`]: crate::Store::verify_references
+//! [`Store::repair_dangling`]: crate::Store::repair_dangling
+use crate::anchor_trait::AnchorWithMetaData;
+use crate::error::StoreError;
+use crate::{Store, block_id_from_bytes, block_id_to_bytes};
+use bdk_chain::bitcoin::{BlockHash, Txid, hashes::Hash};
+use bdk_chain::{BlockId, DescriptorId};
+use redb::{ReadTransaction, ReadableMultimapTable, ReadableTable, TableDefinition};
+use std::ops::Range;
+
+/// Inconsistencies found by [`Store::verify_references`].
+///
+/// [`Store::verify_references`]: crate::Store::verify_references
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Anchors whose transaction is missing from the txs table.
+    pub anchors_missing_tx: Vec<(BlockId, Txid)>,
+    /// Anchors whose block height is in the blocks table but with a different hash.
+    pub anchors_conflicting_block: Vec<(BlockId, BlockHash)>,
+    /// last_seen entries whose transaction is missing from the txs table.
+    pub last_seen_missing_tx: Vec<Txid>,
+    /// first_seen entries whose transaction is missing from the txs table.
+    pub first_seen_missing_tx: Vec<Txid>,
+    /// last_evicted entries whose transaction is missing from the txs table.
+    pub last_evicted_missing_tx: Vec<Txid>,
+    /// Ranges of derivation indices missing from the spk cache of a descriptor, i.e. the cached
+    /// scripts of the descriptor are not contiguous starting from index 0.
+    pub spk_cache_gaps: Vec<(DescriptorId, Range<u32>)>,
+}
+
+impl VerifyReport {
+    /// This function returns `true` if no inconsistency was found.
+    pub fn is_consistent(&self) -> bool {
+        self == &VerifyReport::default()
+    }
+}
+
+/// Number of rows removed from each table by [`Store::repair_dangling`].
+///
+/// [`Store::repair_dangling`]: crate::Store::repair_dangling
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RepairStats {
+    /// Number of anchors removed.
+    pub anchors: u64,
+    /// Number of last_seen entries removed.
+    pub last_seen: u64,
+    /// Number of first_seen entries removed.
+    pub first_seen: u64,
+    /// Number of last_evicted entries removed.
+    pub last_evicted: u64,
+}
+
+impl Store {
+    /// This function audits the tables of the wallet and reports the rows which break the
+    /// referential constraints enforced while persisting. See [`VerifyReport`] for the checks.
+    /// Only anchors of type `A` are checked.
+    ///
+    /// It only reads from the database and goes through the tables entry by entry, so memory usage
+    /// is bounded by the size of the report.
+    pub fn verify_references<A: AnchorWithMetaData>(&self) -> Result<VerifyReport, StoreError> {
+        self.check_anchor_type::<A>()?;
+        let read_tx = self.db.begin_read()?;
+        let mut report = VerifyReport::default();
+
+        self.verify_anchors::<A>(&read_tx, &mut report)?;
+        report.last_seen_missing_tx = self.txids_missing_tx(&read_tx, self.last_seen_defn())?;
+        report.first_seen_missing_tx =
+            self.txids_missing_tx(&read_tx, self.first_seen_table_defn())?;
+        report.last_evicted_missing_tx =
+            self.txids_missing_tx(&read_tx, self.last_evicted_table_defn())?;
+        self.verify_spk_cache(&read_tx, &mut report)?;
+
+        Ok(report)
+    }
+
+    /// This function removes the anchors and last_seen, first_seen and last_evicted entries listed
+    /// in `report` (as returned by [`verify_references`]) whose transaction is missing. All rows
+    /// are removed in a single write transaction.
+    ///
+    /// Transactions are never deleted and a row is only removed if its transaction is still
+    /// missing, so a stale report cannot remove valid data. Other inconsistencies listed in the
+    /// report are left untouched.
+    ///
+    /// [`verify_references`]: Self::verify_references
+    pub fn repair_dangling<A: AnchorWithMetaData>(
+        &self,
+        report: &VerifyReport,
+    ) -> Result<RepairStats, StoreError> {
+        self.check_anchor_type::<A>()?;
+        let mut stats = RepairStats::default();
+        let write_tx = self.db.begin_write()?;
+        {
+            let txs_table = write_tx.open_table(self.txs_table_defn())?;
+
+            let mut table = write_tx.open_multimap_table(self.anchors_table_defn())?;
+            for (block_id, txid) in &report.anchors_missing_tx {
+                let txid = txid.to_byte_array();
+                if txs_table.get(txid)?.is_some() {
+                    continue;
+                }
+                let block_id_bytes = block_id_to_bytes(block_id);
+                let mut removed = Vec::new();
+                for value in table.get(txid)? {
+                    let value = value?;
+                    let (stored_block_id_bytes, tag, metadata) = value.value();
+                    if stored_block_id_bytes == block_id_bytes && tag == A::TAG {
+                        removed.push(metadata.to_vec());
+                    }
+                }
+                for metadata in removed {
+                    table.remove(txid, (block_id_bytes, A::TAG, metadata.as_slice()))?;
+                    stats.anchors += 1;
+                }
+            }
+
+            for (table_defn, txids, count) in [
+                (
+                    self.last_seen_defn(),
+                    &report.last_seen_missing_tx,
+                    &mut stats.last_seen,
+                ),
+                (
+                    self.first_seen_table_defn(),
+                    &report.first_seen_missing_tx,
+                    &mut stats.first_seen,
+                ),
+                (
+                    self.last_evicted_table_defn(),
+                    &report.last_evicted_missing_tx,
+                    &mut stats.last_evicted,
+                ),
+            ] {
+                let mut table = write_tx.open_table(table_defn)?;
+                for txid in txids {
+                    if txs_table.get(txid.to_byte_array())?.is_none()
+                        && table.remove(txid.to_byte_array())?.is_some()
+                    {
+                        *count += 1;
+                    }
+                }
+            }
+        }
+        self.commit(write_tx)?;
+        Ok(stats)
+    }
+
+    // This function checks that every anchor points to a persisted tx and that its block does not
+    // contradict the blocks table.
+    fn verify_anchors<A: AnchorWithMetaData>(
+        &self,
+        read_tx: &ReadTransaction,
+        report: &mut VerifyReport,
+    ) -> Result<(), StoreError> {
+        let table = read_tx.open_multimap_table(self.anchors_table_defn())?;
+        let txs_table = read_tx.open_table(self.txs_table_defn())?;
+        let blocks_table = read_tx.open_table(self.blocks_table_defn())?;
+
+        for entry in table.iter()? {
+            let (txid_bytes, values) = entry?;
+            let txid_bytes = txid_bytes.value();
+            let txid = Txid::from_byte_array(txid_bytes);
+            let tx_missing = txs_table.get(txid_bytes)?.is_none();
+            for value in values {
+                let (block_id_bytes, tag, _) = value?.value();
+                if tag != A::TAG {
+                    continue;
+                }
+                let block_id = block_id_from_bytes(&block_id_bytes)?;
+
+                if tx_missing {
+                    report.anchors_missing_tx.push((block_id, txid));
+                }
+                if let Some(hash) = blocks_table.get(block_id.height)? {
+                    let hash = BlockHash::from_byte_array(hash.value());
+                    if hash != block_id.hash {
+                        report.anchors_conflicting_block.push((block_id, hash));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // This function returns the txids in the given table (last_seen, first_seen or last_evicted)
+    // whose tx is not persisted.
+    fn txids_missing_tx(
+        &self,
+        read_tx: &ReadTransaction,
+        table_defn: TableDefinition<[u8; 32], u64>,
+    ) -> Result<Vec<Txid>, StoreError> {
+        let table = read_tx.open_table(table_defn)?;
+        let txs_table = read_tx.open_table(self.txs_table_defn())?;
+
+        let mut missing = Vec::new();
+        for entry in table.iter()? {
+            let txid_bytes = entry?.0.value();
+            if txs_table.get(txid_bytes)?.is_none() {
+                missing.push(Txid::from_byte_array(txid_bytes));
+            }
+        }
+        Ok(missing)
+    }
+
+    // This function checks that the spk cache of every descriptor is contiguous from index 0.
+    // Entries are sorted by (DescriptorId, index) so a single pass is enough.
+    fn verify_spk_cache(
+        &self,
+        read_tx: &ReadTransaction,
+        report: &mut VerifyReport,
+    ) -> Result<(), StoreError> {
+        let table = read_tx.open_table(self.spk_table_defn())?;
+
+        let mut current: Option<([u8; 32], u32)> = None;
+        for entry in table.iter()? {
+            let (desc, index) = entry?.0.value();
+            let expected = match current {
+                Some((current_desc, last_index)) if current_desc == desc => last_index + 1,
+                _ => 0,
+            };
+            if index > expected {
+                report
+                    .spk_cache_gaps
+                    .push((DescriptorId::from_byte_array(desc), expected..index));
+            }
+            current = Some((desc, index));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+#![warn(missing_docs)]
+//! This module contains [`CborValue`] which lets any serde type be stored in a redb table, for
+//! instance in custom tables kept in the same [`Database`] as the wallet tables. It is available
+//! with the `serde` feature.
+//!
+//! [`Database`]: <https://docs.rs/redb/2.5.0/redb/struct.Database.html>
+use redb::{Key, TypeName, Value};
+use serde::{Serialize, de::DeserializeOwned};
+use std::any::type_name;
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+/// Implements [`Value`] for any `T` which implements serde's `Serialize` and `Deserialize`, by
+/// storing the ciborium encoding of `T`. Tables are declared with `CborValue<T>` and read and
+/// written with `T` directly:
+///
+/// ```
+/// # use bdk_redb::redb::{Database, TableDefinition, backends::InMemoryBackend};
+/// use bdk_redb::wrapper::CborValue;
+///
+/// #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+/// struct Label {
+///     name: String,
+///     spendable: bool,
+/// }
+///
+/// const LABELS: TableDefinition<u32, CborValue<Label>> = TableDefinition::new("labels");
+///
+/// # let db = Database::builder().create_with_backend(InMemoryBackend::new()).unwrap();
+/// let write_tx = db.begin_write().unwrap();
+/// let label = Label {
+///     name: "savings".to_string(),
+///     spendable: false,
+/// };
+/// write_tx
+///     .open_table(LABELS)
+///     .unwrap()
+///     .insert(0, &label)
+///     .unwrap();
+/// write_tx.commit().unwrap();
+///
+/// let read_tx = db.begin_read().unwrap();
+/// assert_eq!(
+///     read_tx
+///         .open_table(LABELS)
+///         .unwrap()
+///         .get(0)
+///         .unwrap()
+///         .unwrap()
+///         .value(),
+///     label
+/// );
+/// ```
+///
+/// The [`TypeName`] of the table is derived from [`std::any::type_name`] of `T`, so renaming or
+/// moving `T` makes redb refuse to open tables created before. Decoding panics if the stored
+/// bytes are not an encoding of `T`, since [`Value::from_bytes`] cannot fail: changes to `T` must
+/// keep existing values decodable.
+///
+/// `CborValue<T>` also implements [`Key`] when `T: Ord`. The CBOR encoding does not preserve the
+/// order of `T` (for instance `-1` is encoded after `0`, and strings are ordered by length
+/// first), so keys are decoded and compared with `T`'s [`Ord`] for every comparison. Lookups and
+/// range queries follow the semantic order of `T`, but are slower than with fixed width keys,
+/// and the [`Ord`] implementation must never change once a table has been written.
+#[derive(Debug)]
+pub struct CborValue<T>(PhantomData<T>);
+
+impl<T: Serialize + DeserializeOwned + Debug> Value for CborValue<T> {
+    type SelfType<'a>
+        = T
+    where
+        Self: 'a;
+    type AsBytes<'a>
+        = Vec<u8>
+    where
+        Self: 'a;
+
+    fn fixed_width() -> Option<usize> {
+        None
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        ciborium::from_reader(data).expect("value must be a ciborium encoding of the type")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a>
+    where
+        Self: 'b,
+    {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(value, &mut bytes).expect("value must be serializable");
+        bytes
+    }
+
+    fn type_name() -> TypeName {
+        TypeName::new(&format!("bdk_redb::CborValue<{}>", type_name::<T>()))
+    }
+}
+
+impl<T: Serialize + DeserializeOwned + Debug + Ord> Key for CborValue<T> {
+    fn compare(data1: &[u8], data2: &[u8]) -> Ordering {
+        Self::from_bytes(data1).cmp(&Self::from_bytes(data2))
+    }
+}
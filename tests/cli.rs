@@ -0,0 +1,199 @@
+#![cfg(feature = "cli")]
+use bdk_chain::bitcoin::{
+    Amount, BlockHash, Network, OutPoint, ScriptBuf, Transaction, TxIn, TxOut, Txid,
+    absolute::LockTime, hashes::Hash, transaction::Version,
+};
+use bdk_chain::{BlockId, ConfirmationBlockTime, tx_graph};
+use bdk_redb::Store;
+use bdk_redb::redb::Database;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::sync::Arc;
+use tempfile::NamedTempFile;
+
+// This function runs the binary with the given arguments.
+fn cli(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_bdk-redb-cli"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+// This function writes a database with a "wallet1" wallet holding a confirmed transaction and an
+// unconfirmed one, and an empty "wallet2" wallet.
+fn create_fixture(path: &Path) -> [Transaction; 2] {
+    let db = Arc::new(Database::create(path).unwrap());
+    let txs = [10_000, 20_000].map(|amount| Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(amount),
+            script_pubkey: ScriptBuf::new(),
+        }],
+    });
+    let store = Store::new(db.clone(), "wallet1".to_string()).unwrap();
+    store.create_tables::<ConfirmationBlockTime>().unwrap();
+    store.persist_network(&Some(Network::Signet)).unwrap();
+    store
+        .persist_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: txs.iter().cloned().map(Arc::new).collect(),
+            anchors: [(
+                ConfirmationBlockTime {
+                    block_id: BlockId {
+                        height: 3,
+                        hash: BlockHash::from_byte_array([3; 32]),
+                    },
+                    confirmation_time: 300,
+                },
+                txs[0].compute_txid(),
+            )]
+            .into(),
+            last_seen: [(txs[1].compute_txid(), 400)].into(),
+            ..Default::default()
+        })
+        .unwrap();
+    let store = Store::new(db, "wallet2".to_string()).unwrap();
+    store.create_tables::<ConfirmationBlockTime>().unwrap();
+    txs
+}
+
+#[test]
+fn test_wallets() {
+    let tmpfile = NamedTempFile::new().unwrap();
+    create_fixture(tmpfile.path());
+    let output = cli(&["wallets", tmpfile.path().to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "wallet1\tsignet\nwallet2\t-\n"
+    );
+}
+
+#[test]
+fn test_stats() {
+    let tmpfile = NamedTempFile::new().unwrap();
+    create_fixture(tmpfile.path());
+    let output = cli(&["stats", tmpfile.path().to_str().unwrap(), "wallet1"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let entries: Vec<(&str, &str)> = stdout
+        .lines()
+        .skip(1)
+        .map(|line| {
+            let mut columns = line.split('\t');
+            (columns.next().unwrap(), columns.next().unwrap())
+        })
+        .collect();
+    assert_eq!(entries.len(), 10);
+    assert!(entries.contains(&("txs", "2")));
+    assert!(entries.contains(&("anchors", "1")));
+    assert!(entries.contains(&("last_seen", "1")));
+    assert!(entries.contains(&("blocks", "0")));
+}
+
+#[test]
+fn test_stats_prefixed_wallet() {
+    let tmpfile = NamedTempFile::new().unwrap();
+    let db = Arc::new(Database::create(tmpfile.path()).unwrap());
+    let store = Store::new_with_prefix(db, "app".to_string(), "wallet1".to_string()).unwrap();
+    store.create_tables::<ConfirmationBlockTime>().unwrap();
+    drop(store);
+
+    // the wallet is opened under the name listed by the wallets command
+    let path = tmpfile.path().to_str().unwrap();
+    let output = cli(&["wallets", path]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "app:wallet1\t-\n"
+    );
+    let output = cli(&["stats", path, "app:wallet1"]);
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_dump() {
+    let tmpfile = NamedTempFile::new().unwrap();
+    let txs = create_fixture(tmpfile.path());
+    let path = tmpfile.path().to_str().unwrap();
+
+    let output = cli(&["dump", path, "wallet1", "--table", "txs"]);
+    assert!(output.status.success());
+    let rows: Vec<serde_json::Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(rows.len(), 2);
+    for tx in &txs {
+        assert!(rows.contains(&serde_json::json!({
+            "txid": tx.compute_txid().to_string(),
+            "raw": bdk_chain::bitcoin::consensus::encode::serialize_hex(tx),
+        })));
+    }
+
+    let output = cli(&["dump", path, "wallet1", "--table", "last_seen"]);
+    assert!(output.status.success());
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap(),
+        serde_json::json!({"txid": txs[1].compute_txid().to_string(), "timestamp": 400})
+    );
+
+    let output = cli(&["dump", path, "wallet1", "--table", "unknown"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_verify() {
+    let tmpfile = NamedTempFile::new().unwrap();
+    create_fixture(tmpfile.path());
+    let path = tmpfile.path().to_str().unwrap();
+
+    let output = cli(&["verify", path, "wallet1"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "ok\n");
+
+    // a last_seen entry whose tx is missing, written directly to bypass the checks of the store
+    let missing = Txid::from_byte_array([7; 32]);
+    {
+        let db = Database::open(tmpfile.path()).unwrap();
+        let write_tx = db.begin_write().unwrap();
+        write_tx
+            .open_table(bdk_redb::redb::TableDefinition::<[u8; 32], u64>::new(
+                "wallet1_last_seen",
+            ))
+            .unwrap()
+            .insert(missing.to_byte_array(), 500)
+            .unwrap();
+        write_tx.commit().unwrap();
+    }
+    let output = cli(&["verify", path, "wallet1"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("last_seen of missing tx {missing}\n")
+    );
+}
+
+#[test]
+fn test_errors() {
+    let output = cli(&[]);
+    assert_eq!(output.status.code(), Some(2));
+    let output = cli(&["stats", "missing.redb"]);
+    assert_eq!(output.status.code(), Some(2));
+
+    // the file is not created
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("missing.redb");
+    let output = cli(&["wallets", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!path.exists());
+
+    let tmpfile = NamedTempFile::new().unwrap();
+    create_fixture(tmpfile.path());
+    let output = cli(&["stats", tmpfile.path().to_str().unwrap(), "unknown"]);
+    assert_eq!(output.status.code(), Some(1));
+}
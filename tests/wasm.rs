@@ -0,0 +1,33 @@
+#![cfg(all(target_arch = "wasm32", feature = "wallet"))]
+//! Smoke test of a store on [`MemoryBackend`] for `wasm32-unknown-unknown`, run with
+//! `wasm-pack test --node -- --test wasm` (see the README).
+use bdk_chain::bitcoin::{BlockHash, Network, hashes::Hash};
+use bdk_chain::local_chain;
+use bdk_redb::Store;
+use bdk_redb::backend::MemoryBackend;
+use bdk_wallet::ChangeSet;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn persist_and_read_on_memory_backend() {
+    let backend = MemoryBackend::new();
+    let store = Store::from_backend(backend.clone(), "wallet1")
+        .unwrap()
+        .with_clock(Box::new(|| 1_700_000_000));
+    let changeset = ChangeSet {
+        network: Some(Network::Signet),
+        local_chain: local_chain::ChangeSet {
+            blocks: [(0, Some(BlockHash::from_byte_array([1; 32])))].into(),
+        },
+        ..Default::default()
+    };
+    store.persist_wallet(&changeset).unwrap();
+    drop(store);
+
+    let store =
+        Store::from_backend(MemoryBackend::from_bytes(backend.to_bytes()), "wallet1").unwrap();
+    let mut read = ChangeSet::default();
+    store.read_wallet(&mut read).unwrap();
+    assert_eq!(read, changeset);
+    assert_eq!(store.last_persist_at().unwrap(), Some(1_700_000_000));
+}